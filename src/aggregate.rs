@@ -0,0 +1,97 @@
+use crate::api::Price;
+use crate::exchange::{self, Exchange};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// a source without a price for this long is left out of the composite
+const STALE_AFTER: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AggregateMode {
+    Median,
+    Average,
+}
+
+struct Quote {
+    price: f64,
+    received: Instant,
+}
+
+struct Aggregator {
+    mode: AggregateMode,
+    // exchanges combined with the selected one, empty disables aggregation
+    sources: Vec<&'static dyn Exchange>,
+    // latest quote by pair name, then by exchange id
+    quotes: HashMap<String, HashMap<&'static str, Quote>>,
+}
+
+impl Aggregator {
+    fn record(&mut self, exchange: &dyn Exchange, price: &Price) {
+        self.quotes.entry(price.name.clone()).or_default().insert(
+            exchange.id(),
+            Quote {
+                price: price.tag_price,
+                received: Instant::now(),
+            },
+        );
+    }
+
+    fn combine(&mut self, exchange: &dyn Exchange, price: &Price) -> Option<f64> {
+        if self.sources.is_empty() {
+            return None;
+        }
+        self.record(exchange, price);
+        let quotes = self.quotes.get_mut(&price.name)?;
+        quotes.retain(|_, quote| quote.received.elapsed() < STALE_AFTER);
+        let mut prices: Vec<f64> = quotes.values().map(|quote| quote.price).collect();
+        prices.sort_by(|a, b| a.total_cmp(b));
+        Some(match self.mode {
+            AggregateMode::Median => {
+                let mid = prices.len() / 2;
+                if prices.len().is_multiple_of(2) {
+                    (prices[mid - 1] + prices[mid]) / 2.
+                } else {
+                    prices[mid]
+                }
+            }
+            AggregateMode::Average => prices.iter().sum::<f64>() / prices.len() as f64,
+        })
+    }
+}
+
+lazy_static! {
+    static ref AGGREGATOR: Mutex<Aggregator> = Mutex::new(Aggregator {
+        mode: AggregateMode::Median,
+        sources: Vec::new(),
+        quotes: HashMap::new(),
+    });
+}
+
+// unknown exchange ids are skipped
+pub fn set_sources(ids: &[String], mode: AggregateMode) {
+    let mut aggregator = AGGREGATOR.lock().unwrap();
+    aggregator.sources = ids
+        .iter()
+        .filter_map(|id| exchange::find_exchange(id))
+        .collect();
+    aggregator.mode = mode;
+}
+
+pub fn sources() -> Vec<&'static dyn Exchange> {
+    AGGREGATOR.lock().unwrap().sources.clone()
+}
+
+// keeps the price of an aggregate source for the next combine of its pair
+pub fn record(exchange: &dyn Exchange, price: &Price) {
+    AGGREGATOR.lock().unwrap().record(exchange, price)
+}
+
+// records the price from exchange and returns the composite of the fresh
+// quotes of its pair, None when aggregation is off
+pub fn combine(exchange: &dyn Exchange, price: &Price) -> Option<f64> {
+    AGGREGATOR.lock().unwrap().combine(exchange, price)
+}
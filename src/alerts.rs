@@ -0,0 +1,244 @@
+use crate::api::Price;
+use crate::format;
+use crate::i18n;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Condition {
+    Above,
+    Below,
+}
+
+// e.g. {"pair_name": "BTC-USDT", "condition": "above", "price": 100000}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertRule {
+    pub pair_name: String,
+    pub condition: Condition,
+    pub price: f64,
+    // wav file path, "none" for silence, absent for the system beep
+    #[serde(default)]
+    pub sound: Option<String>,
+    #[serde(default)]
+    pub webhook: Option<Webhook>,
+}
+
+// posted when the rule fires, e.g. {"url": "http://homeassistant.local:8123/api/webhook/btc"}.
+// {pair}, {price}, {rule} and {timestamp} in the body are replaced, json escaped
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Webhook {
+    pub url: String,
+    #[serde(default = "default_body")]
+    pub body: String,
+}
+
+fn default_body() -> String {
+    r#"{"pair": "{pair}", "price": {price}, "rule": "{rule}", "timestamp": {timestamp}}"#.to_string()
+}
+
+impl Webhook {
+    // timestamp in ms
+    pub fn render(&self, pair: &str, price: f64, rule: &str, timestamp: u64) -> String {
+        // the body puts the quotes, a slack text can hold several fields
+        let escape = |text: &str| {
+            let quoted = serde_json::to_string(text).unwrap_or_default();
+            quoted[1..quoted.len() - 1].to_string()
+        };
+        self.body
+            .replace("{pair}", &escape(pair))
+            .replace("{price}", &price.to_string())
+            .replace("{rule}", &escape(rule))
+            .replace("{timestamp}", &timestamp.to_string())
+    }
+}
+
+impl AlertRule {
+    fn matches(&self, name: &str) -> bool {
+        pair_matches(&self.pair_name, name)
+    }
+
+    // fires only on the tick that crosses the threshold
+    fn crossed(&self, last_price: f64, price: f64) -> bool {
+        match self.condition {
+            Condition::Above => last_price < self.price && price >= self.price,
+            Condition::Below => last_price > self.price && price <= self.price,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        let direction = match self.condition {
+            Condition::Above => i18n::tr("上穿"),
+            Condition::Below => i18n::tr("下穿"),
+        };
+        let price = format::localize(&self.price.to_string());
+        format!("{} {} {}", self.pair_name, direction, price)
+    }
+}
+
+// e.g. {"pair_name": "ETH-USDT", "percent": 2, "minutes": 15}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MoveRule {
+    pub pair_name: String,
+    pub percent: f64,
+    pub minutes: u64,
+    // same as AlertRule::sound
+    #[serde(default)]
+    pub sound: Option<String>,
+    #[serde(default)]
+    pub webhook: Option<Webhook>,
+}
+
+pub struct FiredAlert {
+    pub text: String,
+    pub sound: Option<String>,
+    pub webhook: Option<Webhook>,
+}
+
+impl MoveRule {
+    fn window(&self) -> Duration {
+        Duration::from_secs(self.minutes * 60)
+    }
+
+    pub fn describe(&self, change: f64) -> String {
+        let change = format::localize(&format!("{:+.2}", change));
+        i18n::fill("{} {} 分钟内变动 {}%", &[&self.pair_name, &self.minutes, &change])
+    }
+}
+
+// BTC-USDT and btc/usdt both match the BTCUSDT stream
+fn pair_matches(pair_name: &str, name: &str) -> bool {
+    let pair_name: String = pair_name
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect();
+    pair_name.eq_ignore_ascii_case(name)
+}
+
+struct AlertEngine {
+    rules: Vec<AlertRule>,
+    last_prices: HashMap<String, f64>,
+    move_rules: Vec<MoveRule>,
+    // time-indexed prices per pair, as long as the longest move rule window
+    series: HashMap<String, VecDeque<(Instant, f64)>>,
+    // a move rule stays quiet for its window after firing, by rule index
+    move_fired: HashMap<usize, Instant>,
+}
+
+impl AlertEngine {
+    fn check_crossing(&mut self, price: &Price) -> Vec<FiredAlert> {
+        let last_price = self.last_prices.insert(price.name.clone(), price.tag_price);
+        let last_price = match last_price {
+            Some(last_price) => last_price,
+            None => return Vec::new(),
+        };
+        self.rules
+            .iter()
+            .filter(|rule| rule.matches(&price.name) && rule.crossed(last_price, price.tag_price))
+            .map(|rule| FiredAlert {
+                text: rule.describe(),
+                sound: rule.sound.clone(),
+                webhook: rule.webhook.clone(),
+            })
+            .collect()
+    }
+
+    fn check_moves(&mut self, price: &Price) -> Vec<FiredAlert> {
+        let longest = match self.move_rules.iter().map(|rule| rule.window()).max() {
+            Some(longest) => longest,
+            None => return Vec::new(),
+        };
+        let now = Instant::now();
+        let series = self.series.entry(price.name.clone()).or_default();
+        series.push_back((now, price.tag_price));
+        while let Some((time, _)) = series.front() {
+            if now.duration_since(*time) <= longest {
+                break;
+            }
+            series.pop_front();
+        }
+        let mut fired = Vec::new();
+        for (index, rule) in self.move_rules.iter().enumerate() {
+            if !pair_matches(&rule.pair_name, &price.name) {
+                continue;
+            }
+            if let Some(fired_at) = self.move_fired.get(&index) {
+                if now.duration_since(*fired_at) < rule.window() {
+                    continue;
+                }
+            }
+            // oldest price still inside this rule's window
+            let base = series
+                .iter()
+                .find(|(time, _)| now.duration_since(*time) <= rule.window())
+                .map(|(_, base)| *base);
+            let base = match base {
+                Some(base) if base != 0. => base,
+                _ => continue,
+            };
+            let change = (price.tag_price - base) / base * 100.;
+            if change.abs() >= rule.percent {
+                self.move_fired.insert(index, now);
+                fired.push(FiredAlert {
+                    text: rule.describe(change),
+                    sound: rule.sound.clone(),
+                    webhook: rule.webhook.clone(),
+                });
+            }
+        }
+        fired
+    }
+}
+
+lazy_static! {
+    static ref ALERTS: Mutex<AlertEngine> = Mutex::new(AlertEngine {
+        rules: Vec::new(),
+        last_prices: HashMap::new(),
+        move_rules: Vec::new(),
+        series: HashMap::new(),
+        move_fired: HashMap::new(),
+    });
+}
+
+pub fn set_rules(rules: Vec<AlertRule>, move_rules: Vec<MoveRule>) {
+    let mut engine = ALERTS.lock().unwrap();
+    engine.rules = rules;
+    engine.move_rules = move_rules;
+    engine.move_fired.clear();
+}
+
+// rules fired by this price
+pub fn check(price: &Price) -> Vec<FiredAlert> {
+    let mut engine = ALERTS.lock().unwrap();
+    let mut fired = engine.check_crossing(price);
+    fired.extend(engine.check_moves(price));
+    fired
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_body_is_json_with_the_placeholders_filled() {
+        let webhook: Webhook = serde_json::from_str(r#"{"url": "http://localhost/hook"}"#).unwrap();
+        let body = webhook.render("BTCUSDT", 100000.5, "BTC-USDT 上穿 \"100000\"", 1700000000000);
+        let value: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(value["pair"], "BTCUSDT");
+        assert_eq!(value["price"], 100000.5);
+        assert_eq!(value["rule"], "BTC-USDT 上穿 \"100000\"");
+        assert_eq!(value["timestamp"], 1700000000000u64);
+    }
+
+    #[test]
+    fn custom_body_keeps_its_own_shape() {
+        let webhook = Webhook {
+            url: "http://localhost/hook".to_string(),
+            body: r#"{"text": "{pair} at {price}"}"#.to_string(),
+        };
+        assert_eq!(webhook.render("ETHUSDT", 3000., "", 0), r#"{"text": "ETHUSDT at 3000"}"#);
+    }
+}
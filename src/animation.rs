@@ -0,0 +1,95 @@
+// short effects of the widget on a fast timer, the window repaints on each
+// step until they end
+
+use std::time::{Duration, Instant};
+
+// the background flash of a large move
+pub const FLASH: Duration = Duration::from_millis(800);
+// a new price counting from the shown one
+pub const TWEEN: Duration = Duration::from_millis(200);
+// a step of the animation timer, about 30 frames a second
+pub const FRAME_MS: u32 = 33;
+
+// a move of at least percent between two ticks, 0 never flashes
+pub fn large_move(previous: f64, price: f64, percent: f64) -> bool {
+    if percent <= 0. || previous == 0. {
+        return false;
+    }
+    ((price - previous) / previous * 100.).abs() >= percent
+}
+
+// from 1 down to 0 over duration
+pub fn fade(elapsed: Duration, duration: Duration) -> f32 {
+    if elapsed >= duration {
+        return 0.;
+    }
+    1. - elapsed.as_secs_f32() / duration.as_secs_f32()
+}
+
+// the shown price of a pair between two ticks
+#[derive(Debug, Clone, Copy)]
+pub struct Tween {
+    pub from: f64,
+    pub to: f64,
+    pub start: Instant,
+}
+
+impl Tween {
+    pub fn value(&self) -> f64 {
+        interpolate(self.from, self.to, self.start.elapsed(), TWEEN)
+    }
+
+    pub fn done(&self) -> bool {
+        self.start.elapsed() >= TWEEN
+    }
+}
+
+// eased out, fast at first and settling on to
+pub fn interpolate(from: f64, to: f64, elapsed: Duration, duration: Duration) -> f64 {
+    let left = fade(elapsed, duration) as f64;
+    to - (to - from) * left * left
+}
+
+// the alpha of argb scaled by strength
+pub fn with_alpha(argb: u32, alpha: u8, strength: f32) -> u32 {
+    let alpha = (alpha as f32 * strength.clamp(0., 1.)).round() as u32;
+    (argb & 0x00FFFFFF) | (alpha << 24)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn large_moves_by_percent() {
+        assert!(large_move(100., 101., 1.));
+        assert!(large_move(100., 98.5, 1.));
+        assert!(!large_move(100., 100.5, 1.));
+        assert!(!large_move(100., 200., 0.));
+        assert!(!large_move(0., 1., 1.));
+    }
+
+    #[test]
+    fn fades_to_zero() {
+        let duration = Duration::from_millis(800);
+        assert_eq!(fade(Duration::ZERO, duration), 1.);
+        assert_eq!(fade(Duration::from_millis(400), duration), 0.5);
+        assert_eq!(fade(Duration::from_secs(1), duration), 0.);
+    }
+
+    #[test]
+    fn interpolates_to_the_new_price() {
+        let duration = Duration::from_millis(200);
+        assert_eq!(interpolate(100., 110., Duration::ZERO, duration), 100.);
+        assert_eq!(interpolate(100., 110., Duration::from_millis(100), duration), 107.5);
+        assert_eq!(interpolate(100., 110., Duration::from_millis(300), duration), 110.);
+        assert_eq!(interpolate(110., 100., Duration::from_millis(100), duration), 102.5);
+    }
+
+    #[test]
+    fn scales_the_alpha() {
+        assert_eq!(with_alpha(0xFF00A000, 160, 1.), 0xA000A000);
+        assert_eq!(with_alpha(0xFF00A000, 160, 0.5), 0x5000A000);
+        assert_eq!(with_alpha(0xFF00A000, 160, 0.), 0x0000A000);
+    }
+}
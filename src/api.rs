@@ -1,133 +1,571 @@
+use crate::i18n;
 use crate::my_window;
 use anyhow::Result;
 use futures_channel::mpsc::{UnboundedReceiver, UnboundedSender};
 use futures_util::{future, pin_mut, Stream, StreamExt};
+use crate::aggregate;
+use crate::alerts;
+use crate::config::{Config, Timeouts};
+use crate::exchange::{self, Exchange};
+use crate::fiat;
+use crate::history;
+use crate::http;
+use crate::logging;
+use crate::logos;
+use crate::metrics;
+use crate::notify;
+use crate::pairs::{self, TradePair};
+use crate::throttle;
+use crate::tls;
+use crate::update;
 use lazy_static::lazy_static;
-use serde::{Deserialize, Deserializer};
-use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::os::raw::c_void;
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
 use tokio_tungstenite::tungstenite::protocol::Message;
-use tokio_tungstenite::{client_async_tls, connect_async_tls_with_config, WebSocketStream};
+use tokio_tungstenite::{
+    client_async_tls_with_config, connect_async_tls_with_config, MaybeTlsStream, WebSocketStream,
+};
+use tracing::{debug, info, trace, warn};
 use windows::Win32::Foundation::*;
 use windows::Win32::UI::WindowsAndMessaging::PostMessageW;
 
-#[derive(Deserialize, Debug)]
-#[serde(untagged)]
-enum FlexibleValue {
-    Array(Vec<Value>),
-    Object(serde_json::Map<String, Value>),
-    String(String),
-    Int(i32),
-    Bool(bool),
+#[derive(Debug)]
+pub struct Price {
+    pub time_stamp: u64,
+    pub name: String,
+    pub tag_price: f64,
+    // true when the price came from the rest fallback instead of the ws stream
+    pub polled: bool,
+    // recent prices of the pair, oldest first, for the sparkline
+    pub history: Vec<f64>,
 }
 
-#[derive(Debug, Deserialize)]
-struct ApiResult {
-    result: Option<FlexibleValue>,
-    id: u32,
+impl Price {
+    pub fn new(time_stamp: u64, name: String, tag_price: f64) -> Self {
+        Price {
+            time_stamp,
+            name,
+            tag_price,
+            polled: false,
+            history: Vec::new(),
+        }
+    }
 }
 
-fn string_to_f64<'de, D>(deserializer: D) -> Result<f64, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    let s = String::deserialize(deserializer)?;
-    s.parse::<f64>().map_err(serde::de::Error::custom)
+const HISTORY_LEN: usize = 60;
+
+// rolling buffers of the last HISTORY_LEN prices, by pair name
+struct PriceHistory {
+    prices: HashMap<String, VecDeque<f64>>,
 }
 
-#[derive(Debug, Deserialize)]
-pub struct Price {
-    #[serde(rename = "e")]
-    pub event_type: String,
-    #[serde(rename = "E")]
-    pub time_stamp: u64,
-    #[serde(rename = "s")]
+impl PriceHistory {
+    fn push(&mut self, price: &Price) -> Vec<f64> {
+        let prices = self
+            .prices
+            .entry(price.name.clone())
+            .or_insert_with(|| VecDeque::with_capacity(HISTORY_LEN));
+        if prices.len() == HISTORY_LEN {
+            prices.pop_front();
+        }
+        prices.push_back(price.tag_price);
+        prices.iter().cloned().collect()
+    }
+
+    // fills the free part of the buffer with older closes, oldest first
+    fn backfill(&mut self, name: &str, closes: &[f64]) {
+        let prices = self
+            .prices
+            .entry(name.to_string())
+            .or_insert_with(|| VecDeque::with_capacity(HISTORY_LEN));
+        let free = HISTORY_LEN - prices.len();
+        for close in closes.iter().rev().take(free) {
+            prices.push_front(*close);
+        }
+    }
+
+    // the pairs of a switched exchange, its prices would run into the new ones
+    fn clear(&mut self, exchange: &dyn Exchange, trade_pairs: &[TradePair]) {
+        for trade_pair in trade_pairs {
+            self.prices.remove(&pairs::exchange_pair_info(exchange, trade_pair).pair_name);
+        }
+    }
+}
+
+lazy_static! {
+    static ref PRICE_HISTORY: Mutex<PriceHistory> = Mutex::new(PriceHistory {
+        prices: HashMap::new(),
+    });
+}
+
+fn send_price_to_ui(context: &Context, exchange: &dyn Exchange, mut price: Price) {
+    let hwnd = context.hwnd;
+    if let Some(combined) = aggregate::combine(exchange, &price) {
+        price.tag_price = combined;
+    }
+    for alert in alerts::check(&price) {
+        notify::toast(i18n::tr("价格提醒"), &i18n::fill("{}，当前 {}", &[&alert.text, &price.tag_price]));
+        notify::play_sound(alert.sound.as_deref());
+        if let Some(webhook) = &alert.webhook {
+            let body = webhook.render(&price.name, price.tag_price, &alert.text, price.time_stamp);
+            spawn_webhook(webhook.url.clone(), body, context.proxy.lock().unwrap().clone());
+        }
+        send_message_to_ui(hwnd, ApiMessage::Alert(alert.text));
+    }
+    price.history = PRICE_HISTORY.lock().unwrap().push(&price);
+    send_message_to_ui(hwnd, ApiMessage::Price(price));
+}
+
+#[derive(Debug)]
+pub struct Ticker {
     pub name: String,
-    #[serde(rename = "p", deserialize_with = "string_to_f64")]
-    pub tag_price: f64,
-    #[serde(rename = "i", deserialize_with = "string_to_f64")]
-    pub spot_index_price: f64,
-    #[serde(rename = "P", deserialize_with = "string_to_f64")]
-    pub predict_price: f64,
-    #[serde(rename = "r", deserialize_with = "string_to_f64")]
-    pub fee: f64,
-    #[serde(rename = "T")]
-    pub next_fee_time: u64,
+    pub open_price: f64,
+    pub high_price: f64,
+    pub low_price: f64,
+    // in the base asset
+    pub volume: f64,
+    // in the quote asset, when the exchange reports it
+    pub turnover: Option<f64>,
+}
+
+impl Ticker {
+    // percent change of price against the 24h open
+    pub fn change_percent(&self, price: f64) -> f64 {
+        if self.open_price == 0. {
+            return 0.;
+        }
+        (price - self.open_price) / self.open_price * 100.
+    }
+}
+
+// perpetuals only
+#[derive(Debug)]
+pub struct Funding {
+    pub name: String,
+    pub rate: f64,
+    // ms timestamp of the next settlement
+    pub next_time: u64,
+}
+
+// best bid and offer
+#[derive(Debug)]
+pub struct Bbo {
+    pub name: String,
+    pub bid_price: f64,
+    pub ask_price: f64,
+}
+
+impl Bbo {
+    // spread in basis points of the mid price
+    pub fn spread_bps(&self) -> f64 {
+        let mid = (self.bid_price + self.ask_price) / 2.;
+        if mid == 0. {
+            return 0.;
+        }
+        (self.ask_price - self.bid_price) / mid * 10_000.
+    }
+}
+
+// shallow order book, best levels first, (price, size)
+#[derive(Debug)]
+pub struct Depth {
+    // empty when the stream does not name the pair
+    pub name: String,
+    pub bids: Vec<(f64, f64)>,
+    pub asks: Vec<(f64, f64)>,
+}
+
+// connection state of the selected exchange, drawn as a dot in the widget
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Connected,
+    // between a dropped connection and the next one
+    Reconnecting,
+    // reconnecting after the stage ran out of time
+    TimedOut(Stage),
+    // connected, but no price for Config::stale_seconds, set by the widget
+    Stale,
+    // the rest fallback is serving the prices
+    Polling,
+}
+
+impl Status {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Status::Connected => i18n::tr("已连接"),
+            Status::Reconnecting => i18n::tr("重连中"),
+            Status::TimedOut(stage) => stage.label(),
+            Status::Stale => i18n::tr("行情停滞"),
+            Status::Polling => i18n::tr("轮询"),
+        }
+    }
+}
+
+// reply of the exchange to a subscribe or unsubscribe request
+#[derive(Debug)]
+pub struct Ack {
+    // id of the request, None when the exchange does not echo one
+    pub id: Option<u64>,
+    // bad channel, unknown symbol, rate limit, ..., with the exchange's reason
+    pub error: Option<String>,
+}
+
+impl Ack {
+    pub fn ok(id: Option<u64>) -> Self {
+        Ack { id, error: None }
+    }
+
+    pub fn rejected(id: Option<u64>, reason: String) -> Self {
+        Ack {
+            id,
+            error: Some(reason),
+        }
+    }
 }
 
 pub enum ApiMessage {
     Price(Price),
+    Ticker(Ticker),
+    Funding(Funding),
+    Bbo(Bbo),
+    Depth(Depth),
+    // an alert rule fired, the text describes it
+    Alert(String),
     Notify(String),
+    Status(Status),
+    // ws ping round trip of the selected exchange, in ms
+    Latency(u64),
+    // result of the last proxy probe
+    Proxy(ProxyHealth),
+    // the logo of an asset was downloaded
+    Logo(String),
+    // tag of the latest release, newer than the running one
+    Update(String),
 }
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone)]
-pub enum TradePair {
-    BTCUSDT,
-    ETHUSDT,
-    SOLUSDT,
+// whether the proxy in use accepts connections, probed at startup and every PROXY_PROBE_INTERVAL
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyHealth {
+    // no proxy, or NO_PROXY / the pac script sends the exchange around it
+    Direct,
+    // tcp connect time to the proxy, in ms
+    Reachable(u64),
+    Unreachable,
 }
-#[derive(Debug, PartialEq, Eq, Hash, Clone)]
-pub struct TradePairInfo {
-    pub ws_name: String,
-    pub show_name: String,
-    pub pair_name: String,
+
+impl ProxyHealth {
+    pub fn label(&self) -> String {
+        match self {
+            ProxyHealth::Direct => i18n::tr("直连").to_string(),
+            ProxyHealth::Reachable(latency) => format!("{}ms", latency),
+            ProxyHealth::Unreachable => i18n::tr("不可达").to_string(),
+        }
+    }
 }
 
-lazy_static! {
-    pub static ref TRADE_INFO: HashMap<TradePair, TradePairInfo> = [
-        (
-            TradePair::BTCUSDT,
-            TradePairInfo {
-                ws_name: "btcusdt@markPrice".to_string(),
-                show_name: "BTC/USDT".to_string(),
-                pair_name: "BTCUSDT".to_string(),
-            }
-        ),
-        (
-            TradePair::ETHUSDT,
-            TradePairInfo {
-                ws_name: "ethusdt@markPrice".to_string(),
-                show_name: "ETH/USDT".to_string(),
-                pair_name: "ETHUSDT".to_string()
+// why a pair is on the socket, a pair stays subscribed while any holder needs it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Holder {
+    Selected,
+    Display,
+    Carousel,
+    Alert,
+    Portfolio,
+}
+
+// subscription order after the selected pair
+const HOLDERS: [Holder; 5] = [
+    Holder::Selected,
+    Holder::Display,
+    Holder::Carousel,
+    Holder::Alert,
+    Holder::Portfolio,
+];
+
+pub struct Subscriptions {
+    holders: HashMap<Holder, Vec<TradePair>>,
+    // holders per pair
+    counts: HashMap<TradePair, usize>,
+    // also subscribe the best bid/offer channel on the selected exchange
+    pub bbo: bool,
+    // depth of the primary pair, while the detail popup is open
+    pub depth: bool,
+    // pairs an exchange refused, by exchange id, not subscribed there again
+    // until the pairs change
+    rejected: HashSet<(&'static str, TradePair)>,
+}
+
+impl Subscriptions {
+    pub fn new(primary: TradePair, config: &Config) -> Self {
+        let mut subscriptions = Subscriptions {
+            holders: HashMap::new(),
+            counts: HashMap::new(),
+            bbo: config.show_bbo,
+            depth: false,
+            rejected: HashSet::new(),
+        };
+        subscriptions.set_holder(Holder::Selected, vec![primary]);
+        subscriptions.apply_config(config);
+        subscriptions
+    }
+
+    pub fn primary(&self) -> TradePair {
+        self.holders[&Holder::Selected][0].clone()
+    }
+
+    pub fn set_primary(&mut self, trade_pair: TradePair) {
+        self.set_holder(Holder::Selected, vec![trade_pair]);
+    }
+
+    pub fn apply_config(&mut self, config: &Config) {
+        self.set_holder(Holder::Display, config.extra_pairs());
+        self.set_holder(Holder::Carousel, config.carousel_pairs());
+        self.set_holder(Holder::Alert, config.alert_pairs());
+        let holding_pairs = config.holding_pairs().into_iter().filter_map(|(_, _, pair)| pair).collect();
+        self.set_holder(Holder::Portfolio, holding_pairs);
+    }
+
+    // replaces the pairs of one holder, pairs no holder needs anymore drop out of all()
+    pub fn set_holder(&mut self, holder: Holder, mut pairs: Vec<TradePair>) {
+        self.rejected.clear();
+        let mut seen = Vec::new();
+        pairs.retain(|pair| {
+            let first = !seen.contains(pair);
+            seen.push(pair.clone());
+            first
+        });
+        for pair in &pairs {
+            *self.counts.entry(pair.clone()).or_insert(0) += 1;
+        }
+        for pair in self.holders.insert(holder, pairs).unwrap_or_default() {
+            if let Some(count) = self.counts.get_mut(&pair) {
+                *count -= 1;
+                if *count == 0 {
+                    self.counts.remove(&pair);
+                }
             }
-        ),
-        (
-            TradePair::SOLUSDT,
-            TradePairInfo {
-                ws_name: "solusdt@markPrice".to_string(),
-                show_name: "SOL/USDT".to_string(),
-                pair_name: "SOLUSDT".to_string()
+        }
+    }
+
+    pub fn reject(&mut self, exchange: &dyn Exchange, trade_pair: TradePair) {
+        self.rejected.insert((exchange.id(), trade_pair));
+    }
+
+    pub fn is_rejected(&self, exchange: &dyn Exchange, trade_pair: &TradePair) -> bool {
+        self.rejected.contains(&(exchange.id(), trade_pair.clone()))
+    }
+
+    pub fn is_held(&self, trade_pair: &TradePair) -> bool {
+        self.counts.contains_key(trade_pair)
+    }
+
+    // every subscribed pair once, primary first
+    pub fn all(&self) -> Vec<TradePair> {
+        let mut all: Vec<TradePair> = Vec::new();
+        for holder in HOLDERS {
+            for trade_pair in self.holders.get(&holder).into_iter().flatten() {
+                if !all.contains(trade_pair) {
+                    all.push(trade_pair.clone());
+                }
             }
-        ),
-    ]
-    .iter()
-    .cloned()
-    .collect();
+        }
+        all
+    }
+}
+
+pub enum UiCommand {
+    TradePair(TradePair),
+    Exchange(&'static dyn Exchange),
+    // the detail popup opened or closed
+    Depth(bool),
+    // an asset without a cached logo and Config::logo_url
+    Logo(String, String),
+    // saved from the settings window
+    Config(Box<Config>),
+    // the message loop ended, close the socket and return from run
+    Shutdown,
+}
+
+lazy_static! {
+    // by message kind and pair name
+    static ref COALESCER: Mutex<throttle::Coalescer<(&'static str, String), ApiMessage>> =
+        Mutex::new(throttle::Coalescer::default());
 }
 
+// market data is throttled to throttle::interval per pair, everything else goes at once.
+// the http endpoint and the history see every tick, only the repaints are coalesced
 fn send_message_to_ui(hwnd: usize, message: ApiMessage) {
+    http::record(&message);
+    if let ApiMessage::Price(price) = &message {
+        history::record(price);
+    }
+    let key = match &message {
+        ApiMessage::Price(price) => ("price", price.name.clone()),
+        ApiMessage::Ticker(ticker) => ("ticker", ticker.name.clone()),
+        ApiMessage::Bbo(bbo) => ("bbo", bbo.name.clone()),
+        ApiMessage::Depth(depth) => ("depth", depth.name.clone()),
+        _ => return post_message_to_ui(hwnd, message),
+    };
+    let interval = throttle::interval();
+    if interval.is_zero() {
+        return post_message_to_ui(hwnd, message);
+    }
+    let offer = COALESCER
+        .lock()
+        .unwrap()
+        .offer(key.clone(), message, std::time::Instant::now(), interval);
+    match offer {
+        throttle::Offer::Send(message) => post_message_to_ui(hwnd, message),
+        throttle::Offer::Hold(Some(delay)) => match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                handle.spawn(async move {
+                    time::sleep(delay).await;
+                    let pending = COALESCER.lock().unwrap().flush(&key, std::time::Instant::now());
+                    if let Some(message) = pending {
+                        post_message_to_ui(hwnd, message);
+                    }
+                });
+            }
+            // nothing could flush it later, it goes out now
+            Err(_) => {
+                let pending = COALESCER.lock().unwrap().flush(&key, std::time::Instant::now());
+                if let Some(message) = pending {
+                    post_message_to_ui(hwnd, message);
+                }
+            }
+        },
+        throttle::Offer::Hold(None) => {}
+    }
+}
+
+fn post_message_to_ui(hwnd: usize, message: ApiMessage) {
     let message_p = Box::into_raw(Box::new(message)) as *mut c_void;
     unsafe {
-        let _ = PostMessageW(
+        // the window is already gone while shutting down
+        if PostMessageW(
             HWND(hwnd as *mut c_void),
             my_window::Window::WM_FRESH,
             WPARAM(message_p as usize),
             LPARAM::default(),
         )
-        .expect("post message error");
+        .is_err()
+        {
+            drop(Box::from_raw(message_p as *mut ApiMessage));
+        }
+    }
+}
+
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::Notify;
+use tokio::time::{self, Duration, Instant};
+
+// the local clock, for exchanges whose messages carry no usable time
+pub(crate) fn now_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis() as u64)
+}
+
+const PING_INTERVAL: Duration = Duration::from_secs(20);
+// wait for the close handshake on exit
+pub const CLOSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+// set by UiCommand::Shutdown, the run loop stops instead of reconnecting
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+
+fn shutting_down() -> bool {
+    SHUTTING_DOWN.load(Ordering::Relaxed)
+}
+// read timeouts in a row before the connection is torn down
+const MISSED_HEARTBEATS: u32 = 3;
+// undecodable frames in a row before the connection is torn down
+const DECODE_FAIL_LIMIT: u32 = 5;
+
+lazy_static! {
+    static ref TIMEOUTS: RwLock<Timeouts> = RwLock::new(Timeouts::default());
+}
+
+// applies to the next connection
+pub fn set_timeouts(timeouts: Timeouts) {
+    *TIMEOUTS.write().unwrap() = timeouts;
+}
+
+fn timeouts() -> Timeouts {
+    *TIMEOUTS.read().unwrap()
+}
+
+fn seconds(seconds: u64) -> Duration {
+    Duration::from_secs(seconds.max(1))
+}
+
+// connection stage that ran out of time, named in the retry notice
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    ProxyConnect,
+    TlsHandshake,
+    Read,
+    SubscribeAck,
+}
+
+impl Stage {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Stage::ProxyConnect => i18n::tr("代理连接超时"),
+            Stage::TlsHandshake => i18n::tr("TLS 握手超时"),
+            Stage::Read => i18n::tr("读取超时"),
+            Stage::SubscribeAck => i18n::tr("订阅确认超时"),
+        }
+    }
+}
+
+// queues a frame for the ws writer, false once the writer is gone
+fn queue(tx: &UnboundedSender<Message>, message: Message) -> bool {
+    match tx.unbounded_send(message) {
+        Ok(()) => true,
+        Err(err) => {
+            debug!("ws queue closed:{}", err);
+            false
+        }
+    }
+}
+
+// a ws ping for the latency, plus the application ping of exchanges that need
+// one. false when the connection is gone
+fn send_ping(exchange: &dyn Exchange, tx: &UnboundedSender<Message>) -> bool {
+    let ping = Message::Ping(now_millis().to_be_bytes().to_vec());
+    if !queue(tx, ping) {
+        return false;
+    }
+    match exchange.ping_message() {
+        Some(ping_message) => queue(tx, ping_message),
+        None => true,
     }
 }
 
-use tokio::time::{self, Duration};
+// the subscribed pair a request was sent for
+fn rejected_pair(context: &Context, exchange: &dyn Exchange, pair_name: &str) -> Option<TradePair> {
+    context
+        .subscriptions
+        .lock()
+        .unwrap()
+        .all()
+        .into_iter()
+        .find(|trade_pair| pairs::exchange_pair_info(exchange, trade_pair).pair_name == pair_name)
+}
+
+// a subscription the exchange rejects is dropped, the others keep streaming
 async fn ws_handle<T>(
     ws_stream: T,
-    trade_pair_arc: Arc<Mutex<TradePair>>,
-    hwnd: usize,
+    context: &Context,
+    exchange: &'static dyn Exchange,
     tx: UnboundedSender<Message>,
     rx: &mut UnboundedReceiver<Message>,
-) where
+    // aggregate sources only contribute prices
+    primary: bool,
+) -> Ended
+where
     T: Stream<
         Item = Result<
             tokio_tungstenite::tungstenite::Message,
@@ -136,172 +574,1019 @@ async fn ws_handle<T>(
     >,
     T: futures_util::Sink<Message> + Unpin,
 {
+    let hwnd = context.hwnd;
+    for message in exchange.connect_messages() {
+        queue(&tx, message);
+    }
     {
-        let trade_pair = trade_pair_arc.lock().unwrap();
-        subscribe(&trade_pair, tx.clone());
+        let subscriptions = context.subscriptions.lock().unwrap();
+        let bbo = primary && subscriptions.bbo;
+        for trade_pair in subscriptions.all() {
+            if !subscriptions.is_rejected(exchange, &trade_pair) {
+                subscribe(exchange, &trade_pair, bbo, tx.clone());
+            }
+        }
+        if primary && subscriptions.depth {
+            subscribe_depth(exchange, &subscriptions.primary(), true, tx.clone());
+        }
+    }
+    if primary {
+        send_message_to_ui(hwnd, ApiMessage::Status(Status::Connected));
     }
     let (write, mut read) = ws_stream.split();
     let send_to_ws = rx.map(Ok).forward(write);
-    let timeout_duration = Duration::from_secs(10); 
+    let timeouts = timeouts();
+    let read_timeout = seconds(timeouts.read);
+    let ack_deadline = Instant::now() + seconds(timeouts.subscribe_ack);
     let receiv_from_ws = async{
+        let mut missed = 0;
+        let mut decode_failures = 0;
+        // an ack or a price arrived, exchanges without acks only send prices
+        let mut acked = false;
         loop{
-            let timeout_result = time::timeout(timeout_duration, read.next()).await;
-            if timeout_result.is_err(){
-                println!("连接超时");
-                let test_msg = Message::Text("haha".to_string());
-                    tx.unbounded_send(test_msg).unwrap();
-                continue;
-            }
-            let result = timeout_result.unwrap();
-            if result.is_none(){
-                break;
-            }
-            let message =result.unwrap();
+            let wait = if acked {
+                read_timeout
+            } else {
+                read_timeout.min(ack_deadline.saturating_duration_since(Instant::now()))
+            };
+            let result = match time::timeout(wait, read.next()).await {
+                Ok(result) => result,
+                Err(_) => {
+                    if !acked && Instant::now() >= ack_deadline {
+                        warn!("no ack within {}s", timeouts.subscribe_ack);
+                        return Ended::TimedOut(Stage::SubscribeAck);
+                    }
+                    missed += 1;
+                    warn!("read timeout {}/{}", missed, MISSED_HEARTBEATS);
+                    // a half-open socket never errors, give it up and reconnect
+                    if missed >= MISSED_HEARTBEATS {
+                        return Ended::TimedOut(Stage::Read);
+                    }
+                    if !send_ping(exchange, &tx) {
+                        break;
+                    }
+                    continue;
+                }
+            };
+            missed = 0;
+            let message = match result {
+                Some(message) => message,
+                None => break,
+            };
             match message {
-                Ok(Message::Text(str_data)) => {
-                    println!("str_data:{}", str_data);
-                    let price = serde_json::from_str::<Price>(&str_data);
-                    if !price.is_ok() {
-                        // let api_result = serde_json::from_str::<ApiResult>(&str_data);
-                        // if !api_result.is_ok() {
-                        //     break;
-                        // }
-                        // continue;
+                Ok(message @ (Message::Text(_) | Message::Binary(_))) => {
+                    metrics::count_ws_message();
+                    let str_data = match exchange.decode_message(message) {
+                        Ok(Some(str_data)) => str_data,
+                        Ok(None) => continue,
+                        Err(err) => {
+                            decode_failures += 1;
+                            warn!("decode err:{} ({}/{})", err, decode_failures, DECODE_FAIL_LIMIT);
+                            // a stream that keeps failing is likely out of sync, start over
+                            if decode_failures >= DECODE_FAIL_LIMIT {
+                                break;
+                            }
+                            continue;
+                        }
+                    };
+                    decode_failures = 0;
+                    trace!("str_data:{}", str_data);
+                    if let Some(ack) = exchange.parse_ack(&str_data) {
+                        // an answer either way, the connection is alive
+                        acked = true;
+                        let request = ack.id.and_then(exchange::settle_request);
+                        let description = request.as_ref().map_or("", |request| request.description.as_str());
+                        let reason = match ack.error {
+                            Some(reason) => reason,
+                            None => {
+                                debug!("ack {}", description);
+                                continue;
+                            }
+                        };
+                        warn!("subscribe err:{} {}", description, reason);
+                        let rejected = request.and_then(|request| rejected_pair(context, exchange, &request.pair_name));
+                        if let Some(trade_pair) = rejected {
+                            let mut subscriptions = context.subscriptions.lock().unwrap();
+                            // only the selected pair is worth a notice, it has nothing else to show
+                            if primary && subscriptions.primary() == trade_pair {
+                                let notice = i18n::fill("订阅失败: {}", &[&reason]);
+                                send_message_to_ui(hwnd, ApiMessage::Notify(notice));
+                            }
+                            subscriptions.reject(exchange, trade_pair);
+                        }
                         continue;
                     }
-                    let price = price.unwrap();
-                    send_message_to_ui(hwnd, ApiMessage::Price(price));
+                    if primary {
+                        for ticker in exchange.parse_tickers(&str_data) {
+                            send_message_to_ui(hwnd, ApiMessage::Ticker(ticker));
+                        }
+                        if let Some(funding) = exchange.parse_funding(&str_data) {
+                            send_message_to_ui(hwnd, ApiMessage::Funding(funding));
+                        }
+                        for bbo in exchange.parse_bbos(&str_data) {
+                            send_message_to_ui(hwnd, ApiMessage::Bbo(bbo));
+                        }
+                        if let Some(mut depth) = exchange.parse_depth(&str_data) {
+                            // only the primary pair has a depth subscription
+                            if depth.name.is_empty() {
+                                let primary = context.subscriptions.lock().unwrap().primary();
+                                depth.name = pairs::exchange_pair_info(exchange, &primary).pair_name;
+                            }
+                            send_message_to_ui(hwnd, ApiMessage::Depth(depth));
+                        }
+                    }
+                    let prices = exchange.parse_prices(&str_data);
+                    if prices.is_empty() {
+                        continue;
+                    }
+                    acked = true;
+                    for price in prices {
+                        if primary {
+                            send_price_to_ui(context, exchange, price);
+                        } else {
+                            // the next price of the selected exchange carries it
+                            aggregate::record(exchange, &price);
+                        }
+                    }
                 }
                 Ok(Message::Ping(payload)) => {
-                    println!("ping");
-                    let pong_msg = Message::Pong(payload.clone());
-                    tx.unbounded_send(pong_msg).unwrap();
+                    trace!("ping");
+                    if !queue(&tx, Message::Pong(payload)) {
+                        break;
+                    }
+                }
+                Ok(Message::Pong(payload)) => {
+                    // our pings carry the ms timestamp they were sent at
+                    if let Ok(sent) = <[u8; 8]>::try_from(payload.as_slice()) {
+                        let latency = now_millis().saturating_sub(u64::from_be_bytes(sent));
+                        if primary {
+                            send_message_to_ui(hwnd, ApiMessage::Latency(latency));
+                        }
+                    }
                 }
                 Ok(Message::Close(_)) => {
-                    println!("close");
+                    debug!("close");
                 }
                 Err(err) => {
-                    println!("ws message is err:{:?}", err);
+                    warn!("ws message is err:{:?}", err);
                     break;
                 }
                 _ => {
-                    println!("other ws message");
+                    debug!("other ws message");
                 }
             }
         }
+        Ended::Dropped
+    };
+    let ping_loop = async {
+        let mut interval = time::interval(PING_INTERVAL);
+        loop {
+            interval.tick().await;
+            if !send_ping(exchange, &tx) {
+                break;
+            }
+        }
     };
-    pin_mut!(send_to_ws, receiv_from_ws);
-    future::select(send_to_ws, receiv_from_ws).await;
+    let exchange_changed = context.reconnect.notified();
+    pin_mut!(send_to_ws, receiv_from_ws, ping_loop, exchange_changed);
+    let finished = future::select(
+        future::select(send_to_ws, receiv_from_ws),
+        future::select(ping_loop, exchange_changed),
+    )
+    .await;
+    match finished {
+        future::Either::Left((future::Either::Right((ended, _)), _)) => ended,
+        _ => Ended::Dropped,
+    }
 }
 
-use crate::proxy::InnerProxy::InnerProxy;
-async fn work(
-    trade_pair_arc: Arc<Mutex<TradePair>>,
+const OUTAGE_NOTIFY_AFTER: Duration = Duration::from_secs(60);
+
+// raises a toast when the ws has been down for a while and when it recovers
+struct ConnectionWatch {
+    down_since: Option<Instant>,
+    notified: bool,
+    // false for aggregate sources, which stay quiet
+    primary: bool,
+}
+
+impl ConnectionWatch {
+    fn new(primary: bool) -> Self {
+        ConnectionWatch {
+            down_since: None,
+            notified: false,
+            primary,
+        }
+    }
+
+    fn on_connected(&mut self) {
+        if self.notified {
+            notify::toast(i18n::tr("已重新连接"), i18n::tr("行情连接已恢复"));
+        }
+        self.down_since = None;
+        self.notified = false;
+    }
+
+    fn on_disconnected(&mut self) {
+        let down_since = *self.down_since.get_or_insert_with(Instant::now);
+        if self.primary && !self.notified && down_since.elapsed() >= OUTAGE_NOTIFY_AFTER {
+            notify::toast(i18n::tr("连接已断开"), i18n::tr("超过 1 分钟未能连接到交易所"));
+            self.notified = true;
+        }
+    }
+}
+
+use crate::proxy::{self, InnerProxy::InnerProxy};
+
+// how a connection made by work ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Ended {
+    // connected, then dropped or asked to reconnect
+    Dropped,
+    // no connection
+    Failed,
+    // the proxy could not be reached or refused the tunnel
+    ProxyFailed,
+    TimedOut(Stage),
+}
+
+impl Ended {
+    // the connection was up before it ended
+    fn connected(&self) -> bool {
+        matches!(self, Ended::Dropped | Ended::TimedOut(Stage::Read))
+    }
+}
+
+// false after telling the user when the certificate matches none of the url host's pins
+fn pinned<S: AsyncRead + AsyncWrite + Unpin>(url: &str, stream: &MaybeTlsStream<S>, hwnd: usize, primary: bool) -> bool {
+    let host = url::Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_default();
+    match tls::verify(&host, stream) {
+        Ok(()) => true,
+        Err(reason) => {
+            warn!("pin err:{}", reason);
+            if primary {
+                send_message_to_ui(hwnd, ApiMessage::Notify(i18n::tr("证书不匹配").to_string()));
+            }
+            false
+        }
+    }
+}
+
+// what the connection tasks share with the ui commands
+#[derive(Clone)]
+struct Context {
+    subscriptions: Arc<Mutex<Subscriptions>>,
+    exchange: Arc<Mutex<&'static dyn Exchange>>,
+    proxy: Arc<Mutex<Option<String>>>,
+    // a new exchange, pair or proxy from the ui
+    reconnect: Arc<Notify>,
     hwnd: usize,
+}
+
+async fn work(
+    context: &Context,
     tx: UnboundedSender<Message>,
     rx: &mut UnboundedReceiver<Message>,
     proxy_str: &Option<String>,
-) {
-    let url = "wss://fstream.binance.com/ws".to_string();
-    if !proxy_str.is_none() {
-        let proxy_url = proxy_str.clone().unwrap();
-        let proxy = match InnerProxy::from_proxy_str(&proxy_url) {
+    watch: &mut ConnectionWatch,
+) -> Ended {
+    let exchange = *context.exchange.lock().unwrap();
+    let url = exchange.url().to_string();
+    let timeouts = timeouts();
+    let proxy_str = &{
+        let (proxy_str, target) = (proxy_str.clone(), url.clone());
+        tokio::task::spawn_blocking(move || proxy::for_target(&proxy_str, &target))
+            .await
+            .unwrap_or(None)
+    };
+    if let Some(proxy_url) = proxy_str {
+        let proxy = match InnerProxy::from_proxy_str(proxy_url) {
             Ok(proxy) => proxy,
-            Err(_) => return,
+            Err(_) => return Ended::ProxyFailed,
         };
-        let tcp_stream = match proxy.connect_async(&url).await {
-            Ok(stream) => stream,
-            Err(_) => return,
+        let connect = proxy.connect_async(&url);
+        let tcp_stream = match time::timeout(seconds(timeouts.proxy_connect), connect).await {
+            Ok(Ok(stream)) => stream,
+            Ok(Err(err)) => {
+                // e.g. PermissionDenied when the proxy rejected the credentials
+                warn!("proxy connect err:{:?}", err);
+                return Ended::ProxyFailed;
+            }
+            Err(_) => return Ended::TimedOut(Stage::ProxyConnect),
         };
-        let (ws_stream, _) = match client_async_tls(&url, tcp_stream).await {
-            Ok(stream) => stream,
-            Err(_) => return,
+        let handshake = client_async_tls_with_config(&url, tcp_stream, None, tls::connector());
+        let (ws_stream, _) = match time::timeout(seconds(timeouts.tls_handshake), handshake).await {
+            Ok(Ok(stream)) => stream,
+            Ok(Err(_)) => return Ended::Failed,
+            Err(_) => return Ended::TimedOut(Stage::TlsHandshake),
         };
-        ws_handle(
-            ws_stream,
-            Arc::clone(&trade_pair_arc),
-            hwnd,
-            tx.clone(),
-            rx,
-        )
-        .await;
+        if !pinned(&url, ws_stream.get_ref(), context.hwnd, watch.primary) {
+            return Ended::Failed;
+        }
+        watch.on_connected();
+        ws_handle(ws_stream, context, exchange, tx.clone(), rx, watch.primary).await
     } else {
-        let (ws_stream, _) = match connect_async_tls_with_config(&url, None, true, None).await {
-            Ok(stream) => stream,
-            Err(_) => return,
+        let handshake = connect_async_tls_with_config(&url, None, true, tls::connector());
+        let (ws_stream, _) = match time::timeout(seconds(timeouts.tls_handshake), handshake).await {
+            Ok(Ok(stream)) => stream,
+            Ok(Err(_)) => return Ended::Failed,
+            Err(_) => return Ended::TimedOut(Stage::TlsHandshake),
         };
-        ws_handle(
-            ws_stream,
-            Arc::clone(&trade_pair_arc),
-            hwnd,
-            tx.clone(),
-            rx,
-        )
-        .await;
+        if !pinned(&url, ws_stream.get_ref(), context.hwnd, watch.primary) {
+            return Ended::Failed;
+        }
+        watch.on_connected();
+        ws_handle(ws_stream, context, exchange, tx.clone(), rx, watch.primary).await
+    }
+}
+
+pub(crate) fn http_client(proxy_str: &Option<String>) -> reqwest::Result<reqwest::Client> {
+    // coinbase rejects requests without a user agent
+    let mut builder = reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .user_agent("mjj")
+        // for the pins of tls::send
+        .tls_info(true);
+    for der in tls::extra_roots() {
+        builder = builder.add_root_certificate(reqwest::Certificate::from_der(&der)?);
+    }
+    if let Some(proxy_url) = proxy_str {
+        let proxy = if proxy::pac::is_pac(proxy_url) {
+            let pac_str = proxy_url.clone();
+            reqwest::Proxy::custom(move |url| proxy::for_target_cached(&pac_str, url.as_str()))
+        } else {
+            // reqwest resolves the host itself behind socks5, socks5h leaves it to the proxy
+            let proxy_url = match proxy_url.strip_prefix("socks5://") {
+                Some(address) if proxy::remote_dns() => format!("socks5h://{}", address),
+                _ => proxy_url.clone(),
+            };
+            reqwest::Proxy::all(proxy_url)?.no_proxy(reqwest::NoProxy::from_env())
+        };
+        builder = builder.proxy(proxy);
+    }
+    builder.build()
+}
+
+const BACKOFF_BASE: Duration = Duration::from_secs(1);
+const BACKOFF_MAX: Duration = Duration::from_secs(60);
+// a connection that lasted this long resets the backoff
+const BACKOFF_STABLE: Duration = Duration::from_secs(30);
+
+// exponential reconnect delay, the first retry is immediate
+struct Backoff {
+    attempt: u32,
+}
+
+impl Backoff {
+    fn new() -> Self {
+        Backoff { attempt: 0 }
     }
+
+    fn reset(&mut self) {
+        self.attempt = 0;
+    }
+
+    // doubles up to BACKOFF_MAX, the upper half is randomised so clients
+    // dropped together do not come back together
+    fn next_delay(&mut self) -> Duration {
+        let attempt = self.attempt;
+        self.attempt = self.attempt.saturating_add(1);
+        if attempt == 0 {
+            return Duration::ZERO;
+        }
+        let delay = BACKOFF_BASE
+            .saturating_mul(1 << (attempt - 1).min(16))
+            .min(BACKOFF_MAX);
+        delay / 2 + delay.mul_f64(jitter() / 2.)
+    }
+}
+
+// 0..1 from the clock, good enough to spread retries without a rng crate
+fn jitter() -> f64 {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |duration| duration.subsec_nanos());
+    (nanos % 1000) as f64 / 1000.
+}
+
+const POLL_INTERVAL: Duration = Duration::from_secs(3);
+const POLL_DURATION: Duration = Duration::from_secs(60);
+const WS_FAIL_LIMIT: u32 = 3;
+
+// rest fallback for networks that block the ws endpoint, retries ws after POLL_DURATION
+async fn poll(context: &Context, proxy_str: &Option<String>) {
+    let hwnd = context.hwnd;
+    let client = match http_client(proxy_str) {
+        Ok(client) => client,
+        Err(_) => return,
+    };
+    send_message_to_ui(hwnd, ApiMessage::Status(Status::Polling));
+    let poll_loop = async {
+        let mut interval = time::interval(POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+            let exchange = *context.exchange.lock().unwrap();
+            let trade_pair_info = {
+                let subscriptions = context.subscriptions.lock().unwrap();
+                pairs::exchange_pair_info(exchange, &subscriptions.primary())
+            };
+            let url = exchange.rest_ticker_url(&trade_pair_info);
+            let response = match tls::send(client.get(&url)).await {
+                Ok(response) => response,
+                Err(err) => {
+                    warn!("poll err:{:?}", err);
+                    continue;
+                }
+            };
+            let str_data = match response.text().await {
+                Ok(str_data) => str_data,
+                Err(_) => continue,
+            };
+            trace!("poll str_data:{}", str_data);
+            if let Some(mut price) = exchange.parse_rest_price(&trade_pair_info, &str_data) {
+                price.polled = true;
+                send_price_to_ui(context, exchange, price);
+            }
+        }
+    };
+    let exchange_changed = context.reconnect.notified();
+    pin_mut!(poll_loop, exchange_changed);
+    let _ = time::timeout(POLL_DURATION, future::select(poll_loop, exchange_changed)).await;
+}
+
+const WEBHOOK_ATTEMPTS: u32 = 3;
+
+// through the configured proxy, no_proxy keeps a local home assistant direct.
+// retried with a doubling delay on a network error or a 5xx
+fn spawn_webhook(url: String, body: String, proxy_str: Option<String>) {
+    tokio::spawn(async move {
+        let client = match http_client(&proxy_str) {
+            Ok(client) => client,
+            Err(err) => {
+                warn!("webhook client err:{:?}", err);
+                return;
+            }
+        };
+        let mut delay = BACKOFF_BASE;
+        for attempt in 1..=WEBHOOK_ATTEMPTS {
+            let sent = client
+                .post(&url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.clone())
+                .send()
+                .await;
+            match sent {
+                Ok(response) if !response.status().is_server_error() => {
+                    if !response.status().is_success() {
+                        warn!("webhook {} status {}", url, response.status());
+                    }
+                    return;
+                }
+                Ok(response) => warn!("webhook {} status {} ({}/{})", url, response.status(), attempt, WEBHOOK_ATTEMPTS),
+                Err(err) => warn!("webhook {} err:{:?} ({}/{})", url, err, attempt, WEBHOOK_ATTEMPTS),
+            }
+            if attempt < WEBHOOK_ATTEMPTS {
+                time::sleep(delay).await;
+                delay *= 2;
+            }
+        }
+    });
+}
+
+// 1 minute closes so the sparkline does not start empty
+fn spawn_backfill(
+    exchange: &'static dyn Exchange,
+    trade_pairs: Vec<TradePair>,
+    proxy_str: Option<String>,
+) {
+    let proxy_str = proxy::for_exchange(exchange.id(), proxy_str);
+    tokio::spawn(async move {
+        let client = match http_client(&proxy_str) {
+            Ok(client) => client,
+            Err(_) => return,
+        };
+        for trade_pair in trade_pairs {
+            let trade_pair_info = pairs::exchange_pair_info(exchange, &trade_pair);
+            let url = match exchange.klines_url(&trade_pair_info) {
+                Some(url) => url,
+                None => return,
+            };
+            let str_data = match tls::send(client.get(&url)).await {
+                Ok(response) => response.text().await.unwrap_or_default(),
+                Err(err) => {
+                    warn!("klines err:{:?}", err);
+                    continue;
+                }
+            };
+            if let Some(closes) = exchange.parse_klines(&str_data) {
+                PRICE_HISTORY
+                    .lock()
+                    .unwrap()
+                    .backfill(&trade_pair_info.pair_name, &closes);
+            }
+        }
+    });
 }
 
 async fn receive_from_ui(
-    trade_pair_arc: Arc<Mutex<TradePair>>,
-    hwnd: usize,
-    mut receiver: tokio::sync::mpsc::Receiver<TradePair>,
+    context: Context,
+    // --proxy, ahead of the config one
+    cli_proxy: Option<String>,
+    shutdown: Arc<Notify>,
+    mut receiver: tokio::sync::mpsc::Receiver<UiCommand>,
+    tx: UnboundedSender<Message>,
+) {
+    let Context {
+        subscriptions: subscriptions_arc,
+        exchange: exchange_arc,
+        proxy: proxy_arc,
+        reconnect,
+        hwnd,
+    } = &context;
+    let hwnd = *hwnd;
+    loop {
+        while let Some(command) = receiver.recv().await {
+            match command {
+                UiCommand::Shutdown => {
+                    SHUTTING_DOWN.store(true, Ordering::Relaxed);
+                    queue(&tx, Message::Close(None));
+                    shutdown.notify_one();
+                    return;
+                }
+                UiCommand::TradePair(new_trade_pair) => {
+                    let mut subscriptions = subscriptions_arc.lock().unwrap();
+                    let last_primary = subscriptions.primary();
+                    if last_primary == new_trade_pair {
+                        continue;
+                    }
+                    let last_pairs = subscriptions.all();
+                    // a pair that was streaming already has its history
+                    let held = subscriptions.is_held(&new_trade_pair);
+                    subscriptions.set_primary(new_trade_pair.clone());
+                    let new_pairs = subscriptions.all();
+                    let exchange = exchange_arc.lock().unwrap();
+                    let bbo = subscriptions.bbo;
+                    resubscribe(*exchange, &last_pairs, &new_pairs, bbo, tx.clone());
+                    if !held {
+                        let proxy_str = proxy_arc.lock().unwrap().clone();
+                        spawn_backfill(*exchange, vec![new_trade_pair.clone()], proxy_str);
+                    }
+                    if subscriptions.depth {
+                        subscribe_depth(*exchange, &last_primary, false, tx.clone());
+                        subscribe_depth(*exchange, &new_trade_pair, true, tx.clone());
+                    }
+                    // a pair that is already streaming, e.g. a carousel step, needs no notice
+                    if new_pairs.iter().all(|pair| last_pairs.contains(pair)) {
+                        continue;
+                    }
+                }
+                UiCommand::Exchange(new_exchange) => {
+                    let mut last_exchange = exchange_arc.lock().unwrap();
+                    if exchange::same_exchange(*last_exchange, new_exchange) {
+                        continue;
+                    }
+                    let trade_pairs = subscriptions_arc.lock().unwrap().all();
+                    {
+                        let mut history = PRICE_HISTORY.lock().unwrap();
+                        history.clear(*last_exchange, &trade_pairs);
+                        history.clear(new_exchange, &trade_pairs);
+                    }
+                    *last_exchange = new_exchange;
+                    http::set_exchange(new_exchange.id());
+                    spawn_backfill(new_exchange, trade_pairs, proxy_arc.lock().unwrap().clone());
+                    restart_sources(new_exchange, &context);
+                    reconnect.notify_one();
+                }
+                UiCommand::Depth(on) => {
+                    let mut subscriptions = subscriptions_arc.lock().unwrap();
+                    if subscriptions.depth != on {
+                        subscriptions.depth = on;
+                        let exchange = exchange_arc.lock().unwrap();
+                        subscribe_depth(*exchange, &subscriptions.primary(), on, tx.clone());
+                    }
+                    continue;
+                }
+                UiCommand::Logo(asset, template) => {
+                    let proxy_str = proxy_arc.lock().unwrap().clone();
+                    tokio::spawn(async move {
+                        let downloaded = match http_client(&proxy_str) {
+                            Ok(client) => logos::download(&client, &template, &asset).await,
+                            Err(err) => Err(err.into()),
+                        };
+                        match downloaded {
+                            Ok(()) => send_message_to_ui(hwnd, ApiMessage::Logo(asset)),
+                            Err(err) => warn!("logo {} err:{:?}", asset, err),
+                        }
+                    });
+                    continue;
+                }
+                UiCommand::Config(config) => {
+                    let bbo_changed = {
+                        let mut subscriptions = subscriptions_arc.lock().unwrap();
+                        let last_pairs = subscriptions.all();
+                        subscriptions.apply_config(&config);
+                        let exchange = exchange_arc.lock().unwrap();
+                        let bbo = subscriptions.bbo;
+                        resubscribe(*exchange, &last_pairs, &subscriptions.all(), bbo, tx.clone());
+                        let bbo_changed = subscriptions.bbo != config.show_bbo;
+                        subscriptions.bbo = config.show_bbo;
+                        bbo_changed
+                    };
+                    // before the sources restart, they subscribe by price type too
+                    let price_type_changed = exchange::set_price_type(config.price_type);
+                    proxy::set_direct_fallback(config.direct_fallback);
+                    set_timeouts(config.timeouts);
+                    logging::set_level(&config.log_level);
+                    tls::set_pins(&config.cert_pins);
+                    history::set_interval(config.history_seconds);
+                    throttle::set_max_refresh(config.max_refresh_hz);
+                    update::set_enabled(config.update_check);
+                    let routes_changed = proxy::set_exchange_proxies(&config.exchange_proxies)
+                        | proxy::set_remote_dns(config.proxy_remote_dns)
+                        | tls::set_ca_file(&config.ca_file);
+                    let proxy = cli_proxy.clone().or(config.proxy).or_else(proxy::fallback);
+                    let proxy_changed = {
+                        let mut last_proxy = proxy_arc.lock().unwrap();
+                        let proxy_changed = *last_proxy != proxy;
+                        *last_proxy = proxy;
+                        proxy_changed
+                    };
+                    let connection_changed = proxy_changed || routes_changed || price_type_changed;
+                    // the ui already applied the new aggregate sources
+                    let exchange = *exchange_arc.lock().unwrap();
+                    if connection_changed || sources_changed(exchange) {
+                        restart_sources(exchange, &context);
+                    }
+                    // a new connection picks up the bbo channel and the price stream
+                    if !connection_changed && !bbo_changed {
+                        continue;
+                    }
+                    reconnect.notify_one();
+                }
+            }
+            send_message_to_ui(hwnd, ApiMessage::Notify(i18n::tr("切换中...").to_string()));
+        }
+    }
+}
+
+fn subscribe(exchange: &dyn Exchange, trade_pair: &TradePair, bbo: bool, tx: UnboundedSender<Message>) {
+    let trade_pair_info = pairs::exchange_pair_info(exchange, trade_pair);
+    queue(&tx, exchange.subscribe_message(&trade_pair_info));
+    if let Some(message) = exchange.bbo_subscribe_message(&trade_pair_info).filter(|_| bbo) {
+        queue(&tx, message);
+    }
+}
+fn unsubscribe(exchange: &dyn Exchange, trade_pair: &TradePair, bbo: bool, tx: UnboundedSender<Message>) {
+    let trade_pair_info = pairs::exchange_pair_info(exchange, trade_pair);
+    queue(&tx, exchange.unsubscribe_message(&trade_pair_info));
+    if let Some(message) = exchange.bbo_unsubscribe_message(&trade_pair_info).filter(|_| bbo) {
+        queue(&tx, message);
+    }
+}
+fn subscribe_depth(
+    exchange: &dyn Exchange,
+    trade_pair: &TradePair,
+    on: bool,
     tx: UnboundedSender<Message>,
 ) {
+    let trade_pair_info = pairs::exchange_pair_info(exchange, trade_pair);
+    let message = if on {
+        exchange.depth_subscribe_message(&trade_pair_info)
+    } else {
+        exchange.depth_unsubscribe_message(&trade_pair_info)
+    };
+    if let Some(message) = message {
+        queue(&tx, message);
+    }
+}
+// only touches the pairs that differ, the rest keep streaming
+fn resubscribe(
+    exchange: &dyn Exchange,
+    last_pairs: &[TradePair],
+    new_pairs: &[TradePair],
+    bbo: bool,
+    tx: UnboundedSender<Message>,
+) {
+    for trade_pair in last_pairs.iter().filter(|pair| !new_pairs.contains(pair)) {
+        unsubscribe(exchange, trade_pair, bbo, tx.clone());
+    }
+    for trade_pair in new_pairs.iter().filter(|pair| !last_pairs.contains(pair)) {
+        subscribe(exchange, trade_pair, bbo, tx.clone());
+    }
+}
+
+const SOURCE_RETRY: Duration = Duration::from_secs(5);
+
+lazy_static! {
+    // by exchange id
+    static ref SOURCE_TASKS: Mutex<Vec<(&'static str, tokio::task::JoinHandle<()>)>> =
+        Mutex::new(Vec::new());
+}
+
+fn source_exchanges(primary: &'static dyn Exchange) -> Vec<&'static dyn Exchange> {
+    aggregate::sources()
+        .into_iter()
+        .filter(|exchange| !exchange::same_exchange(*exchange, primary))
+        .collect()
+}
+
+// the configured sources are not the running ones
+fn sources_changed(primary: &'static dyn Exchange) -> bool {
+    let running: Vec<&str> = SOURCE_TASKS.lock().unwrap().iter().map(|(id, _)| *id).collect();
+    let configured: Vec<&str> = source_exchanges(primary).iter().map(|exchange| exchange.id()).collect();
+    running != configured
+}
+
+// one connection per aggregate source besides the selected exchange
+fn restart_sources(primary: &'static dyn Exchange, context: &Context) {
+    let mut tasks = SOURCE_TASKS.lock().unwrap();
+    for (_, task) in tasks.drain(..) {
+        task.abort();
+    }
+    for exchange in source_exchanges(primary) {
+        let task = tokio::spawn(source_loop(exchange, context.clone()));
+        tasks.push((exchange.id(), task));
+    }
+}
+
+// keeps the fiat rate fresh, through the proxy like every other request
+async fn fx_loop(proxy_arc: Arc<Mutex<Option<String>>>) {
+    let mut interval = time::interval(fiat::FX_CHECK);
     loop {
-        while let Some(new_trade_pair) = receiver.recv().await {
-            let mut last_trade_pair = trade_pair_arc.lock().unwrap();
-            if *last_trade_pair == new_trade_pair {
+        interval.tick().await;
+        if !fiat::due() {
+            continue;
+        }
+        let proxy_str = proxy_arc.lock().unwrap().clone();
+        if let Ok(client) = http_client(&proxy_str) {
+            if let Err(err) = fiat::refresh(&client).await {
+                warn!("fx rate err:{:?}", err);
+            }
+        }
+    }
+}
+
+// a found release is announced once, later the menu still links to it
+async fn update_loop(proxy_arc: Arc<Mutex<Option<String>>>, hwnd: usize) {
+    time::sleep(update::FIRST_CHECK).await;
+    let mut interval = time::interval(update::CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        let proxy_str = proxy_arc.lock().unwrap().clone();
+        let release = match http_client(&proxy_str) {
+            Ok(client) => update::check(&client).await,
+            Err(err) => Err(err.into()),
+        };
+        let release = match release {
+            Ok(Some(release)) => release,
+            Ok(None) => continue,
+            Err(err) => {
+                warn!("update check err:{:?}", err);
                 continue;
             }
-            unsubscribe(&last_trade_pair, tx.clone());
-            subscribe(&new_trade_pair, tx.clone());
-            *last_trade_pair = new_trade_pair;
-            send_message_to_ui(hwnd, ApiMessage::Notify("切换中...".to_string()));
+        };
+        // the ui owns the state file, it tells whether this one was announced
+        send_message_to_ui(hwnd, ApiMessage::Update(release.tag_name));
+    }
+}
+
+const PROXY_PROBE_INTERVAL: Duration = Duration::from_secs(60);
+const PROXY_PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+// how often the probe task looks for a changed proxy or exchange
+const PROXY_PROBE_CHECK: Duration = Duration::from_secs(2);
+
+lazy_static! {
+    // last probe result, the retry notice names an unreachable proxy
+    static ref PROXY_HEALTH: Mutex<ProxyHealth> = Mutex::new(ProxyHealth::Direct);
+}
+
+// times a plain tcp connect to the proxy the selected exchange would go through
+async fn probe_proxy(proxy_str: Option<String>, target: String) -> ProxyHealth {
+    let proxy_str = tokio::task::spawn_blocking(move || proxy::for_target(&proxy_str, &target))
+        .await
+        .unwrap_or(None);
+    let proxy = match proxy_str.map(|proxy_str| InnerProxy::from_proxy_str(&proxy_str)) {
+        Some(Ok(proxy)) => proxy,
+        // a malformed url can never connect
+        Some(Err(_)) => return ProxyHealth::Unreachable,
+        None => return ProxyHealth::Direct,
+    };
+    let started = Instant::now();
+    match time::timeout(PROXY_PROBE_TIMEOUT, tokio::net::TcpStream::connect(proxy.address())).await {
+        Ok(Ok(_)) => ProxyHealth::Reachable(started.elapsed().as_millis() as u64),
+        Ok(Err(err)) => {
+            warn!("proxy probe {} err:{:?}", proxy.address(), err);
+            ProxyHealth::Unreachable
+        }
+        Err(_) => {
+            warn!("proxy probe {} timed out", proxy.address());
+            ProxyHealth::Unreachable
         }
     }
 }
 
-fn subscribe(trade_pair: &TradePair, tx: UnboundedSender<Message>) {
-    let ws_name = &TRADE_INFO.get(trade_pair).unwrap().ws_name.clone();
-    let message_str = format!(
-        r##"{{"method":"SUBSCRIBE","params":["{}"],"id": 1}}"##,
-        ws_name
-    );
-    tx.unbounded_send(Message::Text(message_str)).unwrap();
+async fn proxy_loop(
+    proxy_arc: Arc<Mutex<Option<String>>>,
+    exchange_arc: Arc<Mutex<&'static dyn Exchange>>,
+    hwnd: usize,
+) {
+    let mut interval = time::interval(PROXY_PROBE_CHECK);
+    let mut last = None;
+    // proxy and exchange of the last probe, a change from the settings probes right away
+    let mut probed: Option<(Option<String>, String, Instant)> = None;
+    loop {
+        interval.tick().await;
+        let exchange = *exchange_arc.lock().unwrap();
+        let proxy_str = proxy::for_exchange(exchange.id(), proxy_arc.lock().unwrap().clone());
+        let target = exchange.url().to_string();
+        let due = probed.as_ref().is_none_or(|(last_proxy, last_target, at)| {
+            *last_proxy != proxy_str || *last_target != target || at.elapsed() >= PROXY_PROBE_INTERVAL
+        });
+        if !due {
+            continue;
+        }
+        probed = Some((proxy_str.clone(), target.clone(), Instant::now()));
+        let health = probe_proxy(proxy_str, target).await;
+        *PROXY_HEALTH.lock().unwrap() = health;
+        send_message_to_ui(hwnd, ApiMessage::Proxy(health));
+        if health == ProxyHealth::Unreachable && last != Some(health) {
+            send_message_to_ui(hwnd, ApiMessage::Notify(i18n::tr("代理不可达").to_string()));
+        }
+        last = Some(health);
+    }
 }
-fn unsubscribe(trade_pair: &TradePair, tx: UnboundedSender<Message>) {
-    let ws_name = &TRADE_INFO.get(trade_pair).unwrap().ws_name.clone();
-    let message_str = format!(
-        r##"{{"method":"UNSUBSCRIBE","params":["{}"],"id": 1}}"##,
-        ws_name
-    );
-    tx.unbounded_send(Message::Text(message_str)).unwrap();
+
+// reconnects whenever the subscribed pairs change instead of tracking sub/unsub
+async fn source_loop(exchange: &'static dyn Exchange, context: Context) {
+    // the ui never switches a source or asks it to reconnect
+    let context = Context {
+        exchange: Arc::new(Mutex::new(exchange)),
+        reconnect: Arc::new(Notify::new()),
+        ..context
+    };
+    let mut watch = ConnectionWatch::new(false);
+    let mut backoff = Backoff::new();
+    loop {
+        let (tx, mut rx) = futures_channel::mpsc::unbounded::<Message>();
+        let proxy_str = proxy::for_exchange(exchange.id(), context.proxy.lock().unwrap().clone());
+        let last_pairs = context.subscriptions.lock().unwrap().all();
+        let pairs_changed = async {
+            loop {
+                time::sleep(Duration::from_secs(1)).await;
+                if context.subscriptions.lock().unwrap().all() != last_pairs {
+                    break;
+                }
+            }
+        };
+        let connection = work(&context, tx, &mut rx, &proxy_str, &mut watch);
+        let started = Instant::now();
+        pin_mut!(connection, pairs_changed);
+        if let future::Either::Left(_) = future::select(connection, pairs_changed).await {
+            if started.elapsed() >= BACKOFF_STABLE {
+                backoff.reset();
+            }
+            time::sleep(backoff.next_delay().max(SOURCE_RETRY)).await;
+        }
+    }
 }
 
 pub async fn run(
     hwnd: HWND,
-    receiver: tokio::sync::mpsc::Receiver<TradePair>,
-    trade_pair: TradePair,
+    receiver: tokio::sync::mpsc::Receiver<UiCommand>,
+    subscriptions: Subscriptions,
+    exchange: &'static dyn Exchange,
     proxy_str: Option<String>,
+    cli_proxy: Option<String>,
 ) {
     let (tx, mut rx) = futures_channel::mpsc::unbounded::<Message>();
-    let trade_pair_arc = Arc::new(Mutex::new(trade_pair));
+    http::set_exchange(exchange.id());
+    let subscriptions_arc = Arc::new(Mutex::new(subscriptions));
+    let exchange_arc = Arc::new(Mutex::new(exchange));
+    let proxy_arc = Arc::new(Mutex::new(proxy_str));
+    let reconnect = Arc::new(Notify::new());
+    let shutdown = Arc::new(Notify::new());
+    let context = Context {
+        subscriptions: Arc::clone(&subscriptions_arc),
+        exchange: Arc::clone(&exchange_arc),
+        proxy: Arc::clone(&proxy_arc),
+        reconnect: Arc::clone(&reconnect),
+        hwnd: hwnd.0 as usize,
+    };
     tokio::spawn(receive_from_ui(
-        Arc::clone(&trade_pair_arc),
-        hwnd.0 as usize,
+        context.clone(),
+        cli_proxy,
+        Arc::clone(&shutdown),
         receiver,
         tx.clone(),
     ));
-    loop {
-        work(
-            Arc::clone(&trade_pair_arc),
-            hwnd.0 as usize,
-            tx.clone(),
-            &mut rx,
-            &proxy_str,
-        )
-        .await;
-        send_message_to_ui(hwnd.0 as usize, ApiMessage::Notify("重连中...".to_string()));
-        println!("Reconnect...");
+    tokio::spawn(fx_loop(Arc::clone(&proxy_arc)));
+    tokio::spawn(update_loop(Arc::clone(&proxy_arc), hwnd.0 as usize));
+    tokio::spawn(proxy_loop(
+        Arc::clone(&proxy_arc),
+        Arc::clone(&exchange_arc),
+        hwnd.0 as usize,
+    ));
+    let mut ws_fail_count = 0;
+    spawn_backfill(
+        exchange,
+        subscriptions_arc.lock().unwrap().all(),
+        proxy_arc.lock().unwrap().clone(),
+    );
+    restart_sources(exchange, &context);
+    let mut watch = ConnectionWatch::new(true);
+    let mut backoff = Backoff::new();
+    let mut proxy_failures = 0;
+    // connecting around the proxy after proxy::direct_fallback failures in a row
+    let mut direct = false;
+    let mut last_proxy = proxy_arc.lock().unwrap().clone();
+    let serve = async {
+        loop {
+            let exchange = *context.exchange.lock().unwrap();
+            let configured = proxy::for_exchange(exchange.id(), context.proxy.lock().unwrap().clone());
+            // a proxy from the settings gets a fresh chance
+            if configured != last_proxy {
+                last_proxy = configured.clone();
+                proxy_failures = 0;
+                direct = false;
+            }
+            let fallback_after = proxy::direct_fallback();
+            if !direct && configured.is_some() && fallback_after > 0 && proxy_failures >= fallback_after {
+                info!("proxy failed {} times, connecting directly", proxy_failures);
+                direct = true;
+                send_message_to_ui(hwnd.0 as usize, ApiMessage::Notify(i18n::tr("代理失败, 改为直连").to_string()));
+            }
+            let proxy_str = if direct { None } else { configured };
+            if let Ok(client) = http_client(&proxy_str) {
+                if let Err(err) = pairs::discover(exchange, &client).await {
+                    warn!("discover symbols err:{:?}", err);
+                }
+            }
+            let started = Instant::now();
+            let ended = work(&context, tx.clone(), &mut rx, &proxy_str, &mut watch).await;
+            if shutting_down() {
+                break;
+            }
+            watch.on_disconnected();
+            metrics::count_reconnect();
+            let status = match ended {
+                Ended::TimedOut(stage) => Status::TimedOut(stage),
+                _ => Status::Reconnecting,
+            };
+            send_message_to_ui(hwnd.0 as usize, ApiMessage::Status(status));
+            match ended {
+                Ended::ProxyFailed | Ended::TimedOut(Stage::ProxyConnect) => proxy_failures += 1,
+                Ended::Dropped if !direct => proxy_failures = 0,
+                // the direct path does not work either, back to the proxy
+                Ended::Failed if direct => {
+                    info!("direct connection failed, back to the proxy");
+                    direct = false;
+                    proxy_failures = 0;
+                    send_message_to_ui(hwnd.0 as usize, ApiMessage::Notify(i18n::tr("直连失败, 改回代理").to_string()));
+                }
+                _ => {}
+            }
+            if ended.connected() {
+                ws_fail_count = 0;
+                if started.elapsed() >= BACKOFF_STABLE {
+                    backoff.reset();
+                }
+            } else {
+                ws_fail_count += 1;
+            }
+            if ws_fail_count >= WS_FAIL_LIMIT {
+                warn!("ws unavailable, fall back to rest polling");
+                poll(&context, &proxy_str).await;
+                ws_fail_count = 0;
+            }
+            let delay = backoff.next_delay();
+            if !delay.is_zero() {
+                let mut notice = i18n::fill("{}秒后重试", &[&delay.as_secs().max(1)]);
+                if let Ended::TimedOut(stage) = ended {
+                    notice = format!("{}, {}", stage.label(), notice);
+                } else if *PROXY_HEALTH.lock().unwrap() == ProxyHealth::Unreachable {
+                    notice = i18n::fill("代理不可达, {}", &[&notice]);
+                }
+                send_message_to_ui(hwnd.0 as usize, ApiMessage::Notify(notice));
+                // a new exchange, pair or proxy from the ui retries right away
+                let retry_now = reconnect.notified();
+                pin_mut!(retry_now);
+                if let future::Either::Right(_) =
+                    future::select(Box::pin(time::sleep(delay)), retry_now).await
+                {
+                    backoff.reset();
+                }
+            }
+            send_message_to_ui(hwnd.0 as usize, ApiMessage::Notify(i18n::tr("重连中...").to_string()));
+            info!("reconnect");
+        }
+    };
+    let stopping = shutdown.notified();
+    pin_mut!(serve, stopping);
+    if let future::Either::Right(((), serve)) = future::select(serve, stopping).await {
+        // the close frame is queued, give the socket a moment to send it
+        let _ = time::timeout(CLOSE_TIMEOUT, serve).await;
     }
 }
@@ -0,0 +1,75 @@
+// "开机启动": a value under the Run key of the current user, no admin rights needed
+
+use anyhow::Result;
+use windows::core::{w, HSTRING, PCWSTR};
+use windows::Win32::Foundation::ERROR_FILE_NOT_FOUND;
+use windows::Win32::System::Registry::{
+    RegCloseKey, RegDeleteValueW, RegOpenKeyExW, RegQueryValueExW, RegSetValueExW, HKEY,
+    HKEY_CURRENT_USER, KEY_QUERY_VALUE, KEY_SET_VALUE, REG_SAM_FLAGS, REG_SZ,
+};
+
+const RUN_KEY: PCWSTR = w!("Software\\Microsoft\\Windows\\CurrentVersion\\Run");
+const VALUE_NAME: PCWSTR = w!("mjj");
+
+// closed when dropped
+struct Key(HKEY);
+
+impl Key {
+    fn open(access: REG_SAM_FLAGS) -> Result<Key> {
+        let mut key = HKEY::default();
+        unsafe { RegOpenKeyExW(HKEY_CURRENT_USER, RUN_KEY, 0, access, &mut key).ok()? };
+        Ok(Key(key))
+    }
+}
+
+impl Drop for Key {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = RegCloseKey(self.0);
+        }
+    }
+}
+
+pub fn is_enabled() -> bool {
+    let key = match Key::open(KEY_QUERY_VALUE) {
+        Ok(key) => key,
+        Err(_) => return false,
+    };
+    unsafe { RegQueryValueExW(key.0, VALUE_NAME, None, None, None, None).is_ok() }
+}
+
+// started again with the arguments of this run, e.g. --exchange or --proxy
+pub fn set_enabled(enabled: bool) -> Result<()> {
+    let key = Key::open(KEY_SET_VALUE)?;
+    unsafe {
+        if !enabled {
+            let deleted = RegDeleteValueW(key.0, VALUE_NAME);
+            if deleted != ERROR_FILE_NOT_FOUND {
+                deleted.ok()?;
+            }
+            return Ok(());
+        }
+        let command: Vec<u8> = HSTRING::from(command_line()?)
+            .as_wide()
+            .iter()
+            .chain(std::iter::once(&0))
+            .flat_map(|c| c.to_le_bytes())
+            .collect();
+        RegSetValueExW(key.0, VALUE_NAME, 0, REG_SZ, Some(&command)).ok()?;
+    }
+    Ok(())
+}
+
+fn command_line() -> Result<String> {
+    let exe = std::env::current_exe()?;
+    let mut command = format!("\"{}\"", exe.display());
+    for arg in std::env::args().skip(1) {
+        command.push(' ');
+        if arg.is_empty() || arg.contains(' ') {
+            command.push_str(&format!("\"{}\"", arg));
+        } else {
+            command.push_str(&arg);
+        }
+    }
+    Ok(command)
+}
@@ -0,0 +1,37 @@
+// unicode text to the windows clipboard
+
+use anyhow::Result;
+use windows::Win32::Foundation::{GlobalFree, HANDLE, HWND};
+use windows::Win32::System::DataExchange::{
+    CloseClipboard, EmptyClipboard, OpenClipboard, SetClipboardData,
+};
+use windows::Win32::System::Memory::{GlobalAlloc, GlobalLock, GlobalUnlock, GMEM_MOVEABLE};
+use windows::Win32::System::Ole::CF_UNICODETEXT;
+
+pub fn set_text(hwnd: HWND, text: &str) -> Result<()> {
+    let wide: Vec<u16> = text.encode_utf16().chain(std::iter::once(0)).collect();
+    unsafe {
+        OpenClipboard(hwnd)?;
+        let result = fill(&wide);
+        let _ = CloseClipboard();
+        result
+    }
+}
+
+unsafe fn fill(wide: &[u16]) -> Result<()> {
+    EmptyClipboard()?;
+    let memory = GlobalAlloc(GMEM_MOVEABLE, std::mem::size_of_val(wide))?;
+    let target = GlobalLock(memory) as *mut u16;
+    if target.is_null() {
+        let _ = GlobalFree(memory);
+        return Err(windows::core::Error::from_win32().into());
+    }
+    std::ptr::copy_nonoverlapping(wide.as_ptr(), target, wide.len());
+    let _ = GlobalUnlock(memory);
+    // owned by the clipboard once set
+    if let Err(err) = SetClipboardData(CF_UNICODETEXT.0 as u32, HANDLE(memory.0)) {
+        let _ = GlobalFree(memory);
+        return Err(err.into());
+    }
+    Ok(())
+}
@@ -0,0 +1,628 @@
+use crate::aggregate::AggregateMode;
+use crate::alerts::{AlertRule, MoveRule};
+use crate::exchange::{Market, PriceType};
+use crate::format::Separators;
+use crate::i18n::{self, Language};
+use crate::logos;
+use crate::pairs::{TradePair, TradePairInfo};
+use crate::render::{self, Fonts};
+use crate::theme::{Colors, Palette, ThemeName};
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use tracing::warn;
+use windows::core::PCWSTR;
+use windows::Win32::Globalization::{GetLocaleInfoEx, LOCALE_SDECIMAL, LOCALE_STHOUSAND};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PairConfig {
+    // exchange symbol, e.g. DOGEUSDT
+    pub pair_name: String,
+    // defaults to pair_name
+    #[serde(default)]
+    pub show_name: Option<String>,
+    // ws channel name, defaults to the lowercase pair_name
+    #[serde(default)]
+    pub ws_name: Option<String>,
+    // spot or perp, switches to that market of the exchange when selected
+    #[serde(default)]
+    pub market: Option<Market>,
+}
+
+impl PairConfig {
+    // the channel name is left to each exchange, a listed one is the listing exchange's
+    pub fn from_info(info: &TradePairInfo) -> Self {
+        PairConfig {
+            pair_name: info.pair_name.clone(),
+            show_name: Some(info.show_name.clone()),
+            ws_name: None,
+            market: None,
+        }
+    }
+
+    pub fn to_info(&self) -> TradePairInfo {
+        TradePairInfo {
+            ws_name: self
+                .ws_name
+                .clone()
+                .unwrap_or_else(|| self.pair_name.to_lowercase()),
+            show_name: self
+                .show_name
+                .clone()
+                .unwrap_or_else(|| self.pair_name.clone()),
+            pair_name: self.pair_name.clone(),
+            decimals: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    // pairs shown at the top of the context menu, empty means the built-in ones
+    pub pairs: Vec<PairConfig>,
+    pub width: i32,
+    // installed family of the widget and the detail popup, else render::FONT_FAMILY
+    pub font_family: String,
+    // points, of the prices and of the pair names, within FONT_SIZES
+    pub font_size: f32,
+    pub label_font_size: f32,
+    // #RRGGBB, the text of the light theme, also on a light taskbar with the auto theme
+    pub text_color: String,
+    pub theme: ThemeName,
+    // colors of the custom theme
+    pub palette: Palette,
+    // of the menus, notices and windows, auto follows the windows display language
+    pub language: Language,
+    // direct2d falls back to gdi+ when it cannot start
+    pub render_backend: RenderBackend,
+    // overridden by --proxy
+    pub proxy: Option<String>,
+    // proxy failures in a row before connecting directly, 0 keeps waiting on the proxy
+    pub direct_fallback: u32,
+    // exchange id to the proxy it uses instead, "direct" for none, e.g. {"okx": "direct"}
+    pub exchange_proxies: BTreeMap<String, String>,
+    // the proxy resolves the exchange hosts, false looks them up locally first
+    pub proxy_remote_dns: bool,
+    pub timeouts: Timeouts,
+    // ws host to pins its certificate must match, "sha256/<base64>" of the public
+    // key or the hex sha256 of the certificate, e.g. {"stream.binance.com": [...]}
+    pub cert_pins: BTreeMap<String, Vec<String>>,
+    // pem file of extra root certificates, e.g. of a proxy that inspects tls
+    pub ca_file: Option<String>,
+    // filter of the log file, e.g. "debug" or "info,demo::api=trace", overridden by --log-level
+    pub log_level: String,
+    // notification-area icon mirroring the context menu
+    pub tray_icon: bool,
+    pub tray_icon_mode: TrayIconMode,
+    // native notifications for alerts and long disconnects
+    pub toast_notifications: bool,
+    pub alerts: Vec<AlertRule>,
+    // percent moves within a time window, independent of alerts
+    pub move_alerts: Vec<MoveRule>,
+    // pairs shown next to the selected one, at most MAX_DISPLAY_PAIRS - 1
+    pub display_pairs: Vec<String>,
+    pub layout: Layout,
+    // what the row over the price shows
+    pub label_row: LabelRow,
+    // the logo of the base asset before the pair name, downloaded on first use
+    pub show_logos: bool,
+    // where logos are downloaded from, {asset} is the lowercase base asset
+    pub logo_url: String,
+    // watchlist the selected pair rotates through, empty disables the carousel
+    pub carousel: Vec<String>,
+    pub carousel_seconds: u32,
+    // exchange ids whose prices are combined with the selected exchange
+    pub aggregate: Vec<String>,
+    pub aggregate_mode: AggregateMode,
+    // funding rate and countdown instead of the change on perpetuals
+    pub show_funding: bool,
+    // best bid/ask and the spread instead of the last price
+    pub show_bbo: bool,
+    // last, mark or index, unset keeps each exchange's own stream
+    pub price_type: Option<PriceType>,
+    // the price is marked stale after this long without a tick
+    pub stale_seconds: u64,
+    // a tick moving the price at least this percent flashes the background, 0 never does
+    pub flash_percent: f64,
+    // a new price counts up or down from the shown one instead of replacing it,
+    // off by default, a busy pair keeps the animation timer running
+    pub smooth_prices: bool,
+    // price decimals pinned by pair name, over the exchange tick size
+    pub decimals: BTreeMap<String, u32>,
+    // decimal and thousands separators of prices, volumes and alerts
+    pub number_format: NumberFormat,
+    // e.g. CNY, usd quoted prices are shown in it, unset keeps the exchange price
+    pub fiat: Option<String>,
+    // amount held by asset, e.g. {"BTC": 0.5, "ETH": 10}, valued at the asset's usdt pair
+    pub holdings: BTreeMap<String, f64>,
+    // the widget shows the value of the holdings instead of the pairs
+    pub show_portfolio: bool,
+    // entry price by pair name, the widget shows the unrealized pnl against it
+    pub entry_prices: BTreeMap<String, f64>,
+    // a tick per pair is kept this often for the history export, 0 keeps none
+    pub history_seconds: u64,
+    // prices, tickers and books of a pair sent to the widget per second at most,
+    // the ticks in between are coalesced. 10 by default, 0 repaints on every tick
+    pub max_refresh_hz: f64,
+    // middle click copies "BTC/USDT 64123.5 2024-01-31T08:05:09Z" instead of the price alone
+    pub copy_details: bool,
+    // exchange id to the page a double click opens, e.g.
+    // {"binance-spot": "https://www.binance.com/trade/{base}_{quote}"}, over the built-in ones
+    pub trade_urls: BTreeMap<String, String>,
+    // global hotkeys to a pair, its place in the menu or "pause", see hotkeys
+    pub hotkeys: BTreeMap<String, String>,
+    // look for a newer release on github once a day
+    pub update_check: bool,
+    // taskbar the widget sits on, 0 is the primary display's, then the other
+    // displays from left to right
+    pub taskbar_monitor: usize,
+    // a copy of the widget on every other taskbar too
+    pub taskbar_clone: bool,
+    pub taskbar_anchor: TaskbarAnchor,
+    // pixels the widget was dragged along the taskbar from the anchor,
+    // negative toward the start of the taskbar
+    pub taskbar_offset: i32,
+}
+
+pub const MAX_DISPLAY_PAIRS: usize = 3;
+
+// holdings are valued in it
+const HOLDING_QUOTE: &str = "USDT";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Layout {
+    // one row per pair within the taskbar height
+    Stacked,
+    // one column of `width` per pair
+    SideBySide,
+}
+
+// the 24h ones need a ticker, the pair name is shown until the first one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LabelRow {
+    Name,
+    ChangePercent,
+    // the price minus the 24h open
+    Change,
+    // in the base asset
+    Volume,
+    None,
+}
+
+impl LabelRow {
+    pub const ALL: [LabelRow; 5] = [
+        LabelRow::Name,
+        LabelRow::ChangePercent,
+        LabelRow::Change,
+        LabelRow::Volume,
+        LabelRow::None,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            LabelRow::Name => i18n::tr("交易对"),
+            LabelRow::ChangePercent => i18n::tr("24h涨跌幅"),
+            LabelRow::Change => i18n::tr("24h涨跌额"),
+            LabelRow::Volume => i18n::tr("24h成交量"),
+            LabelRow::None => i18n::tr("不显示"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RenderBackend {
+    #[serde(rename = "gdiplus")]
+    GdiPlus,
+    #[serde(rename = "direct2d")]
+    Direct2D,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NumberFormat {
+    // the separators of the windows user locale
+    System,
+    // 100235.5
+    Plain,
+    // 100,235.5
+    Comma,
+    // 100.235,5
+    Dot,
+    // 100 235,5
+    Space,
+}
+
+impl NumberFormat {
+    pub fn separators(self) -> Separators {
+        let (decimal, group) = match self {
+            NumberFormat::System => return system_separators(),
+            NumberFormat::Plain => ('.', None),
+            NumberFormat::Comma => ('.', Some(',')),
+            NumberFormat::Dot => (',', Some('.')),
+            NumberFormat::Space => (',', Some(' ')),
+        };
+        Separators { decimal, group }
+    }
+}
+
+// first character of a locale setting, e.g. LOCALE_SDECIMAL
+fn locale_char(lctype: u32) -> Option<char> {
+    let mut buf = [0u16; 8];
+    // null is LOCALE_NAME_USER_DEFAULT
+    let len = unsafe { GetLocaleInfoEx(PCWSTR::null(), lctype, Some(&mut buf)) };
+    String::from_utf16_lossy(&buf[..(len.max(1) - 1) as usize])
+        .chars()
+        .next()
+}
+
+fn system_separators() -> Separators {
+    Separators {
+        decimal: locale_char(LOCALE_SDECIMAL).unwrap_or('.'),
+        // the narrow no-break space of some locales draws as a box in small fonts
+        group: locale_char(LOCALE_STHOUSAND).map(|c| if c.is_whitespace() { ' ' } else { c }),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrayIconMode {
+    // the application icon
+    Icon,
+    // the current price drawn into the icon
+    Price,
+    // the 24h percent change drawn into the icon
+    Change,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskbarAnchor {
+    // left of the notification area and the clock
+    Clock,
+    // left of the centered app icons of windows 11, the clock where they are not centered
+    Apps,
+}
+
+// seconds each stage of a connection may take before it is given up
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Timeouts {
+    // reaching the proxy and opening the tunnel through it
+    pub proxy_connect: u64,
+    // tls and websocket handshake with the exchange
+    pub tls_handshake: u64,
+    // silence before a keepalive ping, a few in a row drop the connection
+    pub read: u64,
+    // first ack or price after subscribing
+    pub subscribe_ack: u64,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Timeouts {
+            proxy_connect: 10,
+            tls_handshake: 10,
+            read: 10,
+            subscribe_ack: 15,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            pairs: Vec::new(),
+            width: 70,
+            font_family: render::FONT_FAMILY.to_string(),
+            font_size: 9.,
+            label_font_size: 9.,
+            text_color: "#000000".to_string(),
+            theme: ThemeName::Auto,
+            palette: Palette::default(),
+            language: Language::Auto,
+            render_backend: RenderBackend::Direct2D,
+            proxy: None,
+            direct_fallback: 0,
+            exchange_proxies: BTreeMap::new(),
+            proxy_remote_dns: true,
+            timeouts: Timeouts::default(),
+            cert_pins: BTreeMap::new(),
+            ca_file: None,
+            log_level: crate::logging::DEFAULT_LEVEL.to_string(),
+            tray_icon: false,
+            tray_icon_mode: TrayIconMode::Icon,
+            toast_notifications: true,
+            alerts: Vec::new(),
+            move_alerts: Vec::new(),
+            display_pairs: Vec::new(),
+            layout: Layout::Stacked,
+            label_row: LabelRow::Name,
+            show_logos: false,
+            logo_url: logos::DEFAULT_URL.to_string(),
+            carousel: Vec::new(),
+            carousel_seconds: 5,
+            aggregate: Vec::new(),
+            aggregate_mode: AggregateMode::Median,
+            show_funding: false,
+            show_bbo: false,
+            price_type: None,
+            stale_seconds: 15,
+            flash_percent: 0.5,
+            smooth_prices: false,
+            decimals: BTreeMap::new(),
+            number_format: NumberFormat::System,
+            fiat: None,
+            holdings: BTreeMap::new(),
+            show_portfolio: false,
+            entry_prices: BTreeMap::new(),
+            history_seconds: 5,
+            max_refresh_hz: 10.,
+            copy_details: false,
+            trade_urls: BTreeMap::new(),
+            hotkeys: BTreeMap::new(),
+            update_check: true,
+            taskbar_monitor: 0,
+            taskbar_clone: false,
+            taskbar_anchor: TaskbarAnchor::Clock,
+            taskbar_offset: 0,
+        }
+    }
+}
+
+// points, smaller is unreadable and larger does not fit the taskbar
+pub const FONT_SIZES: std::ops::RangeInclusive<f32> = 6.0..=24.0;
+
+pub fn parse_font_size(text: &str) -> Option<f32> {
+    text.trim().parse::<f32>().ok().filter(|size| FONT_SIZES.contains(size))
+}
+
+// "BTCUSDT=0, SOLUSDT=3" to pinned decimals, None on a malformed entry
+pub fn parse_decimals(text: &str) -> Option<BTreeMap<String, u32>> {
+    let mut decimals = BTreeMap::new();
+    for entry in text.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+        let (pair_name, count) = entry.split_once('=')?;
+        let count = count.trim().parse::<u32>().ok().filter(|count| *count <= 10)?;
+        decimals.insert(pair_name.trim().to_uppercase(), count);
+    }
+    Some(decimals)
+}
+
+pub fn decimals_text(decimals: &BTreeMap<String, u32>) -> String {
+    decimals
+        .iter()
+        .map(|(pair_name, count)| format!("{}={}", pair_name, count))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn app_dir() -> PathBuf {
+    let base = std::env::var_os("APPDATA")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("."));
+    base.join("mjj")
+}
+
+// a missing or broken file falls back to the defaults
+fn load_json<T>(path: &Path) -> T
+where
+    T: DeserializeOwned + Default,
+{
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(_) => return T::default(),
+    };
+    match serde_json::from_str::<T>(&content) {
+        Ok(value) => value,
+        Err(err) => {
+            warn!("parse {:?} err:{:?}", path, err);
+            T::default()
+        }
+    }
+}
+
+fn save_json<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, serde_json::to_string_pretty(value)?)?;
+    Ok(())
+}
+
+impl Config {
+    pub fn path() -> PathBuf {
+        app_dir().join("config.json")
+    }
+
+    pub fn load() -> Config {
+        load_json(&Self::path())
+    }
+
+    pub fn save(&self) -> Result<()> {
+        save_json(&Self::path(), self)
+    }
+
+    pub fn colors(&self) -> Colors {
+        match self.theme {
+            ThemeName::Auto if !crate::theme::taskbar_is_light() => Palette::dark().colors(),
+            ThemeName::Auto | ThemeName::Light => Palette {
+                text: self.text_color.clone(),
+                ..Palette::light()
+            }
+            .colors(),
+            ThemeName::Dark => Palette::dark().colors(),
+            ThemeName::Custom => self.palette.colors(),
+        }
+    }
+
+    // a size out of FONT_SIZES is the default one, a family that is not installed FONT_FAMILY
+    pub fn fonts(&self) -> Fonts {
+        let size = |size: f32| if FONT_SIZES.contains(&size) { size } else { 9. };
+        Fonts {
+            family: render::font_family(&self.font_family),
+            size: size(self.font_size),
+            label_size: size(self.label_font_size),
+        }
+    }
+
+    // stars or unstars a pair of the menu, the built-in pairs become the
+    // config's on the first change
+    pub fn toggle_favorite(&mut self, info: &TradePairInfo) {
+        if self.pairs.is_empty() {
+            self.pairs = crate::pairs::default_pairs().iter().map(PairConfig::from_info).collect();
+        }
+        let before = self.pairs.len();
+        // the last favorite stays, pairs::init takes no pairs for the built-in ones
+        if before == 1 && self.pairs[0].pair_name == info.pair_name {
+            return;
+        }
+        self.pairs.retain(|pair| pair.pair_name != info.pair_name);
+        if self.pairs.len() == before {
+            self.pairs.push(PairConfig::from_info(info));
+        }
+    }
+
+    pub fn extra_pairs(&self) -> Vec<TradePair> {
+        self.display_pairs
+            .iter()
+            .take(MAX_DISPLAY_PAIRS - 1)
+            .map(|pair_name| TradePair(pair_name.to_uppercase()))
+            .collect()
+    }
+
+    pub fn carousel_pairs(&self) -> Vec<TradePair> {
+        self.carousel
+            .iter()
+            .map(|pair_name| TradePair(pair_name.to_uppercase()))
+            .collect()
+    }
+
+    // asset, amount and the usdt pair it is valued at, in asset order. usdt
+    // itself has no pair, it is worth 1
+    pub fn holding_pairs(&self) -> Vec<(String, f64, Option<TradePair>)> {
+        self.holdings
+            .iter()
+            .map(|(asset, amount)| (asset.trim().to_uppercase(), *amount))
+            .filter(|(asset, _)| !asset.is_empty())
+            .map(|(asset, amount)| {
+                let pair = Some(TradePair(format!("{}{}", asset, HOLDING_QUOTE)))
+                    .filter(|_| asset != HOLDING_QUOTE);
+                (asset, amount, pair)
+            })
+            .collect()
+    }
+
+    // BTC-USDT and btcusdt both name the BTCUSDT stream
+    pub fn entry_price(&self, pair_name: &str) -> Option<f64> {
+        self.entry_prices
+            .iter()
+            .find(|(name, _)| {
+                let name: String = name.chars().filter(|c| c.is_ascii_alphanumeric()).collect();
+                name.eq_ignore_ascii_case(pair_name)
+            })
+            .map(|(_, price)| *price)
+            .filter(|price| *price > 0.)
+    }
+
+    // pairs watched by price or move alerts, so they fire while another pair is shown
+    pub fn alert_pairs(&self) -> Vec<TradePair> {
+        let names = self
+            .alerts
+            .iter()
+            .map(|rule| &rule.pair_name)
+            .chain(self.move_alerts.iter().map(|rule| &rule.pair_name));
+        let mut pairs: Vec<TradePair> = Vec::new();
+        for name in names {
+            let name: String = name
+                .chars()
+                .filter(|c| c.is_ascii_alphanumeric())
+                .collect();
+            let pair = TradePair(name.to_uppercase());
+            if !name.is_empty() && !pairs.contains(&pair) {
+                pairs.push(pair);
+            }
+        }
+        pairs
+    }
+}
+
+// written by the app itself, kept apart from the user edited config
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct State {
+    pub last_pair: Option<String>,
+    // toggled from the context menu
+    pub sound_muted: bool,
+    // market chosen from the context menu, by pair name
+    pub pair_markets: HashMap<String, Market>,
+    // release tag already announced, each release is announced once
+    pub notified_update: Option<String>,
+    // pair names switched to, the latest first, see remember_pair
+    pub recent_pairs: Vec<String>,
+}
+
+impl State {
+    // shown at the top of the menu, the selected pair is not among them
+    pub const RECENT_PAIRS: usize = 5;
+
+    pub fn remember_pair(&mut self, trade_pair: &TradePair) {
+        self.last_pair = Some(trade_pair.0.clone());
+        self.recent_pairs.retain(|pair_name| *pair_name != trade_pair.0);
+        self.recent_pairs.insert(0, trade_pair.0.clone());
+        self.recent_pairs.truncate(Self::RECENT_PAIRS + 1);
+    }
+
+    // the most recent first, without the selected pair
+    pub fn recent(&self, selected: &TradePair) -> Vec<TradePair> {
+        self.recent_pairs
+            .iter()
+            .filter(|pair_name| **pair_name != selected.0)
+            .take(Self::RECENT_PAIRS)
+            .map(|pair_name| TradePair(pair_name.clone()))
+            .collect()
+    }
+
+    pub fn path() -> PathBuf {
+        app_dir().join("state.json")
+    }
+
+    // a market picked from the menu wins over the configured one
+    pub fn preferred_market(&self, config: &Config, trade_pair: &TradePair) -> Option<Market> {
+        self.pair_markets.get(&trade_pair.0).copied().or_else(|| {
+            config
+                .pairs
+                .iter()
+                .find(|pair| pair.pair_name == trade_pair.0)
+                .and_then(|pair| pair.market)
+        })
+    }
+
+    pub fn load() -> State {
+        load_json(&Self::path())
+    }
+
+    pub fn save(&self) -> Result<()> {
+        save_json(&Self::path(), self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn font_size_within_the_range() {
+        assert_eq!(parse_font_size(" 9.5 "), Some(9.5));
+        assert_eq!(parse_font_size("6"), Some(6.));
+        assert_eq!(parse_font_size("24"), Some(24.));
+        assert_eq!(parse_font_size("5.9"), None);
+        assert_eq!(parse_font_size("30"), None);
+        assert_eq!(parse_font_size("large"), None);
+        assert_eq!(parse_font_size(""), None);
+    }
+}
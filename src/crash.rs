@@ -0,0 +1,83 @@
+// a panic anywhere writes a crash report next to the logs and tells the user,
+// instead of a dead worker thread leaving a frozen price on the taskbar
+
+use crate::config::Config;
+use crate::i18n;
+use crate::logging;
+use std::backtrace::Backtrace;
+use std::fmt::Write;
+use std::panic::{self, PanicHookInfo};
+use std::path::PathBuf;
+use tracing::error;
+use windows::core::HSTRING;
+use windows::Win32::UI::WindowsAndMessaging::{MessageBoxW, MB_ICONERROR, MB_OK, MB_TOPMOST};
+
+pub fn install() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        error!("{}", info);
+        let path = write_report(info);
+        let text = match &path {
+            Some(path) => i18n::fill("程序出错, 即将退出。\n崩溃报告已保存到:\n{}", &[&path.display()]),
+            None => i18n::fill("程序出错, 即将退出。\n{}", &[info]),
+        };
+        unsafe {
+            MessageBoxW(None, &HSTRING::from(text), &HSTRING::from("mjj"), MB_OK | MB_ICONERROR | MB_TOPMOST);
+        }
+        // the file writer runs on a thread of its own, its queue would be lost
+        logging::flush();
+        // the state of the other threads is unknown, start over clean
+        std::process::exit(1);
+    }));
+}
+
+fn write_report(info: &PanicHookInfo) -> Option<PathBuf> {
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let dir = logging::dir().with_file_name("crashes");
+    std::fs::create_dir_all(&dir).ok()?;
+    let path = dir.join(format!("crash-{}.txt", secs));
+    std::fs::write(&path, report(info)).ok()?;
+    Some(path)
+}
+
+fn report(info: &PanicHookInfo) -> String {
+    let mut report = String::new();
+    let thread = std::thread::current();
+    let _ = writeln!(report, "version: {}", env!("CARGO_PKG_VERSION"));
+    let _ = writeln!(report, "thread: {}", thread.name().unwrap_or("unnamed"));
+    let _ = writeln!(report, "{}\n", info);
+    let _ = writeln!(report, "backtrace:\n{}\n", Backtrace::force_capture());
+    let _ = writeln!(report, "config:\n{}", summary(&Config::load()));
+    let _ = writeln!(report, "log:");
+    for line in logging::recent() {
+        let _ = writeln!(report, "{}", line);
+    }
+    report
+}
+
+// what shapes the connection and the drawing, without proxy credentials or holdings
+fn summary(config: &Config) -> String {
+    format!(
+        "render_backend: {:?}\nproxy: {}\nexchange_proxies: {}\ndirect_fallback: {}\n\
+         proxy_remote_dns: {}\ntimeouts: {:?}\ncert_pins: {}\nca_file: {}\n\
+         tray_icon: {}\npairs: {}\ndisplay_pairs: {:?}\naggregate: {:?}\n\
+         price_type: {:?}\nlog_level: {}\n",
+        config.render_backend,
+        if config.proxy.is_some() { "set" } else { "none" },
+        config.exchange_proxies.len(),
+        config.direct_fallback,
+        config.proxy_remote_dns,
+        config.timeouts,
+        config.cert_pins.len(),
+        config.ca_file.is_some(),
+        config.tray_icon,
+        config.pairs.len(),
+        config.display_pairs,
+        config.aggregate,
+        config.price_type,
+        config.log_level,
+    )
+}
@@ -0,0 +1,344 @@
+use crate::api;
+use crate::exchange;
+use crate::fiat;
+use crate::format;
+use crate::i18n;
+use crate::my_window::Window;
+use crate::pairs;
+use crate::render::gdi::{Brush, Font, Graphics, Paint};
+use anyhow::Result;
+use windows::Win32::Graphics::Gdi::InvalidateRect;
+use windows::Win32::Graphics::GdiPlus::{
+    GdipDrawString, GdipFillRectangle, GdipGraphicsClear, GdipSetTextRenderingHint, GpBrush,
+    GpFont, GpGraphics, RectF, TextRenderingHintAntiAlias,
+};
+use windows::{
+    core::*, Win32::Foundation::*, Win32::System::LibraryLoader::GetModuleHandleW,
+    Win32::UI::WindowsAndMessaging::*,
+};
+
+// popup above the widget with the 24h stats and the book of the selected
+// pair, closed as soon as it loses focus
+pub struct DetailWindow {
+    main_hwnd: HWND,
+}
+
+impl DetailWindow {
+    const CLASS_NAME: PCWSTR = w!("mjj_detail");
+    const WIDTH: i32 = 200;
+    const ROW_HEIGHT: i32 = 22;
+    const MARGIN: i32 = 10;
+    // title plus the stats rows
+    const ROWS: i32 = 11;
+    // asks above bids, one bar per level
+    const DEPTH_ROW_HEIGHT: i32 = 15;
+    const DEPTH_ROWS: i32 = exchange::DEPTH_LEVELS as i32 * 2;
+
+    pub fn open(main_hwnd: HWND) -> Result<()> {
+        unsafe {
+            if let Ok(existing) = FindWindowW(Self::CLASS_NAME, None) {
+                if !existing.is_invalid() {
+                    let _ = SetForegroundWindow(existing);
+                    return Ok(());
+                }
+            }
+            let instance = GetModuleHandleW(None)?;
+            let wc = WNDCLASSW {
+                hCursor: LoadCursorW(None, IDC_ARROW)?,
+                hInstance: instance.into(),
+                lpszClassName: Self::CLASS_NAME,
+                lpfnWndProc: Some(Self::wndproc),
+                ..Default::default()
+            };
+            // fails harmlessly when the class is already registered
+            RegisterClassW(&wc);
+            let window = &mut *(GetWindowLongPtrW(main_hwnd, GWLP_USERDATA) as *mut Window);
+            let rows = Self::ROWS + Self::portfolio_rows(window).len() as i32;
+            let mut height = Self::MARGIN * 2 + Self::ROW_HEIGHT * rows;
+            if window.has_depth() {
+                height += Self::MARGIN + Self::DEPTH_ROW_HEIGHT * Self::DEPTH_ROWS;
+            }
+            let mut main_rect = RECT::default();
+            GetWindowRect(main_hwnd, &mut main_rect)?;
+            let hwnd = CreateWindowExW(
+                WS_EX_TOOLWINDOW | WS_EX_TOPMOST,
+                Self::CLASS_NAME,
+                &HSTRING::from(i18n::tr("详情")),
+                WS_POPUP | WS_BORDER,
+                main_rect.right - Self::WIDTH,
+                main_rect.top - height,
+                Self::WIDTH,
+                height,
+                None,
+                None,
+                wc.hInstance,
+                None,
+            )?;
+            let detail = Box::new(DetailWindow { main_hwnd });
+            SetWindowLongPtrW(hwnd, GWLP_USERDATA, Box::into_raw(detail) as isize);
+            window.watch_depth(true);
+            let _ = ShowWindow(hwnd, SW_SHOW);
+            // focus is needed for WM_ACTIVATE to dismiss it later
+            let _ = SetForegroundWindow(hwnd);
+        }
+        Ok(())
+    }
+
+    // repaints an open popup, called when the selected pair gets new data
+    pub fn refresh() {
+        unsafe {
+            if let Ok(hwnd) = FindWindowW(Self::CLASS_NAME, None) {
+                if !hwnd.is_invalid() {
+                    let _ = InvalidateRect(hwnd, None, false);
+                }
+            }
+        }
+    }
+
+    fn rows(window: &Window) -> Vec<(&'static str, String)> {
+        let (price, ticker) = window.current();
+        let funding = window.current_funding();
+        let dash = || "-".to_string();
+        let pair_name = pairs::pair_info(&window.trade_pair()).pair_name;
+        let format_price = |value: f64| pairs::format_price(&pair_name, value);
+        vec![
+            (
+                i18n::tr("价格"),
+                price.map_or_else(dash, |price| format_price(price.tag_price)),
+            ),
+            (
+                i18n::tr("24h 最高"),
+                ticker.map_or_else(dash, |ticker| format_price(ticker.high_price)),
+            ),
+            (
+                i18n::tr("24h 最低"),
+                ticker.map_or_else(dash, |ticker| format_price(ticker.low_price)),
+            ),
+            (
+                i18n::tr("24h 成交量"),
+                ticker.map_or_else(dash, |ticker| format::abbreviate(ticker.volume)),
+            ),
+            (
+                i18n::tr("24h 成交额"),
+                ticker
+                    .and_then(|ticker| ticker.turnover)
+                    .map_or_else(dash, format::abbreviate),
+            ),
+            (
+                i18n::tr("资金费率"),
+                funding.map_or_else(dash, |funding| format!("{:+.4}%", funding.rate * 100.)),
+            ),
+            (
+                i18n::tr("下次结算"),
+                funding.map_or_else(dash, |funding| Window::countdown(funding.next_time)),
+            ),
+            (i18n::tr("更新"), price.map_or_else(dash, Self::updated_ago)),
+            (
+                i18n::tr("延迟"),
+                window.latency().map_or_else(dash, |latency| format!("{}ms", latency)),
+            ),
+            (
+                i18n::tr("代理"),
+                window.proxy_health().map_or_else(dash, |health| health.label()),
+            ),
+        ]
+    }
+
+    // total and one row per held asset, with its share of the total
+    fn portfolio_rows(window: &Window) -> Vec<(String, String)> {
+        let holdings = window.holdings();
+        if holdings.is_empty() {
+            return Vec::new();
+        }
+        let total = window.portfolio_value().map(|(total, _)| total);
+        let mut rows = vec![(
+            i18n::tr("组合").to_string(),
+            total.map_or_else(|| "-".to_string(), fiat::format_usd),
+        )];
+        for (asset, amount, value) in holdings {
+            let label = format!("{} {}", asset, format::localize(&amount.to_string()));
+            let value = match (value, total) {
+                (Some(value), Some(total)) if total > 0. => {
+                    let share = format::localize(&format!("{:.1}", value / total * 100.));
+                    format!("{} {}%", fiat::format_usd(value), share)
+                }
+                (Some(value), _) => fiat::format_usd(value),
+                (None, _) => "-".to_string(),
+            };
+            rows.push((label, value));
+        }
+        rows
+    }
+
+    pub(crate) fn updated_ago(price: &api::Price) -> String {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_millis() as u64);
+        i18n::fill("{}秒前", &[&(now.saturating_sub(price.time_stamp) / 1000)])
+    }
+
+    unsafe fn draw_string(
+        graphics: *mut GpGraphics,
+        content_str: &str,
+        font: *const GpFont,
+        brush: *const GpBrush,
+        lay_box: &RectF,
+    ) {
+        GdipDrawString(
+            graphics,
+            &HSTRING::from(content_str),
+            -1,
+            font,
+            lay_box,
+            std::ptr::null_mut(),
+            brush,
+        );
+    }
+
+    // size bars scaled to the largest level, asks reversed so the spread is in the middle
+    unsafe fn draw_depth(
+        graphics: *mut GpGraphics,
+        font: *const GpFont,
+        brush: *const GpBrush,
+        pair_name: &str,
+        depth: &api::Depth,
+        rows: i32,
+    ) {
+        let top = Self::MARGIN * 2 + Self::ROW_HEIGHT * rows;
+        let width = (Self::WIDTH - Self::MARGIN * 2) as f32;
+        let max_size = depth
+            .bids
+            .iter()
+            .chain(depth.asks.iter())
+            .map(|(_, size)| *size)
+            .fold(0., f64::max);
+        if max_size <= 0. {
+            return;
+        }
+        let brush_ask = Brush::solid(Window::make_argb(60, 220, 0, 0));
+        let brush_bid = Brush::solid(Window::make_argb(60, 0, 160, 0));
+        let asks = depth.asks.iter().rev().map(|level| (level, brush_ask.as_ptr()));
+        let bids = depth.bids.iter().map(|level| (level, brush_bid.as_ptr()));
+        // fewer asks than levels leave a gap at the top, not in the middle
+        let ask_offset = exchange::DEPTH_LEVELS.saturating_sub(depth.asks.len()) as i32;
+        for (index, ((price, size), brush_bar)) in asks.chain(bids).enumerate() {
+            let lay_box = RectF {
+                X: Self::MARGIN as f32,
+                Y: (top + Self::DEPTH_ROW_HEIGHT * (index as i32 + ask_offset)) as f32,
+                Width: width,
+                Height: Self::DEPTH_ROW_HEIGHT as f32,
+            };
+            let bar_width = width * (*size / max_size) as f32;
+            GdipFillRectangle(
+                graphics,
+                brush_bar,
+                lay_box.X + width - bar_width,
+                lay_box.Y + 1.,
+                bar_width,
+                lay_box.Height - 2.,
+            );
+            Self::draw_string(graphics, &pairs::format_price(pair_name, *price), font, brush, &lay_box);
+            let size_str = format!("{:.3}", size);
+            let bound = Window::meansuer_string(
+                graphics,
+                &HSTRING::from(&size_str),
+                font,
+                &lay_box,
+            );
+            let size_box = RectF {
+                X: lay_box.X + lay_box.Width - bound.Width,
+                ..lay_box
+            };
+            Self::draw_string(graphics, &size_str, font, brush, &size_box);
+        }
+    }
+
+    unsafe fn paint(&self, hwnd: HWND) {
+        let paint = Paint::begin(hwnd);
+        let window = GetWindowLongPtrW(self.main_hwnd, GWLP_USERDATA) as *mut Window;
+        if !window.is_null() {
+            let window = &*window;
+            let graphics = Graphics::from_hdc(paint.hdc());
+            // the family of the widget, the rows are sized for 9pt
+            let font = Font::new(&window.fonts.family, 9.);
+            let brush = Brush::solid(Window::make_argb(255, 0, 0, 0));
+            let brush_label = Brush::solid(Window::make_argb(255, 110, 110, 110));
+            let (graphics, font, brush, brush_label) =
+                (graphics.as_ptr(), font.as_ptr(), brush.as_ptr(), brush_label.as_ptr());
+            GdipSetTextRenderingHint(graphics, TextRenderingHintAntiAlias);
+            GdipGraphicsClear(graphics, Window::make_argb(255, 255, 255, 255));
+            let title = pairs::pair_info(&window.trade_pair()).show_name;
+            let mut rows = vec![(String::new(), title)];
+            rows.extend(
+                Self::rows(window)
+                    .into_iter()
+                    .map(|(label, value)| (label.to_string(), value)),
+            );
+            rows.extend(Self::portfolio_rows(window));
+            for (index, (label, value)) in rows.iter().enumerate() {
+                let lay_box = RectF {
+                    X: Self::MARGIN as f32,
+                    Y: (Self::MARGIN + Self::ROW_HEIGHT * index as i32) as f32,
+                    Width: (Self::WIDTH - Self::MARGIN * 2) as f32,
+                    Height: Self::ROW_HEIGHT as f32,
+                };
+                Self::draw_string(graphics, label, font, brush_label, &lay_box);
+                // values are right aligned
+                let bound = Window::meansuer_string(
+                    graphics,
+                    &HSTRING::from(value),
+                    font,
+                    &lay_box,
+                );
+                let value_box = RectF {
+                    X: lay_box.X + lay_box.Width - bound.Width,
+                    ..lay_box
+                };
+                Self::draw_string(graphics, value, font, brush, &value_box);
+            }
+            if let Some(depth) = window.current_depth() {
+                let pair_name = pairs::pair_info(&window.trade_pair()).pair_name;
+                Self::draw_depth(graphics, font, brush, &pair_name, depth, rows.len() as i32);
+            }
+        }
+    }
+
+    extern "system" fn wndproc(
+        hwnd: HWND,
+        message: u32,
+        wparam: WPARAM,
+        lparam: LPARAM,
+    ) -> LRESULT {
+        unsafe {
+            let detail = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut Self;
+            match message {
+                WM_PAINT if !detail.is_null() => {
+                    (*detail).paint(hwnd);
+                    LRESULT(0)
+                }
+                WM_ACTIVATE => {
+                    if (wparam.0 & 0xFFFF) as u32 == WA_INACTIVE {
+                        let _ = DestroyWindow(hwnd);
+                    }
+                    LRESULT(0)
+                }
+                WM_DESTROY if !detail.is_null() => {
+                    let window =
+                        GetWindowLongPtrW((*detail).main_hwnd, GWLP_USERDATA) as *mut Window;
+                    if !window.is_null() {
+                        (*window).watch_depth(false);
+                    }
+                    LRESULT(0)
+                }
+                WM_NCDESTROY => {
+                    if !detail.is_null() {
+                        SetWindowLongPtrW(hwnd, GWLP_USERDATA, 0);
+                        drop(Box::from_raw(detail));
+                    }
+                    LRESULT(0)
+                }
+                _ => DefWindowProcW(hwnd, message, wparam, lparam),
+            }
+        }
+    }
+}
@@ -0,0 +1,539 @@
+use super::{
+    book_levels, kline_closes, string_to_f64, track_request, Exchange, Market, PriceType,
+    DEPTH_LEVELS, KLINE_LIMIT,
+};
+use crate::api::{Ack, Bbo, Depth, Funding, Price, Ticker};
+use crate::format;
+use crate::i18n;
+use crate::pairs::TradePairInfo;
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+#[derive(Debug, Deserialize)]
+struct MarkPriceEvent {
+    #[serde(rename = "E")]
+    time_stamp: u64,
+    #[serde(rename = "s")]
+    name: String,
+    #[serde(rename = "p", deserialize_with = "string_to_f64")]
+    tag_price: f64,
+    #[serde(rename = "i", deserialize_with = "string_to_f64")]
+    spot_index_price: f64,
+    #[serde(rename = "r", deserialize_with = "string_to_f64")]
+    fee: f64,
+    #[serde(rename = "T")]
+    next_fee_time: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct TickerEvent {
+    #[serde(rename = "E")]
+    time_stamp: u64,
+    #[serde(rename = "s")]
+    name: String,
+    #[serde(rename = "c", deserialize_with = "string_to_f64")]
+    last_price: f64,
+    #[serde(rename = "o", deserialize_with = "string_to_f64")]
+    open_price: f64,
+    #[serde(rename = "h", deserialize_with = "string_to_f64")]
+    high_price: f64,
+    #[serde(rename = "l", deserialize_with = "string_to_f64")]
+    low_price: f64,
+    #[serde(rename = "v", deserialize_with = "string_to_f64")]
+    volume: f64,
+    #[serde(rename = "q", deserialize_with = "string_to_f64")]
+    quote_volume: f64,
+}
+
+// the 24h ticker has b and a too, the update id tells the book ticker apart
+#[derive(Debug, Deserialize)]
+struct BookTickerEvent {
+    #[serde(rename = "u")]
+    update_id: u64,
+    #[serde(rename = "s")]
+    name: String,
+    #[serde(rename = "b", deserialize_with = "string_to_f64")]
+    bid_price: f64,
+    #[serde(rename = "a", deserialize_with = "string_to_f64")]
+    ask_price: f64,
+}
+
+// futures partial depth is a depthUpdate event
+#[derive(Debug, Deserialize)]
+struct FuturesDepthEvent {
+    #[serde(rename = "s")]
+    name: String,
+    #[serde(rename = "b")]
+    bids: Vec<Vec<String>>,
+    #[serde(rename = "a")]
+    asks: Vec<Vec<String>>,
+}
+
+// spot partial depth does not name the symbol
+#[derive(Debug, Deserialize)]
+struct SpotDepth {
+    bids: Vec<Vec<String>>,
+    asks: Vec<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiError {
+    code: i64,
+    msg: String,
+}
+
+// {"result":null,"id":1} or {"error":{"code":2,"msg":"..."},"id":1}
+#[derive(Debug, Deserialize)]
+struct ApiResult {
+    #[serde(default)]
+    error: Option<ApiError>,
+    id: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct PremiumIndex {
+    symbol: String,
+    #[serde(rename = "markPrice", deserialize_with = "string_to_f64")]
+    mark_price: f64,
+    #[serde(rename = "indexPrice", deserialize_with = "string_to_f64")]
+    index_price: f64,
+    time: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct Ticker24hr {
+    symbol: String,
+    #[serde(rename = "lastPrice", deserialize_with = "string_to_f64")]
+    last_price: f64,
+    #[serde(rename = "closeTime")]
+    close_time: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct SymbolInfo {
+    symbol: String,
+    status: String,
+    #[serde(rename = "baseAsset")]
+    base_asset: String,
+    #[serde(rename = "quoteAsset")]
+    quote_asset: String,
+    #[serde(rename = "contractType", default)]
+    contract_type: Option<String>,
+    #[serde(default)]
+    filters: Vec<SymbolFilter>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SymbolFilter {
+    #[serde(rename = "filterType")]
+    filter_type: String,
+    // only on PRICE_FILTER
+    #[serde(rename = "tickSize", default)]
+    tick_size: Option<String>,
+}
+
+impl SymbolInfo {
+    fn decimals(&self) -> Option<u32> {
+        self.filters
+            .iter()
+            .find(|filter| filter.filter_type == "PRICE_FILTER")
+            .and_then(|filter| filter.tick_size.as_deref())
+            .and_then(format::tick_decimals)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ExchangeInfo {
+    symbols: Vec<SymbolInfo>,
+}
+
+// keep the symbols accepted by filter, sorted by name for the menu
+fn parse_exchange_info<F>(str_data: &str, filter: F) -> Option<Vec<TradePairInfo>>
+where
+    F: Fn(&SymbolInfo) -> bool,
+{
+    let exchange_info = serde_json::from_str::<ExchangeInfo>(str_data).ok()?;
+    let mut infos: Vec<TradePairInfo> = exchange_info
+        .symbols
+        .iter()
+        .filter(|symbol| symbol.status == "TRADING" && filter(symbol))
+        .map(|symbol| TradePairInfo {
+            ws_name: symbol.symbol.to_lowercase(),
+            show_name: format!("{}/{}", symbol.base_asset, symbol.quote_asset),
+            pair_name: symbol.symbol.clone(),
+            decimals: symbol.decimals(),
+        })
+        .collect();
+    infos.sort_by(|a, b| a.show_name.cmp(&b.show_name));
+    Some(infos)
+}
+
+impl From<MarkPriceEvent> for Price {
+    fn from(event: MarkPriceEvent) -> Self {
+        Price::new(event.time_stamp, event.name, event.tag_price)
+    }
+}
+
+impl From<TickerEvent> for Price {
+    fn from(event: TickerEvent) -> Self {
+        Price::new(event.time_stamp, event.name, event.last_price)
+    }
+}
+
+impl From<TickerEvent> for Ticker {
+    fn from(event: TickerEvent) -> Self {
+        Ticker {
+            name: event.name,
+            open_price: event.open_price,
+            high_price: event.high_price,
+            low_price: event.low_price,
+            volume: event.volume,
+            turnover: Some(event.quote_volume),
+        }
+    }
+}
+
+impl From<Ticker24hr> for Price {
+    fn from(ticker: Ticker24hr) -> Self {
+        Price::new(ticker.close_time, ticker.symbol, ticker.last_price)
+    }
+}
+
+fn stream_message(method: &str, trade_pair: &TradePairInfo, stream_names: &[String]) -> Message {
+    let params = stream_names
+        .iter()
+        .map(|stream_name| format!(r#""{}""#, stream_name))
+        .collect::<Vec<_>>()
+        .join(",");
+    let id = track_request(trade_pair, format!("{} {}", method, stream_names.join(",")));
+    let message_str = format!(
+        r##"{{"method":"{}","params":[{}],"id": {}}}"##,
+        method, params, id
+    );
+    Message::Text(message_str)
+}
+
+fn parse_ticker_event(str_data: &str) -> Option<Ticker> {
+    serde_json::from_str::<TickerEvent>(str_data)
+        .ok()
+        .map(Ticker::from)
+}
+
+fn parse_api_result(str_data: &str) -> Option<Ack> {
+    let result = serde_json::from_str::<ApiResult>(str_data).ok()?;
+    Some(match result.error {
+        Some(error) => Ack::rejected(Some(result.id), format!("{} ({})", error.msg, error.code)),
+        None => Ack::ok(Some(result.id)),
+    })
+}
+
+fn book_stream_name(trade_pair: &TradePairInfo) -> String {
+    format!("{}@bookTicker", trade_pair.ws_name)
+}
+
+// rows are oldest first, the close is the fifth field
+fn parse_kline_rows(str_data: &str) -> Option<Vec<f64>> {
+    let rows = serde_json::from_str::<Vec<Vec<serde_json::Value>>>(str_data).ok()?;
+    Some(kline_closes(&rows, 4))
+}
+
+fn depth_stream_name(trade_pair: &TradePairInfo) -> String {
+    format!("{}@depth{}@100ms", trade_pair.ws_name, DEPTH_LEVELS)
+}
+
+lazy_static! {
+    // the last book ticker update id by exchange and symbol, an older one is
+    // out of order
+    static ref BOOK_UPDATES: Mutex<HashMap<(&'static str, String), u64>> =
+        Mutex::new(HashMap::new());
+}
+
+fn parse_book_ticker(exchange_id: &'static str, str_data: &str) -> Option<Bbo> {
+    let event = serde_json::from_str::<BookTickerEvent>(str_data).ok()?;
+    let mut updates = BOOK_UPDATES.lock().unwrap();
+    let last_update = updates.entry((exchange_id, event.name.clone())).or_default();
+    if event.update_id <= *last_update {
+        return None;
+    }
+    *last_update = event.update_id;
+    Some(Bbo {
+        name: event.name,
+        bid_price: event.bid_price,
+        ask_price: event.ask_price,
+    })
+}
+
+// binance usdt-m perpetual futures, mark price stream
+pub struct BinanceFutures;
+
+impl BinanceFutures {
+    // the mark price stream has no 24h open, so the ticker stream rides along
+    fn stream_names(trade_pair: &TradePairInfo) -> [String; 2] {
+        [
+            format!("{}@markPrice", trade_pair.ws_name),
+            format!("{}@ticker", trade_pair.ws_name),
+        ]
+    }
+}
+
+impl Exchange for BinanceFutures {
+    fn id(&self) -> &'static str {
+        "binance-futures"
+    }
+
+    fn show_name(&self) -> &'static str {
+        i18n::tr("Binance 合约")
+    }
+
+    fn venue(&self) -> &'static str {
+        "binance"
+    }
+
+    fn market(&self) -> Market {
+        Market::Perp
+    }
+
+    fn url(&self) -> &'static str {
+        "wss://fstream.binance.com/ws"
+    }
+
+    fn subscribe_message(&self, trade_pair: &TradePairInfo) -> Message {
+        stream_message("SUBSCRIBE", trade_pair, &Self::stream_names(trade_pair))
+    }
+
+    fn unsubscribe_message(&self, trade_pair: &TradePairInfo) -> Message {
+        stream_message("UNSUBSCRIBE", trade_pair, &Self::stream_names(trade_pair))
+    }
+
+    // the mark price stream carries the index price, the ticker stream the last
+    fn parse_price(&self, str_data: &str) -> Option<Price> {
+        match self.price_type() {
+            PriceType::Last => serde_json::from_str::<TickerEvent>(str_data)
+                .ok()
+                .map(Price::from),
+            PriceType::Mark => serde_json::from_str::<MarkPriceEvent>(str_data)
+                .ok()
+                .map(Price::from),
+            PriceType::Index => serde_json::from_str::<MarkPriceEvent>(str_data)
+                .ok()
+                .map(|event| Price::new(event.time_stamp, event.name, event.spot_index_price)),
+        }
+    }
+
+    fn price_types(&self) -> &'static [PriceType] {
+        &[PriceType::Mark, PriceType::Last, PriceType::Index]
+    }
+
+    fn parse_ticker(&self, str_data: &str) -> Option<Ticker> {
+        parse_ticker_event(str_data)
+    }
+
+    // the mark price stream carries the funding rate
+    fn parse_funding(&self, str_data: &str) -> Option<Funding> {
+        let event = serde_json::from_str::<MarkPriceEvent>(str_data).ok()?;
+        Some(Funding {
+            name: event.name,
+            rate: event.fee,
+            next_time: event.next_fee_time,
+        })
+    }
+
+    fn bbo_subscribe_message(&self, trade_pair: &TradePairInfo) -> Option<Message> {
+        Some(stream_message("SUBSCRIBE", trade_pair, &[book_stream_name(trade_pair)]))
+    }
+
+    fn bbo_unsubscribe_message(&self, trade_pair: &TradePairInfo) -> Option<Message> {
+        Some(stream_message("UNSUBSCRIBE", trade_pair, &[book_stream_name(trade_pair)]))
+    }
+
+    fn parse_bbo(&self, str_data: &str) -> Option<Bbo> {
+        parse_book_ticker(self.id(), str_data)
+    }
+
+    fn depth_subscribe_message(&self, trade_pair: &TradePairInfo) -> Option<Message> {
+        Some(stream_message("SUBSCRIBE", trade_pair, &[depth_stream_name(trade_pair)]))
+    }
+
+    fn depth_unsubscribe_message(&self, trade_pair: &TradePairInfo) -> Option<Message> {
+        Some(stream_message("UNSUBSCRIBE", trade_pair, &[depth_stream_name(trade_pair)]))
+    }
+
+    fn parse_depth(&self, str_data: &str) -> Option<Depth> {
+        let event = serde_json::from_str::<FuturesDepthEvent>(str_data).ok()?;
+        Some(Depth {
+            name: event.name,
+            bids: book_levels(&event.bids),
+            asks: book_levels(&event.asks),
+        })
+    }
+
+    fn rest_ticker_url(&self, trade_pair: &TradePairInfo) -> String {
+        match self.price_type() {
+            PriceType::Last => format!(
+                "https://fapi.binance.com/fapi/v1/ticker/24hr?symbol={}",
+                trade_pair.pair_name
+            ),
+            PriceType::Mark | PriceType::Index => format!(
+                "https://fapi.binance.com/fapi/v1/premiumIndex?symbol={}",
+                trade_pair.pair_name
+            ),
+        }
+    }
+
+    fn parse_rest_price(&self, _trade_pair: &TradePairInfo, str_data: &str) -> Option<Price> {
+        if self.price_type() == PriceType::Last {
+            return serde_json::from_str::<Ticker24hr>(str_data)
+                .ok()
+                .map(Price::from);
+        }
+        let index = serde_json::from_str::<PremiumIndex>(str_data).ok()?;
+        let price = match self.price_type() {
+            PriceType::Index => index.index_price,
+            _ => index.mark_price,
+        };
+        Some(Price::new(index.time, index.symbol, price))
+    }
+
+    // klines of the selected price type
+    fn klines_url(&self, trade_pair: &TradePairInfo) -> Option<String> {
+        let (path, key) = match self.price_type() {
+            PriceType::Last => ("klines", "symbol"),
+            PriceType::Mark => ("markPriceKlines", "symbol"),
+            PriceType::Index => ("indexPriceKlines", "pair"),
+        };
+        Some(format!(
+            "https://fapi.binance.com/fapi/v1/{}?{}={}&interval=1m&limit={}",
+            path, key, trade_pair.pair_name, KLINE_LIMIT
+        ))
+    }
+
+    fn parse_klines(&self, str_data: &str) -> Option<Vec<f64>> {
+        parse_kline_rows(str_data)
+    }
+
+    fn parse_ack(&self, str_data: &str) -> Option<Ack> {
+        parse_api_result(str_data)
+    }
+
+    fn symbols_url(&self) -> &'static str {
+        "https://fapi.binance.com/fapi/v1/exchangeInfo"
+    }
+
+    fn parse_symbols(&self, str_data: &str) -> Option<Vec<TradePairInfo>> {
+        parse_exchange_info(str_data, |symbol| {
+            symbol.contract_type.as_deref() == Some("PERPETUAL")
+        })
+    }
+}
+
+// binance spot, 24h ticker stream
+pub struct BinanceSpot;
+
+impl Exchange for BinanceSpot {
+    fn id(&self) -> &'static str {
+        "binance-spot"
+    }
+
+    fn show_name(&self) -> &'static str {
+        i18n::tr("Binance 现货")
+    }
+
+    fn venue(&self) -> &'static str {
+        "binance"
+    }
+
+    fn market(&self) -> Market {
+        Market::Spot
+    }
+
+    fn url(&self) -> &'static str {
+        "wss://stream.binance.com:9443/ws"
+    }
+
+    fn subscribe_message(&self, trade_pair: &TradePairInfo) -> Message {
+        stream_message("SUBSCRIBE", trade_pair, &[format!("{}@ticker", trade_pair.ws_name)])
+    }
+
+    fn unsubscribe_message(&self, trade_pair: &TradePairInfo) -> Message {
+        stream_message("UNSUBSCRIBE", trade_pair, &[format!("{}@ticker", trade_pair.ws_name)])
+    }
+
+    fn parse_price(&self, str_data: &str) -> Option<Price> {
+        serde_json::from_str::<TickerEvent>(str_data)
+            .ok()
+            .map(Price::from)
+    }
+
+    fn parse_ticker(&self, str_data: &str) -> Option<Ticker> {
+        parse_ticker_event(str_data)
+    }
+
+    fn bbo_subscribe_message(&self, trade_pair: &TradePairInfo) -> Option<Message> {
+        Some(stream_message("SUBSCRIBE", trade_pair, &[book_stream_name(trade_pair)]))
+    }
+
+    fn bbo_unsubscribe_message(&self, trade_pair: &TradePairInfo) -> Option<Message> {
+        Some(stream_message("UNSUBSCRIBE", trade_pair, &[book_stream_name(trade_pair)]))
+    }
+
+    fn parse_bbo(&self, str_data: &str) -> Option<Bbo> {
+        parse_book_ticker(self.id(), str_data)
+    }
+
+    fn depth_subscribe_message(&self, trade_pair: &TradePairInfo) -> Option<Message> {
+        Some(stream_message("SUBSCRIBE", trade_pair, &[depth_stream_name(trade_pair)]))
+    }
+
+    fn depth_unsubscribe_message(&self, trade_pair: &TradePairInfo) -> Option<Message> {
+        Some(stream_message("UNSUBSCRIBE", trade_pair, &[depth_stream_name(trade_pair)]))
+    }
+
+    fn parse_depth(&self, str_data: &str) -> Option<Depth> {
+        let event = serde_json::from_str::<SpotDepth>(str_data).ok()?;
+        Some(Depth {
+            name: String::new(),
+            bids: book_levels(&event.bids),
+            asks: book_levels(&event.asks),
+        })
+    }
+
+    fn rest_ticker_url(&self, trade_pair: &TradePairInfo) -> String {
+        format!(
+            "https://api.binance.com/api/v3/ticker/24hr?symbol={}",
+            trade_pair.pair_name
+        )
+    }
+
+    fn parse_rest_price(&self, _trade_pair: &TradePairInfo, str_data: &str) -> Option<Price> {
+        serde_json::from_str::<Ticker24hr>(str_data)
+            .ok()
+            .map(Price::from)
+    }
+
+    fn klines_url(&self, trade_pair: &TradePairInfo) -> Option<String> {
+        Some(format!(
+            "https://api.binance.com/api/v3/klines?symbol={}&interval=1m&limit={}",
+            trade_pair.pair_name, KLINE_LIMIT
+        ))
+    }
+
+    fn parse_klines(&self, str_data: &str) -> Option<Vec<f64>> {
+        parse_kline_rows(str_data)
+    }
+
+    fn parse_ack(&self, str_data: &str) -> Option<Ack> {
+        parse_api_result(str_data)
+    }
+
+    fn symbols_url(&self) -> &'static str {
+        "https://api.binance.com/api/v3/exchangeInfo"
+    }
+
+    // spot lists thousands of symbols, only usdt quoted ones go in the menu
+    fn parse_symbols(&self, str_data: &str) -> Option<Vec<TradePairInfo>> {
+        parse_exchange_info(str_data, |symbol| symbol.quote_asset == "USDT")
+    }
+}
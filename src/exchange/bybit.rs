@@ -0,0 +1,412 @@
+use super::{
+    kline_closes, string_to_f64, track_request, Exchange, Market, PriceType, DEPTH_LEVELS,
+    KLINE_LIMIT,
+};
+use crate::api::{Ack, Bbo, Depth, Funding, Price, Ticker};
+use crate::format;
+use crate::i18n;
+use crate::pairs::TradePairInfo;
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+#[derive(Debug, Deserialize)]
+struct BybitTicker {
+    symbol: String,
+    #[serde(rename = "lastPrice", deserialize_with = "string_to_f64")]
+    last_price: f64,
+    #[serde(rename = "prevPrice24h", deserialize_with = "string_to_f64")]
+    open_price: f64,
+    #[serde(rename = "highPrice24h", deserialize_with = "string_to_f64")]
+    high_price: f64,
+    #[serde(rename = "lowPrice24h", deserialize_with = "string_to_f64")]
+    low_price: f64,
+    #[serde(rename = "volume24h", deserialize_with = "string_to_f64")]
+    volume: f64,
+    #[serde(rename = "turnover24h", deserialize_with = "string_to_f64")]
+    turnover: f64,
+    #[serde(rename = "markPrice", deserialize_with = "string_to_f64")]
+    mark_price: f64,
+    #[serde(rename = "indexPrice", deserialize_with = "string_to_f64")]
+    index_price: f64,
+    #[serde(rename = "fundingRate", default)]
+    funding_rate: Option<String>,
+    #[serde(rename = "nextFundingTime", default)]
+    next_funding_time: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TickerPush {
+    topic: String,
+    // "snapshot" or "delta"
+    #[serde(rename = "type")]
+    push_type: String,
+    data: Map<String, Value>,
+    ts: u64,
+}
+
+// levels are [price, size], a delta leaves an unchanged side empty
+#[derive(Debug, Deserialize)]
+struct BookData {
+    #[serde(rename = "s")]
+    symbol: String,
+    #[serde(rename = "b")]
+    bids: Vec<Vec<String>>,
+    #[serde(rename = "a")]
+    asks: Vec<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BookPush {
+    topic: String,
+    #[serde(rename = "type")]
+    push_type: String,
+    data: BookData,
+}
+
+// reply to an op, the pong of {"op":"ping"} included
+#[derive(Debug, Deserialize)]
+struct OpResponse {
+    // echoed request id, empty for the ping
+    #[serde(default)]
+    req_id: String,
+    success: bool,
+    #[serde(default)]
+    ret_msg: String,
+    op: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RestList<T> {
+    list: Vec<T>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RestResponse<T> {
+    result: RestList<T>,
+    time: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct InstrumentInfo {
+    symbol: String,
+    status: String,
+    #[serde(rename = "contractType")]
+    contract_type: String,
+    #[serde(rename = "baseCoin")]
+    base_coin: String,
+    #[serde(rename = "quoteCoin")]
+    quote_coin: String,
+    #[serde(rename = "priceFilter")]
+    price_filter: PriceFilter,
+}
+
+#[derive(Debug, Deserialize)]
+struct PriceFilter {
+    #[serde(rename = "tickSize")]
+    tick_size: String,
+}
+
+// price and size of the book levels, best first
+type Levels = Vec<(f64, f64)>;
+
+lazy_static! {
+    // deltas only carry the changed fields, merged onto the last snapshot by symbol
+    static ref TICKERS: Mutex<HashMap<String, Map<String, Value>>> = Mutex::new(HashMap::new());
+    // last best bid and ask by symbol, for the same reason
+    static ref BOOKS: Mutex<HashMap<String, (f64, f64)>> = Mutex::new(HashMap::new());
+    // bids and asks of the popup book by symbol, deltas carry changed levels only
+    static ref DEPTHS: Mutex<HashMap<String, (Levels, Levels)>> = Mutex::new(HashMap::new());
+}
+
+// the shallowest bybit book with more than one level
+const DEPTH_TOPIC: &str = "orderbook.50";
+
+// a zero size removes the level
+fn merge_levels(side: &mut Vec<(f64, f64)>, updates: &[Vec<String>], descending: bool) {
+    for level in updates {
+        let price = level.first().and_then(|price| price.parse::<f64>().ok());
+        let size = level.get(1).and_then(|size| size.parse::<f64>().ok());
+        let (Some(price), Some(size)) = (price, size) else {
+            continue;
+        };
+        side.retain(|(level_price, _)| *level_price != price);
+        if size > 0. {
+            side.push((price, size));
+        }
+    }
+    if descending {
+        side.sort_by(|a, b| b.0.total_cmp(&a.0));
+    } else {
+        side.sort_by(|a, b| a.0.total_cmp(&b.0));
+    }
+}
+
+fn merge_depth(str_data: &str) -> Option<Depth> {
+    let push = serde_json::from_str::<BookPush>(str_data).ok()?;
+    if !push.topic.starts_with(DEPTH_TOPIC) {
+        return None;
+    }
+    let mut depths = DEPTHS.lock().unwrap();
+    let (bids, asks) = depths.entry(push.data.symbol.clone()).or_default();
+    if push.push_type == "snapshot" {
+        bids.clear();
+        asks.clear();
+    }
+    merge_levels(bids, &push.data.bids, true);
+    merge_levels(asks, &push.data.asks, false);
+    Some(Depth {
+        name: push.data.symbol,
+        bids: bids.iter().take(DEPTH_LEVELS).copied().collect(),
+        asks: asks.iter().take(DEPTH_LEVELS).copied().collect(),
+    })
+}
+
+fn best_level(levels: &[Vec<String>]) -> Option<f64> {
+    levels.first()?.first()?.parse::<f64>().ok()
+}
+
+fn merge_book(str_data: &str) -> Option<Bbo> {
+    let push = serde_json::from_str::<BookPush>(str_data).ok()?;
+    if !push.topic.starts_with("orderbook.1.") {
+        return None;
+    }
+    let mut books = BOOKS.lock().unwrap();
+    let book = books.entry(push.data.symbol.clone()).or_insert((0., 0.));
+    if let Some(bid_price) = best_level(&push.data.bids) {
+        book.0 = bid_price;
+    }
+    if let Some(ask_price) = best_level(&push.data.asks) {
+        book.1 = ask_price;
+    }
+    if book.0 == 0. || book.1 == 0. {
+        return None;
+    }
+    Some(Bbo {
+        name: push.data.symbol,
+        bid_price: book.0,
+        ask_price: book.1,
+    })
+}
+
+fn price_field(price_type: PriceType) -> &'static str {
+    match price_type {
+        PriceType::Last => "lastPrice",
+        PriceType::Mark => "markPrice",
+        PriceType::Index => "indexPrice",
+    }
+}
+
+// merged ticker of the push, and whether the push changed the price of the selected type
+fn merge_push(str_data: &str, price_type: PriceType) -> Option<(BybitTicker, u64, bool)> {
+    let push = serde_json::from_str::<TickerPush>(str_data).ok()?;
+    if !push.topic.starts_with("tickers.") {
+        return None;
+    }
+    let symbol = push.topic.trim_start_matches("tickers.").to_string();
+    let price_changed = push.data.contains_key(price_field(price_type));
+    let mut tickers = TICKERS.lock().unwrap();
+    let merged = tickers.entry(symbol).or_default();
+    if push.push_type == "snapshot" {
+        merged.clear();
+    }
+    merged.extend(push.data);
+    let ticker = serde_json::from_value::<BybitTicker>(Value::Object(merged.clone())).ok()?;
+    Some((ticker, push.ts, price_changed))
+}
+
+impl BybitTicker {
+    fn to_price(&self, time_stamp: u64, price_type: PriceType) -> Price {
+        let price = match price_type {
+            PriceType::Last => self.last_price,
+            PriceType::Mark => self.mark_price,
+            PriceType::Index => self.index_price,
+        };
+        Price::new(time_stamp, self.symbol.clone(), price)
+    }
+
+    fn to_funding(&self) -> Option<Funding> {
+        Some(Funding {
+            name: self.symbol.clone(),
+            rate: self.funding_rate.as_deref()?.parse::<f64>().ok()?,
+            next_time: self.next_funding_time.as_deref()?.parse::<u64>().ok()?,
+        })
+    }
+}
+
+impl From<BybitTicker> for Ticker {
+    fn from(ticker: BybitTicker) -> Self {
+        Ticker {
+            name: ticker.symbol,
+            open_price: ticker.open_price,
+            high_price: ticker.high_price,
+            low_price: ticker.low_price,
+            volume: ticker.volume,
+            turnover: Some(ticker.turnover),
+        }
+    }
+}
+
+fn topic_message(op: &str, topic: &str, trade_pair: &TradePairInfo) -> Message {
+    let symbol = &trade_pair.ws_name;
+    let id = track_request(trade_pair, format!("{} {}.{}", op, topic, symbol));
+    Message::Text(format!(
+        r#"{{"req_id":"{}","op":"{}","args":["{}.{}"]}}"#,
+        id, op, topic, symbol
+    ))
+}
+
+// bybit v5 usdt perpetuals, tickers topic
+pub struct BybitLinear;
+
+impl Exchange for BybitLinear {
+    fn id(&self) -> &'static str {
+        "bybit-linear"
+    }
+
+    fn show_name(&self) -> &'static str {
+        i18n::tr("Bybit 永续")
+    }
+
+    fn venue(&self) -> &'static str {
+        "bybit"
+    }
+
+    fn market(&self) -> Market {
+        Market::Perp
+    }
+
+    fn url(&self) -> &'static str {
+        "wss://stream.bybit.com/v5/public/linear"
+    }
+
+    fn subscribe_message(&self, trade_pair: &TradePairInfo) -> Message {
+        topic_message("subscribe", "tickers", trade_pair)
+    }
+
+    fn unsubscribe_message(&self, trade_pair: &TradePairInfo) -> Message {
+        topic_message("unsubscribe", "tickers", trade_pair)
+    }
+
+    // tickers carry all three prices
+    fn price_types(&self) -> &'static [PriceType] {
+        &[PriceType::Last, PriceType::Mark, PriceType::Index]
+    }
+
+    // a delta without the selected price only updates the other fields
+    fn parse_price(&self, str_data: &str) -> Option<Price> {
+        let (ticker, time_stamp, price_changed) = merge_push(str_data, self.price_type())?;
+        if !price_changed {
+            return None;
+        }
+        Some(ticker.to_price(time_stamp, self.price_type()))
+    }
+
+    fn parse_ticker(&self, str_data: &str) -> Option<Ticker> {
+        merge_push(str_data, self.price_type()).map(|(ticker, _, _)| Ticker::from(ticker))
+    }
+
+    fn parse_funding(&self, str_data: &str) -> Option<Funding> {
+        merge_push(str_data, self.price_type())?.0.to_funding()
+    }
+
+    fn bbo_subscribe_message(&self, trade_pair: &TradePairInfo) -> Option<Message> {
+        Some(topic_message("subscribe", "orderbook.1", trade_pair))
+    }
+
+    fn bbo_unsubscribe_message(&self, trade_pair: &TradePairInfo) -> Option<Message> {
+        Some(topic_message("unsubscribe", "orderbook.1", trade_pair))
+    }
+
+    fn parse_bbo(&self, str_data: &str) -> Option<Bbo> {
+        merge_book(str_data)
+    }
+
+    fn depth_subscribe_message(&self, trade_pair: &TradePairInfo) -> Option<Message> {
+        Some(topic_message("subscribe", DEPTH_TOPIC, trade_pair))
+    }
+
+    fn depth_unsubscribe_message(&self, trade_pair: &TradePairInfo) -> Option<Message> {
+        Some(topic_message("unsubscribe", DEPTH_TOPIC, trade_pair))
+    }
+
+    fn parse_depth(&self, str_data: &str) -> Option<Depth> {
+        merge_depth(str_data)
+    }
+
+    fn rest_ticker_url(&self, trade_pair: &TradePairInfo) -> String {
+        format!(
+            "https://api.bybit.com/v5/market/tickers?category=linear&symbol={}",
+            trade_pair.ws_name
+        )
+    }
+
+    fn parse_rest_price(&self, _trade_pair: &TradePairInfo, str_data: &str) -> Option<Price> {
+        let response = serde_json::from_str::<RestResponse<BybitTicker>>(str_data).ok()?;
+        let time = response.time;
+        response
+            .result
+            .list
+            .into_iter()
+            .next()
+            .map(|ticker| ticker.to_price(time, self.price_type()))
+    }
+
+    fn klines_url(&self, trade_pair: &TradePairInfo) -> Option<String> {
+        Some(format!(
+            "https://api.bybit.com/v5/market/kline?category=linear&symbol={}&interval=1&limit={}",
+            trade_pair.ws_name, KLINE_LIMIT
+        ))
+    }
+
+    // the list is newest first
+    fn parse_klines(&self, str_data: &str) -> Option<Vec<f64>> {
+        let response = serde_json::from_str::<RestResponse<Vec<Value>>>(str_data).ok()?;
+        let mut closes = kline_closes(&response.result.list, 4);
+        closes.reverse();
+        Some(closes)
+    }
+
+    fn parse_ack(&self, str_data: &str) -> Option<Ack> {
+        let response = serde_json::from_str::<OpResponse>(str_data).ok()?;
+        let id = response.req_id.parse::<u64>().ok();
+        if response.success {
+            return Some(Ack::ok(id));
+        }
+        Some(Ack::rejected(id, format!("{} {}", response.op, response.ret_msg)))
+    }
+
+    fn symbols_url(&self) -> &'static str {
+        "https://api.bybit.com/v5/market/instruments-info?category=linear&limit=1000"
+    }
+
+    fn parse_symbols(&self, str_data: &str) -> Option<Vec<TradePairInfo>> {
+        let response = serde_json::from_str::<RestResponse<InstrumentInfo>>(str_data).ok()?;
+        let mut infos: Vec<TradePairInfo> = response
+            .result
+            .list
+            .iter()
+            .filter(|info| info.status == "Trading" && info.contract_type == "LinearPerpetual")
+            .map(|info| TradePairInfo {
+                ws_name: info.symbol.clone(),
+                show_name: format!("{}/{}", info.base_coin, info.quote_coin),
+                pair_name: info.symbol.clone(),
+                decimals: format::tick_decimals(&info.price_filter.tick_size),
+            })
+            .collect();
+        infos.sort_by(|a, b| a.show_name.cmp(&b.show_name));
+        Some(infos)
+    }
+
+    fn instrument_id(&self, trade_pair: &TradePairInfo) -> String {
+        trade_pair.pair_name.to_uppercase()
+    }
+
+    // bybit drops connections without an application level ping
+    fn ping_message(&self) -> Option<Message> {
+        Some(Message::Text(r#"{"op":"ping"}"#.to_string()))
+    }
+}
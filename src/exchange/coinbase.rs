@@ -0,0 +1,267 @@
+use super::{kline_closes, split_pair_name, string_to_f64, Exchange, Market, KLINE_LIMIT};
+use crate::api::{now_millis, Ack, Bbo, Price, Ticker};
+use crate::format;
+use crate::i18n;
+use crate::pairs::TradePairInfo;
+use serde::Deserialize;
+use serde_json::Value;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+#[derive(Debug, Deserialize)]
+struct CoinbaseTicker {
+    product_id: String,
+    #[serde(deserialize_with = "string_to_f64")]
+    price: f64,
+    #[serde(rename = "price_percent_chg_24_h", deserialize_with = "string_to_f64")]
+    change_percent: f64,
+    #[serde(rename = "high_24_h", deserialize_with = "string_to_f64")]
+    high_price: f64,
+    #[serde(rename = "low_24_h", deserialize_with = "string_to_f64")]
+    low_price: f64,
+    #[serde(rename = "volume_24_h", deserialize_with = "string_to_f64")]
+    volume: f64,
+    // missing on some snapshot entries
+    #[serde(default, deserialize_with = "string_to_f64")]
+    best_bid: f64,
+    #[serde(default, deserialize_with = "string_to_f64")]
+    best_ask: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct TickerEvent {
+    tickers: Vec<CoinbaseTicker>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChannelMessage {
+    channel: String,
+    events: Vec<TickerEvent>,
+}
+
+// {"type":"error","message":"Failed to subscribe","reason":"..."}
+#[derive(Debug, Deserialize)]
+struct ErrorMessage {
+    #[serde(rename = "type")]
+    message_type: String,
+    #[serde(default)]
+    message: String,
+    #[serde(default)]
+    reason: String,
+}
+
+// public exchange api, used for the rest fallback
+#[derive(Debug, Deserialize)]
+struct ProductTicker {
+    #[serde(deserialize_with = "string_to_f64")]
+    price: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct Product {
+    id: String,
+    base_currency: String,
+    quote_currency: String,
+    status: String,
+    trading_disabled: bool,
+    quote_increment: String,
+}
+
+// BTC-USD to the registry pair name BTCUSD
+fn pair_name_of(product_id: &str) -> String {
+    product_id.replace('-', "")
+}
+
+// the tickers of the message, a snapshot lists one per subscribed product
+fn parse_ticker_message(str_data: &str) -> Vec<CoinbaseTicker> {
+    let message = match serde_json::from_str::<ChannelMessage>(str_data) {
+        Ok(message) if message.channel == "ticker" => message,
+        _ => return Vec::new(),
+    };
+    message
+        .events
+        .into_iter()
+        .flat_map(|event| event.tickers)
+        .collect()
+}
+
+// the best bid and ask, missing on some snapshot entries
+fn bbo_of(ticker: CoinbaseTicker) -> Option<Bbo> {
+    if ticker.best_bid == 0. || ticker.best_ask == 0. {
+        return None;
+    }
+    Some(Bbo {
+        name: pair_name_of(&ticker.product_id),
+        bid_price: ticker.best_bid,
+        ask_price: ticker.best_ask,
+    })
+}
+
+// the channel timestamps are rfc3339, the local clock is close enough for display
+impl From<CoinbaseTicker> for Price {
+    fn from(ticker: CoinbaseTicker) -> Self {
+        Price::new(now_millis(), pair_name_of(&ticker.product_id), ticker.price)
+    }
+}
+
+// coinbase reports the 24h change instead of the open
+impl From<CoinbaseTicker> for Ticker {
+    fn from(ticker: CoinbaseTicker) -> Self {
+        Ticker {
+            name: pair_name_of(&ticker.product_id),
+            open_price: ticker.price / (1. + ticker.change_percent / 100.),
+            high_price: ticker.high_price,
+            low_price: ticker.low_price,
+            volume: ticker.volume,
+            turnover: None,
+        }
+    }
+}
+
+// without it the ws closes while a product has no trades
+fn heartbeats_message() -> Message {
+    Message::Text(r#"{"type":"subscribe","channel":"heartbeats"}"#.to_string())
+}
+
+fn channel_message(message_type: &str, product_id: &str) -> Message {
+    let message_str = format!(
+        r#"{{"type":"{}","product_ids":["{}"],"channel":"ticker"}}"#,
+        message_type, product_id
+    );
+    Message::Text(message_str)
+}
+
+// coinbase advanced trade, spot ticker channel
+pub struct CoinbaseSpot;
+
+impl Exchange for CoinbaseSpot {
+    fn id(&self) -> &'static str {
+        "coinbase-spot"
+    }
+
+    fn show_name(&self) -> &'static str {
+        i18n::tr("Coinbase 现货")
+    }
+
+    fn venue(&self) -> &'static str {
+        "coinbase"
+    }
+
+    fn market(&self) -> Market {
+        Market::Spot
+    }
+
+    fn url(&self) -> &'static str {
+        "wss://advanced-trade-ws.coinbase.com"
+    }
+
+    fn subscribe_message(&self, trade_pair: &TradePairInfo) -> Message {
+        channel_message("subscribe", &trade_pair.ws_name)
+    }
+
+    fn unsubscribe_message(&self, trade_pair: &TradePairInfo) -> Message {
+        channel_message("unsubscribe", &trade_pair.ws_name)
+    }
+
+    fn connect_messages(&self) -> Vec<Message> {
+        vec![heartbeats_message()]
+    }
+
+    fn parse_price(&self, str_data: &str) -> Option<Price> {
+        parse_ticker_message(str_data).pop().map(Price::from)
+    }
+
+    fn parse_prices(&self, str_data: &str) -> Vec<Price> {
+        parse_ticker_message(str_data).into_iter().map(Price::from).collect()
+    }
+
+    fn parse_ticker(&self, str_data: &str) -> Option<Ticker> {
+        parse_ticker_message(str_data).pop().map(Ticker::from)
+    }
+
+    fn parse_tickers(&self, str_data: &str) -> Vec<Ticker> {
+        parse_ticker_message(str_data).into_iter().map(Ticker::from).collect()
+    }
+
+    // the ticker channel carries the best bid and ask
+    fn parse_bbo(&self, str_data: &str) -> Option<Bbo> {
+        parse_ticker_message(str_data).pop().and_then(bbo_of)
+    }
+
+    fn parse_bbos(&self, str_data: &str) -> Vec<Bbo> {
+        parse_ticker_message(str_data).into_iter().filter_map(bbo_of).collect()
+    }
+
+    fn rest_ticker_url(&self, trade_pair: &TradePairInfo) -> String {
+        format!(
+            "https://api.exchange.coinbase.com/products/{}/ticker",
+            trade_pair.ws_name
+        )
+    }
+
+    // the response does not name the product
+    fn parse_rest_price(&self, trade_pair: &TradePairInfo, str_data: &str) -> Option<Price> {
+        let ticker = serde_json::from_str::<ProductTicker>(str_data).ok()?;
+        Some(Price::new(now_millis(), trade_pair.pair_name.clone(), ticker.price))
+    }
+
+    // the exchange api has no limit parameter, it returns up to 300 candles
+    fn klines_url(&self, trade_pair: &TradePairInfo) -> Option<String> {
+        Some(format!(
+            "https://api.exchange.coinbase.com/products/{}/candles?granularity=60",
+            trade_pair.ws_name
+        ))
+    }
+
+    // rows are [time, low, high, open, close, volume], newest first
+    fn parse_klines(&self, str_data: &str) -> Option<Vec<f64>> {
+        let rows = serde_json::from_str::<Vec<Vec<serde_json::Value>>>(str_data).ok()?;
+        let mut closes = kline_closes(&rows, 4);
+        closes.truncate(KLINE_LIMIT);
+        closes.reverse();
+        Some(closes)
+    }
+
+    // successful requests are answered on the subscriptions channel
+    fn parse_ack(&self, str_data: &str) -> Option<Ack> {
+        if let Ok(error) = serde_json::from_str::<ErrorMessage>(str_data) {
+            if error.message_type == "error" {
+                return Some(Ack::rejected(None, format!("{} {}", error.message, error.reason)));
+            }
+        }
+        let message = serde_json::from_str::<Value>(str_data).ok()?;
+        match message.get("channel")?.as_str()? {
+            "subscriptions" => Some(Ack::ok(None)),
+            _ => None,
+        }
+    }
+
+    fn symbols_url(&self) -> &'static str {
+        "https://api.exchange.coinbase.com/products"
+    }
+
+    fn parse_symbols(&self, str_data: &str) -> Option<Vec<TradePairInfo>> {
+        let products = serde_json::from_str::<Vec<Product>>(str_data).ok()?;
+        let mut infos: Vec<TradePairInfo> = products
+            .iter()
+            .filter(|product| product.status == "online" && !product.trading_disabled)
+            .filter(|product| product.quote_currency == "USD" || product.quote_currency == "USDT")
+            .map(|product| TradePairInfo {
+                ws_name: product.id.clone(),
+                show_name: format!("{}/{}", product.base_currency, product.quote_currency),
+                pair_name: pair_name_of(&product.id),
+                decimals: format::tick_decimals(&product.quote_increment),
+            })
+            .collect();
+        infos.sort_by(|a, b| a.show_name.cmp(&b.show_name));
+        Some(infos)
+    }
+
+    // BTCUSD to BTC-USD
+    fn instrument_id(&self, trade_pair: &TradePairInfo) -> String {
+        let pair_name = trade_pair.pair_name.to_uppercase();
+        match split_pair_name(&pair_name) {
+            Some((base, quote)) => format!("{}-{}", base, quote),
+            None => pair_name,
+        }
+    }
+}
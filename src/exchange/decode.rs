@@ -0,0 +1,135 @@
+use flate2::read::GzDecoder;
+use flate2::{Decompress, FlushDecompress, Status};
+use std::io::Read;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum DecodeError {
+    #[error("bad gzip frame: {0}")]
+    Gzip(#[source] std::io::Error),
+    #[error("bad deflate frame: {0}")]
+    Inflate(#[from] flate2::DecompressError),
+    #[error("frame is not utf-8: {0}")]
+    Utf8(#[from] std::string::FromUtf8Error),
+}
+
+// how an exchange compresses its binary frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    // told apart by the first bytes, raw deflate when nothing else fits
+    Auto,
+    Plain,
+    Gzip,
+    // deflate behind a two byte zlib header
+    Zlib,
+    // headerless deflate, also the payload of a permessage-deflate message.
+    // tungstenite does not negotiate that extension, so it only shows up when
+    // an exchange compresses at the application level
+    Deflate,
+}
+
+fn sniff(data: &[u8]) -> Compression {
+    match data {
+        [0x1f, 0x8b, ..] => Compression::Gzip,
+        // 0x78 is deflate with a 32k window, the header checksum is a multiple of 31
+        [0x78, flags, ..] if (0x7800 | *flags as u16).is_multiple_of(31) => Compression::Zlib,
+        _ if std::str::from_utf8(data).is_ok() => Compression::Plain,
+        _ => Compression::Deflate,
+    }
+}
+
+// a permessage-deflate payload ends in a sync flush instead of a final
+// block, so input running out is not an error
+fn inflate(data: &[u8], zlib_header: bool) -> Result<Vec<u8>, DecodeError> {
+    let mut inflater = Decompress::new(zlib_header);
+    let mut decoded = Vec::with_capacity(data.len() * 4);
+    loop {
+        if decoded.len() == decoded.capacity() {
+            decoded.reserve(data.len().max(1024));
+        }
+        let consumed = inflater.total_in() as usize;
+        let produced = decoded.len();
+        let status =
+            inflater.decompress_vec(&data[consumed..], &mut decoded, FlushDecompress::Sync)?;
+        let stalled = inflater.total_in() as usize == consumed && decoded.len() == produced;
+        if status == Status::StreamEnd || stalled {
+            return Ok(decoded);
+        }
+    }
+}
+
+pub fn decode_binary(compression: Compression, data: &[u8]) -> Result<String, DecodeError> {
+    let compression = match compression {
+        Compression::Auto => sniff(data),
+        compression => compression,
+    };
+    let decoded = match compression {
+        Compression::Auto | Compression::Plain => data.to_vec(),
+        Compression::Gzip => {
+            let mut decoded = Vec::new();
+            GzDecoder::new(data)
+                .read_to_end(&mut decoded)
+                .map_err(DecodeError::Gzip)?;
+            decoded
+        }
+        Compression::Zlib => inflate(data, true)?,
+        Compression::Deflate => inflate(data, false)?,
+    };
+    Ok(String::from_utf8(decoded)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_binary, Compression, DecodeError};
+    use flate2::write::{DeflateEncoder, GzEncoder, ZlibEncoder};
+    use flate2::Compression as Level;
+    use std::io::Write;
+
+    const TEXT: &str = "{\"ch\":\"ticker\",\"price\":\"67012.5\"}";
+
+    fn gzip(text: &str) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Level::default());
+        encoder.write_all(text.as_bytes()).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn plain_frames_pass_through() {
+        assert_eq!(decode_binary(Compression::Auto, TEXT.as_bytes()).unwrap(), TEXT);
+    }
+
+    #[test]
+    fn gzip_frames_are_inflated() {
+        assert_eq!(decode_binary(Compression::Auto, &gzip(TEXT)).unwrap(), TEXT);
+    }
+
+    #[test]
+    fn zlib_frames_are_inflated() {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Level::default());
+        encoder.write_all(TEXT.as_bytes()).unwrap();
+        let data = encoder.finish().unwrap();
+        assert_eq!(decode_binary(Compression::Auto, &data).unwrap(), TEXT);
+    }
+
+    #[test]
+    fn sync_flushed_deflate_is_inflated() {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Level::default());
+        encoder.write_all(TEXT.as_bytes()).unwrap();
+        encoder.flush().unwrap();
+        let data = encoder.get_ref().clone();
+        assert_eq!(decode_binary(Compression::Deflate, &data).unwrap(), TEXT);
+    }
+
+    #[test]
+    fn truncated_gzip_is_an_error() {
+        let data = gzip(TEXT);
+        let result = decode_binary(Compression::Auto, &data[..data.len() / 2]);
+        assert!(matches!(result, Err(DecodeError::Gzip(_))));
+    }
+
+    #[test]
+    fn invalid_utf8_is_an_error() {
+        let result = decode_binary(Compression::Plain, &[0xff, 0xfe]);
+        assert!(matches!(result, Err(DecodeError::Utf8(_))));
+    }
+}
@@ -0,0 +1,260 @@
+use super::{kline_closes, split_pair_name, track_request, Exchange, Market, KLINE_LIMIT};
+use crate::api::{now_millis, Ack, Bbo, Price, Ticker};
+use crate::i18n;
+use crate::pairs::TradePairInfo;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+// ticker fields are arrays of strings, e.g. "c": ["price", "lot volume"] and
+// "h": ["today", "last 24 hours"]
+#[derive(Debug, Deserialize)]
+struct KrakenTicker {
+    c: Vec<String>,
+    // ["price", "whole lot volume", "lot volume"]
+    #[serde(default)]
+    a: Vec<String>,
+    #[serde(default)]
+    b: Vec<String>,
+    #[serde(default)]
+    o: Option<Value>,
+    #[serde(default)]
+    h: Vec<String>,
+    #[serde(default)]
+    l: Vec<String>,
+    #[serde(default)]
+    v: Vec<String>,
+    // volume weighted average price, ["today", "last 24 hours"]
+    #[serde(default)]
+    p: Vec<String>,
+}
+
+// {"event":"subscriptionStatus","status":"error","errorMessage":"..."}
+#[derive(Debug, Deserialize)]
+struct SubscriptionStatus {
+    event: String,
+    // echoed request id
+    #[serde(default)]
+    reqid: Option<u64>,
+    #[serde(default)]
+    status: String,
+    #[serde(rename = "errorMessage", default)]
+    error_message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RestResponse<T> {
+    result: HashMap<String, T>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AssetPair {
+    // e.g. XBT/USD, missing on dark pool pairs
+    wsname: Option<String>,
+    #[serde(default)]
+    status: Option<String>,
+    // price decimals
+    #[serde(default)]
+    pair_decimals: Option<u32>,
+}
+
+// kraken calls bitcoin XBT
+fn kraken_asset(asset: &str) -> &str {
+    match asset {
+        "BTC" => "XBT",
+        _ => asset,
+    }
+}
+
+fn common_asset(asset: &str) -> &str {
+    match asset {
+        "XBT" => "BTC",
+        _ => asset,
+    }
+}
+
+// XBT/USD to the registry pair name BTCUSD
+fn pair_name_of(wsname: &str) -> String {
+    wsname
+        .split('/')
+        .map(common_asset)
+        .collect::<Vec<_>>()
+        .concat()
+}
+
+fn field(values: &[String], index: usize) -> Option<f64> {
+    values.get(index)?.parse::<f64>().ok()
+}
+
+// [channel id, ticker, "ticker", "XBT/USD"], events like heartbeats are objects
+fn parse_ticker_payload(str_data: &str) -> Option<(String, KrakenTicker)> {
+    let payload = serde_json::from_str::<Vec<Value>>(str_data).ok()?;
+    if payload.len() != 4 || payload[2].as_str() != Some("ticker") {
+        return None;
+    }
+    let wsname = payload[3].as_str()?;
+    let ticker = serde_json::from_value::<KrakenTicker>(payload[1].clone()).ok()?;
+    Some((pair_name_of(wsname), ticker))
+}
+
+fn subscription_message(event: &str, trade_pair: &TradePairInfo) -> Message {
+    let wsname = &trade_pair.ws_name;
+    let reqid = track_request(trade_pair, format!("{} {}", event, wsname));
+    let message_str = format!(
+        r#"{{"event":"{}","reqid":{},"pair":["{}"],"subscription":{{"name":"ticker"}}}}"#,
+        event, reqid, wsname
+    );
+    Message::Text(message_str)
+}
+
+// kraken spot, public ws v1 ticker subscription
+pub struct KrakenSpot;
+
+impl Exchange for KrakenSpot {
+    fn id(&self) -> &'static str {
+        "kraken-spot"
+    }
+
+    fn show_name(&self) -> &'static str {
+        i18n::tr("Kraken 现货")
+    }
+
+    fn venue(&self) -> &'static str {
+        "kraken"
+    }
+
+    fn market(&self) -> Market {
+        Market::Spot
+    }
+
+    fn url(&self) -> &'static str {
+        "wss://ws.kraken.com"
+    }
+
+    fn subscribe_message(&self, trade_pair: &TradePairInfo) -> Message {
+        subscription_message("subscribe", trade_pair)
+    }
+
+    fn unsubscribe_message(&self, trade_pair: &TradePairInfo) -> Message {
+        subscription_message("unsubscribe", trade_pair)
+    }
+
+    fn parse_price(&self, str_data: &str) -> Option<Price> {
+        let (pair_name, ticker) = parse_ticker_payload(str_data)?;
+        Some(Price::new(now_millis(), pair_name, field(&ticker.c, 0)?))
+    }
+
+    // the ws open is ["today", "last 24 hours"]
+    fn parse_ticker(&self, str_data: &str) -> Option<Ticker> {
+        let (pair_name, ticker) = parse_ticker_payload(str_data)?;
+        let open_price = match ticker.o.as_ref()? {
+            Value::Array(values) => values.get(1)?.as_str()?.parse::<f64>().ok()?,
+            _ => return None,
+        };
+        Some(Ticker {
+            name: pair_name,
+            open_price,
+            high_price: field(&ticker.h, 1)?,
+            low_price: field(&ticker.l, 1)?,
+            volume: field(&ticker.v, 1)?,
+            turnover: field(&ticker.v, 1)
+                .zip(field(&ticker.p, 1))
+                .map(|(volume, vwap)| volume * vwap),
+        })
+    }
+
+    // the ticker carries the best bid and ask
+    fn parse_bbo(&self, str_data: &str) -> Option<Bbo> {
+        let (pair_name, ticker) = parse_ticker_payload(str_data)?;
+        Some(Bbo {
+            name: pair_name,
+            bid_price: field(&ticker.b, 0)?,
+            ask_price: field(&ticker.a, 0)?,
+        })
+    }
+
+    fn rest_ticker_url(&self, trade_pair: &TradePairInfo) -> String {
+        format!(
+            "https://api.kraken.com/0/public/Ticker?pair={}",
+            trade_pair.ws_name.replace('/', "")
+        )
+    }
+
+    // the result is keyed by kraken's internal name, e.g. XXBTZUSD
+    fn parse_rest_price(&self, trade_pair: &TradePairInfo, str_data: &str) -> Option<Price> {
+        let response = serde_json::from_str::<RestResponse<KrakenTicker>>(str_data).ok()?;
+        let ticker = response.result.into_values().next()?;
+        Some(Price::new(
+            now_millis(),
+            trade_pair.pair_name.clone(),
+            field(&ticker.c, 0)?,
+        ))
+    }
+
+    fn klines_url(&self, trade_pair: &TradePairInfo) -> Option<String> {
+        Some(format!(
+            "https://api.kraken.com/0/public/OHLC?pair={}&interval=1",
+            trade_pair.ws_name.replace('/', "")
+        ))
+    }
+
+    // up to 720 rows, oldest first, next to a "last" cursor in the result
+    fn parse_klines(&self, str_data: &str) -> Option<Vec<f64>> {
+        let response = serde_json::from_str::<RestResponse<Value>>(str_data).ok()?;
+        let rows = response
+            .result
+            .into_iter()
+            .find(|(key, _)| key != "last")
+            .and_then(|(_, rows)| serde_json::from_value::<Vec<Vec<Value>>>(rows).ok())?;
+        let closes = kline_closes(&rows, 4);
+        Some(closes[closes.len().saturating_sub(KLINE_LIMIT)..].to_vec())
+    }
+
+    fn parse_ack(&self, str_data: &str) -> Option<Ack> {
+        let status = serde_json::from_str::<SubscriptionStatus>(str_data).ok()?;
+        if status.event != "subscriptionStatus" {
+            return None;
+        }
+        match status.status.as_str() {
+            "error" => Some(Ack::rejected(status.reqid, status.error_message)),
+            _ => Some(Ack::ok(status.reqid)),
+        }
+    }
+
+    fn symbols_url(&self) -> &'static str {
+        "https://api.kraken.com/0/public/AssetPairs"
+    }
+
+    fn parse_symbols(&self, str_data: &str) -> Option<Vec<TradePairInfo>> {
+        let response = serde_json::from_str::<RestResponse<AssetPair>>(str_data).ok()?;
+        let mut infos: Vec<TradePairInfo> = response
+            .result
+            .values()
+            .filter(|pair| pair.status.as_deref().is_none_or(|status| status == "online"))
+            .filter_map(|pair| Some((pair.wsname.as_ref()?, pair.pair_decimals)))
+            .filter(|(wsname, _)| wsname.ends_with("/USD") || wsname.ends_with("/USDT"))
+            .map(|(wsname, decimals)| TradePairInfo {
+                ws_name: wsname.clone(),
+                show_name: wsname
+                    .split('/')
+                    .map(common_asset)
+                    .collect::<Vec<_>>()
+                    .join("/"),
+                pair_name: pair_name_of(wsname),
+                decimals,
+            })
+            .collect();
+        infos.sort_by(|a, b| a.show_name.cmp(&b.show_name));
+        Some(infos)
+    }
+
+    // BTCUSD to XBT/USD
+    fn instrument_id(&self, trade_pair: &TradePairInfo) -> String {
+        let pair_name = trade_pair.pair_name.to_uppercase();
+        match split_pair_name(&pair_name) {
+            Some((base, quote)) => format!("{}/{}", kraken_asset(base), quote),
+            None => pair_name,
+        }
+    }
+}
@@ -0,0 +1,320 @@
+pub mod binance;
+pub mod bybit;
+pub mod coinbase;
+pub mod decode;
+pub mod kraken;
+pub mod okx;
+
+use crate::api::{Ack, Bbo, Depth, Funding, Price, Ticker};
+use crate::i18n;
+use crate::pairs::TradePairInfo;
+use decode::{Compression, DecodeError};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, RwLock};
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Market {
+    Spot,
+    Perp,
+}
+
+impl Market {
+    // shown next to the pair name
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Market::Spot => i18n::tr("现"),
+            Market::Perp => i18n::tr("永"),
+        }
+    }
+
+    pub fn other(&self) -> Market {
+        match self {
+            Market::Spot => Market::Perp,
+            Market::Perp => Market::Spot,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PriceType {
+    // last trade
+    Last,
+    Mark,
+    Index,
+}
+
+impl PriceType {
+    pub const ALL: [PriceType; 3] = [PriceType::Last, PriceType::Mark, PriceType::Index];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PriceType::Last => i18n::tr("最新价"),
+            PriceType::Mark => i18n::tr("标记价格"),
+            PriceType::Index => i18n::tr("指数价格"),
+        }
+    }
+}
+
+pub trait Exchange: Send + Sync {
+    // id used by the command line, e.g. "binance-futures"
+    fn id(&self) -> &'static str;
+    fn show_name(&self) -> &'static str;
+    // exchanges of the same venue are the spot and perp markets of one exchange
+    fn venue(&self) -> &'static str;
+    fn market(&self) -> Market;
+    fn url(&self) -> &'static str;
+    fn subscribe_message(&self, trade_pair: &TradePairInfo) -> Message;
+    fn unsubscribe_message(&self, trade_pair: &TradePairInfo) -> Message;
+    fn parse_price(&self, str_data: &str) -> Option<Price>;
+    // 24h statistics, used for the percent change row
+    fn parse_ticker(&self, str_data: &str) -> Option<Ticker>;
+    // rest endpoint used when the ws stream is unreachable
+    fn rest_ticker_url(&self, trade_pair: &TradePairInfo) -> String;
+    // trade_pair is the polled pair, for responses that do not name it
+    fn parse_rest_price(&self, trade_pair: &TradePairInfo, str_data: &str) -> Option<Price>;
+    // rest endpoint listing all tradable symbols
+    fn symbols_url(&self) -> &'static str;
+    fn parse_symbols(&self, str_data: &str) -> Option<Vec<TradePairInfo>>;
+    // reply to a subscribe or unsubscribe request, None for any other frame
+    fn parse_ack(&self, str_data: &str) -> Option<Ack>;
+
+    // sent once on each new connection, ahead of the pair subscriptions
+    fn connect_messages(&self) -> Vec<Message> {
+        Vec::new()
+    }
+
+    // every price of a message that lists several products, e.g. a snapshot
+    fn parse_prices(&self, str_data: &str) -> Vec<Price> {
+        self.parse_price(str_data).into_iter().collect()
+    }
+
+    fn parse_tickers(&self, str_data: &str) -> Vec<Ticker> {
+        self.parse_ticker(str_data).into_iter().collect()
+    }
+
+    // funding rate of perpetuals, from the price stream or a channel of its own
+    fn parse_funding(&self, _str_data: &str) -> Option<Funding> {
+        None
+    }
+
+    // None when the price stream already carries the best bid/offer
+    fn bbo_subscribe_message(&self, _trade_pair: &TradePairInfo) -> Option<Message> {
+        None
+    }
+
+    fn bbo_unsubscribe_message(&self, _trade_pair: &TradePairInfo) -> Option<Message> {
+        None
+    }
+
+    fn parse_bbo(&self, _str_data: &str) -> Option<Bbo> {
+        None
+    }
+
+    fn parse_bbos(&self, str_data: &str) -> Vec<Bbo> {
+        self.parse_bbo(str_data).into_iter().collect()
+    }
+
+    // shallow book of DEPTH_LEVELS, None when the exchange has no such channel
+    fn depth_subscribe_message(&self, _trade_pair: &TradePairInfo) -> Option<Message> {
+        None
+    }
+
+    fn depth_unsubscribe_message(&self, _trade_pair: &TradePairInfo) -> Option<Message> {
+        None
+    }
+
+    fn parse_depth(&self, _str_data: &str) -> Option<Depth> {
+        None
+    }
+
+    // last hour of 1 minute klines, None when the exchange has no such endpoint
+    fn klines_url(&self, _trade_pair: &TradePairInfo) -> Option<String> {
+        None
+    }
+
+    // closes of the klines, oldest first
+    fn parse_klines(&self, _str_data: &str) -> Option<Vec<f64>> {
+        None
+    }
+
+    // channel name of a pair the symbol list did not cover, e.g. a configured one
+    fn instrument_id(&self, trade_pair: &TradePairInfo) -> String {
+        trade_pair.ws_name.clone()
+    }
+
+    // sent every PING_INTERVAL and on read timeouts by exchanges that need an
+    // application level ping, next to a ws ping
+    fn ping_message(&self) -> Option<Message> {
+        None
+    }
+
+    // price streams the exchange offers, the first is used unless another is selected
+    fn price_types(&self) -> &'static [PriceType] {
+        &[PriceType::Last]
+    }
+
+    // the selected price type when offered, the exchange's own otherwise
+    fn price_type(&self) -> PriceType {
+        let price_types = self.price_types();
+        match *PRICE_TYPE.read().unwrap() {
+            Some(price_type) if price_types.contains(&price_type) => price_type,
+            _ => price_types[0],
+        }
+    }
+
+    // how binary frames are compressed, the default recognises gzip and zlib
+    fn compression(&self) -> Compression {
+        Compression::Auto
+    }
+
+    // turn a ws frame into text, Ok(None) for frames without a payload to parse
+    fn decode_message(&self, message: Message) -> Result<Option<String>, DecodeError> {
+        match message {
+            Message::Text(str_data) => Ok(Some(str_data)),
+            Message::Binary(data) => decode::decode_binary(self.compression(), &data).map(Some),
+            _ => Ok(None),
+        }
+    }
+}
+
+lazy_static! {
+    pub static ref EXCHANGES: Vec<&'static dyn Exchange> =
+        vec![
+            &binance::BinanceFutures,
+            &binance::BinanceSpot,
+            &okx::OkxSwap,
+            &bybit::BybitLinear,
+            &coinbase::CoinbaseSpot,
+            &kraken::KrakenSpot,
+        ];
+}
+
+lazy_static! {
+    // None keeps each exchange on its first price type
+    static ref PRICE_TYPE: RwLock<Option<PriceType>> = RwLock::new(None);
+}
+
+// returns whether the selection changed, the streams need a reconnect then
+pub fn set_price_type(price_type: Option<PriceType>) -> bool {
+    let mut selected = PRICE_TYPE.write().unwrap();
+    let changed = *selected != price_type;
+    *selected = price_type;
+    changed
+}
+
+static NEXT_REQUEST_ID: AtomicU64 = AtomicU64::new(1);
+
+// requests of dropped connections are never answered, the oldest are forgotten
+const MAX_PENDING_REQUESTS: usize = 256;
+
+lazy_static! {
+    // subscribe and unsubscribe requests waiting for their ack, by id
+    static ref PENDING_REQUESTS: Mutex<BTreeMap<u64, Request>> = Mutex::new(BTreeMap::new());
+}
+
+pub struct Request {
+    // names the request in the logs
+    pub description: String,
+    // TradePairInfo::pair_name of the pair it was sent for
+    pub pair_name: String,
+}
+
+// a fresh id for a request of trade_pair
+pub(crate) fn track_request(trade_pair: &TradePairInfo, description: String) -> u64 {
+    let id = NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed);
+    let mut pending = PENDING_REQUESTS.lock().unwrap();
+    let request = Request {
+        description,
+        pair_name: trade_pair.pair_name.clone(),
+    };
+    pending.insert(id, request);
+    while pending.len() > MAX_PENDING_REQUESTS {
+        pending.pop_first();
+    }
+    id
+}
+
+// the acknowledged request, None for an unknown id
+pub fn settle_request(id: u64) -> Option<Request> {
+    PENDING_REQUESTS.lock().unwrap().remove(&id)
+}
+
+pub fn default_exchange() -> &'static dyn Exchange {
+    EXCHANGES[0]
+}
+
+pub fn find_exchange(id: &str) -> Option<&'static dyn Exchange> {
+    EXCHANGES.iter().find(|exchange| exchange.id() == id).copied()
+}
+
+pub fn same_exchange(a: &dyn Exchange, b: &dyn Exchange) -> bool {
+    a.id() == b.id()
+}
+
+// the given market of the venue of exchange, if it has one
+pub fn counterpart(exchange: &dyn Exchange, market: Market) -> Option<&'static dyn Exchange> {
+    EXCHANGES
+        .iter()
+        .find(|other| other.venue() == exchange.venue() && other.market() == market)
+        .copied()
+}
+
+// klines fetched for the sparkline, one per minute
+pub const KLINE_LIMIT: usize = 60;
+
+// closes of [[open time, open, high, low, close, ...]] rows, numbers or strings
+pub(crate) fn kline_closes(rows: &[Vec<serde_json::Value>], close_index: usize) -> Vec<f64> {
+    rows.iter()
+        .filter_map(|row| match row.get(close_index)? {
+            serde_json::Value::String(close) => close.parse::<f64>().ok(),
+            close => close.as_f64(),
+        })
+        .collect()
+}
+
+// levels per side of the detail popup book
+pub const DEPTH_LEVELS: usize = 5;
+
+// [["price", "size", ...]] book levels to (price, size), unparsable ones skipped
+pub(crate) fn book_levels(levels: &[Vec<String>]) -> Vec<(f64, f64)> {
+    levels
+        .iter()
+        .filter_map(|level| {
+            let price = level.first()?.parse::<f64>().ok()?;
+            let size = level.get(1)?.parse::<f64>().ok()?;
+            Some((price, size))
+        })
+        .take(DEPTH_LEVELS)
+        .collect()
+}
+
+// quote currencies recognised when splitting a pair name like BTCUSDT
+pub(crate) const QUOTES: [&str; 4] = ["USDT", "USDC", "USD", "EUR"];
+
+// BTCUSDT to ("BTC", "USDT"), for exchanges naming pairs with a separator
+pub(crate) fn split_pair_name(pair_name: &str) -> Option<(&str, &str)> {
+    let quote = QUOTES.iter().find(|quote| pair_name.ends_with(*quote))?;
+    Some(pair_name.split_at(pair_name.len() - quote.len()))
+}
+
+pub(crate) fn string_to_f64<'de, D>(deserializer: D) -> Result<f64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    s.parse::<f64>().map_err(serde::de::Error::custom)
+}
+
+pub(crate) fn string_to_u64<'de, D>(deserializer: D) -> Result<u64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    s.parse::<u64>().map_err(serde::de::Error::custom)
+}
@@ -0,0 +1,385 @@
+use super::{
+    book_levels, kline_closes, split_pair_name, string_to_f64, string_to_u64, track_request,
+    Exchange, Market, PriceType, KLINE_LIMIT,
+};
+use crate::api::{Ack, Bbo, Depth, Funding, Price, Ticker};
+use crate::format;
+use crate::i18n;
+use crate::pairs::TradePairInfo;
+use serde::Deserialize;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+#[derive(Debug, Deserialize)]
+struct OkxTicker {
+    #[serde(rename = "instId")]
+    inst_id: String,
+    #[serde(deserialize_with = "string_to_f64")]
+    last: f64,
+    #[serde(rename = "open24h", deserialize_with = "string_to_f64")]
+    open_price: f64,
+    #[serde(rename = "high24h", deserialize_with = "string_to_f64")]
+    high_price: f64,
+    #[serde(rename = "low24h", deserialize_with = "string_to_f64")]
+    low_price: f64,
+    // volume in the base currency, vol24h counts contracts on swaps
+    #[serde(rename = "volCcy24h", deserialize_with = "string_to_f64")]
+    volume: f64,
+    #[serde(deserialize_with = "string_to_u64")]
+    ts: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct OkxFunding {
+    #[serde(rename = "instId")]
+    inst_id: String,
+    #[serde(rename = "fundingRate", deserialize_with = "string_to_f64")]
+    funding_rate: f64,
+    // settlement time of the current rate
+    #[serde(rename = "fundingTime", deserialize_with = "string_to_u64")]
+    funding_time: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct OkxMarkPrice {
+    #[serde(rename = "instId")]
+    inst_id: String,
+    #[serde(rename = "markPx", deserialize_with = "string_to_f64")]
+    mark_price: f64,
+    #[serde(deserialize_with = "string_to_u64")]
+    ts: u64,
+}
+
+// the index of the underlying, instId is e.g. BTC-USDT
+#[derive(Debug, Deserialize)]
+struct OkxIndexTicker {
+    #[serde(rename = "instId")]
+    inst_id: String,
+    #[serde(rename = "idxPx", deserialize_with = "string_to_f64")]
+    index_price: f64,
+    #[serde(deserialize_with = "string_to_u64")]
+    ts: u64,
+}
+
+// levels are [price, size, deprecated, order count]
+#[derive(Debug, Deserialize)]
+struct OkxBook {
+    bids: Vec<Vec<String>>,
+    asks: Vec<Vec<String>>,
+}
+
+// {"event":"subscribe",...} or {"event":"error","code":"60012","msg":"..."}
+#[derive(Debug, Deserialize)]
+struct OkxEvent {
+    // echoed request id
+    #[serde(default)]
+    id: Option<String>,
+    event: String,
+    #[serde(default)]
+    code: String,
+    #[serde(default)]
+    msg: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct OkxArg {
+    channel: String,
+    #[serde(rename = "instId")]
+    inst_id: String,
+}
+
+// book pushes name the instrument only in arg
+#[derive(Debug, Deserialize)]
+struct OkxBookPush {
+    arg: OkxArg,
+    data: Vec<OkxBook>,
+}
+
+// ws pushes and rest responses both wrap the tickers in data
+#[derive(Debug, Deserialize)]
+struct OkxData<T> {
+    data: Vec<T>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OkxInstrument {
+    #[serde(rename = "instId")]
+    inst_id: String,
+    // underlying, e.g. BTC-USDT
+    uly: String,
+    #[serde(rename = "settleCcy")]
+    settle_ccy: String,
+    state: String,
+    #[serde(rename = "tickSz")]
+    tick_sz: String,
+}
+
+// BTC-USDT-SWAP to the registry pair name BTCUSDT
+fn pair_name_of(inst_id: &str) -> String {
+    inst_id.trim_end_matches("-SWAP").replace('-', "")
+}
+
+fn parse_ticker_data(str_data: &str) -> Option<OkxTicker> {
+    first_data::<OkxTicker>(str_data)
+}
+
+fn first_data<T: serde::de::DeserializeOwned>(str_data: &str) -> Option<T> {
+    serde_json::from_str::<OkxData<T>>(str_data)
+        .ok()?
+        .data
+        .into_iter()
+        .next()
+}
+
+// the price of the selected type from a ws push or a rest response
+fn parse_typed_price(price_type: PriceType, str_data: &str) -> Option<Price> {
+    match price_type {
+        PriceType::Last => parse_ticker_data(str_data).map(Price::from),
+        PriceType::Mark => first_data::<OkxMarkPrice>(str_data)
+            .map(|mark| Price::new(mark.ts, pair_name_of(&mark.inst_id), mark.mark_price)),
+        PriceType::Index => first_data::<OkxIndexTicker>(str_data)
+            .map(|index| Price::new(index.ts, pair_name_of(&index.inst_id), index.index_price)),
+    }
+}
+
+// BTC-USDT-SWAP to its underlying BTC-USDT
+fn index_id(inst_id: &str) -> &str {
+    inst_id.trim_end_matches("-SWAP")
+}
+
+impl From<OkxTicker> for Price {
+    fn from(ticker: OkxTicker) -> Self {
+        Price::new(ticker.ts, pair_name_of(&ticker.inst_id), ticker.last)
+    }
+}
+
+impl From<OkxTicker> for Ticker {
+    fn from(ticker: OkxTicker) -> Self {
+        Ticker {
+            name: pair_name_of(&ticker.inst_id),
+            open_price: ticker.open_price,
+            high_price: ticker.high_price,
+            low_price: ticker.low_price,
+            volume: ticker.volume,
+            turnover: None,
+        }
+    }
+}
+
+fn best_level(levels: &[Vec<String>]) -> Option<f64> {
+    levels.first()?.first()?.parse::<f64>().ok()
+}
+
+// bbo-tbt for the best bid/offer, books5 for the popup depth
+fn book_message(op: &str, channel: &str, trade_pair: &TradePairInfo) -> Message {
+    let inst_id = &trade_pair.ws_name;
+    let id = track_request(trade_pair, format!("{} {} {}", op, channel, inst_id));
+    let message_str = format!(
+        r#"{{"id":"{}","op":"{}","args":[{{"channel":"{}","instId":"{}"}}]}}"#,
+        id, op, channel, inst_id
+    );
+    Message::Text(message_str)
+}
+
+fn parse_book_push(str_data: &str, channel: &str) -> Option<(String, OkxBook)> {
+    let push = serde_json::from_str::<OkxBookPush>(str_data).ok()?;
+    if push.arg.channel != channel {
+        return None;
+    }
+    let book = push.data.into_iter().next()?;
+    Some((pair_name_of(&push.arg.inst_id), book))
+}
+
+// the funding rate, mark and index prices have channels of their own
+fn channel_message(op: &str, trade_pair: &TradePairInfo, price_type: PriceType) -> Message {
+    let inst_id = &trade_pair.ws_name;
+    let mut args = vec![
+        format!(r#"{{"channel":"tickers","instId":"{}"}}"#, inst_id),
+        format!(r#"{{"channel":"funding-rate","instId":"{}"}}"#, inst_id),
+    ];
+    match price_type {
+        PriceType::Last => {}
+        PriceType::Mark => {
+            args.push(format!(r#"{{"channel":"mark-price","instId":"{}"}}"#, inst_id))
+        }
+        PriceType::Index => args.push(format!(
+            r#"{{"channel":"index-tickers","instId":"{}"}}"#,
+            index_id(inst_id)
+        )),
+    }
+    let id = track_request(trade_pair, format!("{} {}", op, inst_id));
+    Message::Text(format!(
+        r#"{{"id":"{}","op":"{}","args":[{}]}}"#,
+        id,
+        op,
+        args.join(",")
+    ))
+}
+
+// okx usdt-margined perpetual swaps, public tickers channel
+pub struct OkxSwap;
+
+impl Exchange for OkxSwap {
+    fn id(&self) -> &'static str {
+        "okx-swap"
+    }
+
+    fn show_name(&self) -> &'static str {
+        i18n::tr("OKX 永续")
+    }
+
+    fn venue(&self) -> &'static str {
+        "okx"
+    }
+
+    fn market(&self) -> Market {
+        Market::Perp
+    }
+
+    fn url(&self) -> &'static str {
+        "wss://ws.okx.com:8443/ws/v5/public"
+    }
+
+    fn subscribe_message(&self, trade_pair: &TradePairInfo) -> Message {
+        channel_message("subscribe", trade_pair, self.price_type())
+    }
+
+    fn unsubscribe_message(&self, trade_pair: &TradePairInfo) -> Message {
+        channel_message("unsubscribe", trade_pair, self.price_type())
+    }
+
+    fn parse_price(&self, str_data: &str) -> Option<Price> {
+        parse_typed_price(self.price_type(), str_data)
+    }
+
+    fn price_types(&self) -> &'static [PriceType] {
+        &[PriceType::Last, PriceType::Mark, PriceType::Index]
+    }
+
+    fn parse_ticker(&self, str_data: &str) -> Option<Ticker> {
+        parse_ticker_data(str_data).map(Ticker::from)
+    }
+
+    fn parse_funding(&self, str_data: &str) -> Option<Funding> {
+        let funding = first_data::<OkxFunding>(str_data)?;
+        Some(Funding {
+            name: pair_name_of(&funding.inst_id),
+            rate: funding.funding_rate,
+            next_time: funding.funding_time,
+        })
+    }
+
+    fn bbo_subscribe_message(&self, trade_pair: &TradePairInfo) -> Option<Message> {
+        Some(book_message("subscribe", "bbo-tbt", trade_pair))
+    }
+
+    fn bbo_unsubscribe_message(&self, trade_pair: &TradePairInfo) -> Option<Message> {
+        Some(book_message("unsubscribe", "bbo-tbt", trade_pair))
+    }
+
+    fn parse_bbo(&self, str_data: &str) -> Option<Bbo> {
+        let (name, book) = parse_book_push(str_data, "bbo-tbt")?;
+        Some(Bbo {
+            name,
+            bid_price: best_level(&book.bids)?,
+            ask_price: best_level(&book.asks)?,
+        })
+    }
+
+    fn depth_subscribe_message(&self, trade_pair: &TradePairInfo) -> Option<Message> {
+        Some(book_message("subscribe", "books5", trade_pair))
+    }
+
+    fn depth_unsubscribe_message(&self, trade_pair: &TradePairInfo) -> Option<Message> {
+        Some(book_message("unsubscribe", "books5", trade_pair))
+    }
+
+    fn parse_depth(&self, str_data: &str) -> Option<Depth> {
+        let (name, book) = parse_book_push(str_data, "books5")?;
+        Some(Depth {
+            name,
+            bids: book_levels(&book.bids),
+            asks: book_levels(&book.asks),
+        })
+    }
+
+    fn rest_ticker_url(&self, trade_pair: &TradePairInfo) -> String {
+        match self.price_type() {
+            PriceType::Last => format!(
+                "https://www.okx.com/api/v5/market/ticker?instId={}",
+                trade_pair.ws_name
+            ),
+            PriceType::Mark => format!(
+                "https://www.okx.com/api/v5/public/mark-price?instType=SWAP&instId={}",
+                trade_pair.ws_name
+            ),
+            PriceType::Index => format!(
+                "https://www.okx.com/api/v5/market/index-tickers?instId={}",
+                index_id(&trade_pair.ws_name)
+            ),
+        }
+    }
+
+    fn parse_rest_price(&self, _trade_pair: &TradePairInfo, str_data: &str) -> Option<Price> {
+        parse_typed_price(self.price_type(), str_data)
+    }
+
+    fn klines_url(&self, trade_pair: &TradePairInfo) -> Option<String> {
+        Some(format!(
+            "https://www.okx.com/api/v5/market/candles?instId={}&bar=1m&limit={}",
+            trade_pair.ws_name, KLINE_LIMIT
+        ))
+    }
+
+    // candles are newest first
+    fn parse_klines(&self, str_data: &str) -> Option<Vec<f64>> {
+        let candles = serde_json::from_str::<OkxData<Vec<serde_json::Value>>>(str_data).ok()?;
+        let mut closes = kline_closes(&candles.data, 4);
+        closes.reverse();
+        Some(closes)
+    }
+
+    fn parse_ack(&self, str_data: &str) -> Option<Ack> {
+        let event = serde_json::from_str::<OkxEvent>(str_data).ok()?;
+        let id = event.id.as_deref().and_then(|id| id.parse::<u64>().ok());
+        match event.event.as_str() {
+            "subscribe" | "unsubscribe" => Some(Ack::ok(id)),
+            "error" => Some(Ack::rejected(id, format!("{} ({})", event.msg, event.code))),
+            _ => None,
+        }
+    }
+
+    fn symbols_url(&self) -> &'static str {
+        "https://www.okx.com/api/v5/public/instruments?instType=SWAP"
+    }
+
+    fn parse_symbols(&self, str_data: &str) -> Option<Vec<TradePairInfo>> {
+        let instruments = serde_json::from_str::<OkxData<OkxInstrument>>(str_data).ok()?;
+        let mut infos: Vec<TradePairInfo> = instruments
+            .data
+            .iter()
+            .filter(|instrument| instrument.state == "live" && instrument.settle_ccy == "USDT")
+            .map(|instrument| TradePairInfo {
+                ws_name: instrument.inst_id.clone(),
+                show_name: instrument.uly.replace('-', "/"),
+                pair_name: pair_name_of(&instrument.inst_id),
+                decimals: format::tick_decimals(&instrument.tick_sz),
+            })
+            .collect();
+        infos.sort_by(|a, b| a.show_name.cmp(&b.show_name));
+        Some(infos)
+    }
+
+    // BTCUSDT to BTC-USDT-SWAP
+    fn instrument_id(&self, trade_pair: &TradePairInfo) -> String {
+        let pair_name = trade_pair.pair_name.to_uppercase();
+        match split_pair_name(&pair_name) {
+            Some((base, quote)) => format!("{}-{}-SWAP", base, quote),
+            None => pair_name,
+        }
+    }
+
+    // okx closes the connection after 30s without a "ping" text
+    fn ping_message(&self) -> Option<Message> {
+        Some(Message::Text("ping".to_string()))
+    }
+}
@@ -0,0 +1,117 @@
+// usd quoted prices converted into a fiat currency chosen in the config
+
+use crate::format;
+use crate::tls;
+use anyhow::Result;
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+pub const FX_URL: &str = "https://open.er-api.com/v6/latest/USD";
+pub const FX_REFRESH: Duration = Duration::from_secs(30 * 60);
+// how often the refresh task checks for a new currency or an old rate
+pub const FX_CHECK: Duration = Duration::from_secs(60);
+// usdt and usdc are taken at par with usd
+const USD_QUOTES: [&str; 3] = ["USDT", "USDC", "USD"];
+
+#[derive(Debug, Deserialize)]
+struct FxResponse {
+    result: String,
+    rates: HashMap<String, f64>,
+}
+
+#[derive(Debug, Default)]
+struct FxState {
+    // e.g. CNY, none shows the exchange price as is
+    currency: Option<String>,
+    // usd to currency, cleared when the currency changes
+    rate: Option<f64>,
+    fetched: Option<Instant>,
+}
+
+lazy_static! {
+    static ref FX: RwLock<FxState> = RwLock::new(FxState::default());
+}
+
+pub fn set_currency(currency: Option<String>) {
+    let currency = currency
+        .map(|currency| currency.trim().to_uppercase())
+        .filter(|currency| !currency.is_empty() && currency != "USD");
+    let mut fx = FX.write().unwrap();
+    if fx.currency != currency {
+        fx.currency = currency;
+        fx.rate = None;
+        fx.fetched = None;
+    }
+}
+
+// a currency is chosen and its rate is missing or older than FX_REFRESH
+pub fn due() -> bool {
+    let fx = FX.read().unwrap();
+    fx.currency.is_some() && fx.fetched.is_none_or(|fetched| fetched.elapsed() >= FX_REFRESH)
+}
+
+// currency and its rate once fetched
+pub fn rate() -> Option<(String, f64)> {
+    let fx = FX.read().unwrap();
+    Some((fx.currency.clone()?, fx.rate?))
+}
+
+fn symbol(currency: &str) -> String {
+    match currency {
+        "EUR" => "€".to_string(),
+        "CNY" | "JPY" => "¥".to_string(),
+        "GBP" => "£".to_string(),
+        "KRW" => "₩".to_string(),
+        _ => format!("{} ", currency),
+    }
+}
+
+// value of a usd quoted pair in the chosen currency, with its symbol
+pub fn convert(pair_name: &str, value: f64) -> Option<(String, f64)> {
+    let pair_name = pair_name.to_uppercase();
+    if !USD_QUOTES.iter().any(|quote| pair_name.ends_with(quote)) {
+        return None;
+    }
+    let (currency, rate) = rate()?;
+    Some((symbol(&currency), value * rate))
+}
+
+// a usd amount like the portfolio value, in the chosen currency once its rate is known
+pub fn format_usd(value: f64) -> String {
+    match convert("USD", value) {
+        Some((symbol, converted)) => format!("{}{}", symbol, format::price(converted, Some(2))),
+        None => format::price(value, Some(2)),
+    }
+}
+
+// "1 USD = 7.1234 CNY" for the tooltip
+pub fn describe() -> Option<String> {
+    let (currency, rate) = rate()?;
+    Some(format!("1 USD = {:.4} {}", rate, currency))
+}
+
+pub async fn refresh(client: &reqwest::Client) -> Result<()> {
+    let currency = match FX.read().unwrap().currency.clone() {
+        Some(currency) => currency,
+        None => return Ok(()),
+    };
+    let str_data = tls::send(client.get(FX_URL)).await?.text().await?;
+    let response = serde_json::from_str::<FxResponse>(&str_data)?;
+    if response.result != "success" {
+        anyhow::bail!("fx rates: {}", response.result);
+    }
+    let rate = *response
+        .rates
+        .get(&currency)
+        .ok_or_else(|| anyhow::anyhow!("no fx rate for {}", currency))?;
+    let mut fx = FX.write().unwrap();
+    // the currency may have changed while fetching
+    if fx.currency.as_deref() == Some(currency.as_str()) {
+        fx.rate = Some(rate);
+        fx.fetched = Some(Instant::now());
+    }
+    Ok(())
+}
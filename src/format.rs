@@ -0,0 +1,204 @@
+// human-readable numbers for the widget and the detail popup
+
+use lazy_static::lazy_static;
+use std::sync::RwLock;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Separators {
+    pub decimal: char,
+    // thousands grouping, none keeps the digits together
+    pub group: Option<char>,
+}
+
+impl Separators {
+    pub const PLAIN: Separators = Separators {
+        decimal: '.',
+        group: None,
+    };
+}
+
+lazy_static! {
+    // from the config, plain until it is loaded
+    static ref SEPARATORS: RwLock<Separators> = RwLock::new(Separators::PLAIN);
+}
+
+pub fn set_separators(separators: Separators) {
+    *SEPARATORS.write().unwrap() = separators;
+}
+
+// "-64123.50" to "-64,123.50", a sign before and a unit after the number are kept
+pub fn localize_with(text: &str, separators: Separators) -> String {
+    let start = match text.find(|c: char| c.is_ascii_digit()) {
+        Some(start) => start,
+        None => return text.to_string(),
+    };
+    let end = text[start..]
+        .find(|c: char| !c.is_ascii_digit())
+        .map_or(text.len(), |end| start + end);
+    let digits = &text[start..end];
+    let mut localized = text[..start].to_string();
+    for (index, digit) in digits.chars().enumerate() {
+        if index > 0 && (digits.len() - index).is_multiple_of(3) {
+            localized.extend(separators.group);
+        }
+        localized.push(digit);
+    }
+    match text[end..].strip_prefix('.') {
+        Some(rest) => {
+            localized.push(separators.decimal);
+            localized.push_str(rest);
+        }
+        None => localized.push_str(&text[end..]),
+    }
+    localized
+}
+
+// text of a formatted number in the configured separators
+pub fn localize(text: &str) -> String {
+    localize_with(text, *SEPARATORS.read().unwrap())
+}
+
+const UNITS: [(f64, &str); 4] = [(1e12, "T"), (1e9, "B"), (1e6, "M"), (1e3, "K")];
+
+// 1234567 to 1.23M, values below a thousand keep two decimals
+pub fn abbreviate(value: f64) -> String {
+    let magnitude = value.abs();
+    for (unit, suffix) in UNITS {
+        // 999.995K would round up to 1000.00K, the larger unit reads better
+        if magnitude >= unit * 0.999995 {
+            return localize(&format!("{:.2}{}", value / unit, suffix));
+        }
+    }
+    localize(&format!("{:.2}", value))
+}
+
+// decimals of a tick size string, "0.0100" to 2, "1" to 0
+pub fn tick_decimals(tick_size: &str) -> Option<u32> {
+    let tick = tick_size.trim().parse::<f64>().ok().filter(|tick| *tick > 0.)?;
+    match tick_size.trim().split_once('.') {
+        Some((_, fraction)) => Some(fraction.trim_end_matches('0').len() as u32),
+        // exponent form, e.g. 1e-5
+        None if tick < 1. => Some((-tick.log10()).round() as u32),
+        None => Some(0),
+    }
+}
+
+// about five significant digits when the exchange did not tell the tick size
+fn auto_decimals(value: f64) -> u32 {
+    let magnitude = value.abs();
+    if magnitude >= 1000. || magnitude == 0. {
+        return 1;
+    }
+    let digits = magnitude.log10().floor() as i32;
+    (4 - digits).clamp(1, 8) as u32
+}
+
+// price of a pair, decimals from the exchange tick size if known
+pub fn price(value: f64, decimals: Option<u32>) -> String {
+    let decimals = decimals.unwrap_or_else(|| auto_decimals(value)) as usize;
+    localize(&format!("{:.*}", decimals, value))
+}
+
+// ms since the epoch to "2024-01-31T08:05:09Z"
+pub fn utc_time(time_stamp: u64) -> String {
+    let secs = time_stamp / 1000;
+    let days = (secs / 86400) as i64;
+    let (hour, minute, second) = (secs % 86400 / 3600, secs % 3600 / 60, secs % 60);
+    // days to the civil date, after howard hinnant's days_from_civil inverse
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year, month, day, hour, minute, second
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{abbreviate, localize_with, price, tick_decimals, utc_time, Separators};
+
+    #[test]
+    fn small_values_are_not_abbreviated() {
+        assert_eq!(abbreviate(0.), "0.00");
+        assert_eq!(abbreviate(950.5), "950.50");
+    }
+
+    #[test]
+    fn picks_the_largest_unit() {
+        assert_eq!(abbreviate(1_500.), "1.50K");
+        assert_eq!(abbreviate(2_340_000.), "2.34M");
+        assert_eq!(abbreviate(1_200_000_000.), "1.20B");
+        assert_eq!(abbreviate(3.1e12), "3.10T");
+    }
+
+    #[test]
+    fn rounds_up_into_the_next_unit() {
+        assert_eq!(abbreviate(999_999.), "1.00M");
+    }
+
+    #[test]
+    fn keeps_the_sign() {
+        assert_eq!(abbreviate(-45_600.), "-45.60K");
+    }
+
+    #[test]
+    fn tick_size_decimals() {
+        assert_eq!(tick_decimals("0.10"), Some(1));
+        assert_eq!(tick_decimals("0.00001000"), Some(5));
+        assert_eq!(tick_decimals("1"), Some(0));
+        assert_eq!(tick_decimals("1e-5"), Some(5));
+        assert_eq!(tick_decimals("0"), None);
+        assert_eq!(tick_decimals("abc"), None);
+    }
+
+    #[test]
+    fn price_uses_known_decimals() {
+        assert_eq!(price(64_123.5, Some(1)), "64123.5");
+        assert_eq!(price(0.1234, Some(5)), "0.12340");
+        assert_eq!(price(64_123.45, Some(0)), "64123");
+    }
+
+    #[test]
+    fn price_guesses_decimals_by_magnitude() {
+        assert_eq!(price(64_123.75, None), "64123.8");
+        assert_eq!(price(145.678, None), "145.68");
+        assert_eq!(price(0.0000123, None), "0.00001230");
+    }
+
+    #[test]
+    fn groups_thousands() {
+        let comma = Separators {
+            decimal: '.',
+            group: Some(','),
+        };
+        assert_eq!(localize_with("100235.5", comma), "100,235.5");
+        assert_eq!(localize_with("-1234567", comma), "-1,234,567");
+        assert_eq!(localize_with("999.99", comma), "999.99");
+        assert_eq!(localize_with("1.50K", comma), "1.50K");
+    }
+
+    #[test]
+    fn swaps_the_decimal_separator() {
+        let dot = Separators {
+            decimal: ',',
+            group: Some('.'),
+        };
+        assert_eq!(localize_with("100235.5", dot), "100.235,5");
+        assert_eq!(localize_with("+2.35%", dot), "+2,35%");
+        assert_eq!(localize_with("-", dot), "-");
+    }
+
+    #[test]
+    fn utc_time_of_epoch_ms() {
+        assert_eq!(utc_time(0), "1970-01-01T00:00:00Z");
+        assert_eq!(utc_time(951_782_400_000), "2000-02-29T00:00:00Z");
+        assert_eq!(utc_time(1_706_688_309_999), "2024-01-31T08:05:09Z");
+    }
+}
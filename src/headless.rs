@@ -0,0 +1,151 @@
+// --headless: no widget, the api messages are printed to the console the
+// program was started from, to debug the connection and the proxy without the gui
+
+use crate::api::{self, ApiMessage};
+use crate::i18n;
+use crate::my_window::Window;
+use crate::pairs;
+use anyhow::Result;
+use core::ffi::c_void;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use windows::Win32::System::Console::{
+    AllocConsole, AttachConsole, SetConsoleCtrlHandler, ATTACH_PARENT_PROCESS,
+};
+use windows::{
+    core::*, Win32::Foundation::*, Win32::System::LibraryLoader::GetModuleHandleW,
+    Win32::UI::WindowsAndMessaging::*,
+};
+
+// for the ctrl+c handler, which runs on a thread of its own
+static CONSOLE_HWND: AtomicUsize = AtomicUsize::new(0);
+
+// main attaches before parsing the arguments, the modes call it again
+static ATTACHED: AtomicBool = AtomicBool::new(false);
+
+// the windows subsystem starts without a console, a double click gets a new one
+pub fn attach() -> Result<()> {
+    if ATTACHED.swap(true, Ordering::SeqCst) {
+        return Ok(());
+    }
+    unsafe {
+        if AttachConsole(ATTACH_PARENT_PROCESS).is_err() {
+            AllocConsole()?;
+        }
+    }
+    Ok(())
+}
+
+pub struct Console {
+    pub hwnd: usize,
+}
+
+impl Console {
+    const CLASS_NAME: PCWSTR = w!("mjj_console");
+
+    pub fn new() -> Result<Console> {
+        attach()?;
+        unsafe {
+            let instance = GetModuleHandleW(None)?;
+            let wc = WNDCLASSW {
+                hInstance: instance.into(),
+                lpszClassName: Self::CLASS_NAME,
+                lpfnWndProc: Some(Self::wndproc),
+                ..Default::default()
+            };
+            RegisterClassW(&wc);
+            // message-only, it just receives what the api posts
+            let hwnd = CreateWindowExW(
+                WINDOW_EX_STYLE::default(),
+                Self::CLASS_NAME,
+                Self::CLASS_NAME,
+                WINDOW_STYLE::default(),
+                0,
+                0,
+                0,
+                0,
+                HWND_MESSAGE,
+                None,
+                wc.hInstance,
+                None,
+            )?;
+            CONSOLE_HWND.store(hwnd.0 as usize, Ordering::Relaxed);
+            SetConsoleCtrlHandler(Some(Self::ctrl_handler), true)?;
+            Ok(Console {
+                hwnd: hwnd.0 as usize,
+            })
+        }
+    }
+
+    // until ctrl+c or the console closes
+    pub fn run(&self) {
+        println!("headless, ctrl+c to quit");
+        unsafe {
+            let mut message = MSG::default();
+            while GetMessageW(&mut message, None, 0, 0).into() {
+                DispatchMessageW(&message);
+            }
+        }
+    }
+
+    fn line(message: &ApiMessage) -> Option<String> {
+        match message {
+            ApiMessage::Price(price) => Some(format!(
+                "{} {} {}{}",
+                price.time_stamp,
+                price.name,
+                pairs::format_price(&price.name, price.tag_price),
+                if price.polled { format!(" ({})", i18n::tr("轮询")) } else { String::new() }
+            )),
+            ApiMessage::Ticker(ticker) => Some(format!(
+                "ticker {} open {} high {} low {} volume {}",
+                ticker.name, ticker.open_price, ticker.high_price, ticker.low_price, ticker.volume
+            )),
+            ApiMessage::Funding(funding) => {
+                Some(format!("funding {} {:.4}%", funding.name, funding.rate * 100.))
+            }
+            ApiMessage::Bbo(bbo) => Some(format!(
+                "bbo {} {} / {}",
+                bbo.name, bbo.bid_price, bbo.ask_price
+            )),
+            ApiMessage::Alert(text) => Some(format!("alert {}", text)),
+            ApiMessage::Notify(text) => Some(format!("notify {}", text)),
+            ApiMessage::Status(status) => Some(format!("status {}", status.label())),
+            ApiMessage::Latency(latency) => Some(format!("latency {}ms", latency)),
+            ApiMessage::Proxy(health) => Some(format!("proxy {}", health.label())),
+            ApiMessage::Update(tag_name) => Some(format!("update {}", tag_name)),
+            ApiMessage::Depth(_) | ApiMessage::Logo(_) => None,
+        }
+    }
+
+    extern "system" fn ctrl_handler(_ctrl_type: u32) -> BOOL {
+        let hwnd = CONSOLE_HWND.load(Ordering::Relaxed);
+        unsafe {
+            let _ = PostMessageW(HWND(hwnd as *mut c_void), WM_CLOSE, WPARAM(0), LPARAM(0));
+        }
+        TRUE
+    }
+
+    extern "system" fn wndproc(
+        hwnd: HWND,
+        message: u32,
+        wparam: WPARAM,
+        lparam: LPARAM,
+    ) -> LRESULT {
+        unsafe {
+            match message {
+                Window::WM_FRESH => {
+                    let api_msg = Box::from_raw(wparam.0 as *mut api::ApiMessage);
+                    if let Some(line) = Self::line(&api_msg) {
+                        println!("{}", line);
+                    }
+                    LRESULT(0)
+                }
+                WM_DESTROY => {
+                    PostQuitMessage(0);
+                    LRESULT(0)
+                }
+                _ => DefWindowProcW(hwnd, message, wparam, lparam),
+            }
+        }
+    }
+}
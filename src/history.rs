@@ -0,0 +1,132 @@
+// ticks kept in memory for "导出历史", at most one per pair every
+// Config::history_seconds and none older than KEEP_MS
+
+use crate::api::Price;
+use anyhow::Result;
+use lazy_static::lazy_static;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+const KEEP_MS: u64 = 24 * 3600 * 1000;
+
+// 0 records nothing
+static INTERVAL_MS: AtomicU64 = AtomicU64::new(5000);
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Tick {
+    // ms, from the exchange
+    pub time_stamp: u64,
+    pub pair: String,
+    pub price: f64,
+}
+
+#[derive(Default)]
+struct History {
+    ticks: VecDeque<Tick>,
+    // time of the last recorded tick, by pair name
+    last: HashMap<String, u64>,
+}
+
+impl History {
+    fn push(&mut self, tick: Tick, interval_ms: u64) -> bool {
+        if let Some(last) = self.last.get(&tick.pair) {
+            if tick.time_stamp < last + interval_ms {
+                return false;
+            }
+        }
+        let oldest = tick.time_stamp.saturating_sub(KEEP_MS);
+        while self.ticks.front().is_some_and(|front| front.time_stamp < oldest) {
+            self.ticks.pop_front();
+        }
+        self.last.insert(tick.pair.clone(), tick.time_stamp);
+        self.ticks.push_back(tick);
+        true
+    }
+
+    fn since(&self, from_ms: u64) -> Vec<Tick> {
+        self.ticks
+            .iter()
+            .filter(|tick| tick.time_stamp >= from_ms)
+            .cloned()
+            .collect()
+    }
+}
+
+lazy_static! {
+    static ref HISTORY: Mutex<History> = Mutex::new(History::default());
+}
+
+pub fn set_interval(seconds: u64) {
+    INTERVAL_MS.store(seconds * 1000, Ordering::Relaxed);
+}
+
+pub fn record(price: &Price) {
+    let interval_ms = INTERVAL_MS.load(Ordering::Relaxed);
+    if interval_ms == 0 {
+        return;
+    }
+    let tick = Tick {
+        time_stamp: price.time_stamp,
+        pair: price.name.clone(),
+        price: price.tag_price,
+    };
+    HISTORY.lock().unwrap().push(tick, interval_ms);
+}
+
+// oldest first
+pub fn since(from_ms: u64) -> Vec<Tick> {
+    HISTORY.lock().unwrap().since(from_ms)
+}
+
+pub fn to_csv(ticks: &[Tick]) -> String {
+    let mut csv = String::from("time_stamp,pair,price\n");
+    for tick in ticks {
+        let _ = writeln!(csv, "{},{},{}", tick.time_stamp, tick.pair, tick.price);
+    }
+    csv
+}
+
+pub fn to_json(ticks: &[Tick]) -> Result<String> {
+    Ok(serde_json::to_string_pretty(ticks)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tick(time_stamp: u64, pair: &str, price: f64) -> Tick {
+        Tick {
+            time_stamp,
+            pair: pair.to_string(),
+            price,
+        }
+    }
+
+    #[test]
+    fn push_throttles_each_pair() {
+        let mut history = History::default();
+        assert!(history.push(tick(1000, "BTCUSDT", 1.), 5000));
+        assert!(!history.push(tick(3000, "BTCUSDT", 2.), 5000));
+        assert!(history.push(tick(3000, "ETHUSDT", 3.), 5000));
+        assert!(history.push(tick(6000, "BTCUSDT", 4.), 5000));
+        assert_eq!(history.since(0).len(), 3);
+        assert_eq!(history.since(3000), vec![tick(3000, "ETHUSDT", 3.), tick(6000, "BTCUSDT", 4.)]);
+    }
+
+    #[test]
+    fn push_drops_ticks_older_than_keep() {
+        let mut history = History::default();
+        history.push(tick(1000, "BTCUSDT", 1.), 0);
+        history.push(tick(KEEP_MS + 2000, "BTCUSDT", 2.), 0);
+        assert_eq!(history.since(0), vec![tick(KEEP_MS + 2000, "BTCUSDT", 2.)]);
+    }
+
+    #[test]
+    fn csv_has_a_header_and_a_line_per_tick() {
+        let csv = to_csv(&[tick(1000, "BTCUSDT", 65000.5)]);
+        assert_eq!(csv, "time_stamp,pair,price\n1000,BTCUSDT,65000.5\n");
+    }
+}
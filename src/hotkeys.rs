@@ -0,0 +1,125 @@
+// global hotkeys from the config, e.g. {"ctrl+alt+1": "1", "ctrl+alt+e": "ETHUSDT",
+// "ctrl+alt+p": "pause"}. parsed here, registered by the widget.
+// none by default, ctrl+alt is altgr on many keyboard layouts
+
+use std::collections::BTreeMap;
+
+// values of RegisterHotKey's HOT_KEY_MODIFIERS
+const MOD_ALT: u32 = 0x1;
+const MOD_CONTROL: u32 = 0x2;
+const MOD_SHIFT: u32 = 0x4;
+const MOD_WIN: u32 = 0x8;
+// held keys don't repeat the action
+const MOD_NOREPEAT: u32 = 0x4000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    // pauses or resumes the carousel
+    Pause,
+    // 1 for the first pair of the menu
+    Favorite(usize),
+    // anything pairs::find takes
+    Pair(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hotkey {
+    pub modifiers: u32,
+    pub virtual_key: u32,
+    pub action: Action,
+}
+
+// "ctrl+alt+1" to the modifiers and the virtual key, none for an unknown key
+pub fn parse_keys(keys: &str) -> Option<(u32, u32)> {
+    let mut modifiers = MOD_NOREPEAT;
+    let mut virtual_key = None;
+    for part in keys.split('+').map(|part| part.trim().to_uppercase()) {
+        match part.as_str() {
+            "CTRL" | "CONTROL" => modifiers |= MOD_CONTROL,
+            "ALT" => modifiers |= MOD_ALT,
+            "SHIFT" => modifiers |= MOD_SHIFT,
+            "WIN" => modifiers |= MOD_WIN,
+            key if virtual_key.is_none() => virtual_key = Some(parse_key(key)?),
+            _ => return None,
+        }
+    }
+    // a bare key would be taken from every other program
+    if modifiers == MOD_NOREPEAT {
+        return None;
+    }
+    Some((modifiers, virtual_key?))
+}
+
+// letters and digits are their ascii code, F1 is 0x70
+fn parse_key(key: &str) -> Option<u32> {
+    let mut chars = key.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_uppercase() || c.is_ascii_digit() => Some(c as u32),
+        _ => {
+            let number = key.strip_prefix('F')?.parse::<u32>().ok()?;
+            (1..=24).contains(&number).then(|| 0x70 + number - 1)
+        }
+    }
+}
+
+pub fn parse_action(action: &str) -> Action {
+    let action = action.trim();
+    if action.eq_ignore_ascii_case("pause") {
+        return Action::Pause;
+    }
+    match action.parse::<usize>() {
+        Ok(index) if index > 0 => Action::Favorite(index),
+        _ => Action::Pair(action.to_string()),
+    }
+}
+
+// the entries that parse, in key order
+pub fn parse(hotkeys: &BTreeMap<String, String>) -> Vec<Hotkey> {
+    hotkeys
+        .iter()
+        .filter_map(|(keys, action)| {
+            let (modifiers, virtual_key) = parse_keys(keys)?;
+            Some(Hotkey {
+                modifiers,
+                virtual_key,
+                action: parse_action(action),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_modifiers_and_keys() {
+        assert_eq!(parse_keys("ctrl+alt+1"), Some((MOD_NOREPEAT | MOD_CONTROL | MOD_ALT, '1' as u32)));
+        assert_eq!(parse_keys("Shift + Win + p"), Some((MOD_NOREPEAT | MOD_SHIFT | MOD_WIN, 'P' as u32)));
+        assert_eq!(parse_keys("ctrl+F12"), Some((MOD_NOREPEAT | MOD_CONTROL, 0x7B)));
+    }
+
+    #[test]
+    fn rejects_bare_unknown_and_doubled_keys() {
+        assert_eq!(parse_keys("p"), None);
+        assert_eq!(parse_keys("ctrl+enter"), None);
+        assert_eq!(parse_keys("ctrl+a+b"), None);
+        assert_eq!(parse_keys("ctrl+F25"), None);
+    }
+
+    #[test]
+    fn parses_actions() {
+        assert_eq!(parse_action("Pause"), Action::Pause);
+        assert_eq!(parse_action("2"), Action::Favorite(2));
+        assert_eq!(parse_action("eth"), Action::Pair("eth".to_string()));
+    }
+
+    #[test]
+    fn skips_unparsable_entries() {
+        let hotkeys: BTreeMap<String, String> = [("ctrl+alt+1", "1"), ("ctrl+alt+p", "pause"), ("p", "pause")]
+            .iter()
+            .map(|(keys, action)| (keys.to_string(), action.to_string()))
+            .collect();
+        assert_eq!(parse(&hotkeys).len(), 2);
+    }
+}
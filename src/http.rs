@@ -0,0 +1,153 @@
+// --serve: the prices and the connection state the widget shows, as json for
+// local tools like rainmeter skins or scripts, and /metrics for prometheus
+
+use crate::api::{ApiMessage, ProxyHealth, Status};
+use crate::metrics::{self, PairSample};
+use crate::pairs;
+use axum::{routing::get, Json, Router};
+use lazy_static::lazy_static;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::RwLock;
+use std::time::Instant;
+use tokio::net::TcpListener;
+use tracing::{info, warn};
+
+// nothing is recorded until the server is up
+static SERVING: AtomicBool = AtomicBool::new(false);
+
+struct Quote {
+    price: f64,
+    time_stamp: u64,
+    polled: bool,
+    received: Instant,
+}
+
+#[derive(Default)]
+struct Feed {
+    // by pair name, sorted so the output is stable
+    quotes: BTreeMap<String, Quote>,
+    // 24h open of the last ticker, by pair name
+    opens: BTreeMap<String, f64>,
+    exchange: Option<&'static str>,
+    status: Option<Status>,
+    latency: Option<u64>,
+    proxy: Option<ProxyHealth>,
+}
+
+lazy_static! {
+    static ref FEED: RwLock<Feed> = RwLock::new(Feed::default());
+}
+
+// every message the api sends to the widget goes through here
+pub fn record(message: &ApiMessage) {
+    if !SERVING.load(Ordering::Relaxed) {
+        return;
+    }
+    let mut feed = FEED.write().unwrap();
+    match message {
+        ApiMessage::Price(price) => {
+            let quote = Quote {
+                price: price.tag_price,
+                time_stamp: price.time_stamp,
+                polled: price.polled,
+                received: Instant::now(),
+            };
+            feed.quotes.insert(price.name.clone(), quote);
+        }
+        ApiMessage::Ticker(ticker) => {
+            feed.opens.insert(ticker.name.clone(), ticker.open_price);
+        }
+        ApiMessage::Status(status) => feed.status = Some(*status),
+        ApiMessage::Latency(latency) => feed.latency = Some(*latency),
+        ApiMessage::Proxy(health) => feed.proxy = Some(*health),
+        _ => {}
+    }
+}
+
+// the cached prices belong to the previous exchange
+pub fn set_exchange(exchange_id: &'static str) {
+    let mut feed = FEED.write().unwrap();
+    if feed.exchange != Some(exchange_id) {
+        feed.quotes.clear();
+        feed.opens.clear();
+        feed.exchange = Some(exchange_id);
+    }
+}
+
+async fn prices() -> Json<Value> {
+    let feed = FEED.read().unwrap();
+    let prices: serde_json::Map<String, Value> = feed
+        .quotes
+        .iter()
+        .map(|(name, quote)| {
+            let change = feed
+                .opens
+                .get(name)
+                .filter(|open| **open != 0.)
+                .map(|open| (quote.price - open) / open * 100.);
+            let entry = json!({
+                "price": quote.price,
+                "text": pairs::format_price(name, quote.price),
+                "time_stamp": quote.time_stamp,
+                "polled": quote.polled,
+                "change_percent": change,
+            });
+            (name.clone(), entry)
+        })
+        .collect();
+    Json(Value::Object(prices))
+}
+
+async fn status() -> Json<Value> {
+    let feed = FEED.read().unwrap();
+    Json(json!({
+        "exchange": feed.exchange,
+        "status": feed.status.map(|status| format!("{:?}", status).to_lowercase()),
+        "label": feed.status.map(|status| status.label()),
+        "latency_ms": feed.latency,
+        "proxy": feed.proxy.map(|health| health.label()),
+    }))
+}
+
+async fn metrics() -> String {
+    let pairs: Vec<PairSample> = FEED
+        .read()
+        .unwrap()
+        .quotes
+        .iter()
+        .map(|(name, quote)| PairSample {
+            name: name.clone(),
+            price: quote.price,
+            age: quote.received.elapsed().as_secs_f64(),
+        })
+        .collect();
+    metrics::render(&pairs)
+}
+
+// on the api runtime until the process ends
+pub async fn serve(addr: SocketAddr) {
+    // no authentication, the holdings must not reach the network
+    if !addr.ip().is_loopback() {
+        warn!("serve {} refused, only loopback addresses are served", addr);
+        return;
+    }
+    let listener = match TcpListener::bind(addr).await {
+        Ok(listener) => listener,
+        Err(err) => {
+            warn!("serve {} err:{:?}", addr, err);
+            return;
+        }
+    };
+    SERVING.store(true, Ordering::Relaxed);
+    info!("serving prices on http://{}", addr);
+    let app = Router::new()
+        .route("/prices", get(prices))
+        .route("/status", get(status))
+        .route("/metrics", get(metrics));
+    if let Err(err) = axum::serve(listener, app).await {
+        warn!("serve err:{:?}", err);
+    }
+}
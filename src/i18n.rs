@@ -0,0 +1,211 @@
+// text shown to the user, in chinese or english. the chinese text is written
+// in place and is the key of its english one, see tr
+
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, Ordering};
+use windows::Win32::Globalization::GetUserDefaultUILanguage;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    // chinese on a chinese windows, english on any other
+    Auto,
+    Zh,
+    En,
+}
+
+// chinese until set_language
+static ENGLISH: AtomicBool = AtomicBool::new(false);
+
+// primary language id of a windows langid
+const LANG_CHINESE: u16 = 0x04;
+
+// "{}" are filled in order by fill, a translation keeps their order
+const ENGLISH_TEXT: [(&str, &str); 114] = [
+    // status and notices
+    ("启动...", "Starting..."),
+    ("切换中...", "Switching..."),
+    ("重连中...", "Reconnecting..."),
+    ("已连接", "Connected"),
+    ("重连中", "Reconnecting"),
+    ("行情停滞", "Stale"),
+    ("轮询", "Polling"),
+    ("直连", "Direct"),
+    ("不可达", "Unreachable"),
+    ("代理连接超时", "Proxy connect timeout"),
+    ("TLS 握手超时", "TLS handshake timeout"),
+    ("读取超时", "Read timeout"),
+    ("订阅确认超时", "Subscribe ack timeout"),
+    ("订阅失败: {}", "Subscribe failed: {}"),
+    ("日志不可用", "Logging unavailable"),
+    ("无法写入 {}", "Can not write to {}"),
+    ("证书不匹配", "Certificate mismatch"),
+    ("代理不可达", "Proxy unreachable"),
+    ("代理不可达, {}", "Proxy unreachable, {}"),
+    ("代理失败, 改为直连", "Proxy failed, going direct"),
+    ("直连失败, 改回代理", "Direct failed, back to the proxy"),
+    ("{}秒后重试", "Retry in {}s"),
+    ("已复制", "Copied"),
+    ("已导出{}条", "Exported {}"),
+    ("后台已停止", "Backend stopped"),
+    ("操作失败", "Failed"),
+    // toasts
+    ("价格提醒", "Price alert"),
+    ("{}，当前 {}", "{}, now {}"),
+    ("{} {} 分钟内变动 {}%", "{} in {} min moved {}%"),
+    ("上穿", "crossed above"),
+    ("下穿", "crossed below"),
+    ("已重新连接", "Reconnected"),
+    ("行情连接已恢复", "The price feed is back"),
+    ("连接已断开", "Disconnected"),
+    ("超过 1 分钟未能连接到交易所", "Could not reach the exchange for over a minute"),
+    ("有可用更新", "Update available"),
+    ("新版本 {}", "New version {}"),
+    ("{}, 可从菜单下载", "{}, download it from the menu"),
+    ("程序出错, 即将退出。\n崩溃报告已保存到:\n{}", "The program crashed and will exit.\nA crash report was saved to:\n{}"),
+    ("程序出错, 即将退出。\n{}", "The program crashed and will exit.\n{}"),
+    // context and tray menus
+    ("最近使用", "Recent"),
+    ("收藏当前交易对", "Favorite this pair"),
+    ("搜索交易对...", "Search pairs..."),
+    ("全部交易对", "All pairs"),
+    ("其他", "Other"),
+    ("复制价格", "Copy price"),
+    ("提醒静音", "Mute alerts"),
+    ("暂停轮播", "Pause carousel"),
+    ("切换到永续", "Switch to perpetual"),
+    ("切换到现货", "Switch to spot"),
+    ("价格类型", "Price type"),
+    ("导出历史", "Export history"),
+    ("最近1小时...", "Last hour..."),
+    ("最近6小时...", "Last 6 hours..."),
+    ("最近24小时...", "Last 24 hours..."),
+    ("宽度", "Width"),
+    ("主题", "Theme"),
+    ("第二行", "Second row"),
+    ("任务栏", "Taskbar"),
+    ("主显示器", "Main display"),
+    ("显示器{}", "Display {}"),
+    ("所有任务栏", "All taskbars"),
+    ("开机启动", "Start with Windows"),
+    ("下载新版本 {}...", "Download {}..."),
+    ("恢复默认位置", "Reset position"),
+    ("设置...", "Settings..."),
+    ("退出", "Exit"),
+    ("恢复窗口", "Restore window"),
+    ("跟随任务栏", "Follow taskbar"),
+    ("浅色", "Light"),
+    ("深色", "Dark"),
+    ("自定义", "Custom"),
+    ("交易对", "Pair"),
+    ("24h涨跌幅", "24h change %"),
+    ("24h涨跌额", "24h change"),
+    ("24h成交量", "24h volume"),
+    ("不显示", "None"),
+    ("最新价", "Last"),
+    ("标记价格", "Mark"),
+    ("指数价格", "Index"),
+    ("Binance 合约", "Binance Futures"),
+    ("Binance 现货", "Binance Spot"),
+    ("Bybit 永续", "Bybit Perpetual"),
+    ("Coinbase 现货", "Coinbase Spot"),
+    ("Kraken 现货", "Kraken Spot"),
+    ("OKX 永续", "OKX Perpetual"),
+    ("现", "S"),
+    ("永", "P"),
+    // widget, tooltip and detail popup
+    ("组合", "Portfolio"),
+    ("详情", "Details"),
+    ("价格", "Price"),
+    ("24h 涨跌", "24h change"),
+    ("24h 最高", "24h high"),
+    ("24h 最低", "24h low"),
+    ("24h 成交量", "24h volume"),
+    ("24h 成交额", "24h turnover"),
+    ("资金费率", "Funding rate"),
+    ("下次结算", "Next funding"),
+    ("更新", "Updated"),
+    ("{}秒前", "{}s ago"),
+    ("延迟", "Latency"),
+    ("代理", "Proxy"),
+    ("连接", "Connection"),
+    // settings and pair picker
+    ("设置", "Settings"),
+    ("字体", "Font"),
+    ("字体 {} 未安装", "The font {} is not installed"),
+    ("价格字号", "Price size"),
+    ("名称字号", "Name size"),
+    ("颜色", "Color"),
+    ("小数位", "Decimals"),
+    ("保存", "Save"),
+    ("取消", "Cancel"),
+    ("搜索交易对", "Search pairs"),
+    ("切换", "Switch"),
+];
+
+pub fn set_language(language: Language) {
+    let ui_language = unsafe { GetUserDefaultUILanguage() };
+    ENGLISH.store(resolve(language, ui_language) == Language::En, Ordering::Relaxed);
+}
+
+fn resolve(language: Language, ui_language: u16) -> Language {
+    match language {
+        Language::Auto if ui_language & 0x3FF == LANG_CHINESE => Language::Zh,
+        Language::Auto => Language::En,
+        language => language,
+    }
+}
+
+// the text itself in chinese or when it has no english one
+pub fn tr(text: &'static str) -> &'static str {
+    if !ENGLISH.load(Ordering::Relaxed) {
+        return text;
+    }
+    ENGLISH_TEXT
+        .iter()
+        .find(|(chinese, _)| *chinese == text)
+        .map_or(text, |(_, english)| english)
+}
+
+// tr of template with its "{}" replaced by args in order
+pub fn fill(template: &'static str, args: &[&dyn Display]) -> String {
+    let mut parts = tr(template).split("{}");
+    let mut text = parts.next().unwrap_or_default().to_string();
+    for (index, part) in parts.enumerate() {
+        if let Some(arg) = args.get(index) {
+            text.push_str(&arg.to_string());
+        }
+        text.push_str(part);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn auto_follows_the_ui_language() {
+        // zh-CN, zh-TW and en-US
+        assert_eq!(resolve(Language::Auto, 0x0804), Language::Zh);
+        assert_eq!(resolve(Language::Auto, 0x0404), Language::Zh);
+        assert_eq!(resolve(Language::Auto, 0x0409), Language::En);
+        assert_eq!(resolve(Language::Zh, 0x0409), Language::Zh);
+    }
+
+    #[test]
+    fn fill_replaces_placeholders_in_order() {
+        assert_eq!(fill("{}秒后重试", &[&5]), "5秒后重试");
+        assert_eq!(fill("{}，当前 {}", &[&"BTC", &1.5]), "BTC，当前 1.5");
+        assert_eq!(fill("已复制", &[]), "已复制");
+    }
+
+    #[test]
+    fn english_keeps_keys_unique_and_placeholders() {
+        for (index, (chinese, english)) in ENGLISH_TEXT.iter().enumerate() {
+            assert!(!ENGLISH_TEXT[..index].iter().any(|(other, _)| other == chinese), "{}", chinese);
+            assert_eq!(chinese.matches("{}").count(), english.matches("{}").count(), "{}", chinese);
+        }
+    }
+}
@@ -0,0 +1,55 @@
+// one widget per session, a second launch wakes the running one and exits
+
+use crate::ipc;
+use crate::my_window::Window;
+use crate::tray::Tray;
+use anyhow::Result;
+use tracing::warn;
+use windows::core::w;
+use windows::Win32::Foundation::{
+    CloseHandle, GetLastError, ERROR_ALREADY_EXISTS, HANDLE, LPARAM, WPARAM,
+};
+use windows::Win32::System::Threading::CreateMutexW;
+use windows::Win32::UI::WindowsAndMessaging::{FindWindowW, PostMessageW};
+
+// held until the process ends, the system releases it even after a crash
+pub struct Instance(HANDLE);
+
+impl Drop for Instance {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = CloseHandle(self.0);
+        }
+    }
+}
+
+// none when another instance runs, it has been signalled then
+pub fn acquire() -> Result<Option<Instance>> {
+    unsafe {
+        let mutex = CreateMutexW(None, true, w!("Local\\mjj_single_instance"))?;
+        if GetLastError() != ERROR_ALREADY_EXISTS {
+            return Ok(Some(Instance(mutex)));
+        }
+        let _ = CloseHandle(mutex);
+    }
+    signal_running();
+    Ok(None)
+}
+
+// through the control pipe, or the window of an instance without one.
+// the tray and the shell window outlive a closed widget
+fn signal_running() {
+    if ipc::call(&ipc::Request::Activate).is_ok_and(|response| response.ok) {
+        return;
+    }
+    unsafe {
+        let hwnd = FindWindowW(Tray::CLASS_NAME, None)
+            .or_else(|_| FindWindowW(Window::SHELL_CLASS_NAME, None));
+        match hwnd {
+            Ok(hwnd) => {
+                let _ = PostMessageW(hwnd, Window::WM_ACTIVATE_INSTANCE, WPARAM(0), LPARAM(0));
+            }
+            Err(err) => warn!("running instance has no window:{:?}", err),
+        }
+    }
+}
@@ -0,0 +1,156 @@
+// a named pipe for local tools: one json request per line, one json response
+// per line. the widget answers on its own thread, through WM_IPC
+
+use crate::my_window::Window;
+use anyhow::Result;
+use core::ffi::c_void;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::{BufRead, BufReader, Write};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+use tokio::net::windows::named_pipe::{NamedPipeServer, ServerOptions};
+use tokio::sync::oneshot;
+use tokio::time::{self, Duration};
+use tracing::{debug, warn};
+use windows::Win32::Foundation::{HWND, LPARAM, WPARAM};
+use windows::Win32::UI::WindowsAndMessaging::PostMessageW;
+
+// the widget thread may sit in a menu or a dialog for a while
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "cmd", rename_all = "lowercase")]
+pub enum Request {
+    // a pair name like ETHUSDT, a show name like ETH/USDT or just the base asset
+    Switch { pair: String },
+    // the selected pair and its latest price
+    Price,
+    // toggles the carousel
+    Pause,
+    // reads the config file again, like saving the settings window
+    Reload,
+    // what a second launch sends, the widget flashes or comes back from the tray
+    Activate,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Response {
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Response {
+    fn error(error: impl Into<String>) -> Self {
+        Response {
+            ok: false,
+            result: None,
+            error: Some(error.into()),
+        }
+    }
+}
+
+impl From<std::result::Result<Value, String>> for Response {
+    fn from(result: std::result::Result<Value, String>) -> Self {
+        match result {
+            Ok(value) => Response {
+                ok: true,
+                result: Some(value),
+                error: None,
+            },
+            Err(error) => Response::error(error),
+        }
+    }
+}
+
+// posted to the widget in the wparam of WM_IPC, the window procedure owns it then
+pub struct Call {
+    pub request: Request,
+    pub reply: oneshot::Sender<std::result::Result<Value, String>>,
+}
+
+// per user, pipes are not bound to the session like the instance mutex
+pub fn pipe_name() -> String {
+    let user = std::env::var("USERNAME").unwrap_or_default();
+    format!(r"\\.\pipe\mjj_{}", user)
+}
+
+// runs on the api runtime until the process ends, hwnd gets the calls
+pub async fn serve(hwnd: usize) {
+    let mut first = true;
+    loop {
+        // a second widget must not answer in place of the first
+        let server = match ServerOptions::new()
+            .first_pipe_instance(first)
+            .reject_remote_clients(true)
+            .create(pipe_name())
+        {
+            Ok(server) => server,
+            Err(err) => {
+                warn!("ipc pipe err:{:?}", err);
+                return;
+            }
+        };
+        first = false;
+        if let Err(err) = server.connect().await {
+            warn!("ipc connect err:{:?}", err);
+            continue;
+        }
+        tokio::spawn(session(server, hwnd));
+    }
+}
+
+async fn session(pipe: NamedPipeServer, hwnd: usize) {
+    let (read, mut write) = tokio::io::split(pipe);
+    let mut lines = tokio::io::BufReader::new(read).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        debug!("ipc request:{}", line);
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => answer(hwnd, request).await,
+            Err(err) => Response::error(format!("bad request: {}", err)),
+        };
+        let mut text = serde_json::to_string(&response).unwrap_or_default();
+        text.push('\n');
+        if write.write_all(text.as_bytes()).await.is_err() {
+            break;
+        }
+    }
+}
+
+async fn answer(hwnd: usize, request: Request) -> Response {
+    let hwnd = HWND(hwnd as *mut c_void);
+    if request == Request::Activate {
+        return match unsafe { PostMessageW(hwnd, Window::WM_ACTIVATE_INSTANCE, WPARAM(0), LPARAM(0)) } {
+            Ok(()) => Ok(Value::Null).into(),
+            Err(_) => Response::error("widget closed"),
+        };
+    }
+    let (reply, answer) = oneshot::channel();
+    let call_p = Box::into_raw(Box::new(Call { request, reply }));
+    unsafe {
+        if PostMessageW(hwnd, Window::WM_IPC, WPARAM(call_p as usize), LPARAM(0)).is_err() {
+            drop(Box::from_raw(call_p));
+            return Response::error("widget closed");
+        }
+    }
+    match time::timeout(REPLY_TIMEOUT, answer).await {
+        Ok(Ok(result)) => result.into(),
+        _ => Response::error("no answer from the widget"),
+    }
+}
+
+// the client side, for a second launch and the command line
+pub fn call(request: &Request) -> Result<Response> {
+    let mut pipe = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(pipe_name())?;
+    let mut line = serde_json::to_string(request)?;
+    line.push('\n');
+    pipe.write_all(line.as_bytes())?;
+    let mut answer = String::new();
+    BufReader::new(pipe).read_line(&mut answer)?;
+    Ok(serde_json::from_str(&answer)?)
+}
@@ -0,0 +1,137 @@
+// tracing into a daily rotated file under %LOCALAPPDATA%\mjj\logs, the
+// windows subsystem has no console for println
+
+use lazy_static::lazy_static;
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tracing::warn;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{InitError, RollingFileAppender, Rotation};
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{fmt, reload, EnvFilter, Registry};
+
+pub const DEFAULT_LEVEL: &str = "info";
+// days of logs kept
+const KEEP_FILES: usize = 7;
+// lines kept in memory for crash reports
+const RECENT_LINES: usize = 100;
+
+struct Filter {
+    handle: reload::Handle<EnvFilter, Registry>,
+    // --log-level wins over the config
+    pinned: bool,
+}
+
+lazy_static! {
+    static ref FILTER: Mutex<Option<Filter>> = Mutex::new(None);
+    static ref RECENT: Mutex<VecDeque<String>> = Mutex::new(VecDeque::with_capacity(RECENT_LINES));
+    // of the file writer thread, see flush
+    static ref WRITER: Mutex<Option<WorkerGuard>> = Mutex::new(None);
+}
+
+// returned by init, flushes the file when dropped
+pub struct Guard(());
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        flush();
+    }
+}
+
+// writes out the queued lines and stops the file writer, before the process
+// exits; try_lock since a panic hook may run while it is held
+pub fn flush() {
+    if let Ok(mut writer) = WRITER.try_lock() {
+        writer.take();
+    }
+}
+
+// one formatted event, kept in RECENT when dropped
+#[derive(Default)]
+struct Recent(Vec<u8>);
+
+impl Write for Recent {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for Recent {
+    fn drop(&mut self) {
+        let line = String::from_utf8_lossy(&self.0).trim_end().to_string();
+        if let Ok(mut recent) = RECENT.lock() {
+            if recent.len() == RECENT_LINES {
+                recent.pop_front();
+            }
+            recent.push_back(line);
+        }
+    }
+}
+
+// the last logged lines, oldest first; empty when a panic holds the lock
+pub fn recent() -> Vec<String> {
+    RECENT
+        .try_lock()
+        .map(|recent| recent.iter().cloned().collect())
+        .unwrap_or_default()
+}
+
+pub fn dir() -> PathBuf {
+    let base = std::env::var_os("LOCALAPPDATA")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("."));
+    base.join("mjj").join("logs")
+}
+
+// before anything logs, the returned guard flushes the file when dropped;
+// with console the lines also go to stderr, for --headless
+pub fn init(cli_level: Option<&str>, console: bool) -> Result<Guard, InitError> {
+    let appender = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix("mjj")
+        .filename_suffix("log")
+        .max_log_files(KEEP_FILES)
+        .build(dir());
+    // without the file the lines are still kept for crash reports
+    let (file, guard) = match appender {
+        Ok(appender) => {
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            *WRITER.lock().unwrap() = Some(guard);
+            (Some(fmt::layer().with_writer(writer).with_ansi(false)), Ok(Guard(())))
+        }
+        Err(err) => (None, Err(err)),
+    };
+    let (filter, handle) = reload::Layer::new(filter(cli_level.unwrap_or(DEFAULT_LEVEL)));
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(file)
+        .with(fmt::layer().with_writer(Recent::default).with_ansi(false))
+        .with(console.then(|| fmt::layer().with_writer(io::stderr)))
+        .init();
+    *FILTER.lock().unwrap() = Some(Filter {
+        handle,
+        pinned: cli_level.is_some(),
+    });
+    if let Err(err) = &guard {
+        warn!("log file err:{}", err);
+    }
+    guard
+}
+
+// an EnvFilter directive like "debug" or "info,demo::api=trace", a bad one keeps info
+pub fn set_level(level: &str) {
+    if let Some(filter) = FILTER.lock().unwrap().as_ref().filter(|filter| !filter.pinned) {
+        let _ = filter.handle.reload(self::filter(level));
+    }
+}
+
+fn filter(level: &str) -> EnvFilter {
+    EnvFilter::try_new(level).unwrap_or_else(|_| EnvFilter::new(DEFAULT_LEVEL))
+}
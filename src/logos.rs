@@ -0,0 +1,81 @@
+// small png logos of the base assets, downloaded once into
+// %LOCALAPPDATA%\mjj\logos and drawn before the pair names with Config::show_logos
+
+use crate::exchange;
+use crate::tls;
+use anyhow::Result;
+use std::path::PathBuf;
+
+pub const DEFAULT_URL: &str =
+    "https://cdn.jsdelivr.net/gh/spothq/cryptocurrency-icons@master/32/color/{asset}.png";
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+// anything larger is not a logo
+const MAX_BYTES: usize = 256 * 1024;
+
+pub fn dir() -> PathBuf {
+    let base = std::env::var_os("LOCALAPPDATA")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("."));
+    base.join("mjj").join("logos")
+}
+
+pub fn path(asset: &str) -> PathBuf {
+    dir().join(format!("{}.png", asset))
+}
+
+// BTCUSDT to BTC, none for a pair without a known quote
+pub fn asset(pair_name: &str) -> Option<String> {
+    let (base, _) = exchange::split_pair_name(pair_name)?;
+    (!base.is_empty()).then(|| base.to_uppercase())
+}
+
+// {asset} is the lowercase asset, as the icon sets name their files
+pub fn url(template: &str, asset: &str) -> String {
+    template.replace("{asset}", &asset.to_lowercase())
+}
+
+fn is_png(bytes: &[u8]) -> bool {
+    bytes.len() <= MAX_BYTES && bytes.starts_with(&PNG_SIGNATURE)
+}
+
+// on the api runtime, the widget repaints with it once it is saved
+pub async fn download(client: &reqwest::Client, template: &str, asset: &str) -> Result<()> {
+    let bytes = tls::send(client.get(url(template, asset)))
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+    if !is_png(&bytes) {
+        anyhow::bail!("logo of {} is not a png", asset);
+    }
+    std::fs::create_dir_all(dir())?;
+    std::fs::write(path(asset), &bytes)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assets_of_pairs() {
+        assert_eq!(asset("BTCUSDT"), Some("BTC".to_string()));
+        assert_eq!(asset("ethusdc"), None);
+        assert_eq!(asset("USDT"), None);
+    }
+
+    #[test]
+    fn fills_the_url_template() {
+        assert_eq!(
+            url(DEFAULT_URL, "BTC"),
+            "https://cdn.jsdelivr.net/gh/spothq/cryptocurrency-icons@master/32/color/btc.png"
+        );
+    }
+
+    #[test]
+    fn checks_the_png_signature() {
+        assert!(is_png(&[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, 0]));
+        assert!(!is_png(b"<html>not found</html>"));
+    }
+}
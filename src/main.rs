@@ -1,14 +1,47 @@
 #![windows_subsystem = "windows"]
+mod detail_window;
 mod my_window;
+mod notify;
+mod pair_picker;
 mod proxy;
+mod settings_window;
+mod tray;
 use my_window::Window;
 use anyhow::Result;
+mod aggregate;
+mod animation;
+mod clipboard;
+mod alerts;
+mod autostart;
 mod api;
+mod config;
+mod crash;
+mod exchange;
+mod fiat;
+mod format;
+mod headless;
+mod history;
+mod hotkeys;
+mod http;
+mod i18n;
+mod instance;
+mod ipc;
+mod logging;
+mod logos;
+mod metrics;
+mod pairs;
+mod render;
+mod taskbar;
+mod theme;
+mod throttle;
+mod tls;
+mod tooltip;
+mod update;
 use tokio::runtime::Runtime;
 use windows::Win32::Foundation::HWND;
-use std::{ffi::c_void, thread};
+use std::{ffi::c_void, net::SocketAddr, thread};
 use tokio::sync::mpsc;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
 
 /// Simple program to greet a person
@@ -17,20 +50,180 @@ use clap::Parser;
 struct Args {
     #[arg(short, long)]
     proxy: Option<String>,
+    /// exchange id: binance-futures, binance-spot, okx-swap, bybit-linear,
+    /// coinbase-spot or kraken-spot
+    #[arg(short, long)]
+    exchange: Option<String>,
+    /// log file filter, e.g. debug or info,demo::api=trace; overrides the config
+    #[arg(long)]
+    log_level: Option<String>,
+    /// no widget, print prices and status to the console
+    #[arg(long)]
+    headless: bool,
+    /// serve /prices and /status as json and /metrics for prometheus, loopback only, e.g. 127.0.0.1:8787
+    #[arg(long)]
+    serve: Option<SocketAddr>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+/// control the running widget instead of starting one
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// select a pair, e.g. eth, ETHUSDT or ETH/USDT
+    Switch { pair: String },
+    /// print the selected pair and its latest price
+    Price,
+    /// pause or resume the carousel
+    Pause,
+    /// read the config file again
+    Reload,
+}
+
+impl Command {
+    fn request(self) -> ipc::Request {
+        match self {
+            Command::Switch { pair } => ipc::Request::Switch { pair },
+            Command::Price => ipc::Request::Price,
+            Command::Pause => ipc::Request::Pause,
+            Command::Reload => ipc::Request::Reload,
+        }
+    }
+}
+
+// prints the answer of the running instance, exits 1 on an error
+fn control(command: Command) -> Result<()> {
+    headless::attach()?;
+    let response = ipc::call(&command.request())
+        .map_err(|err| anyhow::anyhow!("no running instance: {}", err))?;
+    if !response.ok {
+        eprintln!("{}", response.error.unwrap_or_default());
+        std::process::exit(1);
+    }
+    if let Some(result) = response.result.filter(|result| !result.is_null()) {
+        println!("{}", serde_json::to_string_pretty(&result)?);
+    }
+    Ok(())
 }
 fn main() -> Result<()> {
     
 
+    // clap prints --help, --version and usage errors to a console before we know the mode
+    if std::env::args().len() > 1 {
+        headless::attach()?;
+    }
     let args = Args::parse();
-    let (tx, rx):(mpsc::Sender<api::TradePair>, mpsc::Receiver<api::TradePair>) = mpsc::channel(1);
+    if let Some(command) = args.command {
+        return control(command);
+    }
+    // before logging, its console layer writes to the attached console
+    let console = if args.headless {
+        Some(headless::Console::new()?)
+    } else {
+        None
+    };
+    let log_file = logging::init(args.log_level.as_deref(), console.is_some());
+    crash::install();
+    // a headless run is a debugging aid, it may run next to the widget
+    let _instance = match console {
+        Some(_) => None,
+        None => match instance::acquire()? {
+            Some(instance) => Some(instance),
+            None => return Ok(()),
+        },
+    };
+    let config = config::Config::load();
+    logging::set_level(&config.log_level);
+    i18n::set_language(config.language);
+    // the lines still reach crash reports, but nothing is kept on disk
+    if log_file.is_err() && config.toast_notifications {
+        notify::toast(i18n::tr("日志不可用"), &i18n::fill("无法写入 {}", &[&logging::dir().display()]));
+    }
+    pairs::init(&config);
+    exchange::set_price_type(config.price_type);
+    proxy::set_direct_fallback(config.direct_fallback);
+    proxy::set_exchange_proxies(&config.exchange_proxies);
+    proxy::set_remote_dns(config.proxy_remote_dns);
+    api::set_timeouts(config.timeouts);
+    tls::set_pins(&config.cert_pins);
+    tls::set_ca_file(&config.ca_file);
+    history::set_interval(config.history_seconds);
+    throttle::set_max_refresh(config.max_refresh_hz);
+    update::set_enabled(config.update_check);
+    let state = config::State::load();
+    notify::set_muted(state.sound_muted);
+    let trade_pair = match &state.last_pair {
+        Some(pair_name) => pairs::TradePair(pair_name.clone()),
+        None => pairs::default_trade_pair(),
+    };
+    let exchange = match &args.exchange {
+        Some(id) => exchange::find_exchange(id)
+            .ok_or_else(|| anyhow::anyhow!("unknown exchange: {}", id))?,
+        None => {
+            let exchange = exchange::default_exchange();
+            state
+                .preferred_market(&config, &trade_pair)
+                .and_then(|market| exchange::counterpart(exchange, market))
+                .unwrap_or(exchange)
+        }
+    };
+    let (tx, rx):(mpsc::Sender<api::UiCommand>, mpsc::Receiver<api::UiCommand>) = mpsc::channel(1);
     
-    let mut window = Window::new(None, None, None, tx, api::TradePair::BTCUSDT);
+    // without a configured proxy the environment's or the windows one is used
+    let proxy = args
+        .proxy
+        .clone()
+        .or(config.proxy.clone())
+        .or_else(proxy::fallback);
+    let cli_proxy = args.proxy.clone();
+    let subscriptions = api::Subscriptions::new(trade_pair.clone(), &config);
+    let serve = args.serve;
+    if let Some(console) = console {
+        let hwnd_v = console.hwnd;
+        let api_thread = thread::spawn(move || {
+            let rt = Runtime::new().expect("Runtime::new fail");
+            if let Some(addr) = serve {
+                rt.spawn(http::serve(addr));
+            }
+            rt.block_on(api::run(HWND(hwnd_v as *mut c_void), rx, subscriptions, exchange, proxy, cli_proxy));
+            rt.shutdown_timeout(api::CLOSE_TIMEOUT);
+        });
+        console.run();
+        let _ = tx.blocking_send(api::UiCommand::Shutdown);
+        let _ = api_thread.join();
+        return Ok(());
+    }
+    let mut window = Window::new(None, None, Some(config.width), tx, trade_pair, exchange);
+    let tray_icon = config.tray_icon;
+    let tray_icon_mode = config.tray_icon_mode;
+    window.apply_config(config);
     window.init_window()?;
-    let hwnd_v = window.hwnd;
-    thread::spawn(move || {
+    // with a tray icon api messages go through the tray window, which outlives the widget
+    let tray = if tray_icon {
+        Some(tray::Tray::new(&mut window, tray_icon_mode)?)
+    } else {
+        None
+    };
+    let hwnd_v = match &tray {
+        Some(tray) => tray.hwnd,
+        None => window.shell_hwnd,
+    };
+    let sender = window.sender.clone();
+    let api_thread = thread::spawn(move || {
         let rt = Runtime::new().expect("Runtime::new fail");
+        rt.spawn(ipc::serve(hwnd_v));
+        if let Some(addr) = serve {
+            rt.spawn(http::serve(addr));
+        }
         rt.block_on( api::run(HWND(hwnd_v as *mut c_void), 
-            rx, api::TradePair::BTCUSDT, args.proxy));
+            rx, subscriptions, exchange, proxy, cli_proxy));
+        // the sources and probes still running are dropped
+        rt.shutdown_timeout(api::CLOSE_TIMEOUT);
     });
-    window.run_window()
+    let result = window.run_window();
+    drop(tray);
+    // the widget is gone, close the socket before the process ends
+    let _ = sender.blocking_send(api::UiCommand::Shutdown);
+    let _ = api_thread.join();
+    result
 }
@@ -0,0 +1,99 @@
+// health counters for the /metrics endpoint of --serve, written in the
+// prometheus text format. counted always, they are a few atomic adds
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+static RECONNECTS: AtomicU64 = AtomicU64::new(0);
+static WS_MESSAGES: AtomicU64 = AtomicU64::new(0);
+static RENDERS: AtomicU64 = AtomicU64::new(0);
+// total and last paint time, in microseconds
+static RENDER_MICROS: AtomicU64 = AtomicU64::new(0);
+static LAST_RENDER_MICROS: AtomicU64 = AtomicU64::new(0);
+
+pub fn count_reconnect() {
+    RECONNECTS.fetch_add(1, Ordering::Relaxed);
+}
+
+// text and binary frames of every source, pings not included
+pub fn count_ws_message() {
+    WS_MESSAGES.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn observe_render(elapsed: Duration) {
+    let micros = elapsed.as_micros() as u64;
+    RENDERS.fetch_add(1, Ordering::Relaxed);
+    RENDER_MICROS.fetch_add(micros, Ordering::Relaxed);
+    LAST_RENDER_MICROS.store(micros, Ordering::Relaxed);
+}
+
+// a pair with its latest price and the seconds since it arrived
+pub struct PairSample {
+    pub name: String,
+    pub price: f64,
+    pub age: f64,
+}
+
+pub fn render(pairs: &[PairSample]) -> String {
+    let mut out = String::new();
+    header(&mut out, "mjj_price", "gauge", "Latest price of the pair.");
+    for pair in pairs {
+        let _ = writeln!(out, "mjj_price{{pair=\"{}\"}} {}", label(&pair.name), pair.price);
+    }
+    header(&mut out, "mjj_price_age_seconds", "gauge", "Seconds since the last price of the pair.");
+    for pair in pairs {
+        let _ = writeln!(out, "mjj_price_age_seconds{{pair=\"{}\"}} {}", label(&pair.name), pair.age);
+    }
+    header(&mut out, "mjj_reconnects_total", "counter", "Connections of the selected exchange that ended.");
+    let _ = writeln!(out, "mjj_reconnects_total {}", RECONNECTS.load(Ordering::Relaxed));
+    header(&mut out, "mjj_ws_messages_total", "counter", "Websocket messages received.");
+    let _ = writeln!(out, "mjj_ws_messages_total {}", WS_MESSAGES.load(Ordering::Relaxed));
+    header(&mut out, "mjj_render_seconds", "summary", "Time spent painting the widget.");
+    let _ = writeln!(out, "mjj_render_seconds_sum {}", seconds(&RENDER_MICROS));
+    let _ = writeln!(out, "mjj_render_seconds_count {}", RENDERS.load(Ordering::Relaxed));
+    header(&mut out, "mjj_last_render_seconds", "gauge", "Time the last paint took.");
+    let _ = writeln!(out, "mjj_last_render_seconds {}", seconds(&LAST_RENDER_MICROS));
+    out
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn seconds(micros: &AtomicU64) -> f64 {
+    micros.load(Ordering::Relaxed) as f64 / 1_000_000.
+}
+
+// pair names come from the config, quote what the format reserves
+fn label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn label_escapes_quotes_and_backslashes() {
+        assert_eq!(label("BTCUSDT"), "BTCUSDT");
+        assert_eq!(label("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+
+    #[test]
+    fn render_writes_a_sample_per_pair() {
+        let pairs = [PairSample {
+            name: "BTCUSDT".to_string(),
+            price: 65000.5,
+            age: 1.5,
+        }];
+        let out = render(&pairs);
+        assert!(out.contains("# TYPE mjj_price gauge\nmjj_price{pair=\"BTCUSDT\"} 65000.5\n"));
+        assert!(out.contains("mjj_price_age_seconds{pair=\"BTCUSDT\"} 1.5\n"));
+        assert!(out.contains("# TYPE mjj_reconnects_total counter\n"));
+    }
+}
@@ -1,28 +1,65 @@
 use anyhow::Result;
 use core::ffi::c_void;
 use thiserror::Error;
-use windows::Win32::Graphics::Gdi::BeginPaint;
-use windows::Win32::Graphics::Gdi::{
-    CreateCompatibleBitmap, CreateCompatibleDC, DeleteDC, DeleteObject, EndPaint, SelectObject,
-    AC_SRC_ALPHA, AC_SRC_OVER, BLENDFUNCTION, PAINTSTRUCT,
+use tracing::{error, info, warn};
+use windows::Win32::UI::Accessibility::{SetWinEventHook, UnhookWinEvent, HWINEVENTHOOK};
+use windows::Win32::UI::Input::KeyboardAndMouse::{
+    GetDoubleClickTime, RegisterHotKey, ReleaseCapture, SetCapture, TrackMouseEvent, UnregisterHotKey,
+    HOT_KEY_MODIFIERS, TME_LEAVE, TRACKMOUSEEVENT,
 };
+use windows::Win32::UI::Shell::ShellExecuteW;
+use windows::Win32::UI::Controls::Dialogs::{
+    GetSaveFileNameW, OFN_OVERWRITEPROMPT, OFN_PATHMUSTEXIST, OPENFILENAMEW,
+};
+use windows::Win32::UI::Controls::WM_MOUSELEAVE;
 use windows::Win32::Graphics::GdiPlus::{
-    FontStyleRegular, GdipCreateFont, GdipCreateFontFamilyFromName, GdipCreateFromHDC,
-    GdipCreateSolidFill, GdipDeleteBrush, GdipDeleteFont, GdipDeleteFontFamily, GdipDrawString,
-    GdipGraphicsClear, GdipMeasureString, GdipSetInterpolationMode, GdipSetSmoothingMode,
-    GdipSetTextRenderingHint, GdiplusStartup, GdiplusStartupInput, GpBrush, GpFont, GpFontFamily,
-    GpGraphics, GpSolidFill, InterpolationModeHighQualityBicubic, RectF, SmoothingModeAntiAlias,
-    TextRenderingHintAntiAlias, UnitPoint,
+    GdiplusShutdown, GdiplusStartup, GdiplusStartupInput, GdipMeasureString, GpFont, GpGraphics, PointF, RectF,
 };
 use windows::{
     core::*, Win32::Foundation::*, Win32::Graphics::GdiPlus,
-    Win32::System::LibraryLoader::GetModuleHandleW, Win32::UI::WindowsAndMessaging::FindWindowW,
+    Win32::System::LibraryLoader::GetModuleHandleW,
     Win32::UI::WindowsAndMessaging::*,
 };
 
+use crate::aggregate;
+use crate::animation;
+use crate::clipboard;
+use crate::alerts;
 use crate::api;
+use crate::autostart;
+use crate::config;
+use crate::detail_window::DetailWindow;
+use crate::exchange;
+use crate::fiat;
+use crate::format;
+use crate::history;
+use crate::hotkeys;
+use crate::i18n;
+use crate::ipc;
+use crate::logos;
+use crate::metrics;
+use crate::notify;
+use crate::pairs;
+use crate::render::{self, Renderer};
+use crate::pair_picker::PairPicker;
+use crate::settings_window::SettingsWindow;
+use crate::taskbar;
+use crate::theme;
+use crate::tooltip;
+use crate::update;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use tokio::sync::mpsc;
 
+// the widget the location events of explorer go to, see hook_taskbar
+static HOOKED_HWND: AtomicUsize = AtomicUsize::new(0);
+
+lazy_static! {
+    // broadcast to the top-level windows when explorer creates the taskbar again
+    pub static ref WM_TASKBAR_CREATED: u32 = unsafe { RegisterWindowMessageW(w!("TaskbarCreated")) };
+}
+
 pub struct Window {
     pub hwnd: usize,
     pub width: i32,
@@ -30,8 +67,82 @@ pub struct Window {
     class_name: String,
     title: String,
     pub pos: POINT,
-    pub sender: mpsc::Sender<api::TradePair>,
-    trade_pair: api::TradePair,
+    pub sender: mpsc::Sender<api::UiCommand>,
+    trade_pair: pairs::TradePair,
+    exchange: &'static dyn exchange::Exchange,
+    // latest price and 24h stats of the displayed pairs, by pair name
+    prices: HashMap<String, api::Price>,
+    tickers: HashMap<String, api::Ticker>,
+    fundings: HashMap<String, api::Funding>,
+    bbos: HashMap<String, api::Bbo>,
+    // book of the selected pair, only streamed while the detail popup is open
+    depth: Option<api::Depth>,
+    // pairs behind the COMAMND_PAIR_BASE ids of the last popup menu
+    menu_pairs: Vec<pairs::TradePair>,
+    // pairs behind the COMAMND_RECENT_BASE ids of the last popup menu
+    menu_recent: Vec<pairs::TradePair>,
+    pub config: config::Config,
+    // of the config's theme
+    colors: theme::Colors,
+    // of the config, resolved once as the installed check is not free
+    pub(crate) fonts: render::Fonts,
+    // hidden top-level window that outlives the widget, api messages go through
+    // it or the tray, see shell_wndproc
+    pub shell_hwnd: usize,
+    // the taskbar the widget is a child of
+    taskbar: usize,
+    // docked left or right, the pairs are stacked and the height follows them
+    vertical: bool,
+    // hidden with an auto-hide taskbar while it is slid away
+    hidden: bool,
+    // location events of explorer's windows, zero while unhooked
+    taskbar_hook: usize,
+    // cursor position along the taskbar and Config::taskbar_offset when the
+    // left button went down, see on_drag
+    drag: Option<(i32, i32)>,
+    // the cursor went past the drag threshold, the button up is no click then
+    dragging: bool,
+    // copies on the other taskbars with Config::taskbar_clone, (hwnd, taskbar)
+    mirrors: Vec<(usize, usize)>,
+    // set when the shell window or a tray icon can bring the widget back after
+    // its window is destroyed, e.g. with the taskbar by an explorer restart
+    pub keep_alive: bool,
+    // the background flashes until then after an alert fired
+    alert_until: Option<std::time::Instant>,
+    // since a pair or exchange was picked, until the selected pair's first price
+    switch_started: Option<std::time::Instant>,
+    // made on the first hover
+    tooltip: Option<tooltip::Tooltip>,
+    // client point of the cursor while it is over the widget
+    hover: Option<(i32, i32)>,
+    // decoded logos by asset, none while one downloads or when there is none
+    logos: HashMap<String, Option<render::Pixels>>,
+    // prices counting to the last tick, by pair name, see Config::smooth_prices
+    tweens: HashMap<String, animation::Tween>,
+    // start and color of the background flash of a large move, see Config::flash_percent
+    flash: Option<(std::time::Instant, u32)>,
+    // toggled from the context menu, not persisted
+    carousel_paused: bool,
+    // connection of the selected exchange, the dot in the top left corner
+    status: api::Status,
+    // last ws ping round trip in ms
+    latency: Option<u64>,
+    // last proxy probe, none until the first one finished
+    proxy_health: Option<api::ProxyHealth>,
+    // when the selected pair last got a price, for the stale check
+    last_tick: Option<std::time::Instant>,
+    // made on the first paint, dropped when the config picks another backend
+    renderer: Option<Box<dyn Renderer>>,
+    // the last frame failed, a second failure in a row falls back to gdi+
+    render_failed: bool,
+    // the button up that ends a double click is not a click
+    double_clicked: bool,
+    // precise touchpads scroll in parts of a notch, summed up to a whole one
+    wheel_delta: i32,
+    // from GdiplusStartup, zero until the first window
+    gdiplus_token: usize,
+    // actions of the registered hotkeys, the hotkey id is the index
+    hotkey_actions: Vec<hotkeys::Action>,
 }
 
 #[derive(Error, Debug)]
@@ -40,12 +151,73 @@ struct WindowError {
     erro_msg: String,
 }
 
+// the api thread no longer takes commands, nothing will update anymore
+#[derive(Error, Debug)]
+#[error("api thread stopped")]
+struct ApiStopped;
+
 impl Window {
     pub const WM_FRESH: u32 = WM_USER + 1;
-    const COMAMND_BTCUSDT: usize = 1;
-    const COMAMND_ETHUSDT: usize = 2;
-    const COMAMND_SOLUSDT: usize = 3;
-    const COMAMND_EXIT: usize = 4;
+    // posted by a second launch, see instance
+    pub const WM_ACTIVATE_INSTANCE: u32 = WM_USER + 3;
+    // a request from the control pipe, see ipc
+    pub const WM_IPC: u32 = WM_USER + 4;
+    pub const CLASS_NAME: &'static str = "mjj";
+    pub const SHELL_CLASS_NAME: PCWSTR = w!("mjj_shell");
+    const MIRROR_CLASS_NAME: PCWSTR = w!("mjj_mirror");
+    pub const COMAMND_EXIT: usize = 4;
+    const COMAMND_SETTINGS: usize = 5;
+    const COMAMND_MUTE: usize = 7;
+    const COMAMND_CAROUSEL: usize = 8;
+    const COMAMND_MARKET: usize = 9;
+    const COMAMND_COPY: usize = 10;
+    const COMAMND_AUTOSTART: usize = 11;
+    const COMAMND_UPDATE: usize = 12;
+    const COMAMND_TASKBAR_CLONE: usize = 13;
+    const COMAMND_RESET_POSITION: usize = 14;
+    const COMAMND_FAVORITE: usize = 15;
+    const COMAMND_SEARCH: usize = 16;
+    // listed pairs past this many are grouped into a submenu per quote asset
+    const MENU_GROUP_MIN: usize = 40;
+    // one id per exchange::PriceType::ALL entry
+    const COMAMND_PRICE_TYPE_BASE: usize = 20;
+    // one id per config::LabelRow::ALL entry
+    const COMAMND_LABEL_ROW_BASE: usize = 30;
+    // one id per config::State::RECENT_PAIRS
+    const COMAMND_RECENT_BASE: usize = 50;
+    // one id per EXPORT_RANGES entry
+    const COMAMND_EXPORT_BASE: usize = 40;
+    // one id per taskbar::all entry
+    const COMAMND_TASKBAR_BASE: usize = 60;
+    const MAX_TASKBARS: usize = 16;
+    // one id per WIDTHS entry
+    const COMAMND_WIDTH_BASE: usize = 80;
+    // Config::width choices of the menu, a column per pair side by side
+    const WIDTHS: [i32; 6] = [60, 70, 80, 100, 120, 150];
+    // one id per theme::ThemeName::ALL entry
+    const COMAMND_THEME_BASE: usize = 90;
+    // label and hours of the history export ranges
+    const EXPORT_RANGES: [(&'static str, u64); 3] =
+        [("最近1小时...", 1), ("最近6小时...", 6), ("最近24小时...", 24)];
+
+    // fires when the selected pair went stale_seconds without a price
+    const TIMER_STALE: usize = 1;
+    const TIMER_CAROUSEL: usize = 2;
+    // the centered app icons of windows 11 move without a window event
+    const TIMER_APPS: usize = 3;
+    // steps the animations, only runs while one is on
+    const TIMER_ANIMATION: usize = 4;
+    // a click waits out the double click time, a double click cancels it
+    const TIMER_CLICK: usize = 5;
+    const COMAMND_EXCHANGE_BASE: usize = 100;
+    const COMAMND_PAIR_BASE: usize = 1000;
+
+    // height of a pair on a vertical taskbar per point of font size, three rows of text
+    const VERTICAL_CELL_SCALE: f32 = 6.;
+
+    const ALERT_FLASH: std::time::Duration = std::time::Duration::from_secs(5);
+    // a switch without a price by then, e.g. to a pair the exchange lacks, no longer grays the menu
+    const SWITCH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
 
     const ALPHA_SHIFT: u32 = 24;
     const RED_SHIFT: u32 = 16;
@@ -56,11 +228,12 @@ impl Window {
         class_name: Option<&str>,
         title: Option<&str>,
         width: Option<i32>,
-        sender: mpsc::Sender<api::TradePair>,
-        trade_pair: api::TradePair,
+        sender: mpsc::Sender<api::UiCommand>,
+        trade_pair: pairs::TradePair,
+        exchange: &'static dyn exchange::Exchange,
     ) -> Self {
         let width = width.unwrap_or(70);
-        let class_name = class_name.unwrap_or("mjj").to_string();
+        let class_name = class_name.unwrap_or(Self::CLASS_NAME).to_string();
         let title = title.unwrap_or("mjj").to_string();
         Window {
             hwnd: 0,
@@ -71,255 +244,2237 @@ impl Window {
             title,
             sender,
             trade_pair,
+            exchange,
+            prices: HashMap::new(),
+            tickers: HashMap::new(),
+            fundings: HashMap::new(),
+            bbos: HashMap::new(),
+            depth: None,
+            menu_pairs: Vec::new(),
+            menu_recent: Vec::new(),
+            config: config::Config::default(),
+            colors: theme::Palette::light().colors(),
+            fonts: render::Fonts::default(),
+            shell_hwnd: 0,
+            taskbar: 0,
+            vertical: false,
+            hidden: false,
+            taskbar_hook: 0,
+            drag: None,
+            dragging: false,
+            mirrors: Vec::new(),
+            keep_alive: false,
+            alert_until: None,
+            switch_started: None,
+            tooltip: None,
+            hover: None,
+            logos: HashMap::new(),
+            tweens: HashMap::new(),
+            flash: None,
+            carousel_paused: false,
+            status: api::Status::Reconnecting,
+            latency: None,
+            proxy_health: None,
+            last_tick: None,
+            renderer: None,
+            render_failed: false,
+            double_clicked: false,
+            wheel_delta: 0,
+            gdiplus_token: 0,
+            hotkey_actions: Vec::new(),
+        }
+    }
+
+    // takes effect on the next repaint and the next WM_TIMER reposition
+    pub fn apply_config(&mut self, config: config::Config) {
+        pairs::init(&config);
+        format::set_separators(config.number_format.separators());
+        fiat::set_currency(config.fiat.clone());
+        i18n::set_language(config.language);
+        notify::set_enabled(config.toast_notifications);
+        alerts::set_rules(config.alerts.clone(), config.move_alerts.clone());
+        aggregate::set_sources(&config.aggregate, config.aggregate_mode);
+        if config.render_backend != self.config.render_backend {
+            self.renderer = None;
+        }
+        if config.logo_url != self.config.logo_url {
+            self.logos.clear();
+        }
+        self.colors = config.colors();
+        self.fonts = config.fonts();
+        self.config = config;
+        self.update_width();
+        self.restart_carousel();
+        self.restart_apps_timer();
+        self.register_hotkeys();
+    }
+
+    // a combination another program already took is skipped
+    fn register_hotkeys(&mut self) {
+        self.unregister_hotkeys();
+        if self.hwnd == 0 {
+            return;
+        }
+        let hwnd = HWND(self.hwnd as *mut c_void);
+        for hotkey in hotkeys::parse(&self.config.hotkeys) {
+            let id = self.hotkey_actions.len() as i32;
+            let registered = unsafe {
+                RegisterHotKey(hwnd, id, HOT_KEY_MODIFIERS(hotkey.modifiers), hotkey.virtual_key)
+            };
+            match registered {
+                Ok(()) => self.hotkey_actions.push(hotkey.action),
+                Err(err) => warn!("register hotkey {:?} err:{:?}", hotkey, err),
+            }
+        }
+    }
+
+    fn unregister_hotkeys(&mut self) {
+        if self.hwnd != 0 {
+            let hwnd = HWND(self.hwnd as *mut c_void);
+            for id in 0..self.hotkey_actions.len() {
+                unsafe {
+                    let _ = UnregisterHotKey(hwnd, id as i32);
+                }
+            }
+        }
+        self.hotkey_actions.clear();
+    }
+
+    pub fn make_argb(a: u32, r: u32, g: u32, b: u32) -> u32 {
+        (b << Self::BLUE_SHIFT)
+            | (g << Self::GREEN_SHIFT)
+            | (r << Self::RED_SHIFT)
+            | (a << Self::ALPHA_SHIFT)
+    }
+
+    pub(crate) fn meansuer_string(
+        graphics: *mut GpGraphics,
+        content: &HSTRING,
+        font: *const GpFont,
+        lay_box: &RectF,
+    ) -> RectF {
+        let mut bound_box = RectF::default();
+        unsafe {
+            GdipMeasureString(
+                graphics,
+                content,
+                -1,
+                font,
+                lay_box,
+                std::ptr::null_mut(),
+                &mut bound_box,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            );
+        }
+        bound_box
+    }
+
+    pub(crate) fn generate_mid_rect(lay_rect: &RectF, text_bound: &RectF) -> RectF {
+        let mut dst_rect = RectF::default();
+        if lay_rect.Width >= text_bound.Width {
+            dst_rect.X = (lay_rect.Width - text_bound.Width) / 2. + lay_rect.X;
+        } else {
+            dst_rect.X = lay_rect.X;
+        }
+        if lay_rect.Height >= text_bound.Height {
+            dst_rect.Y = (lay_rect.Height - text_bound.Height) / 2. + lay_rect.Y;
+        } else {
+            dst_rect.Y = lay_rect.Y;
+        }
+        dst_rect.Width = text_bound.Width;
+        dst_rect.Height = text_bound.Height;
+        dst_rect
+    }
+
+    // trend line of the recent prices behind the text of one cell
+    fn draw_sparkline(renderer: &mut dyn Renderer, cell: &RectF, history: &[f64]) {
+        if history.len() < 2 {
+            return;
+        }
+        let min = history.iter().cloned().fold(f64::MAX, f64::min);
+        let max = history.iter().cloned().fold(f64::MIN, f64::max);
+        let range = if max > min { max - min } else { 1. };
+        let margin = cell.Height * 0.1;
+        let height = cell.Height - margin * 2.;
+        let step = cell.Width / (history.len() - 1) as f32;
+        let points: Vec<PointF> = history
+            .iter()
+            .enumerate()
+            .map(|(index, price)| PointF {
+                X: cell.X + index as f32 * step,
+                Y: cell.Y + margin + height * (1. - ((price - min) / range) as f32),
+            })
+            .collect();
+        renderer.draw_lines(Self::make_argb(90, 30, 144, 255), &points);
+    }
+
+    pub(crate) fn trade_pair(&self) -> pairs::TradePair {
+        self.trade_pair.clone()
+    }
+
+    // latest data of the selected pair
+    pub(crate) fn current(&self) -> (Option<&api::Price>, Option<&api::Ticker>) {
+        let pair_name = pairs::pair_info(&self.trade_pair).pair_name;
+        (self.prices.get(&pair_name), self.tickers.get(&pair_name))
+    }
+
+    fn switching(&self) -> bool {
+        self.switch_started
+            .is_some_and(|started| started.elapsed() < Self::SWITCH_TIMEOUT)
+    }
+
+    pub(crate) fn current_funding(&self) -> Option<&api::Funding> {
+        self.fundings.get(&pairs::pair_info(&self.trade_pair).pair_name)
+    }
+
+    pub(crate) fn latency(&self) -> Option<u64> {
+        self.latency
+    }
+
+    pub(crate) fn proxy_health(&self) -> Option<api::ProxyHealth> {
+        self.proxy_health
+    }
+
+    // seconds since the selected pair last got a price
+    pub(crate) fn tick_age(&self) -> Option<u64> {
+        self.last_tick.map(|last_tick| last_tick.elapsed().as_secs())
+    }
+
+    // restarted by every price of the selected pair
+    fn arm_stale_timer(hwnd: &HWND, window: &Window) {
+        let elapse = (window.config.stale_seconds.max(1) * 1000).min(u32::MAX as u64) as u32;
+        unsafe {
+            SetTimer(*hwnd, Self::TIMER_STALE, elapse, None);
+        }
+    }
+
+    // a live connection whose prices stopped for stale_seconds turns stale
+    fn check_stale(hwnd: &HWND, window: &mut Window) {
+        let silent = window.tick_age().is_some_and(|age| age >= window.config.stale_seconds);
+        if window.status == api::Status::Connected && silent {
+            window.status = api::Status::Stale;
+            let _ = Self::paint(hwnd, window, None);
+        }
+    }
+
+    pub(crate) fn current_depth(&self) -> Option<&api::Depth> {
+        self.depth.as_ref()
+    }
+
+    // whether the selected exchange has a shallow book channel
+    pub(crate) fn has_depth(&self) -> bool {
+        let trade_pair_info = pairs::exchange_pair_info(self.exchange, &self.trade_pair);
+        self.exchange
+            .depth_subscribe_message(&trade_pair_info)
+            .is_some()
+    }
+
+    // called by the detail popup when it opens and closes
+    pub(crate) fn watch_depth(&mut self, on: bool) {
+        self.depth = None;
+        let _ = self.send(api::UiCommand::Depth(on));
+    }
+
+    fn send(&self, command: api::UiCommand) -> Result<()> {
+        self.sender.blocking_send(command).map_err(|_| ApiStopped.into())
+    }
+
+    // pairs drawn in the widget, the selected one first
+    fn displayed_pairs(&self) -> Vec<String> {
+        let mut displayed = vec![pairs::pair_info(&self.trade_pair).pair_name];
+        for trade_pair in self.config.extra_pairs() {
+            let pair_name = pairs::pair_info(&trade_pair).pair_name;
+            if !displayed.contains(&pair_name) {
+                displayed.push(pair_name);
+            }
+        }
+        displayed
+    }
+
+    // displayed pairs plus the carousel watchlist and the held assets, whose prices are cached
+    fn watched_pairs(&self) -> Vec<String> {
+        let mut watched = self.displayed_pairs();
+        let holding_pairs = self.config.holding_pairs().into_iter().filter_map(|(_, _, pair)| pair);
+        for trade_pair in self.config.carousel_pairs().into_iter().chain(holding_pairs) {
+            let pair_name = pairs::pair_info(&trade_pair).pair_name;
+            if !watched.contains(&pair_name) {
+                watched.push(pair_name);
+            }
+        }
+        watched
+    }
+
+    // amount and current value of every held asset, none until its pair got a price
+    pub(crate) fn holdings(&self) -> Vec<(String, f64, Option<f64>)> {
+        self.config
+            .holding_pairs()
+            .into_iter()
+            .map(|(asset, amount, trade_pair)| {
+                let value = match trade_pair {
+                    Some(trade_pair) => {
+                        let pair_name = pairs::pair_info(&trade_pair).pair_name;
+                        self.prices.get(&pair_name).map(|price| price.tag_price * amount)
+                    }
+                    None => Some(amount),
+                };
+                (asset, amount, value)
+            })
+            .collect()
+    }
+
+    // total value and its 24h change in percent, once every held asset has a price
+    pub(crate) fn portfolio_value(&self) -> Option<(f64, Option<f64>)> {
+        let mut total = 0.;
+        let mut open_total = Some(0.);
+        for (_, amount, trade_pair) in self.config.holding_pairs() {
+            let trade_pair = match trade_pair {
+                Some(trade_pair) => trade_pair,
+                None => {
+                    total += amount;
+                    open_total = open_total.map(|open_total| open_total + amount);
+                    continue;
+                }
+            };
+            let pair_name = pairs::pair_info(&trade_pair).pair_name;
+            total += self.prices.get(&pair_name)?.tag_price * amount;
+            open_total = open_total
+                .zip(self.tickers.get(&pair_name))
+                .map(|(open_total, ticker)| open_total + ticker.open_price * amount);
+        }
+        let change = open_total
+            .filter(|open_total| *open_total > 0.)
+            .map(|open_total| (total - open_total) / open_total * 100.);
+        Some((total, change))
+    }
+
+    // unrealized pnl against the configured entry price and in percent,
+    // for the held amount of the base asset or per unit when nothing is held
+    fn pnl(&self, price: &api::Price) -> Option<(f64, f64)> {
+        let entry = self.config.entry_price(&price.name)?;
+        let amount = self
+            .config
+            .holding_pairs()
+            .into_iter()
+            .find(|(_, _, trade_pair)| {
+                trade_pair.as_ref().is_some_and(|trade_pair| pairs::pair_info(trade_pair).pair_name == price.name)
+            })
+            .map_or(1., |(_, amount, _)| amount);
+        let change = price.tag_price - entry;
+        Some((change * amount, change / entry * 100.))
+    }
+
+    fn portfolio_mode(&self) -> bool {
+        self.config.show_portfolio && !self.config.holdings.is_empty()
+    }
+
+    fn held(&self, pair_name: &str) -> bool {
+        self.config
+            .holding_pairs()
+            .iter()
+            .filter_map(|(_, _, trade_pair)| trade_pair.as_ref())
+            .any(|trade_pair| pairs::pair_info(trade_pair).pair_name == pair_name)
+    }
+
+    // a vertical taskbar sets the width, see follow_taskbars
+    fn update_width(&mut self) {
+        if !self.vertical {
+            self.width = self.horizontal_width();
+        }
+    }
+
+    // side by side gives every pair a column of the configured width
+    fn horizontal_width(&self) -> i32 {
+        match self.config.layout {
+            _ if self.portfolio_mode() => self.config.width,
+            config::Layout::Stacked => self.config.width,
+            config::Layout::SideBySide => self.config.width * self.displayed_pairs().len() as i32,
+        }
+    }
+
+    // along the taskbar, the width or on a vertical one the height
+    fn length(&self) -> i32 {
+        if !self.vertical {
+            return self.horizontal_width();
+        }
+        let count = if self.portfolio_mode() {
+            1
+        } else {
+            self.displayed_pairs().len().max(1)
+        };
+        (self.config.font_size * Self::VERTICAL_CELL_SCALE) as i32 * count as i32
+    }
+
+    // of the status and the polled dots
+    fn dot_size(&self) -> f32 {
+        let size = if self.vertical { self.width } else { self.height };
+        size as f32 * 0.12
+    }
+
+    fn layout_cells(window: &Window, count: usize) -> Vec<RectF> {
+        let (width, height) = (window.width as f32, window.height as f32);
+        (0..count)
+            .map(|index| match window.config.layout {
+                _ if window.vertical => RectF {
+                    X: 0.,
+                    Y: height / count as f32 * index as f32,
+                    Width: width,
+                    Height: height / count as f32,
+                },
+                config::Layout::Stacked => RectF {
+                    X: 0.,
+                    Y: height / count as f32 * index as f32,
+                    Width: width,
+                    Height: height / count as f32,
+                },
+                config::Layout::SideBySide => RectF {
+                    X: width / count as f32 * index as f32,
+                    Y: 0.,
+                    Width: width / count as f32,
+                    Height: height,
+                },
+            })
+            .collect()
+    }
+
+    // pair name tagged with the market of the current exchange
+    fn pair_label(window: &Window, price: &api::Price) -> String {
+        format!(
+            "{}{}",
+            pairs::pair_info(&pairs::TradePair(price.name.clone())).show_name,
+            window.exchange.market().tag()
+        )
+    }
+
+    // the row over the price by Config::label_row, colored like the change
+    // when it is one, none leaves the row empty
+    fn label_text(window: &Window, price: &api::Price, color: u32) -> Option<(String, u32)> {
+        let ticker = window.tickers.get(&price.name);
+        let text = match (window.config.label_row, ticker) {
+            (config::LabelRow::None, _) => return None,
+            (config::LabelRow::ChangePercent, Some(ticker)) => {
+                let change = ticker.change_percent(price.tag_price);
+                (format::localize(&format!("{:+.2}%", change)), window.colors.change(change))
+            }
+            (config::LabelRow::Change, Some(ticker)) => {
+                let change = price.tag_price - ticker.open_price;
+                let sign = if change >= 0. { "+" } else { "" };
+                let content_str = format!("{}{}", sign, pairs::format_price(&price.name, change));
+                (content_str, window.colors.change(change))
+            }
+            (config::LabelRow::Volume, Some(ticker)) => (format::abbreviate(ticker.volume), color),
+            _ => (Self::pair_label(window, price), color),
+        };
+        Some(text)
+    }
+
+    // from the cache on disk, a missing one is downloaded once a session.
+    // runs in paint, which must not wait for the api task
+    fn load_logos(&mut self) {
+        for pair_name in self.displayed_pairs() {
+            let asset = match logos::asset(&pair_name) {
+                Some(asset) if !self.logos.contains_key(&asset) => asset,
+                _ => continue,
+            };
+            let path = logos::path(&asset);
+            let logo = if path.exists() {
+                let logo = render::load_image(&path);
+                if logo.is_none() {
+                    warn!("decode logo {:?} failed", path);
+                }
+                logo
+            } else {
+                let command = api::UiCommand::Logo(asset.clone(), self.config.logo_url.clone());
+                match self.sender.try_send(command) {
+                    // the next paint asks again
+                    Err(mpsc::error::TrySendError::Full(_)) => continue,
+                    _ => None,
+                }
+            };
+            self.logos.insert(asset, logo);
+        }
+    }
+
+    // the logo at the left of the label box, the label is centered in the rest
+    fn draw_logo(renderer: &mut dyn Renderer, window: &Window, price: &api::Price, lay_box: &RectF) -> RectF {
+        let logo = logos::asset(&price.name)
+            .and_then(|asset| window.logos.get(&asset))
+            .and_then(|logo| logo.as_ref())
+            .filter(|_| window.config.show_logos);
+        let logo = match logo {
+            Some(logo) => logo,
+            None => return *lay_box,
+        };
+        let side = (lay_box.Height * 0.8).min(lay_box.Width / 3.);
+        let logo_box = RectF {
+            X: lay_box.X + side * 0.25,
+            Y: lay_box.Y + (lay_box.Height - side) / 2.,
+            Width: side,
+            Height: side,
+        };
+        renderer.draw_image(logo, &logo_box);
+        RectF {
+            X: lay_box.X + side * 1.25,
+            Width: lay_box.Width - side * 1.25,
+            ..*lay_box
+        }
+    }
+
+    // one line "pair price" for the rows of a stacked layout, the price is
+    // colored by the 24h change when known
+    fn draw_price_row(
+        renderer: &mut dyn Renderer,
+        color: u32,
+        window: &Window,
+        price: &api::Price,
+        cell: &RectF,
+    ) {
+        let lay_box_pair = RectF {
+            Width: cell.Width / 2.,
+            ..*cell
+        };
+        let lay_box_price = RectF {
+            X: cell.X + cell.Width / 2.,
+            Width: cell.Width / 2.,
+            ..*cell
+        };
+        let lay_box_label = Self::draw_logo(renderer, window, price, &lay_box_pair);
+        if let Some((label, label_color)) = Self::label_text(window, price, color) {
+            renderer.draw_label(&label, label_color, &lay_box_label);
+        }
+        if let Some(bbo) = window.bbo_of(&price.name) {
+            let content_str = format!(
+                "{}/{}",
+                pairs::format_price(&price.name, bbo.bid_price),
+                pairs::format_price(&price.name, bbo.ask_price)
+            );
+            renderer.draw_text(&content_str, color, &lay_box_price);
+            return;
+        }
+        let content_str = pairs::format_price(&price.name, window.shown_price(price));
+        let ticker = window.tickers.get(&price.name).filter(|_| window.status != api::Status::Stale);
+        let color = match ticker {
+            Some(ticker) => window.colors.change(ticker.change_percent(price.tag_price)),
+            None => color,
+        };
+        renderer.draw_text(&content_str, color, &lay_box_price);
+    }
+
+    fn draw_price(
+        renderer: &mut dyn Renderer,
+        color: u32,
+        window: &Window,
+        price: &api::Price,
+        cell: &RectF,
+    ) {
+        Self::draw_sparkline(renderer, cell, &price.history);
+        // a vertical taskbar is too narrow for a row, each pair gets a full cell
+        if !window.vertical && cell.Height < window.height as f32 / 1.5 {
+            Self::draw_price_row(renderer, color, window, price, cell);
+            return;
+        }
+        if let Some(bbo) = window.bbo_of(&price.name) {
+            Self::draw_bbo(renderer, color, window, price, bbo, cell);
+            return;
+        }
+        let ticker = window.tickers.get(&price.name);
+        let funding = window
+            .fundings
+            .get(&price.name)
+            .filter(|_| window.config.show_funding);
+        let pnl = window.pnl(price);
+        // with 24h stats, funding or an entry price there is a third row
+        let third_row = ticker.is_some() || funding.is_some() || pnl.is_some();
+        let (lay_box_pair, lay_box_price) = if third_row {
+            (
+                RectF {
+                    X: cell.X,
+                    Y: cell.Y,
+                    Width: cell.Width,
+                    Height: cell.Height / 3.,
+                },
+                RectF {
+                    X: cell.X,
+                    Y: cell.Y + cell.Height / 3.,
+                    Width: cell.Width,
+                    Height: cell.Height / 3.,
+                },
+            )
+        } else {
+            (
+                RectF {
+                    X: cell.X,
+                    Y: cell.Y + cell.Height * 0.1,
+                    Width: cell.Width,
+                    Height: cell.Height / 2.,
+                },
+                RectF {
+                    X: cell.X,
+                    Y: cell.Y + cell.Height / 2.2,
+                    Width: cell.Width,
+                    Height: cell.Height / 2.,
+                },
+            )
+        };
+        let content_str = pairs::format_price(&price.name, window.shown_price(price));
+        renderer.draw_text(&content_str, color, &lay_box_price);
+        let lay_box_label = Self::draw_logo(renderer, window, price, &lay_box_pair);
+        if let Some((label, label_color)) = Self::label_text(window, price, color) {
+            renderer.draw_label(&label, label_color, &lay_box_label);
+        }
+        let lay_box_third = RectF {
+            X: cell.X,
+            Y: cell.Y + cell.Height * 2. / 3.,
+            Width: cell.Width,
+            Height: cell.Height / 3.,
+        };
+        if let Some((pnl, percent)) = pnl {
+            let content_str = format!(
+                "{} {}%",
+                format::localize(&format!("{:+.2}", pnl)),
+                format::localize(&format!("{:+.2}", percent))
+            );
+            renderer.draw_text(&content_str, window.colors.change(pnl), &lay_box_third);
+        } else if let Some(funding) = funding {
+            let content_str = format!(
+                "{:+.4}% {}",
+                funding.rate * 100.,
+                Self::countdown(funding.next_time)
+            );
+            renderer.draw_text(&content_str, window.colors.secondary, &lay_box_third);
+        } else if let Some(ticker) = ticker {
+            let change = ticker.change_percent(price.tag_price);
+            let content_str = format::localize(&format!("{:+.2}%", change));
+            renderer.draw_text(&content_str, window.colors.change(change), &lay_box_third);
+        }
+        if price.polled {
+            // small dot in the top right corner marks rest-polled data
+            let dot_size = window.dot_size();
+            let dot = RectF {
+                X: cell.X + cell.Width - dot_size * 1.5,
+                Y: cell.Y + dot_size * 0.5,
+                Width: dot_size,
+                Height: dot_size,
+            };
+            renderer.fill_ellipse(color, &dot);
+        }
+    }
+
+    // total of the holdings over their 24h change, in place of the pairs
+    fn draw_portfolio(renderer: &mut dyn Renderer, color: u32, window: &Window, cell: &RectF) {
+        let row = |index: f32| RectF {
+            X: cell.X,
+            Y: cell.Y + cell.Height * index / 3.,
+            Width: cell.Width,
+            Height: cell.Height / 3.,
+        };
+        renderer.draw_label(i18n::tr("组合"), window.colors.secondary, &row(0.));
+        let (total, change) = match window.portfolio_value() {
+            Some(portfolio) => portfolio,
+            None => {
+                renderer.draw_text("-", color, &row(1.));
+                return;
+            }
+        };
+        renderer.draw_text(&fiat::format_usd(total), color, &row(1.));
+        if let Some(change) = change {
+            let content_str = format::localize(&format!("{:+.2}%", change));
+            renderer.draw_text(&content_str, window.colors.change(change), &row(2.));
+        }
+    }
+
+    // best bid/offer of a pair when the bbo mode is on
+    fn bbo_of(&self, pair_name: &str) -> Option<&api::Bbo> {
+        self.bbos.get(pair_name).filter(|_| self.config.show_bbo)
+    }
+
+    // pair, bid/ask and the spread in bps on three rows
+    fn draw_bbo(
+        renderer: &mut dyn Renderer,
+        color: u32,
+        window: &Window,
+        price: &api::Price,
+        bbo: &api::Bbo,
+        cell: &RectF,
+    ) {
+        let row = |index: f32| RectF {
+            X: cell.X,
+            Y: cell.Y + cell.Height * index / 3.,
+            Width: cell.Width,
+            Height: cell.Height / 3.,
+        };
+        let show_name = Self::pair_label(window, price);
+        renderer.draw_label(&show_name, color, &row(0.));
+        let content_str = format!(
+            "{}/{}",
+            pairs::format_price(&price.name, bbo.bid_price),
+            pairs::format_price(&price.name, bbo.ask_price)
+        );
+        renderer.draw_text(&content_str, color, &row(1.));
+        let content_str = format!("{:.1}bps", bbo.spread_bps());
+        renderer.draw_text(&content_str, window.colors.secondary, &row(2.));
+    }
+
+    // time left until a ms timestamp, e.g. 2h13m
+    pub(crate) fn countdown(time_stamp: u64) -> String {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_millis() as u64);
+        let minutes = time_stamp.saturating_sub(now) / 60_000;
+        format!("{}h{:02}m", minutes / 60, minutes % 60)
+    }
+
+    // green when live, orange while reconnecting, red after a timeout, grey
+    // when stale, blue when polled
+    fn draw_status(renderer: &mut dyn Renderer, window: &Window) {
+        let color = match window.status {
+            api::Status::Connected => Self::make_argb(255, 0, 160, 0),
+            api::Status::Reconnecting => Self::make_argb(255, 255, 140, 0),
+            api::Status::TimedOut(_) => Self::make_argb(255, 220, 20, 60),
+            api::Status::Stale => Self::make_argb(255, 150, 150, 150),
+            api::Status::Polling => Self::make_argb(255, 30, 144, 255),
+        };
+        let dot_size = window.dot_size();
+        let dot = RectF {
+            X: dot_size * 0.5,
+            Y: dot_size * 0.5,
+            Width: dot_size,
+            Height: dot_size,
+        };
+        renderer.fill_ellipse(color, &dot);
+    }
+
+    fn draw_notify(renderer: &mut dyn Renderer, color: u32, window: &Window, not_msg: &str) {
+        let lay_box = RectF {
+            X: 0.,
+            Y: 0.,
+            Width: window.width as f32,
+            Height: window.height as f32,
+        };
+        renderer.draw_text(not_msg, color, &lay_box);
+    }
+
+    fn fresh_window(hwnd: &HWND, wparam: &WPARAM) -> Result<()> {
+        unsafe {
+            let api_msg = Box::from_raw(wparam.0 as *mut api::ApiMessage);
+            let window = &mut *(GetWindowLongPtrW(*hwnd, GWLP_USERDATA) as *mut Self);
+            if let api::ApiMessage::Alert(_) = *api_msg {
+                window.alert_until = Some(std::time::Instant::now() + Self::ALERT_FLASH);
+                return Ok(());
+            }
+            let watched = window.watched_pairs();
+            window.prices.retain(|name, _| watched.contains(name));
+            window.tickers.retain(|name, _| watched.contains(name));
+            window.fundings.retain(|name, _| watched.contains(name));
+            window.bbos.retain(|name, _| watched.contains(name));
+            match *api_msg {
+                api::ApiMessage::Ticker(ticker) => {
+                    if watched.contains(&ticker.name) {
+                        window.tickers.insert(ticker.name.clone(), ticker);
+                    }
+                    Ok(())
+                }
+                api::ApiMessage::Funding(funding) => {
+                    if watched.contains(&funding.name) {
+                        window.fundings.insert(funding.name.clone(), funding);
+                    }
+                    Ok(())
+                }
+                api::ApiMessage::Bbo(bbo) => {
+                    if !watched.contains(&bbo.name) {
+                        return Ok(());
+                    }
+                    // quotes move without trades, so they repaint too
+                    let repaint = window.config.show_bbo
+                        && window.displayed_pairs().contains(&bbo.name)
+                        && window.prices.contains_key(&bbo.name);
+                    window.bbos.insert(bbo.name.clone(), bbo);
+                    if repaint {
+                        Self::paint(hwnd, window, None)?;
+                    }
+                    Ok(())
+                }
+                api::ApiMessage::Depth(depth) => {
+                    if depth.name == pairs::pair_info(&window.trade_pair).pair_name {
+                        window.depth = Some(depth);
+                        DetailWindow::refresh();
+                    }
+                    Ok(())
+                }
+                api::ApiMessage::Price(price) => {
+                    if !watched.contains(&price.name) {
+                        return Ok(());
+                    }
+                    let displayed = if window.portfolio_mode() {
+                        window.held(&price.name)
+                    } else {
+                        window.displayed_pairs().contains(&price.name)
+                    };
+                    if price.name == pairs::pair_info(&window.trade_pair).pair_name {
+                        window.last_tick = Some(std::time::Instant::now());
+                        window.switch_started = None;
+                        Self::arm_stale_timer(hwnd, window);
+                        if window.status == api::Status::Stale {
+                            window.status = api::Status::Connected;
+                        }
+                        DetailWindow::refresh();
+                    } else if window.held(&price.name) {
+                        // the popup lists the holdings too
+                        DetailWindow::refresh();
+                    }
+                    if displayed {
+                        Self::flash_large_move(hwnd, window, &price);
+                        Self::tween_price(hwnd, window, &price);
+                    }
+                    window.prices.insert(price.name.clone(), price);
+                    if displayed {
+                        Self::paint(hwnd, window, None)?;
+                    }
+                    Ok(())
+                }
+                api::ApiMessage::Notify(not_msg) => Self::paint(hwnd, window, Some(&not_msg)),
+                api::ApiMessage::Latency(latency) => {
+                    window.latency = Some(latency);
+                    DetailWindow::refresh();
+                    Ok(())
+                }
+                api::ApiMessage::Proxy(health) => {
+                    window.proxy_health = Some(health);
+                    DetailWindow::refresh();
+                    Ok(())
+                }
+                api::ApiMessage::Status(status) => {
+                    window.status = status;
+                    // stale too when no price follows the reconnect
+                    if status == api::Status::Connected {
+                        Self::arm_stale_timer(hwnd, window);
+                    }
+                    // without prices a notice is on screen, the next price repaints
+                    if !window.prices.is_empty() {
+                        Self::paint(hwnd, window, None)?;
+                    }
+                    Ok(())
+                }
+                api::ApiMessage::Logo(asset) => {
+                    // decoded on the next paint
+                    window.logos.remove(&asset);
+                    if !window.prices.is_empty() {
+                        Self::paint(hwnd, window, None)?;
+                    }
+                    Ok(())
+                }
+                api::ApiMessage::Update(tag_name) => Self::announce_update(hwnd, window, tag_name),
+                api::ApiMessage::Alert(_) => Ok(()),
+            }
+        }
+    }
+
+    // draws the cached prices of the displayed pairs, or a notice instead
+    fn paint(hwnd: &HWND, window: &mut Window, not_msg: Option<&str>) -> Result<()> {
+        let alerting = window
+            .alert_until
+            .is_some_and(|until| std::time::Instant::now() < until);
+        let flash = window
+            .flash
+            .map(|(since, color)| (animation::fade(since.elapsed(), animation::FLASH), color))
+            .filter(|(strength, _)| *strength > 0.);
+        let clear = if alerting {
+            Self::make_argb(160, 255, 140, 0)
+        } else if let Some((strength, color)) = flash {
+            animation::with_alpha(color, 160, strength)
+        } else {
+            window.colors.background
+        };
+        let color = window.colors.text;
+        if window.config.show_logos {
+            window.load_logos();
+        }
+        // out of the window while the draw functions borrow it
+        let mut renderer = window
+            .renderer
+            .take()
+            .unwrap_or_else(|| render::create(window.config.render_backend));
+        let started = std::time::Instant::now();
+        // the same frame again into each copy on the other taskbars
+        let targets: Vec<HWND> = std::iter::once(*hwnd)
+            .chain(window.mirrors.iter().map(|(mirror, _)| HWND(*mirror as *mut c_void)))
+            .collect();
+        let result = targets.into_iter().try_for_each(|target| {
+            renderer
+                .begin(target, &window.fonts, clear)
+                .and_then(|()| {
+                    Self::draw_frame(renderer.as_mut(), color, window, not_msg);
+                    renderer.end()
+                })
+        });
+        metrics::observe_render(started.elapsed());
+        match result {
+            Ok(()) => {
+                window.render_failed = false;
+                window.renderer = Some(renderer);
+            }
+            Err(err) if !window.render_failed => {
+                // e.g. D2DERR_RECREATE_TARGET after a device loss, the renderer
+                // dropped its target and makes a new one for the frame again
+                warn!("render err:{:?}, drawing again", err);
+                window.render_failed = true;
+                window.renderer = Some(renderer);
+                return Self::paint(hwnd, window, not_msg);
+            }
+            Err(err) => {
+                // gdi+ for the rest of the session, the next price repaints
+                warn!("render err:{:?}, falling back to gdi+", err);
+                window.render_failed = false;
+                window.renderer = Some(render::create(config::RenderBackend::GdiPlus));
+            }
+        }
+        // the stats under the cursor follow the ticks
+        if window.tooltip.as_ref().is_some_and(|tooltip| tooltip.is_shown()) {
+            Self::show_tooltip(hwnd, window)?;
+        }
+        Ok(())
+    }
+
+    fn draw_frame(renderer: &mut dyn Renderer, color: u32, window: &Window, not_msg: Option<&str>) {
+        match not_msg {
+            None if window.portfolio_mode() => {
+                let cell = RectF {
+                    X: 0.,
+                    Y: 0.,
+                    Width: window.width as f32,
+                    Height: window.height as f32,
+                };
+                Self::draw_portfolio(renderer, color, window, &cell);
+            }
+            None => {
+                // a frozen price is greyed out, next to the grey status dot
+                let color = if window.status == api::Status::Stale {
+                    window.colors.secondary
+                } else {
+                    color
+                };
+                let displayed = window.displayed_pairs();
+                let cells = Self::layout_cells(window, displayed.len());
+                for (name, cell) in displayed.iter().zip(cells.iter()) {
+                    if let Some(price) = window.prices.get(name) {
+                        Self::draw_price(renderer, color, window, price, cell);
+                    }
+                }
+            }
+            Some(not_msg) => Self::draw_notify(renderer, color, window, not_msg),
+        }
+        Self::draw_status(renderer, window);
+    }
+
+    // shared by the widget and the tray icon, commands go to the widget's WM_COMMAND
+    pub fn create_context_menu(window: &mut Window) -> Result<HMENU> {
+        unsafe {
+            let menu = CreatePopupMenu()?;
+            // a menu left half built is not shown
+            if let Err(err) = Self::fill_context_menu(window, menu) {
+                let _ = DestroyMenu(menu);
+                return Err(err);
+            }
+            Ok(menu)
+        }
+    }
+
+    // a radio bullet on the first item that is selected, of the items with ids from first on
+    fn check_radio<T>(menu: HMENU, first: usize, items: &[T], selected: impl Fn(&T) -> bool) {
+        if let Some(index) = items.iter().position(selected) {
+            let last = first + items.len() - 1;
+            unsafe {
+                let _ = CheckMenuRadioItem(menu, first as u32, last as u32, (first + index) as u32, MF_BYCOMMAND.0);
+            }
+        }
+    }
+
+    fn fill_context_menu(window: &mut Window, menu: HMENU) -> Result<()> {
+        unsafe {
+            // nothing to pick again until the switch went through
+            let pick_flags = if window.switching() {
+                MF_STRING | MF_GRAYED
+            } else {
+                MF_STRING
+            };
+            // one click back to the pairs switched to before
+            window.menu_recent = config::State::load().recent(&window.trade_pair);
+            if !window.menu_recent.is_empty() {
+                AppendMenuW(menu, MF_STRING | MF_GRAYED, 0, &HSTRING::from(i18n::tr("最近使用")))?;
+                for (index, trade_pair) in window.menu_recent.iter().enumerate() {
+                    let info = pairs::pair_info(trade_pair);
+                    AppendMenuW(
+                        menu,
+                        pick_flags,
+                        Self::COMAMND_RECENT_BASE + index,
+                        &HSTRING::from(&info.show_name),
+                    )?;
+                }
+                AppendMenuW(menu, MF_SEPARATOR, 0, None)?;
+            }
+            let favorites = pairs::PAIR_REGISTRY.read().unwrap().favorites();
+            for (index, info) in favorites.iter().enumerate() {
+                AppendMenuW(
+                    menu,
+                    pick_flags,
+                    Self::COMAMND_PAIR_BASE + index,
+                    &HSTRING::from(&info.show_name),
+                )
+                ?;
+            }
+            Self::check_radio(menu, Self::COMAMND_PAIR_BASE, &favorites, |info| {
+                info.trade_pair() == window.trade_pair
+            });
+            let starred = favorites.iter().any(|info| info.trade_pair() == window.trade_pair);
+            let mut star_flags = if starred { MF_STRING | MF_CHECKED } else { MF_STRING };
+            // an empty list would bring the built-in pairs back
+            if starred && favorites.len() == 1 {
+                star_flags |= MF_GRAYED;
+            }
+            AppendMenuW(menu, star_flags, Self::COMAMND_FAVORITE, &HSTRING::from(i18n::tr("收藏当前交易对")))?;
+            AppendMenuW(menu, pick_flags, Self::COMAMND_SEARCH, &HSTRING::from(i18n::tr("搜索交易对...")))?;
+            // pairs discovered from the exchange, grouped by quote asset when
+            // there are many, windows scrolls long menus
+            let listed = pairs::PAIR_REGISTRY.read().unwrap().listed();
+            let grouped = listed.len() > Self::MENU_GROUP_MIN;
+            let groups = if grouped {
+                pairs::by_quote(listed)
+            } else {
+                vec![("", listed)]
+            };
+            let mut listed = Vec::new();
+            if groups.iter().any(|(_, group)| !group.is_empty()) {
+                let pair_menu = CreatePopupMenu()?;
+                for (quote, group) in groups {
+                    let first = Self::COMAMND_PAIR_BASE + favorites.len() + listed.len();
+                    let group_menu = if grouped { CreatePopupMenu()? } else { pair_menu };
+                    for (index, info) in group.iter().enumerate() {
+                        let name = if favorites.contains(info) {
+                            format!("★ {}", info.show_name)
+                        } else {
+                            info.show_name.clone()
+                        };
+                        AppendMenuW(group_menu, pick_flags, first + index, &HSTRING::from(&name))?;
+                    }
+                    Self::check_radio(group_menu, first, &group, |info| {
+                        info.trade_pair() == window.trade_pair
+                    });
+                    if grouped {
+                        let label = if quote.is_empty() { i18n::tr("其他") } else { quote };
+                        AppendMenuW(pair_menu, MF_POPUP, group_menu.0 as usize, &HSTRING::from(label))?;
+                    }
+                    listed.extend(group);
+                }
+                AppendMenuW(menu, MF_POPUP, pair_menu.0 as usize, &HSTRING::from(i18n::tr("全部交易对")))?;
+            }
+            window.menu_pairs = favorites
+                .iter()
+                .chain(listed.iter())
+                .map(|info| info.trade_pair())
+                .collect();
+            AppendMenuW(menu, MF_SEPARATOR, 0, None)?;
+            for (index, exchange) in exchange::EXCHANGES.iter().enumerate() {
+                AppendMenuW(
+                    menu,
+                    pick_flags,
+                    Self::COMAMND_EXCHANGE_BASE + index,
+                    &HSTRING::from(exchange.show_name()),
+                )
+                ?;
+            }
+            Self::check_radio(menu, Self::COMAMND_EXCHANGE_BASE, &exchange::EXCHANGES, |exchange| {
+                exchange::same_exchange(*exchange, window.exchange)
+            });
+            AppendMenuW(menu, MF_SEPARATOR, 0, None)?;
+            let copy_flags = if window.current().0.is_some() {
+                MF_STRING
+            } else {
+                MF_STRING | MF_GRAYED
+            };
+            AppendMenuW(menu, copy_flags, Self::COMAMND_COPY, &HSTRING::from(i18n::tr("复制价格")))?;
+            let mute_flags = if notify::is_muted() {
+                MF_STRING | MF_CHECKED
+            } else {
+                MF_STRING
+            };
+            AppendMenuW(menu, mute_flags, Self::COMAMND_MUTE, &HSTRING::from(i18n::tr("提醒静音")))?;
+            if !window.config.carousel.is_empty() {
+                let carousel_flags = if window.carousel_paused {
+                    MF_STRING | MF_CHECKED
+                } else {
+                    MF_STRING
+                };
+                AppendMenuW(menu, carousel_flags, Self::COMAMND_CAROUSEL, &HSTRING::from(i18n::tr("暂停轮播")))?;
+            }
+            let market_flags = match exchange::counterpart(window.exchange, window.exchange.market().other()) {
+                Some(_) => MF_STRING,
+                None => MF_STRING | MF_GRAYED,
+            };
+            let market_text = match window.exchange.market() {
+                exchange::Market::Spot => i18n::tr("切换到永续"),
+                exchange::Market::Perp => i18n::tr("切换到现货"),
+            };
+            AppendMenuW(menu, market_flags, Self::COMAMND_MARKET, &HSTRING::from(market_text))?;
+            let price_type_menu = CreatePopupMenu()?;
+            for (index, price_type) in exchange::PriceType::ALL.iter().enumerate() {
+                let mut flags = MF_STRING;
+                if !window.exchange.price_types().contains(price_type) {
+                    flags |= MF_GRAYED;
+                }
+                if window.exchange.price_type() == *price_type {
+                    flags |= MF_CHECKED;
+                }
+                AppendMenuW(
+                    price_type_menu,
+                    flags,
+                    Self::COMAMND_PRICE_TYPE_BASE + index,
+                    &HSTRING::from(price_type.label()),
+                )
+                ?;
+            }
+            AppendMenuW(menu, MF_POPUP, price_type_menu.0 as usize, &HSTRING::from(i18n::tr("价格类型")))?;
+            let export_menu = CreatePopupMenu()?;
+            for (index, (label, _)) in Self::EXPORT_RANGES.iter().enumerate() {
+                AppendMenuW(
+                    export_menu,
+                    MF_STRING,
+                    Self::COMAMND_EXPORT_BASE + index,
+                    &HSTRING::from(i18n::tr(label)),
+                )
+                ?;
+            }
+            AppendMenuW(menu, MF_POPUP, export_menu.0 as usize, &HSTRING::from(i18n::tr("导出历史")))?;
+            let width_menu = CreatePopupMenu()?;
+            for (index, width) in Self::WIDTHS.iter().enumerate() {
+                let flags = if *width == window.config.width {
+                    MF_STRING | MF_CHECKED
+                } else {
+                    MF_STRING
+                };
+                AppendMenuW(
+                    width_menu,
+                    flags,
+                    Self::COMAMND_WIDTH_BASE + index,
+                    &HSTRING::from(&width.to_string()),
+                )
+                ?;
+            }
+            AppendMenuW(menu, MF_POPUP, width_menu.0 as usize, &HSTRING::from(i18n::tr("宽度")))?;
+            let theme_menu = CreatePopupMenu()?;
+            for (index, theme) in theme::ThemeName::ALL.iter().enumerate() {
+                let flags = if *theme == window.config.theme {
+                    MF_STRING | MF_CHECKED
+                } else {
+                    MF_STRING
+                };
+                AppendMenuW(
+                    theme_menu,
+                    flags,
+                    Self::COMAMND_THEME_BASE + index,
+                    &HSTRING::from(theme.label()),
+                )
+                ?;
+            }
+            AppendMenuW(menu, MF_POPUP, theme_menu.0 as usize, &HSTRING::from(i18n::tr("主题")))?;
+            let label_row_menu = CreatePopupMenu()?;
+            for (index, label_row) in config::LabelRow::ALL.iter().enumerate() {
+                let flags = if *label_row == window.config.label_row {
+                    MF_STRING | MF_CHECKED
+                } else {
+                    MF_STRING
+                };
+                AppendMenuW(
+                    label_row_menu,
+                    flags,
+                    Self::COMAMND_LABEL_ROW_BASE + index,
+                    &HSTRING::from(label_row.label()),
+                )
+                ?;
+            }
+            AppendMenuW(menu, MF_POPUP, label_row_menu.0 as usize, &HSTRING::from(i18n::tr("第二行")))?;
+            let taskbars = taskbar::all();
+            if taskbars.len() > 1 {
+                let taskbar_menu = CreatePopupMenu()?;
+                for (index, taskbar) in taskbars.iter().take(Self::MAX_TASKBARS).enumerate() {
+                    let flags = if taskbar.0 as usize == window.taskbar {
+                        MF_STRING | MF_CHECKED
+                    } else {
+                        MF_STRING
+                    };
+                    let text = match index {
+                        0 => i18n::tr("主显示器").to_string(),
+                        index => i18n::fill("显示器{}", &[&(index + 1)]),
+                    };
+                    AppendMenuW(
+                        taskbar_menu,
+                        flags,
+                        Self::COMAMND_TASKBAR_BASE + index,
+                        &HSTRING::from(&text),
+                    )
+                    ?;
+                }
+                AppendMenuW(taskbar_menu, MF_SEPARATOR, 0, None)?;
+                let clone_flags = if window.config.taskbar_clone {
+                    MF_STRING | MF_CHECKED
+                } else {
+                    MF_STRING
+                };
+                AppendMenuW(taskbar_menu, clone_flags, Self::COMAMND_TASKBAR_CLONE, &HSTRING::from(i18n::tr("所有任务栏")))?;
+                AppendMenuW(menu, MF_POPUP, taskbar_menu.0 as usize, &HSTRING::from(i18n::tr("任务栏")))?;
+            }
+            let autostart_flags = if autostart::is_enabled() {
+                MF_STRING | MF_CHECKED
+            } else {
+                MF_STRING
+            };
+            AppendMenuW(menu, autostart_flags, Self::COMAMND_AUTOSTART, &HSTRING::from(i18n::tr("开机启动")))?;
+            if let Some(release) = update::available() {
+                let text = i18n::fill("下载新版本 {}...", &[&release.tag_name]);
+                AppendMenuW(menu, MF_STRING, Self::COMAMND_UPDATE, &HSTRING::from(&text))?;
+            }
+            if window.config.taskbar_offset != 0 {
+                AppendMenuW(menu, MF_STRING, Self::COMAMND_RESET_POSITION, &HSTRING::from(i18n::tr("恢复默认位置")))?;
+            }
+            AppendMenuW(menu, MF_STRING, Self::COMAMND_SETTINGS, &HSTRING::from(i18n::tr("设置...")))?;
+            AppendMenuW(menu, MF_STRING, Self::COMAMND_EXIT, &HSTRING::from(i18n::tr("退出")))?;
+        }
+        Ok(())
+    }
+
+    fn restart_carousel(&self) {
+        if self.hwnd == 0 {
+            return;
+        }
+        let hwnd = HWND(self.hwnd as *mut c_void);
+        unsafe {
+            let _ = KillTimer(hwnd, Self::TIMER_CAROUSEL);
+            if !self.config.carousel.is_empty() {
+                SetTimer(
+                    hwnd,
+                    Self::TIMER_CAROUSEL,
+                    self.config.carousel_seconds.max(1) * 1000,
+                    None,
+                );
+            }
+        }
+    }
+
+    fn restart_apps_timer(&self) {
+        if self.hwnd == 0 {
+            return;
+        }
+        let hwnd = HWND(self.hwnd as *mut c_void);
+        unsafe {
+            let _ = KillTimer(hwnd, Self::TIMER_APPS);
+            if self.config.taskbar_anchor == config::TaskbarAnchor::Apps {
+                SetTimer(hwnd, Self::TIMER_APPS, taskbar::APPS_REFRESH.as_millis() as u32, None);
+            }
+        }
+    }
+
+    // the carousel pairs are always subscribed, so the cached price is shown at once.
+    // unlike a menu switch the pair is not remembered as last_pair
+    fn rotate_carousel(hwnd: &HWND, window: &mut Window) -> Result<()> {
+        let carousel = window.config.carousel_pairs();
+        if carousel.is_empty() {
+            return Ok(());
+        }
+        let next = match carousel.iter().position(|pair| {
+            pairs::pair_info(pair).pair_name == pairs::pair_info(&window.trade_pair).pair_name
+        }) {
+            Some(index) => (index + 1) % carousel.len(),
+            None => 0,
+        };
+        window.trade_pair = carousel[next].clone();
+        window.update_width();
+        window.send(api::UiCommand::TradePair(window.trade_pair.clone()))?;
+        Self::paint(hwnd, window, None)
+    }
+
+    // one pair per notch, scrolling down moves forward through the list
+    fn on_wheel(hwnd: &HWND, window: &mut Window, delta: i32) -> Result<()> {
+        let notch = WHEEL_DELTA as i32;
+        // a turn of the other way starts over
+        if window.wheel_delta.signum() * delta.signum() < 0 {
+            window.wheel_delta = 0;
+        }
+        window.wheel_delta += delta;
+        while window.wheel_delta.abs() >= notch {
+            let forward = window.wheel_delta < 0;
+            window.wheel_delta -= notch * window.wheel_delta.signum();
+            Self::step_pair(hwnd, window, forward)?;
+        }
+        Ok(())
+    }
+
+    // the next or previous pair of the carousel, or of the menu without one
+    fn step_pair(hwnd: &HWND, window: &mut Window, forward: bool) -> Result<()> {
+        let mut watchlist = window.config.carousel_pairs();
+        if watchlist.is_empty() {
+            watchlist = pairs::PAIR_REGISTRY
+                .read()
+                .unwrap()
+                .favorites()
+                .iter()
+                .map(|info| info.trade_pair())
+                .collect();
+        }
+        if watchlist.is_empty() {
+            return Ok(());
+        }
+        let current = pairs::pair_info(&window.trade_pair).pair_name;
+        let next = match watchlist
+            .iter()
+            .position(|pair| pairs::pair_info(pair).pair_name == current)
+        {
+            Some(index) if forward => (index + 1) % watchlist.len(),
+            Some(index) => (index + watchlist.len() - 1) % watchlist.len(),
+            None => 0,
+        };
+        Self::switch_trade_pair(window, watchlist[next].clone())?;
+        // a pair that is already streaming gets no notice that would repaint
+        Self::paint(hwnd, window, None)
+    }
+
+    // a pair from the picker, switched to like a menu pick
+    pub(crate) fn pick_pair(&mut self, trade_pair: pairs::TradePair) -> Result<()> {
+        Self::switch_trade_pair(self, trade_pair)
+    }
+
+    fn switch_trade_pair(window: &mut Window, trade_pair: pairs::TradePair) -> Result<()> {
+        if window.trade_pair != trade_pair {
+            window.trade_pair = trade_pair.clone();
+            window.update_width();
+            let mut state = config::State::load();
+            state.remember_pair(&trade_pair);
+            if let Err(err) = state.save() {
+                error!("save state err:{:?}", err);
+            }
+            window.send(api::UiCommand::TradePair(trade_pair.clone()))?;
+            window.switch_started = Some(std::time::Instant::now());
+            // follow the market configured or last picked for the pair
+            if let Some(new_exchange) = state
+                .preferred_market(&window.config, &trade_pair)
+                .and_then(|market| exchange::counterpart(window.exchange, market))
+            {
+                Self::switch_exchange(window, new_exchange)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn switch_exchange(window: &mut Window, new_exchange: &'static dyn exchange::Exchange) -> Result<()> {
+        if !exchange::same_exchange(window.exchange, new_exchange) {
+            window.exchange = new_exchange;
+            window.prices.clear();
+            window.tickers.clear();
+            window.fundings.clear();
+            window.bbos.clear();
+            window.depth = None;
+            window.send(api::UiCommand::Exchange(new_exchange))?;
+            window.switch_started = Some(std::time::Instant::now());
+        }
+        Ok(())
+    }
+
+    // the selected pair joins or leaves the top of the menu, saved to the config
+    fn toggle_favorite(window: &mut Window) {
+        let info = pairs::pair_info(&window.trade_pair);
+        window.config.toggle_favorite(&info);
+        if let Err(err) = window.config.save() {
+            error!("save config err:{:?}", err);
+        }
+        pairs::init(&window.config);
+    }
+
+    // once per release, the state is only written on the ui thread
+    fn announce_update(hwnd: &HWND, window: &mut Window, tag_name: String) -> Result<()> {
+        let mut state = config::State::load();
+        if state.notified_update.as_deref() == Some(tag_name.as_str()) {
+            return Ok(());
+        }
+        info!("new release {}", tag_name);
+        let text = i18n::fill("新版本 {}", &[&tag_name]);
+        notify::toast(i18n::tr("有可用更新"), &i18n::fill("{}, 可从菜单下载", &[&text]));
+        state.notified_update = Some(tag_name);
+        if let Err(err) = state.save() {
+            warn!("save state err:{:?}", err);
+        }
+        Self::paint(hwnd, window, Some(&text))
+    }
+
+    // remembered per pair, so selecting the pair again returns to this market
+    fn toggle_market(window: &mut Window) -> Result<()> {
+        let market = window.exchange.market().other();
+        let new_exchange = match exchange::counterpart(window.exchange, market) {
+            Some(new_exchange) => new_exchange,
+            None => return Ok(()),
+        };
+        let mut state = config::State::load();
+        state.pair_markets.insert(window.trade_pair.0.clone(), market);
+        if let Err(err) = state.save() {
+            error!("save state err:{:?}", err);
+        }
+        Self::switch_exchange(window, new_exchange)
+    }
+
+    // saved to the config like the settings window does, the api reconnects on it
+    fn select_price_type(window: &mut Window, price_type: exchange::PriceType) -> Result<()> {
+        if window.exchange.price_type() == price_type {
+            return Ok(());
+        }
+        window.config.price_type = Some(price_type);
+        if let Err(err) = window.config.save() {
+            error!("save config err:{:?}", err);
+        }
+        window.prices.clear();
+        window.send(api::UiCommand::Config(Box::new(window.config.clone())))
+    }
+
+    // from the tray too, the widget may be closed to the tray
+    pub(crate) unsafe fn answer_ipc(window: &mut Window, wparam: WPARAM) {
+        let call = Box::from_raw(wparam.0 as *mut ipc::Call);
+        let result = Self::on_ipc(window, call.request).map_err(|err| err.to_string());
+        let _ = call.reply.send(result);
+    }
+
+    fn on_ipc(window: &mut Window, request: ipc::Request) -> Result<serde_json::Value> {
+        match request {
+            ipc::Request::Switch { pair } => {
+                let trade_pair =
+                    pairs::find(&pair).ok_or_else(|| anyhow::anyhow!("unknown pair: {}", pair))?;
+                Self::switch_trade_pair(window, trade_pair)?;
+                Ok(window.price_json())
+            }
+            ipc::Request::Price => Ok(window.price_json()),
+            ipc::Request::Pause => {
+                if window.config.carousel.is_empty() {
+                    return Err(anyhow::anyhow!("no carousel in the config"));
+                }
+                window.carousel_paused = !window.carousel_paused;
+                Ok(serde_json::json!({ "paused": window.carousel_paused }))
+            }
+            ipc::Request::Reload => {
+                let config = config::Config::load();
+                window.apply_config(config.clone());
+                window.send(api::UiCommand::Config(Box::new(config)))?;
+                Ok(serde_json::Value::Null)
+            }
+            // answered by the pipe itself
+            ipc::Request::Activate => Ok(serde_json::Value::Null),
+        }
+    }
+
+    fn price_json(&self) -> serde_json::Value {
+        let info = pairs::pair_info(&self.trade_pair);
+        let price = self.prices.get(&info.pair_name);
+        serde_json::json!({
+            "pair": info.pair_name,
+            "show_name": info.show_name,
+            "exchange": self.exchange.id(),
+            "price": price.map(|price| price.tag_price),
+            "text": price.map(|price| pairs::format_price(&price.name, price.tag_price)),
+            "time_stamp": price.map(|price| price.time_stamp),
+            "status": self.status.label(),
+        })
+    }
+
+    // the detail popup opens once no second click followed, on the clicked widget or mirror
+    fn defer_click(hwnd: HWND, window: &mut Window) {
+        if window.double_clicked {
+            window.double_clicked = false;
+            return;
+        }
+        unsafe {
+            SetTimer(hwnd, Self::TIMER_CLICK, GetDoubleClickTime(), None);
+        }
+    }
+
+    fn cancel_click(hwnd: HWND, window: &mut Window) {
+        unsafe {
+            let _ = KillTimer(hwnd, Self::TIMER_CLICK);
+        }
+        window.double_clicked = true;
+    }
+
+    unsafe fn open_detail(hwnd: HWND) {
+        let _ = KillTimer(hwnd, Self::TIMER_CLICK);
+        let _ = DetailWindow::open(hwnd);
+    }
+
+    // the exchange's trading page of the selected pair
+    fn open_trade_page(window: &Window) -> Result<()> {
+        match pairs::trade_url(window.exchange, &window.trade_pair) {
+            Some(url) => Self::open_url(&url),
+            None => Ok(()),
+        }
+    }
+
+    // in the default browser
+    fn open_url(url: &str) -> Result<()> {
+        let result = unsafe {
+            ShellExecuteW(None, w!("open"), &HSTRING::from(url), None, None, SW_SHOWNORMAL)
+        };
+        // anything up to 32 is an error code
+        if result.0 as usize <= 32 {
+            return Err(WindowError {
+                erro_msg: format!("open {} fail:{}", url, result.0 as usize),
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    // the price of the selected pair as drawn, the notice stays until the next price
+    fn copy_price(hwnd: HWND, window: &mut Window) -> Result<()> {
+        let price = match window.current().0 {
+            Some(price) => price,
+            None => return Ok(()),
+        };
+        let mut text = pairs::format_price(&price.name, price.tag_price);
+        if window.config.copy_details {
+            let show_name = pairs::pair_info(&window.trade_pair).show_name;
+            text = format!("{} {} {}", show_name, text, format::utc_time(price.time_stamp));
+        }
+        clipboard::set_text(hwnd, &text)?;
+        Self::paint(&hwnd, window, Some(i18n::tr("已复制")))
+    }
+
+    // asks for a file, .json writes json and anything else csv
+    fn export_history(hwnd: HWND, window: &mut Window, hours: u64) -> Result<()> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_millis() as u64;
+        let ticks = history::since(now.saturating_sub(hours * 3600 * 1000));
+        let mut file = [0u16; 260];
+        let default_name: Vec<u16> = "mjj_history.csv".encode_utf16().collect();
+        file[..default_name.len()].copy_from_slice(&default_name);
+        let mut dialog = OPENFILENAMEW {
+            lStructSize: std::mem::size_of::<OPENFILENAMEW>() as u32,
+            hwndOwner: hwnd,
+            lpstrFilter: w!("CSV (*.csv)\0*.csv\0JSON (*.json)\0*.json\0\0"),
+            lpstrFile: PWSTR(file.as_mut_ptr()),
+            nMaxFile: file.len() as u32,
+            lpstrDefExt: w!("csv"),
+            Flags: OFN_OVERWRITEPROMPT | OFN_PATHMUSTEXIST,
+            ..Default::default()
+        };
+        unsafe {
+            if !GetSaveFileNameW(&mut dialog).as_bool() {
+                return Ok(());
+            }
+        }
+        let len = file.iter().position(|c| *c == 0).unwrap_or(file.len());
+        let path = std::path::PathBuf::from(String::from_utf16_lossy(&file[..len]));
+        let json = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("json"));
+        let content = if json {
+            history::to_json(&ticks)?
+        } else {
+            history::to_csv(&ticks)
+        };
+        std::fs::write(&path, content)?;
+        Self::paint(&hwnd, window, Some(&i18n::fill("已导出{}条", &[&ticks.len()])))
+    }
+
+    // runs the handler of a message, a failure is logged and shown on the
+    // widget instead of unwinding through the window procedure
+    unsafe fn handle<F>(hwnd: HWND, handler: F) -> LRESULT
+    where
+        F: FnOnce(&mut Window) -> Result<()>,
+    {
+        let window = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut Self;
+        if window.is_null() {
+            return LRESULT(0);
+        }
+        let window = &mut *window;
+        if let Err(err) = handler(window) {
+            warn!("handler err:{:?}", err);
+            // the next price repaints over the notice
+            let notice = if err.is::<ApiStopped>() {
+                i18n::tr("后台已停止")
+            } else {
+                i18n::tr("操作失败")
+            };
+            let _ = Self::paint(&hwnd, window, Some(notice));
+        }
+        LRESULT(0)
+    }
+
+    fn show_context_menu(hwnd: HWND, window: &mut Window, lparam: LPARAM) -> Result<()> {
+        let menu = Self::create_context_menu(window)?;
+        let point = POINT {
+            x: Self::GET_X_LPARAM(lparam),
+            y: Self::GET_Y_LPARAM(lparam),
+        };
+        let mut window_rect = RECT::default();
+        unsafe {
+            let shown = GetWindowRect(hwnd, &mut window_rect);
+            if shown.is_ok() {
+                let _ = TrackPopupMenu(
+                    menu,
+                    TPM_RIGHTBUTTON,
+                    point.x + window_rect.left,
+                    point.y + window_rect.top,
+                    0,
+                    hwnd,
+                    None,
+                );
+            }
+            let _ = DestroyMenu(menu);
+            Ok(shown?)
+        }
+    }
+
+    fn on_command(hwnd: HWND, window: &mut Window, command: usize) -> Result<()> {
+        match command {
+            command
+                if command >= Self::COMAMND_PAIR_BASE
+                    && command < Self::COMAMND_PAIR_BASE + window.menu_pairs.len() =>
+            {
+                let trade_pair = window.menu_pairs[command - Self::COMAMND_PAIR_BASE].clone();
+                Self::switch_trade_pair(window, trade_pair)?;
+            }
+            command
+                if command >= Self::COMAMND_RECENT_BASE
+                    && command < Self::COMAMND_RECENT_BASE + window.menu_recent.len() =>
+            {
+                let trade_pair = window.menu_recent[command - Self::COMAMND_RECENT_BASE].clone();
+                Self::switch_trade_pair(window, trade_pair)?;
+            }
+            Self::COMAMND_EXIT => window.exit(),
+            Self::COMAMND_MARKET => Self::toggle_market(window)?,
+            Self::COMAMND_COPY => Self::copy_price(hwnd, window)?,
+            Self::COMAMND_AUTOSTART => autostart::set_enabled(!autostart::is_enabled())?,
+            Self::COMAMND_UPDATE => {
+                if let Some(release) = update::available() {
+                    Self::open_url(&release.html_url)?;
+                }
+            }
+            command
+                if command >= Self::COMAMND_PRICE_TYPE_BASE
+                    && command < Self::COMAMND_PRICE_TYPE_BASE + exchange::PriceType::ALL.len() =>
+            {
+                let price_type = exchange::PriceType::ALL[command - Self::COMAMND_PRICE_TYPE_BASE];
+                Self::select_price_type(window, price_type)?;
+            }
+            command
+                if command >= Self::COMAMND_EXPORT_BASE
+                    && command < Self::COMAMND_EXPORT_BASE + Self::EXPORT_RANGES.len() =>
+            {
+                let (_, hours) = Self::EXPORT_RANGES[command - Self::COMAMND_EXPORT_BASE];
+                Self::export_history(hwnd, window, hours)?;
+            }
+            command
+                if (Self::COMAMND_TASKBAR_BASE
+                    ..Self::COMAMND_TASKBAR_BASE + Self::MAX_TASKBARS)
+                    .contains(&command) =>
+            {
+                window.config.taskbar_monitor = command - Self::COMAMND_TASKBAR_BASE;
+                Self::save_taskbars(window)?;
+            }
+            Self::COMAMND_TASKBAR_CLONE => {
+                window.config.taskbar_clone = !window.config.taskbar_clone;
+                Self::save_taskbars(window)?;
+            }
+            command
+                if command >= Self::COMAMND_WIDTH_BASE
+                    && command < Self::COMAMND_WIDTH_BASE + Self::WIDTHS.len() =>
+            {
+                Self::select_width(hwnd, window, Self::WIDTHS[command - Self::COMAMND_WIDTH_BASE])?;
+            }
+            command
+                if command >= Self::COMAMND_THEME_BASE
+                    && command < Self::COMAMND_THEME_BASE + theme::ThemeName::ALL.len() =>
+            {
+                Self::select_theme(hwnd, window, theme::ThemeName::ALL[command - Self::COMAMND_THEME_BASE])?;
+            }
+            command
+                if command >= Self::COMAMND_LABEL_ROW_BASE
+                    && command < Self::COMAMND_LABEL_ROW_BASE + config::LabelRow::ALL.len() =>
+            {
+                let label_row = config::LabelRow::ALL[command - Self::COMAMND_LABEL_ROW_BASE];
+                Self::select_label_row(hwnd, window, label_row)?;
+            }
+            Self::COMAMND_FAVORITE => Self::toggle_favorite(window),
+            Self::COMAMND_SEARCH => PairPicker::open(hwnd)?,
+            Self::COMAMND_RESET_POSITION => {
+                window.config.taskbar_offset = 0;
+                Self::save_taskbars(window)?;
+            }
+            Self::COMAMND_CAROUSEL => {
+                window.carousel_paused = !window.carousel_paused;
+            }
+            Self::COMAMND_MUTE => {
+                let muted = !notify::is_muted();
+                notify::set_muted(muted);
+                let mut state = config::State::load();
+                state.sound_muted = muted;
+                if let Err(err) = state.save() {
+                    error!("save state err:{:?}", err);
+                }
+            }
+            Self::COMAMND_SETTINGS => SettingsWindow::open(hwnd, &window.config)?,
+            command
+                if command >= Self::COMAMND_EXCHANGE_BASE
+                    && command < Self::COMAMND_EXCHANGE_BASE + exchange::EXCHANGES.len() =>
+            {
+                let new_exchange = exchange::EXCHANGES[command - Self::COMAMND_EXCHANGE_BASE];
+                Self::switch_exchange(window, new_exchange)?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // the same switch as the menu, through the api sender
+    fn on_hotkey(window: &mut Window, id: usize) -> Result<()> {
+        let action = match window.hotkey_actions.get(id) {
+            Some(action) => action.clone(),
+            None => return Ok(()),
+        };
+        let trade_pair = match action {
+            hotkeys::Action::Pause => {
+                window.carousel_paused = !window.carousel_paused;
+                return Ok(());
+            }
+            hotkeys::Action::Favorite(index) => pairs::PAIR_REGISTRY
+                .read()
+                .unwrap()
+                .favorites()
+                .get(index - 1)
+                .map(|info| info.trade_pair()),
+            hotkeys::Action::Pair(query) => pairs::find(&query),
+        };
+        match trade_pair {
+            Some(trade_pair) => Self::switch_trade_pair(window, trade_pair),
+            None => Ok(()),
+        }
+    }
+
+    fn flash_large_move(hwnd: &HWND, window: &mut Window, price: &api::Price) {
+        let previous = match window.prices.get(&price.name) {
+            Some(previous) => previous.tag_price,
+            None => return,
+        };
+        if animation::large_move(previous, price.tag_price, window.config.flash_percent) {
+            let color = window.colors.change(price.tag_price - previous);
+            window.flash = Some((std::time::Instant::now(), color));
+            Self::start_animation(hwnd);
+        }
+    }
+
+    // from what is on screen, also in the middle of the last tween
+    fn tween_price(hwnd: &HWND, window: &mut Window, price: &api::Price) {
+        if !window.config.smooth_prices {
+            return;
+        }
+        let from = match window.prices.get(&price.name) {
+            Some(previous) => window.shown_price(previous),
+            None => return,
+        };
+        if from == price.tag_price {
+            return;
+        }
+        let tween = animation::Tween {
+            from,
+            to: price.tag_price,
+            start: std::time::Instant::now(),
+        };
+        window.tweens.insert(price.name.clone(), tween);
+        Self::start_animation(hwnd);
+    }
+
+    fn shown_price(&self, price: &api::Price) -> f64 {
+        match self.tweens.get(&price.name) {
+            Some(tween) if !tween.done() => tween.value(),
+            _ => price.tag_price,
+        }
+    }
+
+    fn start_animation(hwnd: &HWND) {
+        unsafe {
+            SetTimer(*hwnd, Self::TIMER_ANIMATION, animation::FRAME_MS, None);
+        }
+    }
+
+    // a frame of whatever is animating, the timer stops after the last one
+    fn step_animation(hwnd: HWND, window: &mut Window) -> Result<()> {
+        if window
+            .flash
+            .is_some_and(|(since, _)| since.elapsed() >= animation::FLASH)
+        {
+            window.flash = None;
+        }
+        window.tweens.retain(|_, tween| !tween.done());
+        if window.flash.is_none() && window.tweens.is_empty() {
+            unsafe {
+                let _ = KillTimer(hwnd, Self::TIMER_ANIMATION);
+            }
+        }
+        Self::paint(&hwnd, window, None)
+    }
+
+    fn on_timer(hwnd: HWND, window: &mut Window, timer: usize) -> Result<()> {
+        if timer == Self::TIMER_ANIMATION {
+            return Self::step_animation(hwnd, window);
+        }
+        if timer == Self::TIMER_CLICK {
+            unsafe { Self::open_detail(hwnd) };
+            return Ok(());
+        }
+        if timer == Self::TIMER_CAROUSEL {
+            if !window.carousel_paused {
+                Self::rotate_carousel(&hwnd, window)?;
+            }
+            return Ok(());
+        }
+        if timer == Self::TIMER_STALE {
+            unsafe {
+                let _ = KillTimer(hwnd, Self::TIMER_STALE);
+            }
+            Self::check_stale(&hwnd, window);
+            return Ok(());
+        }
+        Self::follow_taskbars(&hwnd, window)
+    }
+
+    // explorer reports its windows moving, among them the taskbars sliding
+    // in and out, the notification area growing and a new secondary taskbar.
+    // another process id after explorer restarts, hooked again then
+    fn hook_taskbar(&mut self) {
+        self.unhook_taskbar();
+        let taskbar = match taskbar::primary() {
+            Ok(taskbar) => taskbar,
+            Err(_) => return,
+        };
+        unsafe {
+            let mut process_id = 0;
+            GetWindowThreadProcessId(taskbar, Some(&mut process_id));
+            let hook = SetWinEventHook(
+                EVENT_OBJECT_LOCATIONCHANGE,
+                EVENT_OBJECT_LOCATIONCHANGE,
+                None,
+                Some(Self::on_win_event),
+                process_id,
+                0,
+                WINEVENT_OUTOFCONTEXT | WINEVENT_SKIPOWNPROCESS,
+            );
+            if hook.is_invalid() {
+                warn!("taskbar hook err:{:?}", GetLastError());
+                return;
+            }
+            self.taskbar_hook = hook.0 as usize;
+        }
+        HOOKED_HWND.store(self.hwnd, Ordering::Relaxed);
+    }
+
+    fn unhook_taskbar(&mut self) {
+        if self.taskbar_hook != 0 {
+            unsafe {
+                let _ = UnhookWinEvent(HWINEVENTHOOK(self.taskbar_hook as *mut c_void));
+            }
+            self.taskbar_hook = 0;
+        }
+    }
+
+    // out of context, through the message loop of the widget's thread
+    unsafe extern "system" fn on_win_event(
+        _hook: HWINEVENTHOOK,
+        _event: u32,
+        hwnd: HWND,
+        id_object: i32,
+        _id_child: i32,
+        _thread: u32,
+        _time: u32,
+    ) {
+        if id_object != OBJID_WINDOW.0 || !taskbar::owns(hwnd) {
+            return;
+        }
+        let widget = HWND(HOOKED_HWND.load(Ordering::Relaxed) as *mut c_void);
+        Self::handle(widget, |window| Self::follow_taskbars(&widget, window));
+    }
+
+    // keeps the widget left of the notification area of its taskbar and the
+    // copies next to theirs, taskbars come and go with displays
+    fn follow_taskbars(hwnd: &HWND, window: &mut Window) -> Result<()> {
+        // no taskbar while explorer restarts, TaskbarCreated follows
+        let taskbar = match taskbar::get(window.config.taskbar_monitor) {
+            Ok(taskbar) => taskbar,
+            Err(_) => return Ok(()),
+        };
+        if taskbar.0 as usize != window.taskbar {
+            unsafe {
+                SetParent(*hwnd, taskbar)?;
+            }
+            window.taskbar = taskbar.0 as usize;
+            // moved on below even when the position is the same
+            window.height = 0;
+        }
+        let hidden = taskbar::is_hidden(taskbar);
+        if hidden != window.hidden {
+            window.hidden = hidden;
+            unsafe {
+                let _ = ShowWindow(*hwnd, if hidden { SW_HIDE } else { SW_SHOWNOACTIVATE });
+            }
+            // placed again once the taskbar is back
+            window.height = 0;
+        }
+        if !hidden {
+            Self::place(hwnd, window, taskbar);
+        }
+        let wanted = window.mirror_taskbars();
+        if window.mirrors.iter().map(|(_, taskbar)| *taskbar).ne(wanted.iter().copied()) {
+            window.sync_mirrors(&wanted);
+            Self::paint(hwnd, window, None)?;
+        }
+        for (mirror, taskbar) in &window.mirrors {
+            let taskbar = HWND(*taskbar as *mut c_void);
+            let anchor = match taskbar::anchor(taskbar, window.config.taskbar_anchor) {
+                Ok(anchor) => anchor,
+                Err(_) => continue,
+            };
+            // as large as the widget, the frame is laid out for its size
+            let pos = anchor.top_left(window.width, window.height, window.config.taskbar_offset);
+            let mirror = HWND(*mirror as *mut c_void);
+            unsafe {
+                let hidden = taskbar::is_hidden(taskbar);
+                if hidden == IsWindowVisible(mirror).as_bool() {
+                    let _ = ShowWindow(mirror, if hidden { SW_HIDE } else { SW_SHOWNOACTIVATE });
+                }
+                let _ = SetWindowPos(
+                    mirror,
+                    None,
+                    pos.x,
+                    pos.y,
+                    window.width,
+                    window.height,
+                    SWP_NOREDRAW | SWP_NOACTIVATE,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    // next to the notification area, or the start button, of the taskbar
+    fn place(hwnd: &HWND, window: &mut Window, taskbar: HWND) {
+        let anchor = match taskbar::anchor(taskbar, window.config.taskbar_anchor) {
+            Ok(anchor) => anchor,
+            Err(_) => return,
+        };
+        window.vertical = anchor.vertical;
+        let (width, height) = anchor.size(window.length());
+        let pos = anchor.top_left(width, height, window.config.taskbar_offset);
+        if pos != window.pos || width != window.width || height != window.height {
+            window.pos = pos;
+            window.width = width;
+            window.height = height;
+            unsafe {
+                let _ = SetWindowPos(
+                    *hwnd,
+                    None,
+                    window.pos.x,
+                    window.pos.y,
+                    window.width,
+                    window.height,
+                    SWP_NOREDRAW,
+                );
+            }
+        }
+    }
+
+    // the first move over the widget asks for WM_MOUSELEAVE, dragging hides the tooltip
+    fn on_hover(hwnd: HWND, window: &mut Window, x: i32, y: i32) -> Result<()> {
+        if window.dragging {
+            Self::hide_tooltip(window);
+            return Ok(());
+        }
+        if window.hover.is_none() {
+            let mut track = TRACKMOUSEEVENT {
+                cbSize: std::mem::size_of::<TRACKMOUSEEVENT>() as u32,
+                dwFlags: TME_LEAVE,
+                hwndTrack: hwnd,
+                dwHoverTime: 0,
+            };
+            unsafe { TrackMouseEvent(&mut track)? };
         }
+        window.hover = Some((x, y));
+        Self::show_tooltip(&hwnd, window)
     }
 
-    fn make_argb(a: u32, r: u32, g: u32, b: u32) -> u32 {
-        (b << Self::BLUE_SHIFT)
-            | (g << Self::GREEN_SHIFT)
-            | (r << Self::RED_SHIFT)
-            | (a << Self::ALPHA_SHIFT)
+    fn hide_tooltip(window: &mut Window) {
+        window.hover = None;
+        if let Some(tooltip) = &mut window.tooltip {
+            tooltip.hide();
+        }
     }
 
-    fn string_to_pwcstr(content_str: &str) -> PCWSTR {
-        let mut content: Vec<u16> = content_str.encode_utf16().collect();
-        content.push(0);
-        PCWSTR::from_raw(content.as_ptr())
+    fn show_tooltip(hwnd: &HWND, window: &mut Window) -> Result<()> {
+        let (x, y) = match window.hover {
+            Some(hover) => hover,
+            None => return Ok(()),
+        };
+        let text = match window.hovered_pair(x as f32, y as f32) {
+            Some(pair_name) => window.tooltip_text(&pair_name),
+            None => return Ok(()),
+        };
+        let mut rect = RECT::default();
+        unsafe { GetWindowRect(*hwnd, &mut rect)? };
+        if window.tooltip.is_none() {
+            window.tooltip = Some(tooltip::Tooltip::create(*hwnd)?);
+        }
+        if let Some(tooltip) = &mut window.tooltip {
+            tooltip.show(&text, rect.left + x, &rect);
+        }
+        Ok(())
     }
 
-    fn create_font(font_family_name: &str, font_size: f32) -> *mut GpFont {
-        unsafe {
-            let mut font_family: *mut GpFontFamily = std::ptr::null_mut();
-            GdipCreateFontFamilyFromName(
-                Self::string_to_pwcstr(font_family_name),
-                std::ptr::null_mut(),
-                &mut font_family,
-            );
-            let mut font: *mut GpFont = std::ptr::null_mut();
-            GdipCreateFont(
-                font_family,
-                font_size,
-                FontStyleRegular.0,
-                UnitPoint,
-                &mut font,
-            );
-            GdipDeleteFontFamily(font_family);
-            font
+    // the pair of the cell under a client point, the selected one in the portfolio mode
+    fn hovered_pair(&self, x: f32, y: f32) -> Option<String> {
+        let displayed: Vec<String> = self
+            .displayed_pairs()
+            .into_iter()
+            .filter(|pair_name| self.prices.contains_key(pair_name))
+            .collect();
+        if self.portfolio_mode() || displayed.len() < 2 {
+            return displayed.into_iter().next();
         }
+        let cells = Self::layout_cells(self, displayed.len());
+        cells
+            .iter()
+            .position(|cell| x >= cell.X && x < cell.X + cell.Width && y >= cell.Y && y < cell.Y + cell.Height)
+            .map(|index| displayed[index].clone())
+    }
+
+    fn tooltip_text(&self, pair_name: &str) -> String {
+        let price = self.prices.get(pair_name);
+        let ticker = self.tickers.get(pair_name);
+        let dash = || "-".to_string();
+        let format_price = |value: f64| pairs::format_price(pair_name, value);
+        let title = format!(
+            "{}{}",
+            pairs::pair_info(&pairs::TradePair(pair_name.to_string())).show_name,
+            self.exchange.market().tag()
+        );
+        let change = price.zip(ticker).map(|(price, ticker)| {
+            let change = price.tag_price - ticker.open_price;
+            format!("{} ({:+.2}%)", format_price(change), ticker.change_percent(price.tag_price))
+        });
+        let connection = match self.latency {
+            Some(latency) => format!("{} {}ms", self.status.label(), latency),
+            None => self.status.label().to_string(),
+        };
+        let rows = [
+            (i18n::tr("价格"), price.map_or_else(dash, |price| format_price(price.tag_price))),
+            (i18n::tr("24h 涨跌"), change.unwrap_or_else(dash)),
+            (i18n::tr("24h 最高"), ticker.map_or_else(dash, |ticker| format_price(ticker.high_price))),
+            (i18n::tr("24h 最低"), ticker.map_or_else(dash, |ticker| format_price(ticker.low_price))),
+            (i18n::tr("24h 成交量"), ticker.map_or_else(dash, |ticker| format::abbreviate(ticker.volume))),
+            (i18n::tr("更新"), price.map_or_else(dash, DetailWindow::updated_ago)),
+            (i18n::tr("连接"), connection),
+        ];
+        tooltip::text(&title, &rows)
     }
 
-    fn create_solid_brush(color: u32) -> *mut GpBrush {
+    // screen coordinate of the cursor along the taskbar
+    fn cursor_along(window: &Window) -> Result<i32> {
+        let mut point = POINT::default();
         unsafe {
-            let mut fill: *mut GpSolidFill = std::ptr::null_mut();
-            GdipCreateSolidFill(color, &mut fill);
-            fill as *mut GpBrush
+            GetCursorPos(&mut point)?;
         }
+        Ok(if window.vertical { point.y } else { point.x })
     }
 
-    fn meansuer_string(
-        graphics: *mut GpGraphics,
-        content: PCWSTR,
-        font: *const GpFont,
-        lay_box: &RectF,
-    ) -> RectF {
-        let mut bound_box = RectF::default();
+    fn start_drag(hwnd: HWND, window: &mut Window) -> Result<()> {
+        window.drag = Some((Self::cursor_along(window)?, window.config.taskbar_offset));
+        window.dragging = false;
         unsafe {
-            GdipMeasureString(
-                graphics,
-                content,
-                -1,
-                font,
-                lay_box,
-                std::ptr::null_mut(),
-                &mut bound_box,
-                std::ptr::null_mut(),
-                std::ptr::null_mut(),
-            );
+            SetCapture(hwnd);
         }
-        bound_box
+        Ok(())
     }
 
-    fn generate_mid_rect(lay_rect: &RectF, text_bound: &RectF) -> RectF {
-        let mut dst_rect = RectF::default();
-        if lay_rect.Width >= text_bound.Width {
-            dst_rect.X = (lay_rect.Width - text_bound.Width) / 2. + lay_rect.X;
-        } else {
-            dst_rect.X = lay_rect.X;
+    // past the system drag threshold the widget follows the cursor along the
+    // taskbar, the offset is kept within it
+    fn on_drag(hwnd: HWND, window: &mut Window) -> Result<()> {
+        let (start, offset) = match window.drag {
+            Some(drag) => drag,
+            None => return Ok(()),
+        };
+        let moved = Self::cursor_along(window)? - start;
+        let threshold = unsafe { GetSystemMetrics(if window.vertical { SM_CYDRAG } else { SM_CXDRAG }) };
+        if !window.dragging && moved.abs() < threshold {
+            return Ok(());
         }
-        if lay_rect.Height >= text_bound.Height {
-            dst_rect.Y = (lay_rect.Height - text_bound.Height) / 2. + lay_rect.Y;
+        window.dragging = true;
+        let taskbar = HWND(window.taskbar as *mut c_void);
+        let anchor = taskbar::anchor(taskbar, window.config.taskbar_anchor)?;
+        let home = anchor.top_left(window.width, window.height, 0);
+        let pos = anchor.top_left(window.width, window.height, offset + moved);
+        window.config.taskbar_offset = if window.vertical {
+            pos.y - home.y
         } else {
-            dst_rect.Y = lay_rect.Y;
-        }
-        dst_rect.Width = text_bound.Width;
-        dst_rect.Height = text_bound.Height;
-        dst_rect
+            pos.x - home.x
+        };
+        Self::place(&hwnd, window, taskbar);
+        Ok(())
     }
 
-    fn draw_price(
-        graphics: *mut GpGraphics,
-        font_price: *mut GpFont,
-        brush_price: *mut GpBrush,
-        font_pair: *mut GpFont,
-        brush_pair: *mut GpBrush,
-        window: &mut Window,
-        price:&api::Price
-    ) {
-        let lay_box_price = RectF {
-            X: 0.,
-            Y: window.height as f32 / 2.2,
-            Width: window.width as f32,
-            Height: window.height as f32 / 2.,
-        };
-        let lay_box_pair = RectF {
-            X: 0.,
-            Y: window.height as f32 * 0.1,
-            Width: window.width as f32,
-            Height: window.height as f32 / 2.,
-        };
-        let content_str = format!("{:.1}", price.tag_price);
-        let bound = Self::meansuer_string(
-            graphics,
-            Self::string_to_pwcstr(&content_str),
-            font_price,
-            &lay_box_price,
-        );
-        let dst_rect = Self::generate_mid_rect(&lay_box_price, &bound);
+    // true when a drag ended, the button up is a click otherwise
+    fn end_drag(window: &mut Window) -> bool {
+        if window.drag.take().is_none() {
+            return false;
+        }
         unsafe {
-            GdipDrawString(
-                graphics,
-                Self::string_to_pwcstr(&content_str),
-                -1,
-                font_price,
-                &dst_rect,
-                std::ptr::null_mut(),
-                brush_price,
-            );
+            let _ = ReleaseCapture();
         }
-        let content_str = &api::TRADE_INFO.get(&window.trade_pair).unwrap().show_name;
+        if !std::mem::take(&mut window.dragging) {
+            return false;
+        }
+        if let Err(err) = window.config.save() {
+            error!("save config err:{:?}", err);
+        }
+        true
+    }
 
-        let bound = Self::meansuer_string(
-            graphics,
-            Self::string_to_pwcstr(&content_str),
-            font_pair,
-            &lay_box_pair,
-        );
-        let dst_rect = Self::generate_mid_rect(&lay_box_pair, &bound);
-        unsafe {
-            GdipDrawString(
-                graphics,
-                Self::string_to_pwcstr(&content_str),
-                -1,
-                font_pair,
-                &dst_rect,
-                std::ptr::null_mut(),
-                brush_pair,
-            );
+    fn select_theme(hwnd: HWND, window: &mut Window, theme: theme::ThemeName) -> Result<()> {
+        window.config.theme = theme;
+        if let Err(err) = window.config.save() {
+            error!("save config err:{:?}", err);
         }
+        window.colors = window.config.colors();
+        Self::paint(&hwnd, window, None)
     }
 
-    fn draw_notify(graphics: *mut GpGraphics, font: *const GpFont, brush:* const GpBrush, window:& mut Window, not_msg:&str){
-        let lay_box = RectF {
-            X: 0.,
-            Y: 0.,
-            Width: window.width as f32,
-            Height: window.height as f32,
-        };
-        let bound = Self::meansuer_string(
-            graphics,
-            Self::string_to_pwcstr(not_msg),
-            font,
-            &lay_box,
-        );
-        let dst_rect = Self::generate_mid_rect(&lay_box, &bound);
-        unsafe{GdipDrawString(
-            graphics,
-            Self::string_to_pwcstr(not_msg),
-            -1,
-            font,
-            &dst_rect,
-            std::ptr::null_mut(),
-            brush,
-        );}
+    fn select_label_row(hwnd: HWND, window: &mut Window, label_row: config::LabelRow) -> Result<()> {
+        window.config.label_row = label_row;
+        if let Err(err) = window.config.save() {
+            error!("save config err:{:?}", err);
+        }
+        Self::paint(&hwnd, window, None)
     }
 
-    fn fresh_window(hwnd: &HWND, wparam: &WPARAM) -> Result<()> {
-        unsafe {
-            let api_msg = Box::from_raw(wparam.0 as *mut api::ApiMessage);
-            let window = &mut *(GetWindowLongPtrW(*hwnd, GWLP_USERDATA) as *mut Self);
-            match &*api_msg {
-                api::ApiMessage::Price(price) => {
-                    let check;
-                    let cur_trade_name = api::TRADE_INFO
-                        .get(&window.trade_pair)
-                        .unwrap()
-                        .pair_name
-                        .clone();
-                    check = cur_trade_name == price.name;
-                    if !check {
-                        return Ok(());
-                    }
-                }
-                _ => {}
-            }
-            let mut client_rect = RECT::default();
-            GetClientRect(*hwnd, &mut client_rect)?;
-            let width = client_rect.right - client_rect.left;
-            let height = client_rect.bottom - client_rect.top;
+    // saved like the price type, the cells are laid out again on the repaint
+    fn select_width(hwnd: HWND, window: &mut Window, width: i32) -> Result<()> {
+        window.config.width = width;
+        if let Err(err) = window.config.save() {
+            error!("save config err:{:?}", err);
+        }
+        window.update_width();
+        Self::follow_taskbars(&hwnd, window)?;
+        Self::paint(&hwnd, window, None)
+    }
 
-            let mut ps = PAINTSTRUCT::default();
-            let hdc = BeginPaint(*hwnd, &mut ps);
-            let hdc_mem = CreateCompatibleDC(hdc);
-            let h_bitmap = CreateCompatibleBitmap(hdc, width, height);
-            SelectObject(hdc_mem, h_bitmap);
+    // from the menu, saved like the price type
+    fn save_taskbars(window: &mut Window) -> Result<()> {
+        if let Err(err) = window.config.save() {
+            error!("save config err:{:?}", err);
+        }
+        let hwnd = HWND(window.hwnd as *mut c_void);
+        Self::follow_taskbars(&hwnd, window)
+    }
 
-            let mut graphics: *mut GpGraphics = std::ptr::null_mut();
-            GdipCreateFromHDC(hdc_mem, &mut graphics);
-            GdipSetTextRenderingHint(graphics, TextRenderingHintAntiAlias);
-            GdipSetSmoothingMode(graphics, SmoothingModeAntiAlias);
-            GdipSetInterpolationMode(graphics, InterpolationModeHighQualityBicubic);
+    // the taskbars that should have a copy, by hwnd
+    fn mirror_taskbars(&self) -> Vec<usize> {
+        if !self.config.taskbar_clone {
+            return Vec::new();
+        }
+        taskbar::all()
+            .into_iter()
+            .map(|taskbar| taskbar.0 as usize)
+            .filter(|taskbar| *taskbar != self.taskbar)
+            .collect()
+    }
 
-            GdipGraphicsClear(graphics, Self::make_argb(1, 255, 255, 255));
-            let font = Self::create_font("Microsoft YaHei UI", 9.);
-            let font_small = Self::create_font("Microsoft YaHei UI", 9.);
-            let brush = Self::create_solid_brush(Self::make_argb(255, 0, 0, 0));
+    // copies on taskbars that went away were destroyed with them already
+    fn destroy_mirrors(&mut self) {
+        for (mirror, _) in self.mirrors.drain(..) {
+            unsafe {
+                let _ = DestroyWindow(HWND(mirror as *mut c_void));
+            }
+        }
+    }
 
-            match *api_msg {
-                api::ApiMessage::Price(price) => {
-                    Self::draw_price(graphics, font, brush, font_small, brush, window, &price);
-                }
-                api::ApiMessage::Notify(not_msg) => {
-                    Self::draw_notify(graphics, font, brush, window, &not_msg);
-                }
+    fn sync_mirrors(&mut self, taskbars: &[usize]) {
+        self.destroy_mirrors();
+        for taskbar in taskbars {
+            match self.create_mirror(HWND(*taskbar as *mut c_void)) {
+                Ok(mirror) => self.mirrors.push((mirror.0 as usize, *taskbar)),
+                Err(err) => warn!("create mirror err:{:?}", err),
             }
-            let mut blend = BLENDFUNCTION::default();
-            blend.BlendOp = AC_SRC_OVER as u8;
-            blend.BlendFlags = 0;
-            blend.SourceConstantAlpha = 255;
-            blend.AlphaFormat = AC_SRC_ALPHA as u8;
-            let size = SIZE {
-                cx: width,
-                cy: height,
+        }
+    }
+
+    fn create_mirror(&mut self, taskbar: HWND) -> Result<HWND> {
+        unsafe {
+            let instance = GetModuleHandleW(None)?;
+            let wc = WNDCLASSW {
+                style: CS_DBLCLKS,
+                hCursor: LoadCursorW(None, IDC_ARROW)?,
+                hInstance: instance.into(),
+                lpszClassName: Self::MIRROR_CLASS_NAME,
+                lpfnWndProc: Some(Self::mirror_wndproc),
+                ..Default::default()
             };
-            let point = POINT { x: 0, y: 0 };
-            let _ = UpdateLayeredWindow(
-                *hwnd,
-                hdc,
+            // fails harmlessly when the class is already registered
+            RegisterClassW(&wc);
+            let mirror = CreateWindowExW(
+                WS_EX_LAYERED | WS_EX_TOOLWINDOW | WS_EX_TOPMOST,
+                Self::MIRROR_CLASS_NAME,
+                &HSTRING::from(&self.title),
+                WS_POPUP,
+                0,
+                0,
+                0,
+                0,
+                taskbar,
                 None,
-                Some(&size),
-                hdc_mem,
-                Some(&point),
+                wc.hInstance,
                 None,
-                Some(&blend),
-                ULW_ALPHA,
-            );
+            )?;
+            SetParent(mirror, taskbar)?;
+            SetWindowLongPtrW(mirror, GWLP_USERDATA, self as *mut Self as isize);
+            let _ = ShowWindow(mirror, SW_SHOW);
+            Ok(mirror)
+        }
+    }
 
-            GdipDeleteFont(font);
-            GdipDeleteBrush(brush);
-            let _ = DeleteObject(h_bitmap);
-            let _ = DeleteDC(hdc_mem);
-            let _ = EndPaint(*hwnd, &ps);
-            Ok(())
+    // a copy of the widget on another taskbar. the menu and the detail popup
+    // open over it, everything else is done by the widget
+    extern "system" fn mirror_wndproc(
+        hwnd: HWND,
+        message: u32,
+        wparam: WPARAM,
+        lparam: LPARAM,
+    ) -> LRESULT {
+        unsafe {
+            let window = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut Self;
+            if window.is_null() || (*window).hwnd == 0 {
+                return DefWindowProcW(hwnd, message, wparam, lparam);
+            }
+            let main_hwnd = HWND((*window).hwnd as *mut c_void);
+            match message {
+                WM_LBUTTONUP => Self::handle(main_hwnd, |window| {
+                    Self::defer_click(hwnd, window);
+                    Ok(())
+                }),
+                WM_LBUTTONDBLCLK => Self::handle(main_hwnd, |window| {
+                    Self::cancel_click(hwnd, window);
+                    Self::open_trade_page(window)
+                }),
+                WM_TIMER if wparam.0 == Self::TIMER_CLICK => {
+                    Self::open_detail(hwnd);
+                    LRESULT(0)
+                }
+                WM_RBUTTONDOWN => {
+                    Self::handle(main_hwnd, |window| Self::show_context_menu(hwnd, window, lparam))
+                }
+                WM_MBUTTONUP | WM_MOUSEWHEEL | WM_COMMAND => {
+                    SendMessageW(main_hwnd, message, wparam, lparam)
+                }
+                _ => DefWindowProcW(hwnd, message, wparam, lparam),
+            }
         }
     }
 
@@ -333,126 +2488,87 @@ impl Window {
     ) -> LRESULT {
         unsafe {
             match message {
-                WM_RBUTTONDOWN => {
-                    let menu = CreatePopupMenu().unwrap();
-                    AppendMenuW(
-                        menu,
-                        MF_STRING,
-                        Self::COMAMND_BTCUSDT,
-                        Self::string_to_pwcstr(
-                            &api::TRADE_INFO
-                                .get(&api::TradePair::BTCUSDT)
-                                .unwrap()
-                                .show_name,
-                        ),
-                    )
-                    .unwrap();
-                    AppendMenuW(
-                        menu,
-                        MF_STRING,
-                        Self::COMAMND_ETHUSDT,
-                        Self::string_to_pwcstr(
-                            &api::TRADE_INFO
-                                .get(&api::TradePair::ETHUSDT)
-                                .unwrap()
-                                .show_name,
-                        ),
-                    )
-                    .unwrap();
-                    AppendMenuW(
-                        menu,
-                        MF_STRING,
-                        Self::COMAMND_SOLUSDT,
-                        Self::string_to_pwcstr(
-                            &api::TRADE_INFO
-                                .get(&api::TradePair::SOLUSDT)
-                                .unwrap()
-                                .show_name,
-                        ),
-                    )
-                    .unwrap();
-                    AppendMenuW(menu, MF_SEPARATOR, 0, None).unwrap();
-                    AppendMenuW(menu, MF_STRING, Self::COMAMND_EXIT, w!("退出")).unwrap();
-
-                    let point = POINT {
-                        x: Self::GET_X_LPARAM(lparam),
-                        y: Self::GET_Y_LPARAM(lparam),
-                    };
-                    let mut window_rect = RECT::default();
-                    GetWindowRect(hwnd, &mut window_rect).unwrap();
-                    let _ = TrackPopupMenu(
-                        menu,
-                        TPM_RIGHTBUTTON,
-                        point.x + window_rect.left,
-                        point.y + window_rect.top,
-                        0,
-                        hwnd,
-                        None,
-                    );
-                    LRESULT(0)
-                }
-                WM_COMMAND => {
-                    let window = &mut *(GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut Self);
-                    match wparam.0 as usize {
-                        Self::COMAMND_BTCUSDT => {
-                            if window.trade_pair != api::TradePair::BTCUSDT {
-                                window.trade_pair = api::TradePair::BTCUSDT;
-                                window
-                                    .sender
-                                    .blocking_send(api::TradePair::BTCUSDT)
-                                    .unwrap();
-                            }
-                        }
-                        Self::COMAMND_ETHUSDT => {
-                            if window.trade_pair != api::TradePair::ETHUSDT {
-                                window.trade_pair = api::TradePair::ETHUSDT;
-                                window
-                                    .sender
-                                    .blocking_send(api::TradePair::ETHUSDT)
-                                    .unwrap();
-                            }
-                        }
-                        Self::COMAMND_SOLUSDT => {
-                            if window.trade_pair != api::TradePair::SOLUSDT {
-                                window.trade_pair = api::TradePair::SOLUSDT;
-                                window
-                                    .sender
-                                    .blocking_send(api::TradePair::SOLUSDT)
-                                    .unwrap();
-                            }
-                        }
-                        Self::COMAMND_EXIT => {
-                            std::process::exit(0);
-                        }
-                        _ => {}
+                WM_LBUTTONDOWN => Self::handle(hwnd, |window| {
+                    Self::hide_tooltip(window);
+                    Self::start_drag(hwnd, window)
+                }),
+                WM_MOUSEMOVE => Self::handle(hwnd, |window| {
+                    Self::on_drag(hwnd, window)?;
+                    Self::on_hover(hwnd, window, Self::GET_X_LPARAM(lparam), Self::GET_Y_LPARAM(lparam))
+                }),
+                WM_MOUSELEAVE => Self::handle(hwnd, |window| {
+                    Self::hide_tooltip(window);
+                    Ok(())
+                }),
+                WM_LBUTTONUP => Self::handle(hwnd, |window| {
+                    if !Self::end_drag(window) {
+                        Self::defer_click(hwnd, window);
                     }
+                    Ok(())
+                }),
+                // e.g. another window took the mouse, the drag is over
+                WM_CAPTURECHANGED => Self::handle(hwnd, |window| {
+                    Self::end_drag(window);
+                    Ok(())
+                }),
+                WM_LBUTTONDBLCLK => Self::handle(hwnd, |window| {
+                    Self::cancel_click(hwnd, window);
+                    Self::open_trade_page(window)
+                }),
+                WM_MBUTTONUP => Self::handle(hwnd, |window| Self::copy_price(hwnd, window)),
+                WM_RBUTTONDOWN => Self::handle(hwnd, |window| {
+                    Self::hide_tooltip(window);
+                    Self::show_context_menu(hwnd, window, lparam)
+                }),
+                WM_COMMAND => Self::handle(hwnd, |window| Self::on_command(hwnd, window, wparam.0)),
+                WM_TIMER => Self::handle(hwnd, |window| Self::on_timer(hwnd, window, wparam.0)),
+                WM_MOUSEWHEEL => {
+                    let delta = (wparam.0 >> 16) as u16 as i16;
+                    Self::handle(hwnd, |window| Self::on_wheel(&hwnd, window, delta as i32))
+                }
+                WM_HOTKEY => Self::handle(hwnd, |window| Self::on_hotkey(window, wparam.0)),
+                Self::WM_FRESH => {
+                    let _ = Self::fresh_window(&hwnd, &wparam);
                     LRESULT(0)
                 }
-                WM_TIMER => {
-                    let window = &mut *(GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut Self);
-                    let (mut window_base_pos, window_height) = Self::get_window_base_pos().unwrap();
-                    window_base_pos.x -= window.width;
-                    if window_base_pos != window.pos || window_height != window.height {
-                        window.pos = window_base_pos;
-                        window.height = window_height;
-                        let _ = SetWindowPos(
-                            HWND(window.hwnd as *mut c_void),
-                            None,
-                            window.pos.x,
-                            window.pos.y,
-                            window.width,
-                            window.height,
-                            SWP_NOREDRAW,
-                        );
+                Self::WM_IPC => {
+                    let window = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut Self;
+                    if window.is_null() {
+                        drop(Box::from_raw(wparam.0 as *mut ipc::Call));
+                    } else {
+                        Self::answer_ipc(&mut *window, wparam);
                     }
                     LRESULT(0)
                 }
-                Self::WM_FRESH => {
-                    let _ = Self::fresh_window(&hwnd, &wparam);
-                    LRESULT(0)
+                Self::WM_ACTIVATE_INSTANCE => Self::handle(hwnd, |window| {
+                    // flashes like an alert so the running widget is easy to spot
+                    window.alert_until = Some(std::time::Instant::now() + Self::ALERT_FLASH);
+                    Self::paint(&hwnd, window, None)
+                }),
+                WM_DPICHANGED | WM_THEMECHANGED | WM_SETTINGCHANGE | WM_DISPLAYCHANGE => {
+                    // fonts and surfaces are remade for the new dpi or theme on the next paint
+                    let window = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut Self;
+                    if !window.is_null() {
+                        (*window).renderer = None;
+                    }
+                    DefWindowProcW(hwnd, message, wparam, lparam)
                 }
                 WM_DESTROY => {
-                    PostQuitMessage(0);
+                    let window = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut Self;
+                    if !window.is_null() {
+                        // registered again when the widget is restored
+                        (*window).unregister_hotkeys();
+                        (*window).destroy_mirrors();
+                        (*window).unhook_taskbar();
+                        (*window).tooltip = None;
+                        (*window).hover = None;
+                        (*window).hwnd = 0;
+                        (*window).taskbar = 0;
+                        (*window).hidden = false;
+                    }
+                    if window.is_null() || !(*window).keep_alive {
+                        PostQuitMessage(0);
+                    }
                     LRESULT(0)
                 }
                 _ => DefWindowProcW(hwnd, message, wparam, lparam),
@@ -460,7 +2576,138 @@ impl Window {
         }
     }
 
-    fn init_gdi_plus() -> Result<()> {
+    // creates the widget again after it was destroyed, shown with the cached prices
+    pub fn restore(&mut self) -> Result<()> {
+        if self.hwnd != 0 {
+            return Ok(());
+        }
+        self.init_window()?;
+        let hwnd = HWND(self.hwnd as *mut c_void);
+        unsafe {
+            let _ = ShowWindow(hwnd, SW_SHOW);
+        }
+        Self::paint(&hwnd, self, None)
+    }
+
+    // explorer restarted, the old taskbar took the widget with it. a widget
+    // that survived is moved onto the new taskbar
+    fn on_taskbar_created(&mut self) -> Result<()> {
+        if self.hwnd == 0 {
+            return self.restore();
+        }
+        // the copies went with the old taskbars
+        self.mirrors.clear();
+        self.taskbar = 0;
+        self.hook_taskbar();
+        let hwnd = HWND(self.hwnd as *mut c_void);
+        Self::follow_taskbars(&hwnd, self)
+    }
+
+    // once, before the api starts posting to it
+    fn create_shell_window(&mut self) -> Result<()> {
+        unsafe {
+            let instance = GetModuleHandleW(None)?;
+            let wc = WNDCLASSW {
+                hInstance: instance.into(),
+                lpszClassName: Self::SHELL_CLASS_NAME,
+                lpfnWndProc: Some(Self::shell_wndproc),
+                ..Default::default()
+            };
+            RegisterClassW(&wc);
+            // not message-only, those miss the TaskbarCreated broadcast
+            let hwnd = CreateWindowExW(
+                WS_EX_TOOLWINDOW,
+                Self::SHELL_CLASS_NAME,
+                Self::SHELL_CLASS_NAME,
+                WS_POPUP,
+                0,
+                0,
+                0,
+                0,
+                None,
+                None,
+                wc.hInstance,
+                None,
+            )?;
+            SetWindowLongPtrW(hwnd, GWLP_USERDATA, self as *mut Self as isize);
+            self.shell_hwnd = hwnd.0 as usize;
+        }
+        self.keep_alive = true;
+        Ok(())
+    }
+
+    // forwards to the widget while it exists, like the tray does
+    extern "system" fn shell_wndproc(
+        hwnd: HWND,
+        message: u32,
+        wparam: WPARAM,
+        lparam: LPARAM,
+    ) -> LRESULT {
+        unsafe {
+            let window = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut Self;
+            if window.is_null() {
+                return DefWindowProcW(hwnd, message, wparam, lparam);
+            }
+            let window = &mut *window;
+            let window_hwnd = HWND(window.hwnd as *mut c_void);
+            match message {
+                Self::WM_FRESH => {
+                    if window.hwnd == 0
+                        || PostMessageW(window_hwnd, message, wparam, lparam).is_err()
+                    {
+                        drop(Box::from_raw(wparam.0 as *mut api::ApiMessage));
+                    }
+                }
+                Self::WM_IPC => Self::answer_ipc(window, wparam),
+                Self::WM_ACTIVATE_INSTANCE => {
+                    if window.hwnd == 0 {
+                        if let Err(err) = window.restore() {
+                            warn!("restore window err:{:?}", err);
+                        }
+                    } else {
+                        let _ = PostMessageW(window_hwnd, message, wparam, lparam);
+                    }
+                }
+                message if message == *WM_TASKBAR_CREATED => {
+                    if let Err(err) = window.on_taskbar_created() {
+                        warn!("taskbar created err:{:?}", err);
+                    }
+                }
+                // only top-level windows get these, the widget is a child of
+                // the taskbar. displays come and go, auto-hide and the
+                // taskbar color are settings
+                WM_DISPLAYCHANGE | WM_SETTINGCHANGE if window.hwnd != 0 => {
+                    let _ = Self::follow_taskbars(&window_hwnd, window);
+                    let colors = window.config.colors();
+                    if colors != window.colors {
+                        window.colors = colors;
+                        let _ = Self::paint(&window_hwnd, window, None);
+                    }
+                    return DefWindowProcW(hwnd, message, wparam, lparam);
+                }
+                _ => return DefWindowProcW(hwnd, message, wparam, lparam),
+            }
+            LRESULT(0)
+        }
+    }
+
+    // ends the message loop through WM_DESTROY, main stops the api after it
+    pub fn exit(&mut self) {
+        self.keep_alive = false;
+        unsafe {
+            if self.hwnd != 0 {
+                let _ = DestroyWindow(HWND(self.hwnd as *mut c_void));
+            } else {
+                PostQuitMessage(0);
+            }
+        }
+    }
+
+    // once, the widget is created again when restored from the tray
+    fn init_gdi_plus(&mut self) -> Result<()> {
+        if self.gdiplus_token != 0 {
+            return Ok(());
+        }
         let mut gdiplus_token: usize = 0;
         let mut gdiplus_startup_input = GdiplusStartupInput::default();
         gdiplus_startup_input.GdiplusVersion = 1;
@@ -477,24 +2724,53 @@ impl Window {
                 return Err(err.into());
             }
         }
+        self.gdiplus_token = gdiplus_token;
         Ok(())
     }
 
+    // on the way out, whichever path ended the message loop
+    fn save_state(&mut self) {
+        // a drag cut short keeps the offset it reached
+        Self::end_drag(self);
+        let mut state = config::State::load();
+        state.last_pair = Some(self.trade_pair.0.clone());
+        state.sound_muted = notify::is_muted();
+        if let Err(err) = state.save() {
+            error!("save state err:{:?}", err);
+        }
+    }
+
+    // after the gdi+ objects of the renderer are gone
+    fn shutdown_gdi_plus(&mut self) {
+        self.renderer = None;
+        if self.gdiplus_token != 0 {
+            unsafe { GdiplusShutdown(self.gdiplus_token) };
+            self.gdiplus_token = 0;
+        }
+    }
+
     pub fn init_window(&mut self) -> Result<()> {
-        Self::init_gdi_plus()?;
-        let taskbar_hwnd = Self::get_taskbar_hwnd()?;
-        let (window_base_pos, height) = Self::get_window_base_pos()?;
+        self.init_gdi_plus()?;
+        if self.shell_hwnd == 0 {
+            self.create_shell_window()?;
+        }
+        let taskbar_hwnd = taskbar::get(self.config.taskbar_monitor)?;
+        let anchor = taskbar::anchor(taskbar_hwnd, self.config.taskbar_anchor)?;
         unsafe {
             let instance = GetModuleHandleW(None)?;
+            // the class name must outlive RegisterClassW and CreateWindowExW
+            let class_name = HSTRING::from(&self.class_name);
             let wc = WNDCLASSW {
+                style: CS_DBLCLKS,
                 hCursor: LoadCursorW(None, IDC_ARROW)?,
                 hInstance: instance.into(),
-                lpszClassName: Self::string_to_pwcstr(&self.class_name),
+                lpszClassName: PCWSTR(class_name.as_ptr()),
                 lpfnWndProc: Some(Self::wndproc),
                 ..Default::default()
             };
             let atom = RegisterClassW(&wc);
-            if atom == 0 {
+            // the class survives when the widget is recreated from the tray
+            if atom == 0 && GetLastError() != ERROR_CLASS_ALREADY_EXISTS {
                 let err = WindowError {
                     erro_msg: "registe window fail".to_string(),
                 };
@@ -502,8 +2778,8 @@ impl Window {
             }
             let hwnd = CreateWindowExW(
                 WS_EX_LAYERED | WS_EX_TOOLWINDOW | WS_EX_TOPMOST,
-                Self::string_to_pwcstr(&self.class_name),
-                Self::string_to_pwcstr(&self.title),
+                &class_name,
+                &HSTRING::from(&self.title),
                 WS_POPUP,
                 0,
                 0,
@@ -522,11 +2798,10 @@ impl Window {
             }
             self.hwnd = hwnd.0 as usize;
             SetParent(HWND(self.hwnd as *mut c_void), taskbar_hwnd)?;
-            self.pos = POINT {
-                x: window_base_pos.x - self.width,
-                y: window_base_pos.y,
-            };
-            self.height = height;
+            self.taskbar = taskbar_hwnd.0 as usize;
+            self.vertical = anchor.vertical;
+            (self.width, self.height) = anchor.size(self.length());
+            self.pos = anchor.top_left(self.width, self.height, self.config.taskbar_offset);
             SetWindowPos(
                 HWND(self.hwnd as *mut c_void),
                 None,
@@ -537,71 +2812,55 @@ impl Window {
                 SET_WINDOW_POS_FLAGS(0),
             )?;
             SetWindowLongPtrW(hwnd, GWLP_USERDATA, self as *mut Self as isize);
-            SetTimer(hwnd, 1, 200, None);
+            self.hook_taskbar();
+            self.restart_apps_timer();
+            self.restart_carousel();
+            self.register_hotkeys();
+            let wanted = self.mirror_taskbars();
+            self.sync_mirrors(&wanted);
         }
         Ok(())
     }
 
-    fn get_taskbar_hwnd() -> Result<HWND> {
-        unsafe { Ok(FindWindowW(w!("Shell_TrayWnd"), None)?) }
-    }
-
-    fn get_window_base_pos() -> Result<(POINT, i32)> {
-        unsafe {
-            let parent_hwnd = Self::get_taskbar_hwnd()?;
-            if parent_hwnd.is_invalid() {
-                let err = WindowError {
-                    erro_msg: "can not find Shell_TrayWnd window".to_string(),
-                };
-                return Err(err.into());
-            }
-            let child_hwnd = FindWindowExW(parent_hwnd, None, w!("TrayNotifyWnd"), None)?;
-            if child_hwnd.is_invalid() {
-                let err = WindowError {
-                    erro_msg: "can not find TrayNotifyWnd window".to_string(),
-                };
-                return Err(err.into());
-            }
-            let mut child_rect = RECT::default();
-            GetWindowRect(child_hwnd, &mut child_rect)?;
-            let mut parent_rect = RECT::default();
-            GetWindowRect(parent_hwnd, &mut parent_rect)?;
-            let pos = POINT {
-                x: child_rect.left - parent_rect.left,
-                y: child_rect.top - parent_rect.top,
-            };
-            Ok((pos, child_rect.bottom - child_rect.top))
-        }
-    }
-
     pub fn run_window(&mut self) -> Result<()> {
         unsafe {
             let _ = ShowWindow(HWND(self.hwnd as *mut c_void), SW_SHOW);
             {
-                let message = api::ApiMessage::Notify("启动...".to_string());
+                let message = api::ApiMessage::Notify(i18n::tr("启动...").to_string());
                 let message_p = Box::into_raw(Box::new(message)) as *mut c_void;
                 PostMessageW(
                     HWND(self.hwnd as *mut c_void),
                     Self::WM_FRESH,
                     WPARAM(message_p as usize),
                     LPARAM::default(),
-                )
-                .unwrap();
-                let message = api::ApiMessage::Notify("启动...".to_string());
+                )?;
+                let message = api::ApiMessage::Notify(i18n::tr("启动...").to_string());
                 let message_p = Box::into_raw(Box::new(message)) as *mut c_void;
                 PostMessageW(
                     HWND(self.hwnd as *mut c_void),
                     Self::WM_FRESH,
                     WPARAM(message_p as usize),
                     LPARAM::default(),
-                )
-                .unwrap();
+                )?;
             }
             let mut message = MSG::default();
             while GetMessageW(&mut message, None, 0, 0).into() {
+                if PairPicker::is_dialog_message(&message) {
+                    continue;
+                }
+                // edit controls of the settings window need WM_CHAR
+                let _ = TranslateMessage(&message);
                 DispatchMessageW(&message);
             }
         }
+        self.save_state();
+        self.shutdown_gdi_plus();
+        if self.shell_hwnd != 0 {
+            unsafe {
+                let _ = DestroyWindow(HWND(self.shell_hwnd as *mut c_void));
+            }
+            self.shell_hwnd = 0;
+        }
         Ok(())
     }
 }
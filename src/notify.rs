@@ -0,0 +1,83 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::warn;
+use windows::core::{w, HSTRING};
+use windows::Data::Xml::Dom::XmlDocument;
+use windows::UI::Notifications::{ToastNotification, ToastNotificationManager};
+use windows::Win32::Media::Audio::{
+    PlaySoundW, SND_ALIAS, SND_ASYNC, SND_FILENAME, SND_NODEFAULT,
+};
+use windows::Win32::System::WinRT::{RoInitialize, RO_INIT_MULTITHREADED};
+
+// unpackaged apps have no AppUserModelID of their own, borrow the one of
+// powershell which is registered on every windows install
+const APP_ID: &str =
+    "{1AC14E77-02E7-4E5D-B744-2EB1AE5198B7}\\WindowsPowerShell\\v1.0\\powershell.exe";
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+static MUTED: AtomicBool = AtomicBool::new(false);
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn set_muted(muted: bool) {
+    MUTED.store(muted, Ordering::Relaxed);
+}
+
+pub fn is_muted() -> bool {
+    MUTED.load(Ordering::Relaxed)
+}
+
+// sound is a wav path, "none" for silence, None for the system beep
+pub fn play_sound(sound: Option<&str>) {
+    if is_muted() {
+        return;
+    }
+    unsafe {
+        let _ = match sound {
+            Some("none") => return,
+            Some(path) => PlaySoundW(
+                &HSTRING::from(path),
+                None,
+                SND_FILENAME | SND_ASYNC | SND_NODEFAULT,
+            ),
+            None => PlaySoundW(w!("SystemExclamation"), None, SND_ALIAS | SND_ASYNC),
+        };
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn show_toast(title: &str, body: &str) -> windows::core::Result<()> {
+    unsafe {
+        // S_FALSE or RPC_E_CHANGED_MODE when already initialized, both fine
+        let _ = RoInitialize(RO_INIT_MULTITHREADED);
+    }
+    let xml = XmlDocument::new()?;
+    xml.LoadXml(&HSTRING::from(format!(
+        r#"<toast><visual><binding template="ToastGeneric"><text>{}</text><text>{}</text></binding></visual></toast>"#,
+        escape_xml(title),
+        escape_xml(body)
+    )))?;
+    let toast = ToastNotification::CreateToastNotification(&xml)?;
+    ToastNotificationManager::CreateToastNotifierWithId(&HSTRING::from(APP_ID))?.Show(&toast)
+}
+
+// shows a native windows notification without blocking the caller
+pub fn toast(title: &str, body: &str) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let title = title.to_string();
+    let body = body.to_string();
+    std::thread::spawn(move || {
+        if let Err(err) = show_toast(&title, &body) {
+            warn!("toast err:{:?}", err);
+        }
+    });
+}
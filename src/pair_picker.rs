@@ -0,0 +1,243 @@
+use crate::i18n;
+use crate::my_window::Window;
+use crate::pairs::{self, TradePair};
+use anyhow::Result;
+use core::ffi::c_void;
+use std::sync::atomic::{AtomicIsize, Ordering};
+use tracing::error;
+use windows::Win32::Graphics::Gdi::{GetStockObject, GetSysColorBrush, COLOR_BTNFACE, DEFAULT_GUI_FONT};
+use windows::{
+    core::*, Win32::Foundation::*, Win32::System::LibraryLoader::GetModuleHandleW,
+    Win32::UI::Input::KeyboardAndMouse::SetFocus, Win32::UI::WindowsAndMessaging::*,
+};
+
+// the open picker, 0 when there is none
+static PICKER_HWND: AtomicIsize = AtomicIsize::new(0);
+
+// "搜索交易对...": the favorite and listed pairs filtered as the user types,
+// the picked one is switched to like a menu pick
+pub struct PairPicker {
+    main_hwnd: HWND,
+    edit: HWND,
+    list: HWND,
+    // pairs of the list, in its order
+    found: Vec<TradePair>,
+}
+
+impl PairPicker {
+    const CLASS_NAME: PCWSTR = w!("mjj_pair_picker");
+    // IDOK and IDCANCEL, what enter and escape send through is_dialog_message
+    const ID_PICK: usize = 1;
+    const ID_CANCEL: usize = 2;
+    const ID_EDIT: usize = 3;
+    const ID_LIST: usize = 4;
+
+    const WIDTH: i32 = 300;
+    const LIST_HEIGHT: i32 = 260;
+    const MARGIN: i32 = 12;
+
+    pub fn open(main_hwnd: HWND) -> Result<()> {
+        unsafe {
+            if let Ok(existing) = FindWindowW(Self::CLASS_NAME, None) {
+                if !existing.is_invalid() {
+                    let _ = SetForegroundWindow(existing);
+                    return Ok(());
+                }
+            }
+            let instance = GetModuleHandleW(None)?;
+            let wc = WNDCLASSW {
+                hCursor: LoadCursorW(None, IDC_ARROW)?,
+                hInstance: instance.into(),
+                lpszClassName: Self::CLASS_NAME,
+                lpfnWndProc: Some(Self::wndproc),
+                hbrBackground: GetSysColorBrush(COLOR_BTNFACE),
+                ..Default::default()
+            };
+            // fails harmlessly when the class is already registered
+            RegisterClassW(&wc);
+            let hwnd = CreateWindowExW(
+                WS_EX_TOPMOST,
+                Self::CLASS_NAME,
+                &HSTRING::from(i18n::tr("搜索交易对")),
+                WS_OVERLAPPED | WS_CAPTION | WS_SYSMENU,
+                CW_USEDEFAULT,
+                CW_USEDEFAULT,
+                Self::WIDTH,
+                Self::MARGIN * 2 + Self::LIST_HEIGHT + 110,
+                None,
+                None,
+                wc.hInstance,
+                None,
+            )?;
+            let (edit, list) = Self::create_controls(hwnd, wc.hInstance)?;
+            let mut picker = Box::new(PairPicker {
+                main_hwnd,
+                edit,
+                list,
+                found: Vec::new(),
+            });
+            picker.filter();
+            SetWindowLongPtrW(hwnd, GWLP_USERDATA, Box::into_raw(picker) as isize);
+            PICKER_HWND.store(hwnd.0 as isize, Ordering::Relaxed);
+            let _ = ShowWindow(hwnd, SW_SHOW);
+            let _ = SetFocus(edit);
+        }
+        Ok(())
+    }
+
+    // tab, enter and escape in the picker, called by the message loop
+    pub fn is_dialog_message(message: &MSG) -> bool {
+        let hwnd = PICKER_HWND.load(Ordering::Relaxed);
+        hwnd != 0 && unsafe { IsDialogMessageW(HWND(hwnd as *mut c_void), message).as_bool() }
+    }
+
+    unsafe fn create_controls(hwnd: HWND, instance: HINSTANCE) -> Result<(HWND, HWND)> {
+        let font = GetStockObject(DEFAULT_GUI_FONT);
+        let inner_width = Self::WIDTH - Self::MARGIN * 2 - 16;
+        let edit = CreateWindowExW(
+            WS_EX_CLIENTEDGE,
+            w!("EDIT"),
+            w!(""),
+            WS_CHILD | WS_VISIBLE | WS_TABSTOP | WINDOW_STYLE(ES_AUTOHSCROLL as u32),
+            Self::MARGIN,
+            Self::MARGIN,
+            inner_width,
+            24,
+            hwnd,
+            HMENU(Self::ID_EDIT as *mut c_void),
+            instance,
+            None,
+        )?;
+        let list = CreateWindowExW(
+            WS_EX_CLIENTEDGE,
+            w!("LISTBOX"),
+            w!(""),
+            WS_CHILD
+                | WS_VISIBLE
+                | WS_TABSTOP
+                | WS_VSCROLL
+                | WINDOW_STYLE((LBS_NOTIFY | LBS_NOINTEGRALHEIGHT) as u32),
+            Self::MARGIN,
+            Self::MARGIN + 32,
+            inner_width,
+            Self::LIST_HEIGHT,
+            hwnd,
+            HMENU(Self::ID_LIST as *mut c_void),
+            instance,
+            None,
+        )?;
+        SendMessageW(edit, WM_SETFONT, WPARAM(font.0 as usize), LPARAM(1));
+        SendMessageW(list, WM_SETFONT, WPARAM(font.0 as usize), LPARAM(1));
+        let y = Self::MARGIN + 32 + Self::LIST_HEIGHT + 8;
+        for (id, text, style, x) in [
+            (Self::ID_PICK, &HSTRING::from(i18n::tr("切换")), BS_DEFPUSHBUTTON, inner_width - 172),
+            (Self::ID_CANCEL, &HSTRING::from(i18n::tr("取消")), BS_PUSHBUTTON, inner_width - 80),
+        ] {
+            let button = CreateWindowExW(
+                WINDOW_EX_STYLE::default(),
+                w!("BUTTON"),
+                text,
+                WS_CHILD | WS_VISIBLE | WS_TABSTOP | WINDOW_STYLE(style as u32),
+                Self::MARGIN + x,
+                y,
+                80,
+                26,
+                hwnd,
+                HMENU(id as *mut c_void),
+                instance,
+                None,
+            )?;
+            SendMessageW(button, WM_SETFONT, WPARAM(font.0 as usize), LPARAM(1));
+        }
+        Ok((edit, list))
+    }
+
+    fn query(&self) -> String {
+        unsafe {
+            let len = GetWindowTextLengthW(self.edit);
+            let mut buf = vec![0u16; len as usize + 1];
+            let copied = GetWindowTextW(self.edit, &mut buf);
+            String::from_utf16_lossy(&buf[..copied as usize])
+        }
+    }
+
+    // refills the list from the query, the best match selected
+    fn filter(&mut self) {
+        let found = pairs::search(&self.query());
+        unsafe {
+            SendMessageW(self.list, LB_RESETCONTENT, WPARAM(0), LPARAM(0));
+            for info in &found {
+                let name = HSTRING::from(format!("{}  {}", info.show_name, info.pair_name));
+                SendMessageW(self.list, LB_ADDSTRING, WPARAM(0), LPARAM(name.as_ptr() as isize));
+            }
+            SendMessageW(self.list, LB_SETCURSEL, WPARAM(0), LPARAM(0));
+        }
+        self.found = found.iter().map(|info| info.trade_pair()).collect();
+    }
+
+    // false when nothing is selected
+    fn pick(&self) -> bool {
+        let index = unsafe { SendMessageW(self.list, LB_GETCURSEL, WPARAM(0), LPARAM(0)).0 };
+        let trade_pair = match usize::try_from(index).ok().and_then(|index| self.found.get(index)) {
+            Some(trade_pair) => trade_pair.clone(),
+            None => return false,
+        };
+        unsafe {
+            // the widget may be gone, e.g. during shutdown
+            let window = GetWindowLongPtrW(self.main_hwnd, GWLP_USERDATA) as *mut Window;
+            if window.is_null() {
+                return true;
+            }
+            if let Err(err) = (*window).pick_pair(trade_pair) {
+                error!("pick pair err:{:?}", err);
+            }
+        }
+        true
+    }
+
+    // the picker stays open while nothing is selected
+    unsafe fn pick_and_close(hwnd: HWND, picker: *mut Self) {
+        if (*picker).pick() {
+            let _ = DestroyWindow(hwnd);
+        }
+    }
+
+    extern "system" fn wndproc(
+        hwnd: HWND,
+        message: u32,
+        wparam: WPARAM,
+        lparam: LPARAM,
+    ) -> LRESULT {
+        unsafe {
+            match message {
+                WM_COMMAND => {
+                    let picker = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut Self;
+                    if picker.is_null() {
+                        return LRESULT(0);
+                    }
+                    let notification = (wparam.0 >> 16) as u32 & 0xFFFF;
+                    match wparam.0 & 0xFFFF {
+                        Self::ID_EDIT if notification == EN_CHANGE => (*picker).filter(),
+                        Self::ID_LIST if notification == LBN_DBLCLK => Self::pick_and_close(hwnd, picker),
+                        Self::ID_PICK => Self::pick_and_close(hwnd, picker),
+                        Self::ID_CANCEL => {
+                            let _ = DestroyWindow(hwnd);
+                        }
+                        _ => {}
+                    }
+                    LRESULT(0)
+                }
+                WM_NCDESTROY => {
+                    let picker = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut Self;
+                    PICKER_HWND.store(0, Ordering::Relaxed);
+                    if !picker.is_null() {
+                        SetWindowLongPtrW(hwnd, GWLP_USERDATA, 0);
+                        drop(Box::from_raw(picker));
+                    }
+                    LRESULT(0)
+                }
+                _ => DefWindowProcW(hwnd, message, wparam, lparam),
+            }
+        }
+    }
+}
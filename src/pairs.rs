@@ -0,0 +1,354 @@
+use crate::config::Config;
+use crate::exchange::{self, Exchange};
+use crate::fiat;
+use crate::format;
+use crate::tls;
+use anyhow::Result;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct TradePair(pub String);
+
+impl From<&str> for TradePair {
+    fn from(pair_name: &str) -> Self {
+        TradePair(pair_name.to_string())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct TradePairInfo {
+    pub ws_name: String,
+    pub show_name: String,
+    pub pair_name: String,
+    // price decimals from the exchange tick size
+    pub decimals: Option<u32>,
+}
+
+impl TradePairInfo {
+    pub fn new(base: &str, quote: &str) -> Self {
+        let pair_name = format!("{}{}", base, quote);
+        TradePairInfo {
+            ws_name: pair_name.to_lowercase(),
+            show_name: format!("{}/{}", base, quote),
+            pair_name,
+            decimals: None,
+        }
+    }
+
+    pub fn trade_pair(&self) -> TradePair {
+        TradePair(self.pair_name.clone())
+    }
+}
+
+pub struct PairRegistry {
+    infos: HashMap<TradePair, TradePairInfo>,
+    // pairs at the top of the menu, from the config or the built-in defaults
+    favorites: Vec<TradePair>,
+    // pairs listed by the exchange, in menu order
+    listed: Vec<TradePair>,
+    // exchange the listed pairs were fetched from
+    listed_exchange: Option<&'static str>,
+    // exchange specific channel names, e.g. BTC-USDT-SWAP on okx, by exchange id
+    instruments: HashMap<(&'static str, TradePair), String>,
+    // decimals set in the config, over the tick size of any exchange
+    pinned_decimals: HashMap<TradePair, u32>,
+    // web trading page by exchange id, see trade_url
+    trade_urls: HashMap<String, String>,
+}
+
+// {pair}, {base}, {quote} and the exchange's {symbol}, each also as {..._lower}
+const TRADE_URLS: [(&str, &str); 6] = [
+    ("binance-futures", "https://www.binance.com/futures/{pair}"),
+    ("binance-spot", "https://www.binance.com/trade/{base}_{quote}"),
+    ("okx-swap", "https://www.okx.com/trade-swap/{symbol_lower}"),
+    ("bybit-linear", "https://www.bybit.com/trade/usdt/{pair}"),
+    ("coinbase-spot", "https://www.coinbase.com/advanced-trade/spot/{symbol}"),
+    ("kraken-spot", "https://pro.kraken.com/app/trade/{base_lower}-{quote_lower}"),
+];
+
+fn default_trade_urls() -> HashMap<String, String> {
+    TRADE_URLS
+        .iter()
+        .map(|(id, template)| (id.to_string(), template.to_string()))
+        .collect()
+}
+
+impl PairRegistry {
+    fn new() -> Self {
+        let mut registry = PairRegistry {
+            infos: HashMap::new(),
+            favorites: Vec::new(),
+            listed: Vec::new(),
+            listed_exchange: None,
+            instruments: HashMap::new(),
+            pinned_decimals: HashMap::new(),
+            trade_urls: default_trade_urls(),
+        };
+        registry.set_favorites(default_pairs());
+        registry
+    }
+
+    pub fn set_favorites(&mut self, infos: Vec<TradePairInfo>) {
+        self.favorites = infos.iter().map(|info| info.trade_pair()).collect();
+        for info in infos {
+            self.infos.insert(info.trade_pair(), info);
+        }
+    }
+
+    pub fn favorites(&self) -> Vec<TradePairInfo> {
+        self.favorites
+            .iter()
+            .map(|trade_pair| self.info(trade_pair))
+            .collect()
+    }
+
+    pub fn info(&self, trade_pair: &TradePair) -> TradePairInfo {
+        match self.infos.get(trade_pair) {
+            Some(info) => info.clone(),
+            None => TradePairInfo {
+                ws_name: trade_pair.0.to_lowercase(),
+                show_name: trade_pair.0.clone(),
+                pair_name: trade_pair.0.clone(),
+                decimals: None,
+            },
+        }
+    }
+
+    // info with ws_name set to the channel name of exchange
+    pub fn exchange_info(&self, exchange: &dyn Exchange, trade_pair: &TradePair) -> TradePairInfo {
+        let mut info = self.info(trade_pair);
+        info.ws_name = match self.instruments.get(&(exchange.id(), trade_pair.clone())) {
+            Some(instrument) => instrument.clone(),
+            None => exchange.instrument_id(&info),
+        };
+        info
+    }
+
+    pub fn decimals(&self, trade_pair: &TradePair) -> Option<u32> {
+        match self.pinned_decimals.get(trade_pair) {
+            Some(decimals) => Some(*decimals),
+            None => self.infos.get(trade_pair).and_then(|info| info.decimals),
+        }
+    }
+
+    pub fn trade_url(&self, exchange: &dyn Exchange, trade_pair: &TradePair) -> Option<String> {
+        let template = self.trade_urls.get(exchange.id())?;
+        let info = self.exchange_info(exchange, trade_pair);
+        let (base, quote) = exchange::split_pair_name(&info.pair_name).unwrap_or((&info.pair_name, ""));
+        let mut url = template.clone();
+        for (name, value) in [
+            ("pair", info.pair_name.as_str()),
+            ("base", base),
+            ("quote", quote),
+            ("symbol", info.ws_name.as_str()),
+        ] {
+            url = url
+                .replace(&format!("{{{}}}", name), value)
+                .replace(&format!("{{{}_lower}}", name), &value.to_lowercase());
+        }
+        Some(url)
+    }
+
+    pub fn listed(&self) -> Vec<TradePairInfo> {
+        self.listed
+            .iter()
+            .map(|trade_pair| self.info(trade_pair))
+            .collect()
+    }
+
+    pub fn listed_exchange(&self) -> Option<&'static str> {
+        self.listed_exchange
+    }
+
+    fn set_listed(&mut self, exchange_id: &'static str, infos: Vec<TradePairInfo>) {
+        self.listed = infos.iter().map(|info| info.trade_pair()).collect();
+        for info in &infos {
+            self.instruments
+                .insert((exchange_id, info.trade_pair()), info.ws_name.clone());
+        }
+        for info in infos {
+            // favorites keep their names but take the tick size of this exchange
+            self.infos
+                .entry(info.trade_pair())
+                .and_modify(|known| known.decimals = info.decimals)
+                .or_insert(info);
+        }
+        self.listed_exchange = Some(exchange_id);
+    }
+}
+
+lazy_static! {
+    pub static ref PAIR_REGISTRY: RwLock<PairRegistry> = RwLock::new(PairRegistry::new());
+}
+
+pub fn default_pairs() -> Vec<TradePairInfo> {
+    vec![
+        TradePairInfo::new("BTC", "USDT"),
+        TradePairInfo::new("ETH", "USDT"),
+        TradePairInfo::new("SOL", "USDT"),
+    ]
+}
+
+// register the user defined pairs from the config
+pub fn init(config: &Config) {
+    let infos = if config.pairs.is_empty() {
+        default_pairs()
+    } else {
+        config.pairs.iter().map(|pair| pair.to_info()).collect()
+    };
+    let mut registry = PAIR_REGISTRY.write().unwrap();
+    registry.set_favorites(infos);
+    registry.pinned_decimals = config
+        .decimals
+        .iter()
+        .map(|(pair_name, decimals)| (TradePair(pair_name.to_uppercase()), *decimals))
+        .collect();
+    registry.trade_urls = default_trade_urls();
+    registry.trade_urls.extend(config.trade_urls.clone());
+}
+
+// listed pairs by quote asset in exchange::QUOTES order, the rest last under
+// an empty quote; the order within each group is kept
+pub fn by_quote(infos: Vec<TradePairInfo>) -> Vec<(&'static str, Vec<TradePairInfo>)> {
+    let mut groups: Vec<(&'static str, Vec<TradePairInfo>)> = exchange::QUOTES
+        .iter()
+        .chain(std::iter::once(&""))
+        .map(|quote| (*quote, Vec::new()))
+        .collect();
+    for info in infos {
+        let quote = exchange::split_pair_name(&info.pair_name).map_or("", |(_, quote)| quote);
+        if let Some((_, group)) = groups.iter_mut().find(|(group_quote, _)| *group_quote == quote) {
+            group.push(info);
+        }
+    }
+    groups.retain(|(_, group)| !group.is_empty());
+    groups
+}
+
+// first pair of the menu, used when nothing else was selected
+pub fn default_trade_pair() -> TradePair {
+    PAIR_REGISTRY.read().unwrap().favorites[0].clone()
+}
+
+// a favorite or listed pair by pair name, show name or base asset, any case
+pub fn find(query: &str) -> Option<TradePair> {
+    let query = query.to_uppercase();
+    let registry = PAIR_REGISTRY.read().unwrap();
+    let known: Vec<TradePairInfo> = registry
+        .favorites()
+        .into_iter()
+        .chain(registry.listed())
+        .collect();
+    known
+        .iter()
+        .find(|info| info.pair_name == query || info.show_name.to_uppercase() == query)
+        .or_else(|| {
+            known
+                .iter()
+                .find(|info| info.show_name.split('/').next().map(str::to_uppercase) == Some(query.clone()))
+        })
+        .map(|info| info.trade_pair())
+}
+
+// favorite and listed pairs whose pair or show name contains query, any
+// case, base assets starting with it first. an empty query takes them all
+pub fn search(query: &str) -> Vec<TradePairInfo> {
+    let registry = PAIR_REGISTRY.read().unwrap();
+    let known: Vec<TradePairInfo> = registry
+        .favorites()
+        .into_iter()
+        .chain(registry.listed())
+        .collect();
+    matching(known, query)
+}
+
+fn matching(known: Vec<TradePairInfo>, query: &str) -> Vec<TradePairInfo> {
+    let query = query.trim().to_uppercase();
+    let mut found: Vec<TradePairInfo> = Vec::new();
+    for info in known {
+        let show_name = info.show_name.to_uppercase();
+        if (info.pair_name.contains(&query) || show_name.contains(&query)) && !found.contains(&info) {
+            found.push(info);
+        }
+    }
+    // stable, so the menu order is kept within each rank
+    found.sort_by_key(|info| !info.show_name.to_uppercase().starts_with(&query));
+    found
+}
+
+pub fn pair_info(trade_pair: &TradePair) -> TradePairInfo {
+    PAIR_REGISTRY.read().unwrap().info(trade_pair)
+}
+
+// price of pair_name with the pinned decimals or those of its tick size,
+// in the configured fiat currency once its rate is known
+pub fn format_price(pair_name: &str, value: f64) -> String {
+    let trade_pair = TradePair(pair_name.to_string());
+    let registry = PAIR_REGISTRY.read().unwrap();
+    match fiat::convert(pair_name, value) {
+        // the tick size is in the quote currency, not in the fiat one
+        Some((symbol, converted)) => {
+            let decimals = registry.pinned_decimals.get(&trade_pair).copied();
+            format!("{}{}", symbol, format::price(converted, decimals))
+        }
+        None => format::price(value, registry.decimals(&trade_pair)),
+    }
+}
+
+// none for an exchange without a template
+pub fn trade_url(exchange: &dyn Exchange, trade_pair: &TradePair) -> Option<String> {
+    PAIR_REGISTRY.read().unwrap().trade_url(exchange, trade_pair)
+}
+
+pub fn exchange_pair_info(exchange: &dyn Exchange, trade_pair: &TradePair) -> TradePairInfo {
+    PAIR_REGISTRY.read().unwrap().exchange_info(exchange, trade_pair)
+}
+
+// fetch the symbol list of exchange unless it is already loaded
+pub async fn discover(exchange: &'static dyn Exchange, client: &reqwest::Client) -> Result<()> {
+    if PAIR_REGISTRY.read().unwrap().listed_exchange() == Some(exchange.id()) {
+        return Ok(());
+    }
+    let str_data = tls::send(client.get(exchange.symbols_url())).await?.text().await?;
+    let infos = exchange
+        .parse_symbols(&str_data)
+        .ok_or_else(|| anyhow::anyhow!("bad symbol list from {}", exchange.id()))?;
+    PAIR_REGISTRY
+        .write()
+        .unwrap()
+        .set_listed(exchange.id(), infos);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matching_ranks_base_prefixes_first() {
+        let known = vec![
+            TradePairInfo::new("BTC", "USDT"),
+            TradePairInfo::new("ARB", "SOL"),
+            TradePairInfo::new("SOL", "USDT"),
+            TradePairInfo::new("SOL", "USDT"),
+            TradePairInfo::new("SOL", "BTC"),
+        ];
+        let found: Vec<String> = matching(known.clone(), " sol")
+            .into_iter()
+            .map(|info| info.show_name)
+            .collect();
+        assert_eq!(found, vec!["SOL/USDT", "SOL/BTC", "ARB/SOL"]);
+        assert_eq!(matching(known.clone(), "sol/u").len(), 1);
+        assert_eq!(matching(known, "").len(), 4);
+    }
+
+    #[test]
+    fn by_quote_keeps_the_quotes_order() {
+        let groups = by_quote(vec![TradePairInfo::new("ETH", "BTC"), TradePairInfo::new("ETH", "USDT")]);
+        let quotes: Vec<&str> = groups.iter().map(|(quote, _)| *quote).collect();
+        assert_eq!(quotes[0], "USDT");
+        assert_eq!(groups.len(), 2);
+    }
+}
@@ -1,20 +1,37 @@
 use std::io::{Error, ErrorKind};
+use std::net::{IpAddr, SocketAddr};
+use percent_encoding::percent_decode_str;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpStream;
-use tokio_socks::tcp::Socks5Stream;
+use tokio_socks::tcp::{Socks4Stream, Socks5Stream};
 use url::Url;
 use super::ProxyStream::ProxyStream;
+use super::sspi::SspiAuth;
+
+// handshake rounds before giving up on a proxy that keeps answering 407
+const AUTH_ROUNDS: usize = 3;
 
 pub enum InnerProxy {
     // http or https
     Http {
-        auth: Option<Vec<u8>>,
+        // user and password from the url, DOMAIN\user for ntlm
+        credentials: Option<(String, String)>,
         url: String,
+        // CONNECT names the host instead of an address resolved here
+        remote_dns: bool,
     },
-    // socks5
+    // socks5, socks5h always lets the proxy resolve the host
     Socks {
         auth: Option<(String, String)>,
         url: String,
-    }
+        remote_dns: bool,
+    },
+    // socks4 takes an ipv4 target only, socks4a lets the proxy resolve the host
+    Socks4 {
+        user_id: Option<String>,
+        url: String,
+        remote_dns: bool,
+    },
 }
 
 impl InnerProxy {
@@ -30,18 +47,19 @@ impl InnerProxy {
 
         match url.scheme() {
             "http" | "https" => {
-                let mut basic_bytes: Option<Vec<u8>> = None;
-                if let Some(pwd) = url.password() {
-                    let encoded_str = format!("Basic {}", base64::encode(&format!("{}:{}", url.username(), pwd)));
-                    basic_bytes = Some(encoded_str.into_bytes());
-                };
+                // the url keeps them percent encoded, e.g. DOMAIN%5Cuser
+                let decode = |part: &str| percent_decode_str(part).decode_utf8_lossy().to_string();
+                let credentials = url
+                    .password()
+                    .map(|pwd| (decode(url.username()), decode(pwd)));
 
                 Ok(InnerProxy::Http {
-                    auth: basic_bytes,
+                    credentials,
                     url: addr.to_string(),
+                    remote_dns: super::remote_dns(),
                 })
             },
-            "socks5" => {
+            "socks5" | "socks5h" => {
                 let mut auth_pair = None;
                 if let Some(pwd) = url.password() {
                     auth_pair = Some((url.username().to_string(), pwd.to_string()))
@@ -50,6 +68,19 @@ impl InnerProxy {
                 Ok(InnerProxy::Socks {
                     auth: auth_pair,
                     url: addr.to_string(),
+                    remote_dns: url.scheme() == "socks5h" || super::remote_dns(),
+                })
+            }
+            "socks4" | "socks4a" => {
+                // socks4 has no password, the user part of the url is the user id
+                let user_id = Some(url.username())
+                    .filter(|user_id| !user_id.is_empty())
+                    .map(|user_id| percent_decode_str(user_id).decode_utf8_lossy().to_string());
+
+                Ok(InnerProxy::Socks4 {
+                    user_id,
+                    url: addr.to_string(),
+                    remote_dns: url.scheme() == "socks4a",
                 })
             }
 
@@ -58,6 +89,15 @@ impl InnerProxy {
 
     }
 
+    // host:port of the proxy itself
+    pub fn address(&self) -> &str {
+        match self {
+            InnerProxy::Http { url, .. } => url,
+            InnerProxy::Socks { url, .. } => url,
+            InnerProxy::Socks4 { url, .. } => url,
+        }
+    }
+
     pub async fn connect_async(&self, target: &str) -> Result<ProxyStream, Error> {
         let target_url = Url::parse(target)
             .unwrap_or_else(|e| panic!("failed to parse target url: {}", target));
@@ -68,71 +108,225 @@ impl InnerProxy {
         };
         let port = target_url.port().unwrap_or(443);
         match self {
-            InnerProxy::Http {auth, url } => {
-                let mut tcp_stream = TcpStream::connect(url).await
-                    .expect("failed to connect http[s] proxy");
-                Ok(ProxyStream::Http(Self::tunnel(tcp_stream, host, port, auth).await.unwrap()))
+            InnerProxy::Http { credentials, url, remote_dns } => {
+                let host = if *remote_dns {
+                    host
+                } else {
+                    match Self::lookup(&host, port, false).await?.ip() {
+                        IpAddr::V4(ip) => ip.to_string(),
+                        IpAddr::V6(ip) => format!("[{}]", ip),
+                    }
+                };
+                let tcp_stream = TcpStream::connect(url).await?;
+                Ok(ProxyStream::Http(Self::tunnel(tcp_stream, url, host, port, credentials).await?))
             },
-            InnerProxy::Socks { auth, url} => {
-                let stream = match auth {
-                    Some(au) => Socks5Stream::connect_with_password(
-                        url.as_str(), (host.as_str(), port), &au.0, &au.1).await,
-                    None => Socks5Stream::connect(url.as_str(), (host.as_str(), port)).await,
+            InnerProxy::Socks { auth, url, remote_dns } => {
+                let stream = if *remote_dns {
+                    Self::connect_socks5(url, (host.as_str(), port), auth).await
+                } else {
+                    let target = Self::lookup(&host, port, false).await?;
+                    Self::connect_socks5(url, target, auth).await
                 };
                 match stream {
                     Ok(s) => Ok(ProxyStream::Socks(s)),
                     Err(e) => Err(Error::new(ErrorKind::NotConnected, "failed to create socks proxy stream"))
                 }
             }
+            InnerProxy::Socks4 { user_id, url, remote_dns } => {
+                let stream = if *remote_dns {
+                    Self::connect_socks4(url, (host.as_str(), port), user_id).await
+                } else {
+                    let target = Self::lookup(&host, port, true).await?;
+                    Self::connect_socks4(url, target, user_id).await
+                };
+                stream
+                    .map(ProxyStream::Socks4)
+                    .map_err(|e| Error::new(ErrorKind::NotConnected, format!("failed to create socks4 proxy stream: {}", e)))
+            }
         }
     }
 
+    // resolves the target here, for proxies told not to or unable to do it
+    async fn lookup(host: &str, port: u16, ipv4_only: bool) -> Result<SocketAddr, Error> {
+        tokio::net::lookup_host((host, port))
+            .await?
+            .find(|addr| !ipv4_only || addr.is_ipv4())
+            .ok_or_else(|| Error::new(ErrorKind::AddrNotAvailable, format!("no address for {}", host)))
+    }
+
+    async fn connect_socks5<'t, T>(url: &str,
+                                   target: T,
+                                   auth: &Option<(String, String)>) -> Result<Socks5Stream<TcpStream>, tokio_socks::Error>
+    where
+        T: tokio_socks::IntoTargetAddr<'t>,
+    {
+        match auth {
+            Some(au) => Socks5Stream::connect_with_password(url, target, &au.0, &au.1).await,
+            None => Socks5Stream::connect(url, target).await,
+        }
+    }
+
+    async fn connect_socks4<'t, T>(url: &str,
+                                   target: T,
+                                   user_id: &Option<String>) -> Result<Socks4Stream<TcpStream>, tokio_socks::Error>
+    where
+        T: tokio_socks::IntoTargetAddr<'t>,
+    {
+        match user_id {
+            Some(user_id) => Socks4Stream::connect_with_userid(url, target, user_id).await,
+            None => Socks4Stream::connect(url, target).await,
+        }
+    }
+
+    // CONNECT with basic auth when the url has credentials, a 407 offering
+    // ntlm or negotiate continues with that handshake on the same connection
     async fn tunnel(mut conn: TcpStream,
+                    proxy_url: &str,
                     host: String,
                     port: u16,
-                    auth: &Option<Vec<u8>>) -> Result<TcpStream, Error>
+                    credentials: &Option<(String, String)>) -> Result<TcpStream, Error>
+    {
+        let basic = credentials.as_ref().map(|(username, password)| {
+            format!("Basic {}", base64::encode(format!("{}:{}", username, password)))
+        });
+        let response = Self::connect_request(&mut conn, &host, port, basic.as_deref()).await?;
+        match response.status {
+            200 => return Ok(conn),
+            407 => {}
+            status => return Err(Error::other(format!("unsuccessful tunnel: {}", status))),
+        }
+        let package = match ["Negotiate", "NTLM"]
+            .into_iter()
+            .find(|package| response.challenge(package).is_some())
+        {
+            Some(package) => package,
+            // basic only, and the credentials were missing or rejected
+            None => return Err(Error::new(ErrorKind::PermissionDenied, "proxy authentication required")),
+        };
+        if response.closes() {
+            conn = TcpStream::connect(proxy_url).await?;
+        }
+        let proxy_host = proxy_url.rsplit_once(':').map_or(proxy_url, |(host, _)| host);
+        let mut sspi = SspiAuth::new(package, credentials.as_ref(), proxy_host)?;
+        let mut challenge: Option<Vec<u8>> = None;
+        for _ in 0..AUTH_ROUNDS {
+            let token = sspi.step(challenge.as_deref())?;
+            let authorization = format!("{} {}", package, base64::encode(&token));
+            let response = Self::connect_request(&mut conn, &host, port, Some(&authorization)).await?;
+            match response.status {
+                200 => return Ok(conn),
+                407 if !response.closes() => {
+                    challenge = response
+                        .challenge(package)
+                        .filter(|token| !token.is_empty())
+                        .and_then(|token| base64::decode(token).ok());
+                    if challenge.is_none() {
+                        break;
+                    }
+                }
+                407 => break,
+                status => return Err(Error::other(format!("unsuccessful tunnel: {}", status))),
+            }
+        }
+        Err(Error::new(ErrorKind::PermissionDenied, "proxy authentication failed"))
+    }
+
+    async fn connect_request(conn: &mut TcpStream,
+                             host: &str,
+                             port: u16,
+                             authorization: Option<&str>) -> Result<ProxyResponse, Error>
     {
-        use tokio::io::{AsyncReadExt, AsyncWriteExt};
         let mut buf = format!(
             "\
          CONNECT {0}:{1} HTTP/1.1\r\n\
          Host: {0}:{1}\r\n\
+         Proxy-Connection: keep-alive\r\n\
          ",
             host, port
         ).into_bytes();
 
-        if let Some(au) = auth {
+        if let Some(au) = authorization {
             buf.extend_from_slice(b"Proxy-Authorization: ");
-            buf.extend_from_slice(au.as_slice());
+            buf.extend_from_slice(au.as_bytes());
             buf.extend_from_slice(b"\r\n");
         }
 
         buf.extend_from_slice(b"\r\n");
-        conn.write_all(&buf).await.unwrap();
+        conn.write_all(&buf).await?;
+        ProxyResponse::read(conn).await
+    }
+}
 
-        let mut buf = [0; 1024];
-        let mut pos = 0;
+const MAX_HEADERS_LEN: usize = 8192;
 
-        loop {
-            let n = conn.read(&mut buf[pos..]).await?;
-            if n == 0 {
-                return Err(Error::new(ErrorKind::UnexpectedEof, "0 bytes in reading tunnel"));
-            }
-            pos += n;
+struct ProxyResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+}
 
-            let recvd = &buf[..pos];
-            if recvd.starts_with(b"HTTP/1.1 200") || recvd.starts_with(b"HTTP/1.0 200") {
-                if recvd.ends_with(b"\r\n\r\n") {
-                    return Ok(conn);
-                }
-                if pos == buf.len() {
-                    return Err(Error::new(ErrorKind::UnexpectedEof, "proxy headers too long than tunnel"));
+impl ProxyResponse {
+    // reads the head byte by byte, the tunnelled stream starts right after it
+    async fn read(conn: &mut TcpStream) -> Result<ProxyResponse, Error> {
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            if head.len() >= MAX_HEADERS_LEN {
+                return Err(Error::new(ErrorKind::InvalidData, "proxy headers too long than tunnel"));
+            }
+            match conn.read_u8().await {
+                Ok(byte) => head.push(byte),
+                Err(err) if err.kind() == ErrorKind::UnexpectedEof => {
+                    return Err(Error::new(ErrorKind::UnexpectedEof, "0 bytes in reading tunnel"));
                 }
-            } else if recvd.starts_with(b"HTTP/1.1 407") {
-                return Err(Error::new(ErrorKind::PermissionDenied, "proxy authentication required"));
-            } else {
-                return Err(Error::new(ErrorKind::Other, "unsuccessful tunnel"));
+                Err(err) => return Err(err),
             }
         }
+        let head = String::from_utf8_lossy(&head);
+        let mut lines = head.split("\r\n");
+        let status = lines
+            .next()
+            .and_then(|line| line.split_whitespace().nth(1))
+            .and_then(|status| status.parse::<u16>().ok())
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "bad proxy status line"))?;
+        let headers: Vec<(String, String)> = lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+            .collect();
+        let response = ProxyResponse { status, headers };
+        // the 407 body has to be consumed before the next request on this connection
+        if status != 200 {
+            let length = response
+                .header("content-length")
+                .and_then(|length| length.parse::<u64>().ok())
+                .unwrap_or(0);
+            let mut body = conn.take(length);
+            tokio::io::copy(&mut body, &mut tokio::io::sink()).await?;
+        }
+        Ok(response)
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    // token after the scheme in Proxy-Authenticate, empty on the first 407
+    fn challenge(&self, scheme: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .filter(|(header, _)| header == "proxy-authenticate")
+            .find_map(|(_, value)| {
+                let (name, token) = value.split_once(' ').unwrap_or((value.as_str(), ""));
+                name.eq_ignore_ascii_case(scheme).then(|| token.trim())
+            })
     }
-}
\ No newline at end of file
+
+    fn closes(&self) -> bool {
+        ["connection", "proxy-connection"]
+            .iter()
+            .any(|name| self.header(name).is_some_and(|value| value.eq_ignore_ascii_case("close")))
+            // without a length the body runs until the proxy closes
+            || (self.status != 200 && self.header("content-length").is_none())
+    }
+}
@@ -1,11 +1,12 @@
 use std::{io::Error, task::{Context, Poll}};
 use std::pin::Pin;
 use tokio::{io::{AsyncRead, AsyncWrite, ReadBuf}, net::TcpStream};
-use tokio_socks::tcp::Socks5Stream;
+use tokio_socks::tcp::{Socks4Stream, Socks5Stream};
 
 pub enum ProxyStream {
     Http(TcpStream),
-    Socks(Socks5Stream<TcpStream>)
+    Socks(Socks5Stream<TcpStream>),
+    Socks4(Socks4Stream<TcpStream>),
 }
 
 impl AsyncRead for ProxyStream {
@@ -15,6 +16,7 @@ impl AsyncRead for ProxyStream {
         match self.get_mut() {
             ProxyStream::Http(s) => Pin::new(s).poll_read(cx, buf),
             ProxyStream::Socks(s) => Pin::new(s).poll_read(cx, buf),
+            ProxyStream::Socks4(s) => Pin::new(s).poll_read(cx, buf),
         }
     }
 }
@@ -26,6 +28,7 @@ impl AsyncWrite for ProxyStream {
         match self.get_mut() {
             ProxyStream::Http(s) => Pin::new(s).poll_write(cx, buf),
             ProxyStream::Socks(s) => Pin::new(s).poll_write(cx, buf),
+            ProxyStream::Socks4(s) => Pin::new(s).poll_write(cx, buf),
         }
     }
 
@@ -33,6 +36,7 @@ impl AsyncWrite for ProxyStream {
         match self.get_mut() {
             ProxyStream::Http(s) => Pin::new(s).poll_flush(cx),
             ProxyStream::Socks(s) => Pin::new(s).poll_flush(cx),
+            ProxyStream::Socks4(s) => Pin::new(s).poll_flush(cx),
         }
     }
 
@@ -40,6 +44,7 @@ impl AsyncWrite for ProxyStream {
         match self.get_mut() {
             ProxyStream::Http(s) => Pin::new(s).poll_shutdown(cx),
             ProxyStream::Socks(s) => Pin::new(s).poll_shutdown(cx),
+            ProxyStream::Socks4(s) => Pin::new(s).poll_shutdown(cx),
         }
     }
 }
\ No newline at end of file
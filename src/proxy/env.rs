@@ -0,0 +1,76 @@
+use std::env;
+use tracing::info;
+
+// the upper case name wins, like curl
+fn var(name: &str) -> Option<String> {
+    env::var(name.to_uppercase())
+        .or_else(|_| env::var(name))
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+// the exchanges are all wss, so https_proxy comes first
+pub fn detect() -> Option<String> {
+    let proxy = ["https_proxy", "all_proxy", "http_proxy"]
+        .iter()
+        .find_map(|name| var(name))?;
+    let proxy = if proxy.contains("://") {
+        proxy
+    } else {
+        format!("http://{}", proxy)
+    };
+    info!("env proxy:{}", proxy);
+    Some(proxy)
+}
+
+// whether NO_PROXY sends host around the proxy
+pub fn bypassed(host: &str) -> bool {
+    var("no_proxy").is_some_and(|no_proxy| no_proxy_matches(&no_proxy, host))
+}
+
+// "*" or a comma separated list of hosts and domain suffixes, ports ignored
+pub fn no_proxy_matches(no_proxy: &str, host: &str) -> bool {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    no_proxy
+        .split([',', ' '])
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .any(|entry| {
+            if entry == "*" {
+                return true;
+            }
+            let entry = match entry.rsplit_once(':') {
+                // keeps ipv6 addresses whole
+                Some((name, port)) if !name.contains(':') && port.parse::<u16>().is_ok() => name,
+                _ => entry,
+            };
+            let domain = entry.trim_start_matches("*.").trim_start_matches('.').to_ascii_lowercase();
+            host == domain || host.ends_with(&format!(".{}", domain))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::no_proxy_matches;
+
+    #[test]
+    fn wildcard_bypasses_everything() {
+        assert!(no_proxy_matches("*", "stream.binance.com"));
+    }
+
+    #[test]
+    fn matches_hosts_and_subdomains() {
+        let no_proxy = "localhost, .okx.com,bybit.com:443";
+        assert!(no_proxy_matches(no_proxy, "ws.okx.com"));
+        assert!(no_proxy_matches(no_proxy, "stream.bybit.com"));
+        assert!(no_proxy_matches(no_proxy, "bybit.com"));
+        assert!(!no_proxy_matches(no_proxy, "notbybit.com"));
+        assert!(!no_proxy_matches(no_proxy, "stream.binance.com"));
+    }
+
+    #[test]
+    fn empty_list_bypasses_nothing() {
+        assert!(!no_proxy_matches("", "stream.binance.com"));
+    }
+}
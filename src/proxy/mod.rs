@@ -1,2 +1,99 @@
 pub mod InnerProxy;
-pub mod ProxyStream;
\ No newline at end of file
+pub mod ProxyStream;
+pub mod env;
+pub mod pac;
+pub mod sspi;
+pub mod system;
+
+use lazy_static::lazy_static;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::RwLock;
+
+// value of an exchange_proxies entry that connects the exchange without a proxy
+pub const DIRECT: &str = "direct";
+
+// proxy failures in a row before the selected exchange is tried without it, 0 never does
+static DIRECT_FALLBACK: AtomicU32 = AtomicU32::new(0);
+
+pub fn set_direct_fallback(after: u32) {
+    DIRECT_FALLBACK.store(after, Ordering::Relaxed);
+}
+
+pub fn direct_fallback() -> u32 {
+    DIRECT_FALLBACK.load(Ordering::Relaxed)
+}
+
+// hostnames are resolved by the proxy unless the config turns it off, the
+// socks5h and socks4a schemes always do and socks4 never can
+static REMOTE_DNS: AtomicBool = AtomicBool::new(true);
+
+// returns whether it changed, the connections need a restart then
+pub fn set_remote_dns(remote: bool) -> bool {
+    REMOTE_DNS.swap(remote, Ordering::Relaxed) != remote
+}
+
+pub fn remote_dns() -> bool {
+    REMOTE_DNS.load(Ordering::Relaxed)
+}
+
+lazy_static! {
+    // exchange id to its own proxy url or DIRECT, from the config
+    static ref EXCHANGE_PROXIES: RwLock<HashMap<String, String>> = RwLock::new(HashMap::new());
+}
+
+// returns whether the routes changed, the connections need a restart then
+pub fn set_exchange_proxies(proxies: &BTreeMap<String, String>) -> bool {
+    let proxies: HashMap<String, String> = proxies
+        .iter()
+        .map(|(id, proxy)| (id.to_lowercase(), proxy.trim().to_string()))
+        .collect();
+    let mut routes = EXCHANGE_PROXIES.write().unwrap();
+    let changed = *routes != proxies;
+    *routes = proxies;
+    changed
+}
+
+// the exchange's own route when the config has one, else the shared proxy
+pub fn for_exchange(exchange_id: &str, proxy_str: Option<String>) -> Option<String> {
+    match EXCHANGE_PROXIES.read().unwrap().get(exchange_id) {
+        Some(proxy) if proxy.is_empty() || proxy.eq_ignore_ascii_case(DIRECT) => None,
+        Some(proxy) => Some(proxy.clone()),
+        None => proxy_str,
+    }
+}
+
+// used when neither --proxy nor the config names one
+pub fn fallback() -> Option<String> {
+    env::detect().or_else(system::detect)
+}
+
+// proxy for one connection, none when NO_PROXY lists the host or a pac script
+// says DIRECT; blocks while a pac script is fetched
+pub fn for_target(proxy_str: &Option<String>, target: &str) -> Option<String> {
+    let proxy_str = proxy_str.as_ref()?;
+    let host = url::Url::parse(target)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_default();
+    if env::bypassed(&host) {
+        return None;
+    }
+    if pac::is_pac(proxy_str) {
+        return pac::resolve(proxy_str, target);
+    }
+    Some(proxy_str.clone())
+}
+
+// for_target without blocking, for the reqwest proxy of http_client: until
+// the pac script answered for the host on a worker thread, requests go direct
+pub fn for_target_cached(proxy_str: &str, target: &str) -> Option<String> {
+    let host = url::Url::parse(target)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_default();
+    if env::bypassed(&host) {
+        return None;
+    }
+    pac::cached(proxy_str, target).flatten()
+}
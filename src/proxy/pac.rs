@@ -0,0 +1,125 @@
+use super::system::{parse_proxy_list, take_string};
+use lazy_static::lazy_static;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use tracing::{debug, warn};
+use windows::core::{HSTRING, PCWSTR};
+use windows::Win32::Foundation::TRUE;
+use windows::Win32::Networking::WinHttp::{
+    WinHttpCloseHandle, WinHttpGetProxyForUrl, WinHttpOpen, WINHTTP_ACCESS_TYPE_NAMED_PROXY,
+    WINHTTP_ACCESS_TYPE_NO_PROXY, WINHTTP_AUTOPROXY_AUTO_DETECT, WINHTTP_AUTOPROXY_CONFIG_URL,
+    WINHTTP_AUTOPROXY_OPTIONS, WINHTTP_AUTO_DETECT_TYPE_DHCP, WINHTTP_AUTO_DETECT_TYPE_DNS_A,
+    WINHTTP_PROXY_INFO,
+};
+
+// pac://http://wpad/wpad.dat, pac://wpad/wpad.dat, or pac://auto for wpad discovery
+pub const SCHEME: &str = "pac://";
+const AUTO_DETECT: &str = "auto";
+
+lazy_static! {
+    // (script, target host) to the proxy it chose, none for DIRECT
+    static ref RESOLVED: Mutex<HashMap<(String, String), Option<String>>> = Mutex::new(HashMap::new());
+    // keys of RESOLVED being looked up by cached
+    static ref RESOLVING: Mutex<HashSet<(String, String)>> = Mutex::new(HashSet::new());
+}
+
+pub fn is_pac(proxy_str: &str) -> bool {
+    proxy_str.starts_with(SCHEME)
+}
+
+// proxy the pac script picks for target, none to connect directly; blocks
+// while winhttp downloads and runs the script, then answers from the cache
+pub fn resolve(proxy_str: &str, target: &str) -> Option<String> {
+    let script = &proxy_str[SCHEME.len()..];
+    let key = key(proxy_str, target);
+    if let Some(proxy) = RESOLVED.lock().unwrap().get(&key) {
+        return proxy.clone();
+    }
+    // pac scripts are written against http urls, wss is https for them
+    let target = target.replacen("wss://", "https://", 1).replacen("ws://", "http://", 1);
+    match get_proxy_for_url(script, &target) {
+        Ok(proxy) => {
+            debug!("pac proxy for {}:{:?}", key.1, proxy);
+            RESOLVED.lock().unwrap().insert(key, proxy.clone());
+            proxy
+        }
+        Err(err) => {
+            warn!("pac err:{:?}", err);
+            // a network without wpad stays direct instead of waiting on discovery
+            // every time, a configured script is tried again on the next connection
+            if script == AUTO_DETECT {
+                RESOLVED.lock().unwrap().insert(key, None);
+            }
+            None
+        }
+    }
+}
+
+// the answer of resolve when it is cached, None while it runs on a blocking
+// task; for callers on the runtime
+pub fn cached(proxy_str: &str, target: &str) -> Option<Option<String>> {
+    let key = key(proxy_str, target);
+    if let Some(proxy) = RESOLVED.lock().unwrap().get(&key) {
+        return Some(proxy.clone());
+    }
+    if RESOLVING.lock().unwrap().insert(key.clone()) {
+        let (proxy_str, target) = (proxy_str.to_string(), target.to_string());
+        tokio::task::spawn_blocking(move || {
+            resolve(&proxy_str, &target);
+            RESOLVING.lock().unwrap().remove(&key);
+        });
+    }
+    None
+}
+
+// the script and the target host
+fn key(proxy_str: &str, target: &str) -> (String, String) {
+    let host = url::Url::parse(target)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_default();
+    (proxy_str[SCHEME.len()..].to_string(), host)
+}
+
+fn get_proxy_for_url(script: &str, target: &str) -> windows::core::Result<Option<String>> {
+    let script_url = if script.contains("://") {
+        script.to_string()
+    } else {
+        format!("http://{}", script)
+    };
+    let script_url = HSTRING::from(script_url);
+    let mut options = WINHTTP_AUTOPROXY_OPTIONS {
+        // the script may sit behind integrated windows auth
+        fAutoLogonIfChallenged: TRUE,
+        ..Default::default()
+    };
+    if script == AUTO_DETECT {
+        options.dwFlags = WINHTTP_AUTOPROXY_AUTO_DETECT;
+        options.dwAutoDetectFlags = WINHTTP_AUTO_DETECT_TYPE_DHCP | WINHTTP_AUTO_DETECT_TYPE_DNS_A;
+    } else {
+        options.dwFlags = WINHTTP_AUTOPROXY_CONFIG_URL;
+        options.lpszAutoConfigUrl = PCWSTR(script_url.as_ptr());
+    }
+    unsafe {
+        let session = WinHttpOpen(
+            &HSTRING::from("mjj"),
+            WINHTTP_ACCESS_TYPE_NO_PROXY,
+            PCWSTR::null(),
+            PCWSTR::null(),
+            0,
+        );
+        if session.is_null() {
+            return Err(windows::core::Error::from_win32());
+        }
+        let mut proxy_info = WINHTTP_PROXY_INFO::default();
+        let result = WinHttpGetProxyForUrl(session, &HSTRING::from(target), &mut options, &mut proxy_info);
+        let _ = WinHttpCloseHandle(session);
+        result?;
+        let _ = take_string(proxy_info.lpszProxyBypass);
+        let proxy_list = take_string(proxy_info.lpszProxy);
+        if proxy_info.dwAccessType != WINHTTP_ACCESS_TYPE_NAMED_PROXY {
+            return Ok(None);
+        }
+        Ok(proxy_list.and_then(|proxy_list| parse_proxy_list(&proxy_list)))
+    }
+}
@@ -0,0 +1,146 @@
+use std::io::{Error, ErrorKind};
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::{SEC_E_OK, SEC_I_CONTINUE_NEEDED};
+use windows::Win32::Security::Authentication::Identity::{
+    AcquireCredentialsHandleW, DeleteSecurityContext, FreeContextBuffer, FreeCredentialsHandle,
+    InitializeSecurityContextW, SecBuffer, SecBufferDesc, ISC_REQ_ALLOCATE_MEMORY,
+    ISC_REQ_CONNECTION, SECBUFFER_TOKEN, SECBUFFER_VERSION, SECPKG_CRED_OUTBOUND,
+    SECURITY_NATIVE_DREP,
+};
+use windows::Win32::Security::Credentials::SecHandle;
+use windows::Win32::System::Rpc::{SEC_WINNT_AUTH_IDENTITY_UNICODE, SEC_WINNT_AUTH_IDENTITY_W};
+
+fn wide(text: &str) -> Vec<u16> {
+    text.encode_utf16().chain(std::iter::once(0)).collect()
+}
+
+// ntlm or negotiate handshake through the windows security support provider,
+// as the logged on user unless the proxy url names one
+pub struct SspiAuth {
+    credentials: SecHandle,
+    context: Option<SecHandle>,
+    // HTTP/<proxy host>, kerberos needs it to find the proxy's account
+    target: Vec<u16>,
+}
+
+impl SspiAuth {
+    // package is the scheme of the challenge, "NTLM" or "Negotiate"
+    pub fn new(
+        package: &str,
+        credentials: Option<&(String, String)>,
+        proxy_host: &str,
+    ) -> Result<SspiAuth, Error> {
+        // DOMAIN\user, a bare user logs on to the proxy's own domain
+        let (domain, user, password) = match credentials {
+            Some((username, password)) => {
+                let (domain, user) = username.split_once('\\').unwrap_or(("", username));
+                (wide(domain), wide(user), wide(password))
+            }
+            None => (Vec::new(), Vec::new(), Vec::new()),
+        };
+        let identity = SEC_WINNT_AUTH_IDENTITY_W {
+            User: user.as_ptr() as *mut u16,
+            UserLength: user.len().saturating_sub(1) as u32,
+            Domain: domain.as_ptr() as *mut u16,
+            DomainLength: domain.len().saturating_sub(1) as u32,
+            Password: password.as_ptr() as *mut u16,
+            PasswordLength: password.len().saturating_sub(1) as u32,
+            Flags: SEC_WINNT_AUTH_IDENTITY_UNICODE,
+        };
+        let auth_data = credentials.map(|_| &identity as *const _ as *const core::ffi::c_void);
+        let package = wide(package);
+        let mut handle = SecHandle::default();
+        unsafe {
+            AcquireCredentialsHandleW(
+                PCWSTR::null(),
+                PCWSTR(package.as_ptr()),
+                SECPKG_CRED_OUTBOUND,
+                None,
+                auth_data,
+                None,
+                None,
+                &mut handle,
+                None,
+            )
+            .map_err(|err| Error::new(ErrorKind::PermissionDenied, err.message()))?;
+        }
+        Ok(SspiAuth {
+            credentials: handle,
+            context: None,
+            target: wide(&format!("HTTP/{}", proxy_host)),
+        })
+    }
+
+    // next token for Proxy-Authorization, challenge is the token of the last 407
+    pub fn step(&mut self, challenge: Option<&[u8]>) -> Result<Vec<u8>, Error> {
+        let mut challenge = challenge.map(|challenge| challenge.to_vec());
+        let mut input_buffer = SecBuffer {
+            cbBuffer: challenge.as_ref().map_or(0, |challenge| challenge.len() as u32),
+            BufferType: SECBUFFER_TOKEN,
+            pvBuffer: challenge
+                .as_mut()
+                .map_or(std::ptr::null_mut(), |challenge| challenge.as_mut_ptr().cast()),
+        };
+        let input = SecBufferDesc {
+            ulVersion: SECBUFFER_VERSION,
+            cBuffers: 1,
+            pBuffers: &mut input_buffer,
+        };
+        let mut output_buffer = SecBuffer {
+            cbBuffer: 0,
+            BufferType: SECBUFFER_TOKEN,
+            pvBuffer: std::ptr::null_mut(),
+        };
+        let mut output = SecBufferDesc {
+            ulVersion: SECBUFFER_VERSION,
+            cBuffers: 1,
+            pBuffers: &mut output_buffer,
+        };
+        let mut new_context = self.context.unwrap_or_default();
+        let mut attributes = 0u32;
+        let status = unsafe {
+            InitializeSecurityContextW(
+                Some(&self.credentials),
+                self.context.as_ref().map(|context| context as *const SecHandle),
+                Some(self.target.as_ptr()),
+                ISC_REQ_CONNECTION | ISC_REQ_ALLOCATE_MEMORY,
+                0,
+                SECURITY_NATIVE_DREP,
+                challenge.as_ref().map(|_| &input as *const SecBufferDesc),
+                0,
+                Some(&mut new_context),
+                Some(&mut output),
+                &mut attributes,
+                None,
+            )
+        };
+        if status != SEC_E_OK && status != SEC_I_CONTINUE_NEEDED {
+            return Err(Error::new(ErrorKind::PermissionDenied, status.message()));
+        }
+        self.context = Some(new_context);
+        if output_buffer.pvBuffer.is_null() {
+            return Ok(Vec::new());
+        }
+        let token = unsafe {
+            let token = std::slice::from_raw_parts(
+                output_buffer.pvBuffer as *const u8,
+                output_buffer.cbBuffer as usize,
+            )
+            .to_vec();
+            let _ = FreeContextBuffer(output_buffer.pvBuffer);
+            token
+        };
+        Ok(token)
+    }
+}
+
+impl Drop for SspiAuth {
+    fn drop(&mut self) {
+        unsafe {
+            if let Some(context) = &self.context {
+                let _ = DeleteSecurityContext(context);
+            }
+            let _ = FreeCredentialsHandle(&self.credentials);
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use super::pac;
+use tracing::info;
+use windows::core::PWSTR;
+use windows::Win32::Foundation::{GlobalFree, HGLOBAL};
+use windows::Win32::Networking::WinHttp::{
+    WinHttpGetDefaultProxyConfiguration, WinHttpGetIEProxyConfigForCurrentUser,
+    WINHTTP_ACCESS_TYPE_NAMED_PROXY, WINHTTP_CURRENT_USER_IE_PROXY_CONFIG, WINHTTP_PROXY_INFO,
+};
+
+// strings handed out by winhttp are freed with GlobalFree
+pub(super) unsafe fn take_string(pwstr: PWSTR) -> Option<String> {
+    if pwstr.is_null() {
+        return None;
+    }
+    let string = pwstr.to_string().ok();
+    let _ = GlobalFree(HGLOBAL(pwstr.0.cast()));
+    string.filter(|string| !string.trim().is_empty())
+}
+
+// the setup script of the internet options, their manual proxy, the machine wide
+// winhttp one set by `netsh winhttp set proxy`, then wpad when detection is on
+pub fn detect() -> Option<String> {
+    let mut auto_detect = false;
+    let proxy = unsafe {
+        let mut ie_config = WINHTTP_CURRENT_USER_IE_PROXY_CONFIG::default();
+        let ie_proxy = match WinHttpGetIEProxyConfigForCurrentUser(&mut ie_config) {
+            Ok(()) => {
+                auto_detect = ie_config.fAutoDetect.as_bool();
+                let _ = take_string(ie_config.lpszProxyBypass);
+                let manual = take_string(ie_config.lpszProxy);
+                take_string(ie_config.lpszAutoConfigUrl)
+                    .map(|script_url| format!("{}{}", pac::SCHEME, script_url))
+                    .or_else(|| parse_proxy_list(&manual?))
+            }
+            Err(_) => None,
+        };
+        ie_proxy.or_else(|| {
+            let mut proxy_info = WINHTTP_PROXY_INFO::default();
+            WinHttpGetDefaultProxyConfiguration(&mut proxy_info).ok()?;
+            let _ = take_string(proxy_info.lpszProxyBypass);
+            let proxy_list = take_string(proxy_info.lpszProxy);
+            proxy_list
+                .filter(|_| proxy_info.dwAccessType == WINHTTP_ACCESS_TYPE_NAMED_PROXY)
+                .and_then(|proxy_list| parse_proxy_list(&proxy_list))
+        })
+    }
+    .or_else(|| auto_detect.then(|| format!("{}auto", pac::SCHEME)));
+    info!("system proxy:{:?}", proxy);
+    proxy
+}
+
+// "host:port" or "http=host:port;https=host:port;socks=host:port" to a proxy url,
+// the https entry first since the websockets are tunnelled with CONNECT
+pub fn parse_proxy_list(proxy_list: &str) -> Option<String> {
+    let entries: Vec<(&str, &str)> = proxy_list
+        .split([';', ' '])
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| entry.split_once('=').unwrap_or(("", entry)))
+        .collect();
+    for wanted in ["https", "", "http", "socks"] {
+        if let Some((kind, address)) = entries.iter().find(|(kind, _)| kind.eq_ignore_ascii_case(wanted)) {
+            if address.contains("://") {
+                return Some(address.to_string());
+            }
+            // the socks entry of the internet options is socks4
+            let scheme = if kind.eq_ignore_ascii_case("socks") { "socks4a" } else { "http" };
+            return Some(format!("{}://{}", scheme, address));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_proxy_list;
+
+    #[test]
+    fn single_proxy_is_http() {
+        assert_eq!(parse_proxy_list("proxy:8080"), Some("http://proxy:8080".to_string()));
+        assert_eq!(parse_proxy_list(" http://proxy:3128 "), Some("http://proxy:3128".to_string()));
+    }
+
+    #[test]
+    fn prefers_the_https_entry() {
+        let proxy_list = "http=web:80;https=secure:443;socks=sock:1080";
+        assert_eq!(parse_proxy_list(proxy_list), Some("http://secure:443".to_string()));
+    }
+
+    #[test]
+    fn socks_entry_is_socks4a() {
+        assert_eq!(parse_proxy_list("socks=sock:1080"), Some("socks4a://sock:1080".to_string()));
+    }
+
+    #[test]
+    fn empty_list_has_no_proxy() {
+        assert_eq!(parse_proxy_list(""), None);
+        assert_eq!(parse_proxy_list("ftp=files:21"), None);
+    }
+}
@@ -0,0 +1,323 @@
+use super::gdi::{Bitmap, MemDC, Paint};
+use super::{wide, Fonts, Pixels, Renderer};
+use anyhow::Result;
+use std::collections::HashMap;
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::{HWND, POINT, RECT, SIZE};
+use windows::Win32::Graphics::Direct2D::Common::{
+    D2D1_ALPHA_MODE_PREMULTIPLIED, D2D1_COLOR_F, D2D1_PIXEL_FORMAT, D2D_POINT_2F, D2D_RECT_F,
+    D2D_SIZE_U,
+};
+use windows::Win32::Graphics::Direct2D::{
+    D2D1CreateFactory, D2D1_BITMAP_INTERPOLATION_MODE_LINEAR, D2D1_BITMAP_PROPERTIES, ID2D1DCRenderTarget, ID2D1Factory, ID2D1SolidColorBrush,
+    D2D1_DRAW_TEXT_OPTIONS_CLIP, D2D1_ELLIPSE, D2D1_FACTORY_TYPE_SINGLE_THREADED,
+    D2D1_FEATURE_LEVEL_DEFAULT, D2D1_RENDER_TARGET_PROPERTIES, D2D1_RENDER_TARGET_TYPE_DEFAULT,
+    D2D1_RENDER_TARGET_USAGE_NONE, D2D1_TEXT_ANTIALIAS_MODE_GRAYSCALE,
+};
+use windows::Win32::Graphics::DirectWrite::{
+    DWriteCreateFactory, IDWriteFactory, IDWriteTextFormat, DWRITE_FACTORY_TYPE_SHARED,
+    DWRITE_FONT_STRETCH_NORMAL, DWRITE_FONT_STYLE_NORMAL, DWRITE_FONT_WEIGHT_NORMAL,
+    DWRITE_MEASURING_MODE_NATURAL, DWRITE_PARAGRAPH_ALIGNMENT_CENTER, DWRITE_TEXT_ALIGNMENT_CENTER,
+    DWRITE_WORD_WRAPPING_NO_WRAP,
+};
+use windows::Win32::Graphics::Dxgi::Common::DXGI_FORMAT_B8G8R8A8_UNORM;
+use windows::Win32::Graphics::Gdi::{
+    GetDeviceCaps, AC_SRC_ALPHA, AC_SRC_OVER, BLENDFUNCTION, HDC, LOGPIXELSY,
+};
+use windows::Win32::Graphics::GdiPlus::{PointF, RectF};
+use windows::Win32::UI::WindowsAndMessaging::{GetClientRect, UpdateLayeredWindow, ULW_ALPHA};
+
+// fields drop in order, the dc before its bitmap before the end of the paint
+struct Frame {
+    hdc_mem: MemDC,
+    // only kept alive, it is selected into hdc_mem
+    _bitmap: Bitmap,
+    paint: Paint,
+    width: i32,
+    height: i32,
+}
+
+// direct2d into a premultiplied dib with directwrite text, sharper and
+// cheaper than gdi+ on every tick
+pub struct D2dRenderer {
+    factory: ID2D1Factory,
+    dwrite: IDWriteFactory,
+    // recreated after the device is lost, brushes belong to it
+    target: Option<ID2D1DCRenderTarget>,
+    brushes: HashMap<u32, ID2D1SolidColorBrush>,
+    // the fonts they were made for, the text format and the label format
+    text_formats: Option<(Fonts, IDWriteTextFormat, IDWriteTextFormat)>,
+    frame: Option<Frame>,
+}
+
+fn color(argb: u32) -> D2D1_COLOR_F {
+    let channel = |shift: u32| ((argb >> shift) & 0xFF) as f32 / 255.;
+    D2D1_COLOR_F {
+        r: channel(16),
+        g: channel(8),
+        b: channel(0),
+        a: channel(24),
+    }
+}
+
+fn rect(lay_box: &RectF) -> D2D_RECT_F {
+    D2D_RECT_F {
+        left: lay_box.X,
+        top: lay_box.Y,
+        right: lay_box.X + lay_box.Width,
+        bottom: lay_box.Y + lay_box.Height,
+    }
+}
+
+impl D2dRenderer {
+    pub fn new() -> Result<Self> {
+        unsafe {
+            Ok(D2dRenderer {
+                factory: D2D1CreateFactory(D2D1_FACTORY_TYPE_SINGLE_THREADED, None)?,
+                dwrite: DWriteCreateFactory(DWRITE_FACTORY_TYPE_SHARED)?,
+                target: None,
+                brushes: HashMap::new(),
+                text_formats: None,
+                frame: None,
+            })
+        }
+    }
+
+    fn target(&mut self) -> Result<ID2D1DCRenderTarget> {
+        if let Some(target) = &self.target {
+            return Ok(target.clone());
+        }
+        // 96 dpi keeps one unit one pixel like the gdi+ path
+        let properties = D2D1_RENDER_TARGET_PROPERTIES {
+            r#type: D2D1_RENDER_TARGET_TYPE_DEFAULT,
+            pixelFormat: D2D1_PIXEL_FORMAT {
+                format: DXGI_FORMAT_B8G8R8A8_UNORM,
+                alphaMode: D2D1_ALPHA_MODE_PREMULTIPLIED,
+            },
+            dpiX: 96.,
+            dpiY: 96.,
+            usage: D2D1_RENDER_TARGET_USAGE_NONE,
+            minLevel: D2D1_FEATURE_LEVEL_DEFAULT,
+        };
+        let target = unsafe { self.factory.CreateDCRenderTarget(&properties)? };
+        // cleartype needs an opaque background, the widget is see-through
+        unsafe { target.SetTextAntialiasMode(D2D1_TEXT_ANTIALIAS_MODE_GRAYSCALE) };
+        self.brushes.clear();
+        self.target = Some(target.clone());
+        Ok(target)
+    }
+
+    fn brush(&mut self, argb: u32) -> Option<ID2D1SolidColorBrush> {
+        if let Some(brush) = self.brushes.get(&argb) {
+            return Some(brush.clone());
+        }
+        let brush = unsafe { self.target.as_ref()?.CreateSolidColorBrush(&color(argb), None).ok()? };
+        self.brushes.insert(argb, brush.clone());
+        Some(brush)
+    }
+
+    // points to pixels at the dpi of the screen, as gdi+ sizes its fonts
+    fn text_format(&self, family: &str, font_size: f32, hdc: HDC) -> Result<IDWriteTextFormat> {
+        let dpi = unsafe { GetDeviceCaps(hdc, LOGPIXELSY) }.max(96) as f32;
+        let family = wide(family);
+        let locale = wide("zh-cn");
+        unsafe {
+            let text_format = self.dwrite.CreateTextFormat(
+                PCWSTR(family.as_ptr()),
+                None,
+                DWRITE_FONT_WEIGHT_NORMAL,
+                DWRITE_FONT_STYLE_NORMAL,
+                DWRITE_FONT_STRETCH_NORMAL,
+                font_size * dpi / 72.,
+                PCWSTR(locale.as_ptr()),
+            )?;
+            text_format.SetTextAlignment(DWRITE_TEXT_ALIGNMENT_CENTER)?;
+            text_format.SetParagraphAlignment(DWRITE_PARAGRAPH_ALIGNMENT_CENTER)?;
+            text_format.SetWordWrapping(DWRITE_WORD_WRAPPING_NO_WRAP)?;
+            Ok(text_format)
+        }
+    }
+
+    fn text_formats(&mut self, fonts: &Fonts, hdc: HDC) -> Result<()> {
+        if matches!(&self.text_formats, Some((made_for, _, _)) if made_for == fonts) {
+            return Ok(());
+        }
+        let text = self.text_format(&fonts.family, fonts.size, hdc)?;
+        let label = self.text_format(&fonts.family, fonts.label_size, hdc)?;
+        self.text_formats = Some((fonts.clone(), text, label));
+        Ok(())
+    }
+
+    fn draw(&mut self, text: &str, color: u32, lay_box: &RectF, label: bool) {
+        let (target, text_format) = match (&self.target, &self.text_formats, &self.frame) {
+            (Some(target), Some((_, text_format, label_format)), Some(_)) => {
+                (target.clone(), if label { label_format.clone() } else { text_format.clone() })
+            }
+            _ => return,
+        };
+        let brush = match self.brush(color) {
+            Some(brush) => brush,
+            None => return,
+        };
+        let content: Vec<u16> = text.encode_utf16().collect();
+        unsafe {
+            target.DrawText(
+                &content,
+                &text_format,
+                &rect(lay_box),
+                &brush,
+                D2D1_DRAW_TEXT_OPTIONS_CLIP,
+                DWRITE_MEASURING_MODE_NATURAL,
+            );
+        }
+    }
+}
+
+impl Renderer for D2dRenderer {
+    fn begin(&mut self, hwnd: HWND, fonts: &Fonts, clear: u32) -> Result<()> {
+        unsafe {
+            let mut client_rect = RECT::default();
+            GetClientRect(hwnd, &mut client_rect)?;
+            let width = client_rect.right - client_rect.left;
+            let height = client_rect.bottom - client_rect.top;
+
+            let paint = Paint::begin(hwnd);
+            let hdc_mem = MemDC::compatible(paint.hdc());
+            let bitmap = Bitmap::dib(hdc_mem.hdc(), width, height)?;
+            hdc_mem.select(&bitmap);
+            self.target().and_then(|target| {
+                target.BindDC(hdc_mem.hdc(), &client_rect)?;
+                self.text_formats(fonts, paint.hdc())?;
+                target.BeginDraw();
+                target.Clear(Some(&color(clear)));
+                Ok(())
+            })?;
+            self.frame = Some(Frame {
+                hdc_mem,
+                _bitmap: bitmap,
+                paint,
+                width,
+                height,
+            });
+        }
+        Ok(())
+    }
+
+    fn draw_text(&mut self, text: &str, color: u32, lay_box: &RectF) {
+        self.draw(text, color, lay_box, false);
+    }
+
+    fn draw_label(&mut self, text: &str, color: u32, lay_box: &RectF) {
+        self.draw(text, color, lay_box, true);
+    }
+
+    // a bitmap per draw, logos are small and few
+    fn draw_image(&mut self, image: &Pixels, lay_box: &RectF) {
+        let target = match (&self.target, &self.frame) {
+            (Some(target), Some(_)) => target.clone(),
+            _ => return,
+        };
+        let properties = D2D1_BITMAP_PROPERTIES {
+            pixelFormat: D2D1_PIXEL_FORMAT {
+                format: DXGI_FORMAT_B8G8R8A8_UNORM,
+                alphaMode: D2D1_ALPHA_MODE_PREMULTIPLIED,
+            },
+            dpiX: 96.,
+            dpiY: 96.,
+        };
+        let size = D2D_SIZE_U {
+            width: image.width,
+            height: image.height,
+        };
+        unsafe {
+            let bitmap = target.CreateBitmap(
+                size,
+                Some(image.bgra.as_ptr() as *const core::ffi::c_void),
+                image.width * 4,
+                &properties,
+            );
+            if let Ok(bitmap) = bitmap {
+                target.DrawBitmap(
+                    &bitmap,
+                    Some(&rect(lay_box)),
+                    1.,
+                    D2D1_BITMAP_INTERPOLATION_MODE_LINEAR,
+                    None,
+                );
+            }
+        }
+    }
+
+    fn fill_ellipse(&mut self, color: u32, lay_box: &RectF) {
+        let target = match (&self.target, &self.frame) {
+            (Some(target), Some(_)) => target.clone(),
+            _ => return,
+        };
+        if let Some(brush) = self.brush(color) {
+            let ellipse = D2D1_ELLIPSE {
+                point: D2D_POINT_2F {
+                    x: lay_box.X + lay_box.Width / 2.,
+                    y: lay_box.Y + lay_box.Height / 2.,
+                },
+                radiusX: lay_box.Width / 2.,
+                radiusY: lay_box.Height / 2.,
+            };
+            unsafe { target.FillEllipse(&ellipse, &brush) };
+        }
+    }
+
+    fn draw_lines(&mut self, color: u32, points: &[PointF]) {
+        let target = match (&self.target, &self.frame) {
+            (Some(target), Some(_)) => target.clone(),
+            _ => return,
+        };
+        if let Some(brush) = self.brush(color) {
+            for segment in points.windows(2) {
+                let point = |point: &PointF| D2D_POINT_2F { x: point.X, y: point.Y };
+                unsafe { target.DrawLine(point(&segment[0]), point(&segment[1]), &brush, 1., None) };
+            }
+        }
+    }
+
+    fn end(&mut self) -> Result<()> {
+        let frame = match self.frame.take() {
+            Some(frame) => frame,
+            None => return Ok(()),
+        };
+        let drawn = match &self.target {
+            Some(target) => unsafe { target.EndDraw(None, None) },
+            None => Ok(()),
+        };
+        if let Err(err) = drawn {
+            // e.g. D2DERR_RECREATE_TARGET, the next frame starts over
+            self.target = None;
+            self.brushes.clear();
+            return Err(err.into());
+        }
+        unsafe {
+            let blend = BLENDFUNCTION {
+                BlendOp: AC_SRC_OVER as u8,
+                BlendFlags: 0,
+                SourceConstantAlpha: 255,
+                AlphaFormat: AC_SRC_ALPHA as u8,
+            };
+            let size = SIZE {
+                cx: frame.width,
+                cy: frame.height,
+            };
+            let point = POINT { x: 0, y: 0 };
+            let _ = UpdateLayeredWindow(
+                frame.paint.hwnd(),
+                frame.paint.hdc(),
+                None,
+                Some(&size),
+                frame.hdc_mem.hdc(),
+                Some(&point),
+                None,
+                Some(&blend),
+                ULW_ALPHA,
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,284 @@
+// owners of gdi and gdi+ handles, released when dropped so early returns
+// and errors in the paint paths can't leak them
+
+use super::{wide, Pixels};
+use std::path::Path;
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::HWND;
+use windows::Win32::Graphics::Gdi::{
+    BeginPaint, CreateCompatibleBitmap, CreateCompatibleDC, CreateDIBSection, DeleteDC,
+    DeleteObject, EndPaint, SelectObject, BITMAPINFO, BITMAPINFOHEADER, BI_RGB, DIB_RGB_COLORS,
+    HBITMAP, HDC, PAINTSTRUCT,
+};
+use windows::Win32::Graphics::GdiPlus::{
+    BitmapData, FontStyleRegular, GdipBitmapLockBits, GdipBitmapUnlockBits, GdipCreateBitmapFromFile,
+    GdipCreateBitmapFromScan0, GdipCreateFont, GdipCreateFontFamilyFromName,
+    GdipCreateFromHDC, GdipCreatePen1, GdipCreateSolidFill, GdipDeleteBrush, GdipDeleteFont,
+    GdipDeleteFontFamily, GdipDeleteGraphics, GdipDeletePen, GdipDisposeImage,
+    GdipGetImageGraphicsContext, GpBitmap, GpBrush, GpFont, GpFontFamily, GpGraphics, GpImage,
+    GpPen, GpSolidFill, ImageLockModeRead, Ok as GdipOk, UnitPixel, UnitPoint,
+};
+
+const PIXEL_FORMAT_32BPP_ARGB: i32 = 0x0026200A;
+const PIXEL_FORMAT_32BPP_PARGB: i32 = 0x000E200B;
+
+pub struct Font(*mut GpFont);
+
+impl Font {
+    // size in points
+    pub fn new(family: &str, size: f32) -> Font {
+        let family = wide(family);
+        unsafe {
+            let mut font_family: *mut GpFontFamily = std::ptr::null_mut();
+            GdipCreateFontFamilyFromName(PCWSTR(family.as_ptr()), std::ptr::null_mut(), &mut font_family);
+            let mut font: *mut GpFont = std::ptr::null_mut();
+            GdipCreateFont(font_family, size, FontStyleRegular.0, UnitPoint, &mut font);
+            GdipDeleteFontFamily(font_family);
+            Font(font)
+        }
+    }
+
+    pub fn as_ptr(&self) -> *mut GpFont {
+        self.0
+    }
+}
+
+impl Drop for Font {
+    fn drop(&mut self) {
+        unsafe { GdipDeleteFont(self.0) };
+    }
+}
+
+pub struct Brush(*mut GpBrush);
+
+impl Brush {
+    pub fn solid(color: u32) -> Brush {
+        unsafe {
+            let mut fill: *mut GpSolidFill = std::ptr::null_mut();
+            GdipCreateSolidFill(color, &mut fill);
+            Brush(fill as *mut GpBrush)
+        }
+    }
+
+    pub fn as_ptr(&self) -> *mut GpBrush {
+        self.0
+    }
+}
+
+impl Drop for Brush {
+    fn drop(&mut self) {
+        unsafe { GdipDeleteBrush(self.0) };
+    }
+}
+
+pub struct Pen(*mut GpPen);
+
+impl Pen {
+    // width in pixels
+    pub fn new(color: u32, width: f32) -> Pen {
+        let mut pen: *mut GpPen = std::ptr::null_mut();
+        unsafe { GdipCreatePen1(color, width, UnitPixel, &mut pen) };
+        Pen(pen)
+    }
+
+    pub fn as_ptr(&self) -> *mut GpPen {
+        self.0
+    }
+}
+
+impl Drop for Pen {
+    fn drop(&mut self) {
+        unsafe { GdipDeletePen(self.0) };
+    }
+}
+
+// a gdi+ argb bitmap in memory, not a gdi one
+pub struct Image(*mut GpBitmap);
+
+impl Image {
+    pub fn argb(width: i32, height: i32) -> Image {
+        let mut bitmap: *mut GpBitmap = std::ptr::null_mut();
+        unsafe { GdipCreateBitmapFromScan0(width, height, 0, PIXEL_FORMAT_32BPP_ARGB, None, &mut bitmap) };
+        Image(bitmap)
+    }
+
+    // over the pixels without a copy, must be dropped before them
+    pub fn borrow(pixels: &Pixels) -> Image {
+        let mut bitmap: *mut GpBitmap = std::ptr::null_mut();
+        unsafe {
+            GdipCreateBitmapFromScan0(
+                pixels.width as i32,
+                pixels.height as i32,
+                pixels.width as i32 * 4,
+                PIXEL_FORMAT_32BPP_PARGB,
+                Some(pixels.bgra.as_ptr()),
+                &mut bitmap,
+            )
+        };
+        Image(bitmap)
+    }
+
+    // png, bmp, ico and the other formats gdi+ decodes
+    pub fn from_file(path: &Path) -> Option<Image> {
+        let path = wide(&path.to_string_lossy());
+        let mut bitmap: *mut GpBitmap = std::ptr::null_mut();
+        let status = unsafe { GdipCreateBitmapFromFile(PCWSTR(path.as_ptr()), &mut bitmap) };
+        (status == GdipOk && !bitmap.is_null()).then_some(Image(bitmap))
+    }
+
+    // a copy of the premultiplied bgra pixels
+    pub fn pixels(&self) -> Option<Pixels> {
+        let mut data = BitmapData::default();
+        unsafe {
+            let status = GdipBitmapLockBits(
+                self.0,
+                std::ptr::null(),
+                ImageLockModeRead.0 as u32,
+                PIXEL_FORMAT_32BPP_PARGB,
+                &mut data,
+            );
+            if status != GdipOk {
+                return None;
+            }
+            let row = data.Width as usize * 4;
+            let mut bgra = Vec::with_capacity(row * data.Height as usize);
+            for y in 0..data.Height as isize {
+                let line = (data.Scan0 as *const u8).offset(y * data.Stride as isize);
+                bgra.extend_from_slice(std::slice::from_raw_parts(line, row));
+            }
+            GdipBitmapUnlockBits(self.0, &mut data);
+            Some(Pixels {
+                width: data.Width,
+                height: data.Height,
+                bgra,
+            })
+        }
+    }
+
+    pub fn as_ptr(&self) -> *mut GpBitmap {
+        self.0
+    }
+}
+
+impl Drop for Image {
+    fn drop(&mut self) {
+        unsafe { GdipDisposeImage(self.0 as *mut GpImage) };
+    }
+}
+
+// must be dropped before what it draws on
+pub struct Graphics(*mut GpGraphics);
+
+impl Graphics {
+    pub fn from_hdc(hdc: HDC) -> Graphics {
+        let mut graphics: *mut GpGraphics = std::ptr::null_mut();
+        unsafe { GdipCreateFromHDC(hdc, &mut graphics) };
+        Graphics(graphics)
+    }
+
+    pub fn from_image(image: &Image) -> Graphics {
+        let mut graphics: *mut GpGraphics = std::ptr::null_mut();
+        unsafe { GdipGetImageGraphicsContext(image.as_ptr() as *mut GpImage, &mut graphics) };
+        Graphics(graphics)
+    }
+
+    pub fn as_ptr(&self) -> *mut GpGraphics {
+        self.0
+    }
+}
+
+impl Drop for Graphics {
+    fn drop(&mut self) {
+        unsafe { GdipDeleteGraphics(self.0) };
+    }
+}
+
+// a memory dc, dropped before the bitmap selected into it
+pub struct MemDC(HDC);
+
+impl MemDC {
+    pub fn compatible(hdc: HDC) -> MemDC {
+        MemDC(unsafe { CreateCompatibleDC(hdc) })
+    }
+
+    pub fn select(&self, bitmap: &Bitmap) {
+        unsafe { SelectObject(self.0, bitmap.0) };
+    }
+
+    pub fn hdc(&self) -> HDC {
+        self.0
+    }
+}
+
+impl Drop for MemDC {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = DeleteDC(self.0);
+        }
+    }
+}
+
+pub struct Bitmap(HBITMAP);
+
+impl Bitmap {
+    pub fn compatible(hdc: HDC, width: i32, height: i32) -> Bitmap {
+        Bitmap(unsafe { CreateCompatibleBitmap(hdc, width, height) })
+    }
+
+    // top-down 32 bit dib, a layered window takes its alpha as is
+    pub fn dib(hdc: HDC, width: i32, height: i32) -> windows::core::Result<Bitmap> {
+        let info = BITMAPINFO {
+            bmiHeader: BITMAPINFOHEADER {
+                biSize: std::mem::size_of::<BITMAPINFOHEADER>() as u32,
+                biWidth: width,
+                biHeight: -height,
+                biPlanes: 1,
+                biBitCount: 32,
+                biCompression: BI_RGB.0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut bits = std::ptr::null_mut();
+        unsafe { CreateDIBSection(hdc, &info, DIB_RGB_COLORS, &mut bits, None, 0).map(Bitmap) }
+    }
+}
+
+impl Drop for Bitmap {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = DeleteObject(self.0);
+        }
+    }
+}
+
+// BeginPaint to EndPaint
+pub struct Paint {
+    hwnd: HWND,
+    ps: PAINTSTRUCT,
+    hdc: HDC,
+}
+
+impl Paint {
+    pub fn begin(hwnd: HWND) -> Paint {
+        let mut ps = PAINTSTRUCT::default();
+        let hdc = unsafe { BeginPaint(hwnd, &mut ps) };
+        Paint { hwnd, ps, hdc }
+    }
+
+    pub fn hwnd(&self) -> HWND {
+        self.hwnd
+    }
+
+    pub fn hdc(&self) -> HDC {
+        self.hdc
+    }
+}
+
+impl Drop for Paint {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = EndPaint(self.hwnd, &self.ps);
+        }
+    }
+}
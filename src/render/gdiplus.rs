@@ -0,0 +1,221 @@
+use super::gdi::{Bitmap, Brush, Font, Graphics, Image, MemDC, Paint, Pen};
+use super::{wide, Fonts, Pixels, Renderer};
+use crate::my_window::Window;
+use anyhow::Result;
+use std::collections::HashMap;
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::{HWND, POINT, RECT, SIZE};
+use windows::Win32::Graphics::Gdi::{AC_SRC_ALPHA, AC_SRC_OVER, BLENDFUNCTION, HDC};
+use windows::Win32::Graphics::GdiPlus::{
+    GdipDrawImageRect, GdipDrawLines, GdipDrawString, GdipFillEllipse, GdipGraphicsClear, GdipMeasureString,
+    GdipSetInterpolationMode, GdipSetSmoothingMode, GdipSetTextRenderingHint, GpGraphics,
+    InterpolationModeHighQualityBicubic, PointF, RectF, SmoothingModeAntiAlias,
+    TextRenderingHintAntiAlias, GpImage,
+};
+use windows::Win32::UI::WindowsAndMessaging::{GetClientRect, UpdateLayeredWindow, ULW_ALPHA};
+
+// memory dc and bitmap frames are drawn into, kept while the size stays;
+// fields drop in order, the graphics before the dc before the bitmap
+struct Surface {
+    graphics: Graphics,
+    hdc_mem: MemDC,
+    // only kept alive, it is selected into hdc_mem
+    _bitmap: Bitmap,
+    width: i32,
+    height: i32,
+}
+
+// draws into a memory dc with gdi+, the original renderer; its objects live
+// as long as the renderer, the window drops it on dpi and theme changes
+pub struct GdiplusRenderer {
+    surface: Option<Surface>,
+    // the fonts they were made for, the text font and the label font
+    fonts: Option<(Fonts, Font, Font)>,
+    brushes: HashMap<u32, Brush>,
+    pens: HashMap<u32, Pen>,
+    // the paint begun by begin
+    paint: Option<Paint>,
+}
+
+impl GdiplusRenderer {
+    pub fn new() -> Self {
+        GdiplusRenderer {
+            surface: None,
+            fonts: None,
+            brushes: HashMap::new(),
+            pens: HashMap::new(),
+            paint: None,
+        }
+    }
+
+    fn surface(&mut self, hdc: HDC, width: i32, height: i32) -> *mut GpGraphics {
+        if let Some(surface) = &self.surface {
+            if surface.width == width && surface.height == height {
+                return surface.graphics.as_ptr();
+            }
+        }
+        self.surface = None;
+        let hdc_mem = MemDC::compatible(hdc);
+        let bitmap = Bitmap::compatible(hdc, width, height);
+        hdc_mem.select(&bitmap);
+        let graphics = Graphics::from_hdc(hdc_mem.hdc());
+        unsafe {
+            GdipSetTextRenderingHint(graphics.as_ptr(), TextRenderingHintAntiAlias);
+            GdipSetSmoothingMode(graphics.as_ptr(), SmoothingModeAntiAlias);
+            GdipSetInterpolationMode(graphics.as_ptr(), InterpolationModeHighQualityBicubic);
+        }
+        let ptr = graphics.as_ptr();
+        self.surface = Some(Surface {
+            graphics,
+            hdc_mem,
+            _bitmap: bitmap,
+            width,
+            height,
+        });
+        ptr
+    }
+
+    fn fonts(&mut self, fonts: &Fonts) {
+        if !matches!(&self.fonts, Some((made_for, _, _)) if made_for == fonts) {
+            let text = Font::new(&fonts.family, fonts.size);
+            let label = Font::new(&fonts.family, fonts.label_size);
+            self.fonts = Some((fonts.clone(), text, label));
+        }
+    }
+
+    fn brush(&mut self, color: u32) -> &Brush {
+        self.brushes.entry(color).or_insert_with(|| Brush::solid(color))
+    }
+
+    fn pen(&mut self, color: u32) -> &Pen {
+        self.pens.entry(color).or_insert_with(|| Pen::new(color, 1.))
+    }
+
+    fn graphics(&self) -> Option<*mut GpGraphics> {
+        self.paint.as_ref()?;
+        self.surface.as_ref().map(|surface| surface.graphics.as_ptr())
+    }
+
+    // centered in lay_box with the label font or the text font
+    fn draw(&mut self, text: &str, color: u32, lay_box: &RectF, label: bool) {
+        let (graphics, font) = match (self.graphics(), &self.fonts) {
+            (Some(graphics), Some((_, text_font, label_font))) => {
+                (graphics, if label { label_font.as_ptr() } else { text_font.as_ptr() })
+            }
+            _ => return,
+        };
+        let brush = self.brush(color).as_ptr();
+        let content = wide(text);
+        let mut bound = RectF::default();
+        unsafe {
+            GdipMeasureString(
+                graphics,
+                PCWSTR(content.as_ptr()),
+                -1,
+                font,
+                lay_box,
+                std::ptr::null_mut(),
+                &mut bound,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            );
+            let dst_rect = Window::generate_mid_rect(lay_box, &bound);
+            GdipDrawString(
+                graphics,
+                PCWSTR(content.as_ptr()),
+                -1,
+                font,
+                &dst_rect,
+                std::ptr::null_mut(),
+                brush,
+            );
+        }
+    }
+}
+
+impl Renderer for GdiplusRenderer {
+    fn begin(&mut self, hwnd: HWND, fonts: &Fonts, clear: u32) -> Result<()> {
+        let mut client_rect = RECT::default();
+        unsafe { GetClientRect(hwnd, &mut client_rect)? };
+        let width = client_rect.right - client_rect.left;
+        let height = client_rect.bottom - client_rect.top;
+
+        let paint = Paint::begin(hwnd);
+        let graphics = self.surface(paint.hdc(), width, height);
+        unsafe { GdipGraphicsClear(graphics, clear) };
+        self.fonts(fonts);
+        self.paint = Some(paint);
+        Ok(())
+    }
+
+    fn draw_text(&mut self, text: &str, color: u32, lay_box: &RectF) {
+        self.draw(text, color, lay_box, false);
+    }
+
+    fn draw_label(&mut self, text: &str, color: u32, lay_box: &RectF) {
+        self.draw(text, color, lay_box, true);
+    }
+
+    fn draw_image(&mut self, image: &Pixels, lay_box: &RectF) {
+        if let Some(graphics) = self.graphics() {
+            let bitmap = Image::borrow(image);
+            unsafe {
+                GdipDrawImageRect(
+                    graphics,
+                    bitmap.as_ptr() as *mut GpImage,
+                    lay_box.X,
+                    lay_box.Y,
+                    lay_box.Width,
+                    lay_box.Height,
+                )
+            };
+        }
+    }
+
+    fn fill_ellipse(&mut self, color: u32, rect: &RectF) {
+        if let Some(graphics) = self.graphics() {
+            let brush = self.brush(color).as_ptr();
+            unsafe { GdipFillEllipse(graphics, brush, rect.X, rect.Y, rect.Width, rect.Height) };
+        }
+    }
+
+    fn draw_lines(&mut self, color: u32, points: &[PointF]) {
+        if let Some(graphics) = self.graphics() {
+            let pen = self.pen(color).as_ptr();
+            unsafe { GdipDrawLines(graphics, pen, points.as_ptr(), points.len() as i32) };
+        }
+    }
+
+    fn end(&mut self) -> Result<()> {
+        // dropping the paint ends it
+        let (paint, surface) = match (self.paint.take(), &self.surface) {
+            (Some(paint), Some(surface)) => (paint, surface),
+            _ => return Ok(()),
+        };
+        unsafe {
+            let blend = BLENDFUNCTION {
+                BlendOp: AC_SRC_OVER as u8,
+                BlendFlags: 0,
+                SourceConstantAlpha: 255,
+                AlphaFormat: AC_SRC_ALPHA as u8,
+            };
+            let size = SIZE {
+                cx: surface.width,
+                cy: surface.height,
+            };
+            let point = POINT { x: 0, y: 0 };
+            let _ = UpdateLayeredWindow(
+                paint.hwnd(),
+                paint.hdc(),
+                None,
+                Some(&size),
+                surface.hdc_mem.hdc(),
+                Some(&point),
+                None,
+                Some(&blend),
+                ULW_ALPHA,
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,115 @@
+pub mod d2d;
+pub mod gdi;
+pub mod gdiplus;
+
+use crate::config::RenderBackend;
+use anyhow::Result;
+use std::path::Path;
+use tracing::warn;
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::{BOOL, HWND};
+use windows::Win32::Graphics::DirectWrite::{DWriteCreateFactory, IDWriteFactory, DWRITE_FACTORY_TYPE_SHARED};
+use windows::Win32::Graphics::GdiPlus::{PointF, RectF};
+
+// the default family, also what a family that is not installed falls back to
+pub const FONT_FAMILY: &str = "Microsoft YaHei UI";
+
+// sizes in points
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fonts {
+    pub family: String,
+    // prices, notices and the rows under them
+    pub size: f32,
+    // pair names and the portfolio label
+    pub label_size: f32,
+}
+
+impl Default for Fonts {
+    fn default() -> Self {
+        Fonts {
+            family: FONT_FAMILY.to_string(),
+            size: 9.,
+            label_size: 9.,
+        }
+    }
+}
+
+// premultiplied bgra rows without padding, what both renderers draw from
+#[derive(Debug, Clone)]
+pub struct Pixels {
+    pub width: u32,
+    pub height: u32,
+    pub bgra: Vec<u8>,
+}
+
+// decoded by gdi+, which must be started
+pub fn load_image(path: &Path) -> Option<Pixels> {
+    gdi::Image::from_file(path)?.pixels()
+}
+
+// draws one frame of the widget into its layered window, colors are argb
+pub trait Renderer {
+    // starts a frame over the client area of hwnd, cleared to color
+    fn begin(&mut self, hwnd: HWND, fonts: &Fonts, clear: u32) -> Result<()>;
+    // centered in lay_box
+    fn draw_text(&mut self, text: &str, color: u32, lay_box: &RectF);
+    // draw_text in the label size
+    fn draw_label(&mut self, text: &str, color: u32, lay_box: &RectF);
+    // scaled into lay_box
+    fn draw_image(&mut self, image: &Pixels, lay_box: &RectF);
+    fn fill_ellipse(&mut self, color: u32, rect: &RectF);
+    // connected segments one pixel wide
+    fn draw_lines(&mut self, color: u32, points: &[PointF]);
+    // copies the frame to the window, always ends the paint begun by begin
+    fn end(&mut self) -> Result<()>;
+}
+
+// direct2d unless it is turned off or cannot start, gdi+ always can
+pub fn create(backend: RenderBackend) -> Box<dyn Renderer> {
+    if backend == RenderBackend::Direct2D {
+        match d2d::D2dRenderer::new() {
+            Ok(renderer) => return Box::new(renderer),
+            Err(err) => warn!("direct2d err:{:?}, using gdi+", err),
+        }
+    }
+    Box::new(gdiplus::GdiplusRenderer::new())
+}
+
+// by the system font collection, gdi+ and directwrite both draw a missing
+// family in some other font without an error
+pub fn installed(family: &str) -> bool {
+    let name = wide(family);
+    let found = unsafe {
+        DWriteCreateFactory::<IDWriteFactory>(DWRITE_FACTORY_TYPE_SHARED).and_then(|dwrite| {
+            let mut collection = None;
+            dwrite.GetSystemFontCollection(&mut collection, false)?;
+            let (mut index, mut exists) = (0u32, BOOL::default());
+            if let Some(collection) = collection {
+                collection.FindFamilyName(PCWSTR(name.as_ptr()), &mut index, &mut exists)?;
+            }
+            Ok(exists.as_bool())
+        })
+    };
+    found.unwrap_or_else(|err| {
+        warn!("font {} err:{:?}", family, err);
+        false
+    })
+}
+
+// the family if it is installed, else FONT_FAMILY
+pub fn font_family(family: &str) -> String {
+    let family = family.trim();
+    if family.is_empty() || family == FONT_FAMILY {
+        return FONT_FAMILY.to_string();
+    }
+    if installed(family) {
+        family.to_string()
+    } else {
+        warn!("font {} is not installed, using {}", family, FONT_FAMILY);
+        FONT_FAMILY.to_string()
+    }
+}
+
+pub(crate) fn wide(text: &str) -> Vec<u16> {
+    text.encode_utf16().chain(std::iter::once(0)).collect()
+}
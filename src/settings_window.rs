@@ -0,0 +1,298 @@
+use crate::api;
+use crate::config::{self, Config, PairConfig};
+use crate::i18n;
+use crate::my_window::Window;
+use crate::pairs;
+use crate::render;
+use crate::theme;
+use anyhow::Result;
+use core::ffi::c_void;
+use tracing::{error, warn};
+use windows::Win32::Graphics::Gdi::{GetStockObject, GetSysColorBrush, COLOR_BTNFACE, DEFAULT_GUI_FONT};
+use windows::Win32::UI::Input::KeyboardAndMouse::SetFocus;
+use windows::{
+    core::*, Win32::Foundation::*, Win32::System::LibraryLoader::GetModuleHandleW,
+    Win32::UI::WindowsAndMessaging::*,
+};
+
+pub struct SettingsWindow {
+    main_hwnd: HWND,
+    config: Config,
+    // edit controls, in the order of FIELDS
+    edits: Vec<HWND>,
+}
+
+impl SettingsWindow {
+    const CLASS_NAME: PCWSTR = w!("mjj_settings");
+    const ID_SAVE: usize = 1;
+    const ID_CANCEL: usize = 2;
+    const ID_EDIT_BASE: usize = 100;
+
+    const FIELD_PAIRS: usize = 0;
+    const FIELD_WIDTH: usize = 1;
+    const FIELD_FONT_FAMILY: usize = 2;
+    const FIELD_FONT_SIZE: usize = 3;
+    const FIELD_LABEL_FONT_SIZE: usize = 4;
+    const FIELD_TEXT_COLOR: usize = 5;
+    const FIELD_PROXY: usize = 6;
+    const FIELD_DECIMALS: usize = 7;
+    const FIELDS: [&'static str; 8] = ["交易对", "宽度", "字体", "价格字号", "名称字号", "颜色", "代理", "小数位"];
+
+    const ROW_HEIGHT: i32 = 32;
+    const MARGIN: i32 = 12;
+
+    pub fn open(main_hwnd: HWND, config: &Config) -> Result<()> {
+        unsafe {
+            if let Ok(existing) = FindWindowW(Self::CLASS_NAME, None) {
+                if !existing.is_invalid() {
+                    let _ = SetForegroundWindow(existing);
+                    return Ok(());
+                }
+            }
+            let instance = GetModuleHandleW(None)?;
+            let wc = WNDCLASSW {
+                hCursor: LoadCursorW(None, IDC_ARROW)?,
+                hInstance: instance.into(),
+                lpszClassName: Self::CLASS_NAME,
+                lpfnWndProc: Some(Self::wndproc),
+                hbrBackground: GetSysColorBrush(COLOR_BTNFACE),
+                ..Default::default()
+            };
+            // fails harmlessly when the class is already registered
+            RegisterClassW(&wc);
+            let hwnd = CreateWindowExW(
+                WS_EX_TOPMOST,
+                Self::CLASS_NAME,
+                &HSTRING::from(i18n::tr("设置")),
+                WS_OVERLAPPED | WS_CAPTION | WS_SYSMENU,
+                CW_USEDEFAULT,
+                CW_USEDEFAULT,
+                390,
+                Self::MARGIN * 2 + Self::ROW_HEIGHT * (Self::FIELDS.len() as i32 + 1) + 40,
+                None,
+                None,
+                wc.hInstance,
+                None,
+            )?;
+            let edits = Self::create_controls(hwnd, wc.hInstance, config)?;
+            let settings = Box::new(SettingsWindow {
+                main_hwnd,
+                config: config.clone(),
+                edits,
+            });
+            SetWindowLongPtrW(hwnd, GWLP_USERDATA, Box::into_raw(settings) as isize);
+            let _ = ShowWindow(hwnd, SW_SHOW);
+        }
+        Ok(())
+    }
+
+    fn field_values(config: &Config) -> [String; 8] {
+        let pair_names = pairs::PAIR_REGISTRY
+            .read()
+            .unwrap()
+            .favorites()
+            .iter()
+            .map(|info| info.pair_name.clone())
+            .collect::<Vec<_>>()
+            .join(",");
+        [
+            pair_names,
+            config.width.to_string(),
+            config.font_family.clone(),
+            config.font_size.to_string(),
+            config.label_font_size.to_string(),
+            config.text_color.clone(),
+            config.proxy.clone().unwrap_or_default(),
+            config::decimals_text(&config.decimals),
+        ]
+    }
+
+    unsafe fn create_controls(hwnd: HWND, instance: HINSTANCE, config: &Config) -> Result<Vec<HWND>> {
+        let font = GetStockObject(DEFAULT_GUI_FONT);
+        let values = Self::field_values(config);
+        let mut edits = Vec::new();
+        for (index, label) in Self::FIELDS.iter().enumerate() {
+            let y = Self::MARGIN + Self::ROW_HEIGHT * index as i32;
+            let label = CreateWindowExW(
+                WINDOW_EX_STYLE::default(),
+                w!("STATIC"),
+                &HSTRING::from(i18n::tr(label)),
+                WS_CHILD | WS_VISIBLE,
+                Self::MARGIN,
+                y + 4,
+                70,
+                20,
+                hwnd,
+                None,
+                instance,
+                None,
+            )?;
+            let edit = CreateWindowExW(
+                WS_EX_CLIENTEDGE,
+                w!("EDIT"),
+                &HSTRING::from(values[index].as_str()),
+                WS_CHILD | WS_VISIBLE | WS_TABSTOP | WINDOW_STYLE(ES_AUTOHSCROLL as u32),
+                Self::MARGIN + 80,
+                y,
+                270,
+                24,
+                hwnd,
+                HMENU((Self::ID_EDIT_BASE + index) as *mut c_void),
+                instance,
+                None,
+            )?;
+            SendMessageW(label, WM_SETFONT, WPARAM(font.0 as usize), LPARAM(1));
+            SendMessageW(edit, WM_SETFONT, WPARAM(font.0 as usize), LPARAM(1));
+            edits.push(edit);
+        }
+        let y = Self::MARGIN + Self::ROW_HEIGHT * Self::FIELDS.len() as i32 + 4;
+        for (id, text, x) in [
+            (Self::ID_SAVE, &HSTRING::from(i18n::tr("保存")), 180),
+            (Self::ID_CANCEL, &HSTRING::from(i18n::tr("取消")), 270),
+        ] {
+            let button = CreateWindowExW(
+                WINDOW_EX_STYLE::default(),
+                w!("BUTTON"),
+                text,
+                WS_CHILD | WS_VISIBLE | WS_TABSTOP | WINDOW_STYLE(BS_PUSHBUTTON as u32),
+                x,
+                y,
+                80,
+                26,
+                hwnd,
+                HMENU(id as *mut c_void),
+                instance,
+                None,
+            )?;
+            SendMessageW(button, WM_SETFONT, WPARAM(font.0 as usize), LPARAM(1));
+        }
+        Ok(edits)
+    }
+
+    fn edit_text(&self, field: usize) -> String {
+        unsafe {
+            let edit = self.edits[field];
+            let len = GetWindowTextLengthW(edit);
+            let mut buf = vec![0u16; len as usize + 1];
+            let copied = GetWindowTextW(edit, &mut buf);
+            String::from_utf16_lossy(&buf[..copied as usize])
+                .trim()
+                .to_string()
+        }
+    }
+
+    // invalid numbers and colors keep their previous value
+    fn read_config(&self) -> Config {
+        let mut config = self.config.clone();
+        config.pairs = self
+            .edit_text(Self::FIELD_PAIRS)
+            .split(',')
+            .map(|pair_name| pair_name.trim().to_uppercase())
+            .filter(|pair_name| !pair_name.is_empty())
+            .map(|pair_name| {
+                match self.config.pairs.iter().find(|pair| pair.pair_name == pair_name) {
+                    Some(pair) => pair.clone(),
+                    None => PairConfig {
+                        pair_name,
+                        show_name: None,
+                        ws_name: None,
+                        market: None,
+                    },
+                }
+            })
+            .collect();
+        if let Ok(width) = self.edit_text(Self::FIELD_WIDTH).parse::<i32>() {
+            if width > 0 {
+                config.width = width;
+            }
+        }
+        // a family that is not installed keeps the previous one
+        let font_family = self.edit_text(Self::FIELD_FONT_FAMILY);
+        if font_family.is_empty() {
+            config.font_family = render::FONT_FAMILY.to_string();
+        } else if render::installed(&font_family) {
+            config.font_family = font_family;
+        } else {
+            warn!("font {} is not installed", font_family);
+        }
+        if let Some(font_size) = config::parse_font_size(&self.edit_text(Self::FIELD_FONT_SIZE)) {
+            config.font_size = font_size;
+        }
+        if let Some(font_size) = config::parse_font_size(&self.edit_text(Self::FIELD_LABEL_FONT_SIZE)) {
+            config.label_font_size = font_size;
+        }
+        let text_color = self.edit_text(Self::FIELD_TEXT_COLOR);
+        if theme::parse_argb(&text_color).is_some() {
+            config.text_color = text_color;
+        }
+        let proxy = self.edit_text(Self::FIELD_PROXY);
+        config.proxy = if proxy.is_empty() { None } else { Some(proxy) };
+        if let Some(decimals) = config::parse_decimals(&self.edit_text(Self::FIELD_DECIMALS)) {
+            config.decimals = decimals;
+        }
+        config
+    }
+
+    // a family that is not installed is pointed out, the dialog stays open to correct it
+    unsafe fn font_missing(&self, hwnd: HWND) -> bool {
+        let font_family = self.edit_text(Self::FIELD_FONT_FAMILY);
+        if font_family.is_empty() || render::installed(&font_family) {
+            return false;
+        }
+        let text = i18n::fill("字体 {} 未安装", &[&font_family]);
+        MessageBoxW(hwnd, &HSTRING::from(text), &HSTRING::from(i18n::tr("设置")), MB_OK | MB_ICONWARNING);
+        let _ = SetFocus(self.edits[Self::FIELD_FONT_FAMILY]);
+        true
+    }
+
+    fn save(&self) {
+        let config = self.read_config();
+        if let Err(err) = config.save() {
+            error!("save config err:{:?}", err);
+        }
+        unsafe {
+            let window = &mut *(GetWindowLongPtrW(self.main_hwnd, GWLP_USERDATA) as *mut Window);
+            window.apply_config(config.clone());
+            let _ = window.sender.blocking_send(api::UiCommand::Config(Box::new(config)));
+        }
+    }
+
+    extern "system" fn wndproc(
+        hwnd: HWND,
+        message: u32,
+        wparam: WPARAM,
+        lparam: LPARAM,
+    ) -> LRESULT {
+        unsafe {
+            match message {
+                WM_COMMAND => {
+                    let settings = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut Self;
+                    if settings.is_null() {
+                        return LRESULT(0);
+                    }
+                    match wparam.0 & 0xFFFF {
+                        Self::ID_SAVE if (*settings).font_missing(hwnd) => {}
+                        Self::ID_SAVE => {
+                            (*settings).save();
+                            let _ = DestroyWindow(hwnd);
+                        }
+                        Self::ID_CANCEL => {
+                            let _ = DestroyWindow(hwnd);
+                        }
+                        _ => {}
+                    }
+                    LRESULT(0)
+                }
+                WM_NCDESTROY => {
+                    let settings = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut Self;
+                    if !settings.is_null() {
+                        SetWindowLongPtrW(hwnd, GWLP_USERDATA, 0);
+                        drop(Box::from_raw(settings));
+                    }
+                    LRESULT(0)
+                }
+                _ => DefWindowProcW(hwnd, message, wparam, lparam),
+            }
+        }
+    }
+}
@@ -0,0 +1,361 @@
+// the taskbars of all displays. the primary one is Shell_TrayWnd, explorer
+// adds a Shell_SecondaryTrayWnd per other display when the taskbar is shown
+// on all of them
+
+use crate::config::TaskbarAnchor;
+use anyhow::Result;
+use core::ffi::c_void;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::sync::{mpsc, Mutex};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use windows::core::{w, VARIANT};
+use windows::Win32::Foundation::{HWND, POINT, RECT};
+use windows::Win32::Graphics::Gdi::{
+    GetMonitorInfoW, MonitorFromWindow, MONITORINFO, MONITOR_DEFAULTTONEAREST,
+};
+use windows::Win32::System::Com::{
+    CoCreateInstance, CoInitializeEx, CLSCTX_INPROC_SERVER, COINIT_MULTITHREADED,
+};
+use windows::Win32::UI::Accessibility::{
+    CUIAutomation, IUIAutomation, TreeScope_Descendants, UIA_AutomationIdPropertyId,
+};
+use windows::Win32::UI::Shell::{SHAppBarMessage, ABM_GETSTATE, ABS_AUTOHIDE, APPBARDATA};
+use windows::Win32::UI::WindowsAndMessaging::{
+    FindWindowExW, FindWindowW, GetAncestor, GetClassNameW, GetWindowRect, GA_ROOT,
+};
+
+// the app icons of windows 11 move as apps start and close, ui automation
+// is asked again after this long
+pub const APPS_REFRESH: Duration = Duration::from_secs(1);
+
+#[derive(Default)]
+struct StartButton {
+    rect: Option<RECT>,
+    // none until the first lookup finished
+    found: Option<Instant>,
+    // asked from the worker, not answered yet
+    pending: bool,
+}
+
+lazy_static! {
+    // the last start button found, by taskbar
+    static ref START_BUTTONS: Mutex<HashMap<isize, StartButton>> = Mutex::new(HashMap::new());
+    // taskbars for the lookup thread
+    static ref LOOKUPS: Mutex<mpsc::Sender<isize>> = Mutex::new(spawn_lookups());
+}
+
+#[derive(Error, Debug)]
+#[error("can not find {0} window")]
+struct NotFound(&'static str);
+
+// the primary taskbar first, then the others from left to right
+pub fn all() -> Vec<HWND> {
+    let mut secondary = Vec::new();
+    unsafe {
+        let mut after = HWND::default();
+        while let Ok(hwnd) = FindWindowExW(None, after, w!("Shell_SecondaryTrayWnd"), None) {
+            if hwnd.is_invalid() {
+                break;
+            }
+            let mut rect = RECT::default();
+            let _ = GetWindowRect(hwnd, &mut rect);
+            secondary.push((rect.left, rect.top, hwnd));
+            after = hwnd;
+        }
+    }
+    secondary.sort_by_key(|(left, top, _)| (*left, *top));
+    primary()
+        .ok()
+        .into_iter()
+        .chain(secondary.into_iter().map(|(_, _, hwnd)| hwnd))
+        .collect()
+}
+
+pub fn primary() -> Result<HWND> {
+    unsafe {
+        match FindWindowW(w!("Shell_TrayWnd"), None) {
+            Ok(hwnd) if !hwnd.is_invalid() => Ok(hwnd),
+            _ => Err(NotFound("Shell_TrayWnd").into()),
+        }
+    }
+}
+
+// the taskbar at index of all(), the primary one when that display is gone
+pub fn get(index: usize) -> Result<HWND> {
+    match all().get(index) {
+        Some(hwnd) => Ok(*hwnd),
+        None => primary(),
+    }
+}
+
+// a taskbar or a window on one, for the location events of explorer
+pub fn owns(hwnd: HWND) -> bool {
+    let mut class = [0u16; 32];
+    let len = unsafe { GetClassNameW(GetAncestor(hwnd, GA_ROOT), &mut class) };
+    let class = String::from_utf16_lossy(&class[..len.max(0) as usize]);
+    class == "Shell_TrayWnd" || class == "Shell_SecondaryTrayWnd"
+}
+
+// slid off its display with auto-hide, or on the way in or out. the setting
+// is the same for the taskbars of all displays
+pub fn is_hidden(taskbar: HWND) -> bool {
+    unsafe {
+        let mut data = APPBARDATA {
+            cbSize: std::mem::size_of::<APPBARDATA>() as u32,
+            ..Default::default()
+        };
+        if SHAppBarMessage(ABM_GETSTATE, &mut data) as u32 & ABS_AUTOHIDE == 0 {
+            return false;
+        }
+        let mut rect = RECT::default();
+        if GetWindowRect(taskbar, &mut rect).is_err() {
+            return false;
+        }
+        let mut info = MONITORINFO {
+            cbSize: std::mem::size_of::<MONITORINFO>() as u32,
+            ..Default::default()
+        };
+        let monitor = MonitorFromWindow(taskbar, MONITOR_DEFAULTTONEAREST);
+        if !GetMonitorInfoW(monitor, &mut info).as_bool() {
+            return false;
+        }
+        off_monitor(&rect, &info.rcMonitor)
+    }
+}
+
+// a hidden taskbar keeps a strip of a few pixels on the display
+fn off_monitor(taskbar: &RECT, monitor: &RECT) -> bool {
+    let visible_width = taskbar.right.min(monitor.right) - taskbar.left.max(monitor.left);
+    let visible_height = taskbar.bottom.min(monitor.bottom) - taskbar.top.max(monitor.top);
+    visible_width < taskbar.right - taskbar.left || visible_height < taskbar.bottom - taskbar.top
+}
+
+// where the widget goes on a taskbar, relative to it: next to the
+// notification area or the clock, at the end of a taskbar without either
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Anchor {
+    // the top right corner of the widget on a horizontal taskbar, the bottom
+    // left one on a taskbar docked left or right
+    pub pos: POINT,
+    // room across the taskbar, the height of a horizontal one
+    pub thickness: i32,
+    // length of the taskbar, the widget is kept within it
+    pub extent: i32,
+    pub vertical: bool,
+}
+
+impl Anchor {
+    // width and height of a widget `length` long along the taskbar
+    pub fn size(&self, length: i32) -> (i32, i32) {
+        if self.vertical {
+            (self.thickness, length)
+        } else {
+            (length, self.thickness)
+        }
+    }
+
+    // top left of a widget of that size, centered across the taskbar and
+    // moved offset pixels along it, negative toward its start
+    pub fn top_left(&self, width: i32, height: i32, offset: i32) -> POINT {
+        if self.vertical {
+            POINT {
+                x: self.pos.x + (self.thickness - width).max(0) / 2,
+                y: (self.pos.y - height + offset).min(self.extent - height).max(0),
+            }
+        } else {
+            POINT {
+                x: (self.pos.x - width + offset).min(self.extent - width).max(0),
+                y: self.pos.y + (self.thickness - height).max(0) / 2,
+            }
+        }
+    }
+}
+
+pub fn anchor(taskbar: HWND, mode: TaskbarAnchor) -> Result<Anchor> {
+    unsafe {
+        let mut parent_rect = RECT::default();
+        GetWindowRect(taskbar, &mut parent_rect)?;
+        if mode == TaskbarAnchor::Apps {
+            if let Some(anchor) = start_button(taskbar).and_then(|start| apps_anchor(&parent_rect, &start)) {
+                return Ok(anchor);
+            }
+        }
+        let notify = FindWindowExW(taskbar, None, w!("TrayNotifyWnd"), None)
+            .or_else(|_| FindWindowExW(taskbar, None, w!("ClockButton"), None))
+            .ok()
+            .filter(|hwnd| !hwnd.is_invalid());
+        let child_rect = match notify {
+            Some(child) => {
+                let mut child_rect = RECT::default();
+                GetWindowRect(child, &mut child_rect)?;
+                Some(child_rect)
+            }
+            None if taskbar == primary()? => return Err(NotFound("TrayNotifyWnd").into()),
+            None => None,
+        };
+        Ok(anchor_of(&parent_rect, child_rect.as_ref()))
+    }
+}
+
+// the start button leads the app icons, windows 10 has it as a window of its
+// own, windows 11 only through ui automation. the last one found, a stale one
+// is looked up again off the ui thread, FindFirst waits on a busy explorer
+fn start_button(taskbar: HWND) -> Option<RECT> {
+    let key = taskbar.0 as isize;
+    let mut cache = START_BUTTONS.lock().unwrap();
+    // e.g. a taskbar of a display that was unplugged
+    cache.retain(|_, button| {
+        button.pending || button.found.is_none_or(|found| found.elapsed() < APPS_REFRESH * 10)
+    });
+    let button = cache.entry(key).or_default();
+    let stale = button.found.is_none_or(|found| found.elapsed() >= APPS_REFRESH);
+    if stale && !button.pending && LOOKUPS.lock().unwrap().send(key).is_ok() {
+        button.pending = true;
+    }
+    button.rect
+}
+
+// com objects stay on the thread that made them, the lookups get one of their own
+fn spawn_lookups() -> mpsc::Sender<isize> {
+    let (sender, receiver) = mpsc::channel::<isize>();
+    std::thread::spawn(move || {
+        let automation: Option<IUIAutomation> = unsafe {
+            let _ = CoInitializeEx(None, COINIT_MULTITHREADED);
+            CoCreateInstance(&CUIAutomation, None, CLSCTX_INPROC_SERVER).ok()
+        };
+        for key in receiver {
+            let taskbar = HWND(key as *mut c_void);
+            let rect = automation.as_ref().and_then(|automation| find_start_button(automation, taskbar));
+            if let Some(button) = START_BUTTONS.lock().unwrap().get_mut(&key) {
+                button.rect = rect;
+                button.found = Some(Instant::now());
+                button.pending = false;
+            }
+        }
+    });
+    sender
+}
+
+fn find_start_button(automation: &IUIAutomation, taskbar: HWND) -> Option<RECT> {
+    unsafe {
+        let condition = automation
+            .CreatePropertyCondition(UIA_AutomationIdPropertyId, &VARIANT::from("StartButton"))
+            .ok()?;
+        let element = automation.ElementFromHandle(taskbar).ok()?;
+        let start = element.FindFirst(TreeScope_Descendants, &condition).ok()?;
+        start.CurrentBoundingRectangle().ok()
+    }
+}
+
+// left of the start button when the icons are centered, none when they start
+// at the edge like on windows 10 or with the left alignment of windows 11
+fn apps_anchor(parent: &RECT, start: &RECT) -> Option<Anchor> {
+    let width = parent.right - parent.left;
+    let height = parent.bottom - parent.top;
+    if height > width || start.left - parent.left < width / 4 {
+        return None;
+    }
+    Some(Anchor {
+        pos: POINT {
+            x: start.left - parent.left,
+            y: 0,
+        },
+        thickness: height,
+        extent: width,
+        vertical: false,
+    })
+}
+
+// the notification area is at the bottom of a vertical taskbar
+fn anchor_of(parent: &RECT, child: Option<&RECT>) -> Anchor {
+    let vertical = parent.bottom - parent.top > parent.right - parent.left;
+    if vertical {
+        Anchor {
+            pos: POINT {
+                x: 0,
+                y: child.map_or(parent.bottom, |child| child.top) - parent.top,
+            },
+            thickness: parent.right - parent.left,
+            extent: parent.bottom - parent.top,
+            vertical,
+        }
+    } else {
+        let child = child.copied().unwrap_or(RECT {
+            left: parent.right,
+            ..*parent
+        });
+        Anchor {
+            pos: POINT {
+                x: child.left - parent.left,
+                y: child.top - parent.top,
+            },
+            thickness: child.bottom - child.top,
+            extent: parent.right - parent.left,
+            vertical,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(left: i32, top: i32, right: i32, bottom: i32) -> RECT {
+        RECT { left, top, right, bottom }
+    }
+
+    #[test]
+    fn horizontal_widget_sits_left_of_the_notification_area() {
+        let anchor = anchor_of(&rect(0, 1040, 1920, 1080), Some(&rect(1700, 1040, 1920, 1080)));
+        assert!(!anchor.vertical);
+        assert_eq!(anchor.size(70), (70, 40));
+        assert_eq!(anchor.top_left(70, 40, 0), POINT { x: 1630, y: 0 });
+    }
+
+    #[test]
+    fn vertical_widget_sits_above_the_notification_area() {
+        let anchor = anchor_of(&rect(0, 0, 62, 1080), Some(&rect(0, 900, 62, 1080)));
+        assert!(anchor.vertical);
+        assert_eq!(anchor.size(120), (62, 120));
+        assert_eq!(anchor.top_left(62, 120, 0), POINT { x: 0, y: 780 });
+        // a narrower copy is centered across the taskbar
+        assert_eq!(anchor.top_left(50, 40, 0), POINT { x: 6, y: 860 });
+    }
+
+    #[test]
+    fn offset_moves_the_widget_within_the_taskbar() {
+        let anchor = anchor_of(&rect(0, 1040, 1920, 1080), Some(&rect(1700, 1040, 1920, 1080)));
+        assert_eq!(anchor.top_left(70, 40, -600), POINT { x: 1030, y: 0 });
+        assert_eq!(anchor.top_left(70, 40, -5000), POINT { x: 0, y: 0 });
+        assert_eq!(anchor.top_left(70, 40, 5000), POINT { x: 1850, y: 0 });
+        let anchor = anchor_of(&rect(0, 0, 62, 1080), Some(&rect(0, 900, 62, 1080)));
+        assert_eq!(anchor.top_left(62, 120, -100), POINT { x: 0, y: 680 });
+    }
+
+    #[test]
+    fn apps_anchor_only_for_centered_icons() {
+        let parent = rect(0, 1032, 1920, 1080);
+        let anchor = apps_anchor(&parent, &rect(700, 1032, 748, 1080)).unwrap();
+        assert_eq!(anchor.top_left(70, 48, 0), POINT { x: 630, y: 0 });
+        assert_eq!(apps_anchor(&parent, &rect(0, 1032, 48, 1080)), None);
+        assert_eq!(apps_anchor(&rect(0, 0, 62, 1080), &rect(0, 0, 62, 48)), None);
+    }
+
+    #[test]
+    fn auto_hidden_taskbar_is_off_the_monitor() {
+        let monitor = rect(0, 0, 1920, 1080);
+        assert!(!off_monitor(&rect(0, 1040, 1920, 1080), &monitor));
+        assert!(off_monitor(&rect(0, 1078, 1920, 1118), &monitor));
+        assert!(off_monitor(&rect(-60, 0, 2, 1080), &monitor));
+    }
+
+    #[test]
+    fn without_a_notification_area_the_widget_goes_to_the_end() {
+        let anchor = anchor_of(&rect(1920, 1040, 3840, 1080), None);
+        assert_eq!(anchor.top_left(70, 40, 0), POINT { x: 1850, y: 0 });
+        let anchor = anchor_of(&rect(1920, 0, 1982, 1080), None);
+        assert_eq!(anchor.top_left(62, 120, 0), POINT { x: 0, y: 960 });
+    }
+}
@@ -0,0 +1,215 @@
+// colors of the widget: the built-in light and dark palettes or a custom one
+// from the config, colors are "#RRGGBB" or "#AARRGGBB"
+
+use crate::i18n;
+use serde::{Deserialize, Serialize};
+use windows::core::{w, PCWSTR};
+use windows::Win32::System::Registry::{RegGetValueW, HKEY_CURRENT_USER, RRF_RT_REG_DWORD};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThemeName {
+    // light or dark by the color of the taskbar, see taskbar_is_light
+    Auto,
+    // dark text for a light taskbar, the text is Config::text_color
+    Light,
+    // light text for a dark taskbar
+    Dark,
+    // Config::palette
+    Custom,
+}
+
+impl ThemeName {
+    pub const ALL: [ThemeName; 4] = [ThemeName::Auto, ThemeName::Light, ThemeName::Dark, ThemeName::Custom];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ThemeName::Auto => i18n::tr("跟随任务栏"),
+            ThemeName::Light => i18n::tr("浅色"),
+            ThemeName::Dark => i18n::tr("深色"),
+            ThemeName::Custom => i18n::tr("自定义"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Palette {
+    // pair names, prices and notices
+    pub text: String,
+    // the third row: funding, spreads and the portfolio label
+    pub secondary: String,
+    // 24h change and pnl
+    pub up: String,
+    pub down: String,
+    // an alpha of at least 1 keeps the widget clickable where nothing is drawn
+    pub background: String,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::light()
+    }
+}
+
+impl Palette {
+    pub fn light() -> Self {
+        Palette {
+            text: "#000000".to_string(),
+            secondary: "#505050".to_string(),
+            up: "#00A000".to_string(),
+            down: "#DC0000".to_string(),
+            background: "#01FFFFFF".to_string(),
+        }
+    }
+
+    pub fn dark() -> Self {
+        Palette {
+            text: "#FFFFFF".to_string(),
+            secondary: "#C0C0C0".to_string(),
+            up: "#3DDC84".to_string(),
+            down: "#FF5A5A".to_string(),
+            background: "#01000000".to_string(),
+        }
+    }
+
+    // a color that does not parse falls back to the light palette's
+    pub fn colors(&self) -> Colors {
+        let light = Palette::light();
+        let color = |value: &str, fallback: &str| {
+            parse_argb(value).or_else(|| parse_argb(fallback)).unwrap_or(0xFF000000)
+        };
+        let mut background = color(&self.background, &light.background);
+        // a fully transparent pixel is not hit, clicks would go to the taskbar
+        if background >> 24 == 0 {
+            background |= 0x01000000;
+        }
+        Colors {
+            text: color(&self.text, &light.text),
+            secondary: color(&self.secondary, &light.secondary),
+            up: color(&self.up, &light.up),
+            down: color(&self.down, &light.down),
+            background,
+        }
+    }
+}
+
+// argb values of a palette, what the renderers take
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Colors {
+    pub text: u32,
+    pub secondary: u32,
+    pub up: u32,
+    pub down: u32,
+    pub background: u32,
+}
+
+impl Colors {
+    pub fn change(&self, change: f64) -> u32 {
+        if change >= 0. {
+            self.up
+        } else {
+            self.down
+        }
+    }
+}
+
+fn dword(key: PCWSTR, value: PCWSTR) -> Option<u32> {
+    let mut data = 0u32;
+    let mut size = std::mem::size_of::<u32>() as u32;
+    let result = unsafe {
+        RegGetValueW(
+            HKEY_CURRENT_USER,
+            key,
+            value,
+            RRF_RT_REG_DWORD,
+            None,
+            Some(&mut data as *mut u32 as *mut core::ffi::c_void),
+            Some(&mut size),
+        )
+    };
+    result.is_ok().then_some(data)
+}
+
+// read again on WM_SETTINGCHANGE, the taskbar follows the windows mode of
+// the personalization settings or the accent color when it is shown on it
+pub fn taskbar_is_light() -> bool {
+    let personalize = w!("Software\\Microsoft\\Windows\\CurrentVersion\\Themes\\Personalize");
+    is_light(
+        dword(personalize, w!("SystemUsesLightTheme")),
+        dword(personalize, w!("ColorPrevalence")),
+        dword(w!("Software\\Microsoft\\Windows\\DWM"), w!("AccentColor")),
+    )
+}
+
+// accent is 0xAABBGGRR. windows before the light mode have a dark taskbar
+fn is_light(uses_light_theme: Option<u32>, color_prevalence: Option<u32>, accent: Option<u32>) -> bool {
+    if color_prevalence == Some(1) {
+        if let Some(accent) = accent {
+            let (r, g, b) = (accent & 0xFF, (accent >> 8) & 0xFF, (accent >> 16) & 0xFF);
+            // rec. 601 luma
+            return r * 299 + g * 587 + b * 114 > 128_000;
+        }
+    }
+    uses_light_theme == Some(1)
+}
+
+// "#RRGGBB" is opaque
+pub fn parse_argb(color: &str) -> Option<u32> {
+    let hex = color.trim().trim_start_matches('#');
+    let value = u32::from_str_radix(hex, 16).ok()?;
+    match hex.len() {
+        6 => Some(0xFF000000 | value),
+        8 => Some(value),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_rgb_and_argb() {
+        assert_eq!(parse_argb("#FF8000"), Some(0xFFFF8000));
+        assert_eq!(parse_argb("01ffffff"), Some(0x01FFFFFF));
+        assert_eq!(parse_argb("#FFF"), None);
+        assert_eq!(parse_argb("#GG0000"), None);
+    }
+
+    #[test]
+    fn background_keeps_some_alpha() {
+        let palette = Palette {
+            background: "#00123456".to_string(),
+            ..Palette::dark()
+        };
+        assert_eq!(palette.colors().background, 0x01123456);
+        let palette = Palette {
+            background: "#80123456".to_string(),
+            ..Palette::dark()
+        };
+        assert_eq!(palette.colors().background, 0x80123456);
+    }
+
+    #[test]
+    fn light_taskbar_by_mode_or_accent() {
+        assert!(is_light(Some(1), Some(0), None));
+        assert!(!is_light(Some(0), None, Some(0xFFFFFFFF)));
+        assert!(!is_light(None, None, None));
+        // a dark blue accent and a light yellow one on the taskbar
+        assert!(!is_light(Some(1), Some(1), Some(0xFF8A4A00)));
+        assert!(is_light(Some(0), Some(1), Some(0xFF80F0FF)));
+    }
+
+    #[test]
+    fn bad_colors_fall_back_to_light() {
+        let palette = Palette {
+            text: "white".to_string(),
+            ..Palette::dark()
+        };
+        let colors = palette.colors();
+        assert_eq!(colors.text, 0xFF000000);
+        assert_eq!(colors.up, 0xFF3DDC84);
+        assert_eq!(colors.change(-1.), 0xFFFF5A5A);
+    }
+}
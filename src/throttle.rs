@@ -0,0 +1,114 @@
+// at most Config::max_refresh_hz messages of a kind per pair go to the widget,
+// the ticks in between are coalesced into the latest one, sent when the
+// interval is up
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+// 0 sends every tick
+static INTERVAL_MS: AtomicU64 = AtomicU64::new(0);
+
+pub fn set_max_refresh(hz: f64) {
+    let interval_ms = if hz > 0. { (1000. / hz).round() as u64 } else { 0 };
+    INTERVAL_MS.store(interval_ms, Ordering::Relaxed);
+}
+
+pub fn interval() -> Duration {
+    Duration::from_millis(INTERVAL_MS.load(Ordering::Relaxed))
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Offer<T> {
+    // goes out now
+    Send(T),
+    // kept as the pending one, flush it after the duration. none when an
+    // earlier pending one is replaced, its flush is already due
+    Hold(Option<Duration>),
+}
+
+pub struct Coalescer<K, T> {
+    sent: HashMap<K, Instant>,
+    pending: HashMap<K, T>,
+}
+
+impl<K, T> Default for Coalescer<K, T> {
+    fn default() -> Self {
+        Coalescer {
+            sent: HashMap::new(),
+            pending: HashMap::new(),
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone, T> Coalescer<K, T> {
+    pub fn offer(&mut self, key: K, value: T, now: Instant, interval: Duration) -> Offer<T> {
+        let due = match self.sent.get(&key) {
+            Some(sent) => *sent + interval,
+            None => now,
+        };
+        if now >= due {
+            // a pending one is older than this
+            self.pending.remove(&key);
+            self.sent.insert(key, now);
+            return Offer::Send(value);
+        }
+        match self.pending.insert(key, value) {
+            Some(_) => Offer::Hold(None),
+            None => Offer::Hold(Some(due - now)),
+        }
+    }
+
+    // the pending value of key once its flush is due
+    pub fn flush(&mut self, key: &K, now: Instant) -> Option<T> {
+        let value = self.pending.remove(key)?;
+        self.sent.insert(key.clone(), now);
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INTERVAL: Duration = Duration::from_millis(500);
+
+    #[test]
+    fn first_tick_goes_out_and_the_rest_are_coalesced() {
+        let start = Instant::now();
+        let mut coalescer = Coalescer::default();
+        assert_eq!(coalescer.offer("BTCUSDT", 1, start, INTERVAL), Offer::Send(1));
+        let at = |ms| start + Duration::from_millis(ms);
+        assert_eq!(
+            coalescer.offer("BTCUSDT", 2, at(100), INTERVAL),
+            Offer::Hold(Some(Duration::from_millis(400)))
+        );
+        assert_eq!(coalescer.offer("BTCUSDT", 3, at(200), INTERVAL), Offer::Hold(None));
+        // other pairs have their own interval
+        assert_eq!(coalescer.offer("ETHUSDT", 4, at(200), INTERVAL), Offer::Send(4));
+        assert_eq!(coalescer.flush(&"BTCUSDT", at(500)), Some(3));
+        assert_eq!(coalescer.flush(&"BTCUSDT", at(500)), None);
+    }
+
+    #[test]
+    fn a_due_tick_drops_the_older_pending_one() {
+        let start = Instant::now();
+        let mut coalescer = Coalescer::default();
+        coalescer.offer("BTCUSDT", 1, start, INTERVAL);
+        coalescer.offer("BTCUSDT", 2, start + Duration::from_millis(100), INTERVAL);
+        assert_eq!(
+            coalescer.offer("BTCUSDT", 3, start + INTERVAL, INTERVAL),
+            Offer::Send(3)
+        );
+        assert_eq!(coalescer.flush(&"BTCUSDT", start + INTERVAL), None);
+    }
+
+    #[test]
+    fn zero_interval_sends_everything() {
+        let start = Instant::now();
+        let mut coalescer = Coalescer::default();
+        coalescer.offer("BTCUSDT", 1, start, Duration::ZERO);
+        assert_eq!(coalescer.offer("BTCUSDT", 2, start, Duration::ZERO), Offer::Send(2));
+    }
+}
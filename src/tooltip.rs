@@ -0,0 +1,217 @@
+// tracking tooltip over the widget with the stats of the pair under the
+// cursor, shown while hovering, see Window::show_tooltip
+
+use crate::render::wide;
+use anyhow::Result;
+use windows::core::PWSTR;
+use windows::Win32::Foundation::{HWND, LPARAM, RECT, WPARAM};
+use windows::Win32::Graphics::Gdi::{GetMonitorInfoW, MonitorFromRect, MONITORINFO, MONITOR_DEFAULTTONEAREST};
+use windows::Win32::UI::Controls::{
+    TOOLTIPS_CLASSW, TTF_ABSOLUTE, TTF_IDISHWND, TTF_TRACK, TTM_ADDTOOLW, TTM_GETBUBBLESIZE,
+    TTM_SETMAXTIPWIDTH, TTM_TRACKACTIVATE, TTM_TRACKPOSITION, TTM_UPDATETIPTEXTW, TTS_ALWAYSTIP,
+    TTS_NOPREFIX, TTTOOLINFOW,
+};
+use windows::Win32::UI::WindowsAndMessaging::{
+    CreateWindowExW, DestroyWindow, SendMessageW, WINDOW_STYLE, WS_EX_TOPMOST, WS_POPUP,
+};
+
+// wide enough for the longest row, the lines break at \n
+const MAX_WIDTH: isize = 400;
+// between the tooltip and the widget
+const GAP: i32 = 4;
+
+pub struct Tooltip {
+    hwnd: HWND,
+    // the widget, also the id of the only tool
+    owner: HWND,
+    shown: bool,
+}
+
+impl Tooltip {
+    pub fn create(owner: HWND) -> Result<Tooltip> {
+        unsafe {
+            let hwnd = CreateWindowExW(
+                WS_EX_TOPMOST,
+                TOOLTIPS_CLASSW,
+                None,
+                WS_POPUP | WINDOW_STYLE(TTS_NOPREFIX | TTS_ALWAYSTIP),
+                0,
+                0,
+                0,
+                0,
+                owner,
+                None,
+                None,
+                None,
+            )?;
+            let tooltip = Tooltip {
+                hwnd,
+                owner,
+                shown: false,
+            };
+            let mut empty = wide("");
+            let mut tool = tooltip.tool(PWSTR(empty.as_mut_ptr()));
+            SendMessageW(hwnd, TTM_ADDTOOLW, WPARAM(0), LPARAM(&mut tool as *mut _ as isize));
+            SendMessageW(hwnd, TTM_SETMAXTIPWIDTH, WPARAM(0), LPARAM(MAX_WIDTH));
+            Ok(tooltip)
+        }
+    }
+
+    fn tool(&self, text: PWSTR) -> TTTOOLINFOW {
+        TTTOOLINFOW {
+            cbSize: std::mem::size_of::<TTTOOLINFOW>() as u32,
+            uFlags: TTF_IDISHWND | TTF_TRACK | TTF_ABSOLUTE,
+            hwnd: self.owner,
+            uId: self.owner.0 as usize,
+            lpszText: text,
+            ..Default::default()
+        }
+    }
+
+    // above the widget at the cursor, below it when there is no room above,
+    // e.g. on a taskbar at the top of the screen
+    pub fn show(&mut self, text: &str, cursor_x: i32, widget: &RECT) {
+        unsafe {
+            let mut text = wide(text);
+            let mut tool = self.tool(PWSTR(text.as_mut_ptr()));
+            let tool_ptr = LPARAM(&mut tool as *mut _ as isize);
+            SendMessageW(self.hwnd, TTM_UPDATETIPTEXTW, WPARAM(0), tool_ptr);
+            let size = SendMessageW(self.hwnd, TTM_GETBUBBLESIZE, WPARAM(0), tool_ptr).0;
+            let (width, height) = ((size & 0xFFFF) as i32, ((size >> 16) & 0xFFFF) as i32);
+            let (x, y) = position(cursor_x, width, height, widget, &work_area(widget));
+            let point = ((y as u16 as u32) << 16) | x as u16 as u32;
+            SendMessageW(self.hwnd, TTM_TRACKPOSITION, WPARAM(0), LPARAM(point as isize));
+            if !self.shown {
+                SendMessageW(self.hwnd, TTM_TRACKACTIVATE, WPARAM(1), tool_ptr);
+                self.shown = true;
+            }
+        }
+    }
+
+    pub fn hide(&mut self) {
+        if !self.shown {
+            return;
+        }
+        let mut empty = wide("");
+        let mut tool = self.tool(PWSTR(empty.as_mut_ptr()));
+        unsafe {
+            SendMessageW(self.hwnd, TTM_TRACKACTIVATE, WPARAM(0), LPARAM(&mut tool as *mut _ as isize));
+        }
+        self.shown = false;
+    }
+
+    pub fn is_shown(&self) -> bool {
+        self.shown
+    }
+}
+
+impl Drop for Tooltip {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = DestroyWindow(self.hwnd);
+        }
+    }
+}
+
+// the monitor of the widget without its taskbars, unbounded when windows can't tell
+fn work_area(widget: &RECT) -> RECT {
+    let mut info = MONITORINFO {
+        cbSize: std::mem::size_of::<MONITORINFO>() as u32,
+        ..Default::default()
+    };
+    unsafe {
+        let monitor = MonitorFromRect(widget, MONITOR_DEFAULTTONEAREST);
+        if GetMonitorInfoW(monitor, &mut info).as_bool() {
+            return info.rcWork;
+        }
+    }
+    RECT {
+        left: i32::MIN / 2,
+        top: i32::MIN / 2,
+        right: i32::MAX / 2,
+        bottom: i32::MAX / 2,
+    }
+}
+
+// top left of the tooltip, centered on the cursor and kept inside the work area,
+// e.g. not under a secondary monitor's edge
+fn position(cursor_x: i32, width: i32, height: i32, widget: &RECT, work: &RECT) -> (i32, i32) {
+    let x = (cursor_x - width / 2).min(work.right - width).max(work.left);
+    let y = if widget.top - GAP - height >= work.top {
+        widget.top - GAP - height
+    } else {
+        widget.bottom + GAP
+    };
+    (x, y.min(work.bottom - height).max(work.top))
+}
+
+// a title line over "label value" lines
+pub fn text(title: &str, rows: &[(&str, String)]) -> String {
+    let mut text = title.to_string();
+    for (label, value) in rows {
+        text.push('\n');
+        text.push_str(label);
+        text.push(' ');
+        text.push_str(value);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn above_a_bottom_taskbar_and_below_a_top_one() {
+        let bottom = RECT {
+            left: 1500,
+            top: 1040,
+            right: 1570,
+            bottom: 1080,
+        };
+        let work = RECT {
+            left: 0,
+            top: 0,
+            right: 1920,
+            bottom: 1040,
+        };
+        assert_eq!(position(1530, 200, 100, &bottom, &work), (1430, 936));
+        let top = RECT {
+            left: 0,
+            top: 0,
+            right: 70,
+            bottom: 40,
+        };
+        let work = RECT {
+            left: 0,
+            top: 40,
+            right: 1920,
+            bottom: 1080,
+        };
+        assert_eq!(position(30, 200, 100, &top, &work), (0, 44));
+    }
+
+    #[test]
+    fn kept_inside_the_work_area() {
+        // the right end of a taskbar on a monitor left of the primary one
+        let widget = RECT {
+            left: -80,
+            top: 1040,
+            right: -10,
+            bottom: 1080,
+        };
+        let work = RECT {
+            left: -1920,
+            top: 0,
+            right: 0,
+            bottom: 1040,
+        };
+        assert_eq!(position(-20, 200, 100, &widget, &work), (-200, 936));
+    }
+
+    #[test]
+    fn joins_the_rows() {
+        let rows = [("价格", "64123.5".to_string()), ("24h 涨跌", "+1.20%".to_string())];
+        assert_eq!(text("BTC/USDT", &rows), "BTC/USDT\n价格 64123.5\n24h 涨跌 +1.20%");
+    }
+}
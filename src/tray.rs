@@ -0,0 +1,355 @@
+use crate::api;
+use crate::config::TrayIconMode;
+use crate::fiat;
+use crate::i18n;
+use crate::my_window::Window;
+use crate::pairs;
+use crate::render::gdi::{Brush, Font, Graphics, Image};
+use crate::render::FONT_FAMILY;
+use anyhow::Result;
+use core::ffi::c_void;
+use tracing::warn;
+use windows::Win32::Graphics::GdiPlus::{
+    GdipCreateHICONFromBitmap, GdipDrawString, GdipGraphicsClear, GdipSetTextRenderingHint, RectF,
+    TextRenderingHintAntiAlias,
+};
+use windows::Win32::UI::Shell::{
+    Shell_NotifyIconW, NIF_ICON, NIF_MESSAGE, NIF_TIP, NIM_ADD, NIM_DELETE, NIM_MODIFY,
+    NOTIFYICONDATAW,
+};
+use windows::{
+    core::*, Win32::Foundation::*, Win32::System::LibraryLoader::GetModuleHandleW,
+    Win32::UI::WindowsAndMessaging::*,
+};
+
+// hidden top-level window owning the notification-area icon. it outlives the
+// widget, so api messages are posted here and forwarded to the widget.
+pub struct Tray {
+    pub hwnd: usize,
+    window: *mut Window,
+    mode: TrayIconMode,
+    // 24h open of the last ticker, by pair name
+    open_price: Option<(String, f64)>,
+    // generated price icon, destroyed when replaced
+    price_icon: Option<HICON>,
+    // last ws ping round trip in ms
+    latency: Option<u64>,
+}
+
+impl Tray {
+    pub const WM_TRAY: u32 = WM_USER + 2;
+    pub const CLASS_NAME: PCWSTR = w!("mjj_tray");
+    const ICON_ID: u32 = 1;
+    const COMAMND_RESTORE: usize = 6;
+
+    pub fn new(window: &mut Window, mode: TrayIconMode) -> Result<Box<Tray>> {
+        let mut tray = Box::new(Tray {
+            hwnd: 0,
+            window: window as *mut Window,
+            mode,
+            open_price: None,
+            price_icon: None,
+            latency: None,
+        });
+        unsafe {
+            let instance = GetModuleHandleW(None)?;
+            let wc = WNDCLASSW {
+                hInstance: instance.into(),
+                lpszClassName: Self::CLASS_NAME,
+                lpfnWndProc: Some(Self::wndproc),
+                ..Default::default()
+            };
+            RegisterClassW(&wc);
+            let hwnd = CreateWindowExW(
+                WS_EX_TOOLWINDOW,
+                Self::CLASS_NAME,
+                Self::CLASS_NAME,
+                WS_POPUP,
+                0,
+                0,
+                0,
+                0,
+                None,
+                None,
+                wc.hInstance,
+                None,
+            )?;
+            tray.hwnd = hwnd.0 as usize;
+            SetWindowLongPtrW(hwnd, GWLP_USERDATA, &mut *tray as *mut Tray as isize);
+            window.keep_alive = true;
+        }
+        tray.add_icon(i18n::tr("启动..."))?;
+        Ok(tray)
+    }
+
+    // again after explorer restarts, the new taskbar starts without it
+    fn add_icon(&self, tip: &str) -> Result<()> {
+        let mut nid = self.notify_icon_data();
+        nid.uFlags = NIF_ICON | NIF_MESSAGE | NIF_TIP;
+        nid.uCallbackMessage = Self::WM_TRAY;
+        nid.hIcon = match self.price_icon {
+            Some(icon) => icon,
+            None => unsafe { LoadIconW(None, IDI_APPLICATION)? },
+        };
+        Self::copy_tip(&mut nid, tip);
+        unsafe {
+            let _ = Shell_NotifyIconW(NIM_ADD, &nid);
+        }
+        Ok(())
+    }
+
+    fn notify_icon_data(&self) -> NOTIFYICONDATAW {
+        NOTIFYICONDATAW {
+            cbSize: std::mem::size_of::<NOTIFYICONDATAW>() as u32,
+            hWnd: HWND(self.hwnd as *mut c_void),
+            uID: Self::ICON_ID,
+            ..Default::default()
+        }
+    }
+
+    fn copy_tip(nid: &mut NOTIFYICONDATAW, tip: &str) {
+        let tip: Vec<u16> = tip.encode_utf16().take(nid.szTip.len() - 1).collect();
+        nid.szTip[..tip.len()].copy_from_slice(&tip);
+        nid.szTip[tip.len()] = 0;
+    }
+
+    fn set_tip(&self, tip: &str) {
+        let mut nid = self.notify_icon_data();
+        nid.uFlags = NIF_TIP;
+        Self::copy_tip(&mut nid, tip);
+        unsafe {
+            let _ = Shell_NotifyIconW(NIM_MODIFY, &nid);
+        }
+    }
+
+    // short enough for a 16px icon, e.g. 67k, 3.4k, 152, 0.52
+    fn icon_text(&self, price: &api::Price) -> Option<String> {
+        match self.mode {
+            TrayIconMode::Icon => None,
+            TrayIconMode::Price => {
+                let value = price.tag_price;
+                Some(if value >= 10000. {
+                    format!("{:.0}k", value / 1000.)
+                } else if value >= 1000. {
+                    format!("{:.1}k", value / 1000.)
+                } else if value >= 100. {
+                    format!("{:.0}", value)
+                } else {
+                    format!("{:.2}", value)
+                })
+            }
+            TrayIconMode::Change => match &self.open_price {
+                Some((name, open_price)) if *name == price.name && *open_price != 0. => {
+                    Some(format!("{:+.1}", (price.tag_price - open_price) / open_price * 100.))
+                }
+                _ => None,
+            },
+        }
+    }
+
+    fn set_price_icon(&mut self, text: &str) {
+        unsafe {
+            let size = GetSystemMetrics(SM_CXSMICON);
+            let bitmap = Image::argb(size, size);
+            let graphics = Graphics::from_image(&bitmap);
+            GdipSetTextRenderingHint(graphics.as_ptr(), TextRenderingHintAntiAlias);
+            GdipGraphicsClear(graphics.as_ptr(), Window::make_argb(0, 0, 0, 0));
+            let font = Font::new(FONT_FAMILY, size as f32 * 0.4);
+            let brush = Brush::solid(Window::make_argb(255, 255, 255, 255));
+            let lay_box = RectF {
+                X: 0.,
+                Y: 0.,
+                Width: size as f32,
+                Height: size as f32,
+            };
+            let bound = Window::meansuer_string(
+                graphics.as_ptr(),
+                &HSTRING::from(text),
+                font.as_ptr(),
+                &lay_box,
+            );
+            let dst_rect = Window::generate_mid_rect(&lay_box, &bound);
+            GdipDrawString(
+                graphics.as_ptr(),
+                &HSTRING::from(text),
+                -1,
+                font.as_ptr(),
+                &dst_rect,
+                std::ptr::null_mut(),
+                brush.as_ptr(),
+            );
+            drop(graphics);
+            let mut icon = HICON::default();
+            GdipCreateHICONFromBitmap(bitmap.as_ptr(), &mut icon);
+
+            let mut nid = self.notify_icon_data();
+            nid.uFlags = NIF_ICON;
+            nid.hIcon = icon;
+            let _ = Shell_NotifyIconW(NIM_MODIFY, &nid);
+            if let Some(old_icon) = self.price_icon.replace(icon) {
+                let _ = DestroyIcon(old_icon);
+            }
+        }
+    }
+
+    fn remove_icon(&self) {
+        let nid = self.notify_icon_data();
+        unsafe {
+            let _ = Shell_NotifyIconW(NIM_DELETE, &nid);
+        }
+    }
+
+    // connection status for the tooltip
+    fn status_of(&self, api_msg: &api::ApiMessage) -> Option<String> {
+        match api_msg {
+            api::ApiMessage::Price(price) => {
+                let show_name = pairs::pair_info(&pairs::TradePair(price.name.clone())).show_name;
+                let source = i18n::tr(if price.polled { "轮询" } else { "已连接" });
+                let price_str = pairs::format_price(&price.name, price.tag_price);
+                let mut status = format!("{} {} {}", source, show_name, price_str);
+                if let Some(latency) = self.latency.filter(|_| !price.polled) {
+                    status.push_str(&format!(" {}ms", latency));
+                }
+                if let Some(fx) = fiat::describe() {
+                    status.push('\n');
+                    status.push_str(&fx);
+                }
+                Some(status)
+            }
+            api::ApiMessage::Notify(not_msg) => Some(not_msg.clone()),
+            api::ApiMessage::Status(status) => Some(status.label().to_string()),
+            _ => None,
+        }
+    }
+
+    fn show_menu(&self) -> Result<()> {
+        unsafe {
+            let hwnd = HWND(self.hwnd as *mut c_void);
+            let window = &mut *self.window;
+            let menu = if window.hwnd != 0 {
+                Window::create_context_menu(window)?
+            } else {
+                let menu = CreatePopupMenu()?;
+                let _ = AppendMenuW(menu, MF_STRING, Self::COMAMND_RESTORE, &HSTRING::from(i18n::tr("恢复窗口")));
+                let _ = AppendMenuW(menu, MF_STRING, Window::COMAMND_EXIT, &HSTRING::from(i18n::tr("退出")));
+                menu
+            };
+            let mut point = POINT::default();
+            let _ = GetCursorPos(&mut point);
+            // required for the menu to close when clicking elsewhere
+            let _ = SetForegroundWindow(hwnd);
+            let _ = TrackPopupMenu(menu, TPM_RIGHTBUTTON, point.x, point.y, 0, hwnd, None);
+            let _ = DestroyMenu(menu);
+        }
+        Ok(())
+    }
+
+    fn restore(&self) {
+        let window = unsafe { &mut *self.window };
+        if let Err(err) = window.restore() {
+            warn!("restore window err:{:?}", err);
+        }
+    }
+
+    extern "system" fn wndproc(
+        hwnd: HWND,
+        message: u32,
+        wparam: WPARAM,
+        lparam: LPARAM,
+    ) -> LRESULT {
+        unsafe {
+            let tray = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut Self;
+            if tray.is_null() {
+                return DefWindowProcW(hwnd, message, wparam, lparam);
+            }
+            let tray = &mut *tray;
+            let window = &mut *tray.window;
+            let window_hwnd = HWND(window.hwnd as *mut c_void);
+            match message {
+                Window::WM_FRESH => {
+                    let api_msg = &*(wparam.0 as *const api::ApiMessage);
+                    if let Some(status) = tray.status_of(api_msg) {
+                        tray.set_tip(&status);
+                    }
+                    match api_msg {
+                        api::ApiMessage::Latency(latency) => tray.latency = Some(*latency),
+                        api::ApiMessage::Ticker(ticker) => {
+                            tray.open_price = Some((ticker.name.clone(), ticker.open_price));
+                        }
+                        api::ApiMessage::Price(price) => {
+                            if let Some(text) = tray.icon_text(price) {
+                                tray.set_price_icon(&text);
+                            }
+                        }
+                        _ => {}
+                    }
+                    if window.hwnd == 0
+                        || PostMessageW(window_hwnd, Window::WM_FRESH, wparam, lparam).is_err()
+                    {
+                        drop(Box::from_raw(wparam.0 as *mut api::ApiMessage));
+                    }
+                    LRESULT(0)
+                }
+                Window::WM_IPC => {
+                    Window::answer_ipc(window, wparam);
+                    LRESULT(0)
+                }
+                Window::WM_ACTIVATE_INSTANCE => {
+                    // a closed widget comes back, an open one flashes
+                    tray.restore();
+                    let window_hwnd = HWND(window.hwnd as *mut c_void);
+                    if window.hwnd != 0 {
+                        let _ = PostMessageW(window_hwnd, message, wparam, lparam);
+                    }
+                    LRESULT(0)
+                }
+                // the shell window brings the widget back
+                message if message == *crate::my_window::WM_TASKBAR_CREATED => {
+                    if let Err(err) = tray.add_icon(i18n::tr("重连中...")) {
+                        warn!("tray icon err:{:?}", err);
+                    }
+                    LRESULT(0)
+                }
+                Self::WM_TRAY => {
+                    match (lparam.0 & 0xFFFF) as u32 {
+                        WM_RBUTTONUP => {
+                            if let Err(err) = tray.show_menu() {
+                                warn!("tray menu err:{:?}", err);
+                            }
+                        }
+                        WM_LBUTTONDBLCLK => tray.restore(),
+                        _ => {}
+                    }
+                    LRESULT(0)
+                }
+                WM_COMMAND => {
+                    match wparam.0 {
+                        Self::COMAMND_RESTORE => tray.restore(),
+                        Window::COMAMND_EXIT => {
+                            tray.remove_icon();
+                            window.exit();
+                        }
+                        _ => {
+                            if window.hwnd != 0 {
+                                SendMessageW(window_hwnd, WM_COMMAND, wparam, lparam);
+                            }
+                        }
+                    }
+                    LRESULT(0)
+                }
+                _ => DefWindowProcW(hwnd, message, wparam, lparam),
+            }
+        }
+    }
+}
+
+impl Drop for Tray {
+    fn drop(&mut self) {
+        self.remove_icon();
+        if let Some(icon) = self.price_icon.take() {
+            unsafe {
+                let _ = DestroyIcon(icon);
+            }
+        }
+    }
+}
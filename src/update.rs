@@ -0,0 +1,92 @@
+// a new release on github, checked now and then through the proxy like every
+// other request. the tray or the widget menu links to its page
+
+use crate::tls;
+use anyhow::Result;
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::RwLock;
+use std::time::Duration;
+
+pub const RELEASES_URL: &str = "https://api.github.com/repos/clearskyaaa/demo/releases/latest";
+// the first check waits until the connection to the exchange settled
+pub const FIRST_CHECK: Duration = Duration::from_secs(60);
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(24 * 3600);
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Release {
+    // e.g. v1.2.0
+    pub tag_name: String,
+    // the release page with the downloads
+    pub html_url: String,
+}
+
+lazy_static! {
+    // newer than the running version, none until a check found one
+    static ref AVAILABLE: RwLock<Option<Release>> = RwLock::new(None);
+}
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn available() -> Option<Release> {
+    AVAILABLE.read().unwrap().clone()
+}
+
+// "v1.10.0" against "1.9.2", parts that are not numbers count as 0
+pub fn is_newer(tag: &str, current: &str) -> bool {
+    let parts = |version: &str| -> Vec<u64> {
+        version
+            .trim()
+            .trim_start_matches(['v', 'V'])
+            .split(['.', '-', '+'])
+            .take(3)
+            .map(|part| part.parse().unwrap_or(0))
+            .collect()
+    };
+    parts(tag) > parts(current)
+}
+
+// the release when it is newer, none when checks are off or it is not
+pub async fn check(client: &reqwest::Client) -> Result<Option<Release>> {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return Ok(None);
+    }
+    let request = client
+        .get(RELEASES_URL)
+        .header(reqwest::header::ACCEPT, "application/vnd.github+json");
+    let str_data = tls::send(request)
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let release = serde_json::from_str::<Release>(&str_data)?;
+    if !is_newer(&release.tag_name, env!("CARGO_PKG_VERSION")) {
+        return Ok(None);
+    }
+    *AVAILABLE.write().unwrap() = Some(release.clone());
+    Ok(Some(release))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_newer;
+
+    #[test]
+    fn compares_numerically() {
+        assert!(is_newer("v1.10.0", "1.9.2"));
+        assert!(is_newer("0.2.0", "0.1.9"));
+        assert!(!is_newer("v0.1.0", "0.1.0"));
+        assert!(!is_newer("v0.0.9", "0.1.0"));
+    }
+
+    #[test]
+    fn ignores_suffixes() {
+        assert!(is_newer("v1.2.0-beta", "1.1.0"));
+        assert!(!is_newer("v1.1.0-rc1", "1.1.0"));
+    }
+}